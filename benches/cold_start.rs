@@ -0,0 +1,38 @@
+use blockchain_workshop::storage::{BlockStore, StateChanges, StoredBlocks};
+use blockchain_workshop::traits::Hashable;
+use blockchain_workshop::types::{Block, Blockchain};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const TARGET: u128 = u128::MAX >> 8;
+const BLOCKS: u64 = 100_000;
+
+// A workshop chain of BLOCKS empty blocks, written straight to the store since mining
+// them would take longer than the benchmark itself
+fn store() -> BlockStore {
+    let store = BlockStore::temporary().unwrap();
+    let mut prev_hash = None;
+    for height in 0..BLOCKS {
+        let mut block = Block::new(prev_hash);
+        block.set_height(height);
+        block.set_timestamp(height as u128);
+        store.append(height, &block, TARGET, &StateChanges::default()).unwrap();
+        prev_hash = Some(block.hash());
+    }
+    store
+}
+
+fn cold_start(c: &mut Criterion) {
+    let store = store();
+    let mut group = c.benchmark_group("cold_start_100k_blocks");
+    group.sample_size(10);
+    group.bench_function("open", |b| {
+        b.iter(|| Blockchain::<StoredBlocks>::open(store.clone(), TARGET).unwrap())
+    });
+    group.bench_function("replay", |b| {
+        b.iter(|| Blockchain::with_max_target(TARGET).with_store(store.clone()).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, cold_start);
+criterion_main!(benches);
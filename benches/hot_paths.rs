@@ -0,0 +1,120 @@
+use blockchain_workshop::consensus::BLOCK_TIME;
+use blockchain_workshop::testing::Fixtures;
+use blockchain_workshop::traits::Hashable;
+use blockchain_workshop::types::{Block, Blockchain, Transaction, TransactionData};
+use blockchain_workshop::utils::{append_block, append_block_with_tx, mine_block};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use ed25519_dalek::{Keypair, Signer};
+
+const TARGET: u128 = u128::MAX >> 8;
+
+fn transfer(keypair: &Keypair, nonce: u128, timestamp: u128) -> Transaction {
+    let mut transaction = Transaction::new(
+        TransactionData::Transfer {
+            to: "bob".to_string(),
+            amount: 1,
+        },
+        Some("alice".to_string()),
+        timestamp,
+    );
+    transaction.set_nonce(nonce);
+    transaction.sign(Some(keypair.sign(&transaction.signing_message())));
+    transaction
+}
+
+// A chain whose genesis creates alice and bob, with enough for `supply` transfers. The
+// same every time, so a block mined on one such chain fits all of them.
+fn chain(supply: usize) -> (Blockchain, Keypair) {
+    let fixtures = &mut Fixtures::default();
+    let alice = fixtures.keypair();
+    let mut bc = Blockchain::with_max_target(TARGET).with_clock(fixtures.clock().clone());
+    let time = fixtures.timestamp();
+    let genesis = vec![
+        Transaction::create_account("alice".to_string(), &alice, time),
+        Transaction::create_account("bob".to_string(), &fixtures.keypair(), time),
+        Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: "alice".to_string(),
+                amount: supply as u128,
+            },
+            None,
+            time,
+        ),
+    ];
+    append_block_with_tx(&mut bc, 0, genesis).unwrap();
+
+    (bc, alice)
+}
+
+fn block_with_transfers(bc: &Blockchain, alice: &Keypair, transactions: usize) -> Block {
+    let mut block = bc.next_block();
+    block.set_timestamp(bc.now());
+    let mut transfers: Vec<Transaction> = (1..=transactions).map(|nonce| transfer(alice, nonce as u128, bc.now())).collect();
+    bc.sort_transactions(&mut transfers);
+    for transaction in transfers {
+        block.add_transaction(transaction);
+    }
+    mine_block(&mut block, bc.target());
+
+    block
+}
+
+fn transaction_hash(c: &mut Criterion) {
+    let keypair = Fixtures::default().keypair();
+    let transaction = transfer(&keypair, 1, 0);
+
+    c.bench_function("transaction_hash", |b| b.iter(|| transaction.hash()));
+}
+
+fn mining(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mine_block");
+    group.sample_size(10);
+    for leading_zero_bits in &[4u32, 8, 12] {
+        let target = u128::MAX >> leading_zero_bits;
+        group.bench_with_input(BenchmarkId::from_parameter(leading_zero_bits), &target, |b, target| {
+            let mut nonce = 0;
+            b.iter(|| {
+                // A fresh timestamp each time, so the search doesn't repeat the same nonces
+                let mut block = Block::new(None);
+                nonce += 1;
+                block.set_timestamp(nonce);
+                mine_block(&mut block, *target);
+                block
+            })
+        });
+    }
+    group.finish();
+}
+
+fn append(c: &mut Criterion) {
+    let mut group = c.benchmark_group("append_block");
+    group.sample_size(10);
+    for transactions in &[1usize, 100, 1000] {
+        let (bc, alice) = chain(*transactions);
+        let block = block_with_transfers(&bc, &alice, *transactions);
+
+        group.bench_with_input(BenchmarkId::from_parameter(transactions), transactions, |b, transactions| {
+            b.iter_batched(
+                || chain(*transactions).0,
+                |mut bc| bc.append_block(block.clone()).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn validate(c: &mut Criterion) {
+    let fixtures = &mut Fixtures::default();
+    let mut bc = Blockchain::with_max_target(TARGET).with_clock(fixtures.clock().clone());
+    for nonce in 0..100 {
+        // On schedule, so the target doesn't tighten
+        fixtures.clock().advance(BLOCK_TIME);
+        append_block(&mut bc, fixtures, nonce);
+    }
+
+    c.bench_function("validate_100_blocks", |b| b.iter(|| bc.validate().unwrap()));
+}
+
+criterion_group!(benches, transaction_hash, mining, append, validate);
+criterion_main!(benches);
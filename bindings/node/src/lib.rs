@@ -0,0 +1,184 @@
+use blockchain_workshop::rpc::{AccountView, BlockView, SubmitTransactionRequest, TransactionView};
+use blockchain_workshop::traits::{Hashable, WorldState};
+use blockchain_workshop::types::{Blockchain, Transaction};
+use blockchain_workshop::utils;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+use napi::{Error, Result, Status};
+use napi_derive::napi;
+use serde_json::Value;
+
+// Node.js bindings: wallet helpers sign exactly like the crate does, and Chain wraps a
+// Blockchain for the explorer. Blocks, accounts and transactions use the same JSON shapes
+// as the REST and JSON-RPC APIs.
+
+fn invalid_arg<E: ToString>(error: E) -> Error {
+    Error::new(Status::InvalidArg, error.to_string())
+}
+
+fn to_json<T: serde::Serialize>(value: T) -> Result<Value> {
+    serde_json::to_value(value).map_err(|error| Error::from_reason(error.to_string()))
+}
+
+fn parse_request(request: Value) -> Result<SubmitTransactionRequest> {
+    serde_json::from_value(request).map_err(invalid_arg)
+}
+
+fn parse_transaction(request: Value) -> Result<Transaction> {
+    parse_request(request)?.to_transaction().map_err(invalid_arg)
+}
+
+fn keypair(secret_key: &str) -> Result<Keypair> {
+    let bytes = hex::decode(secret_key).map_err(invalid_arg)?;
+    let secret = SecretKey::from_bytes(&bytes).map_err(invalid_arg)?;
+    let public = PublicKey::from(&secret);
+
+    Ok(Keypair { secret, public })
+}
+
+#[napi(object)]
+pub struct WalletKeys {
+    pub public_key: String,
+    pub secret_key: String,
+}
+
+#[napi]
+pub fn generate_keys() -> WalletKeys {
+    let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+
+    WalletKeys {
+        public_key: hex::encode(keypair.public.as_bytes()),
+        secret_key: hex::encode(keypair.secret.as_bytes()),
+    }
+}
+
+#[napi]
+pub fn generate_account_id() -> String {
+    utils::generate_account_id()
+}
+
+#[napi]
+pub fn transaction_hash(request: Value) -> Result<String> {
+    Ok(parse_transaction(request)?.hash())
+}
+
+// Returns the request with its `signature` filled in, ready for submission
+#[napi]
+pub fn sign_transaction(request: Value, secret_key: String) -> Result<Value> {
+    let mut request = parse_request(request)?;
+    request.signature = None;
+    let message = request.to_transaction().map_err(invalid_arg)?.signing_message();
+
+    let signature = keypair(&secret_key)?.sign(&message);
+    request.signature = Some(hex::encode(signature.to_bytes()));
+
+    to_json(request)
+}
+
+#[napi]
+pub struct Chain {
+    blockchain: Blockchain,
+}
+
+#[napi]
+impl Chain {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Self {
+            blockchain: Blockchain::new(),
+        }
+    }
+
+    #[napi(getter)]
+    pub fn height(&self) -> Option<i64> {
+        if self.blockchain.is_empty() {
+            None
+        } else {
+            Some(self.blockchain.len() as i64 - 1)
+        }
+    }
+
+    #[napi(getter)]
+    pub fn tip_hash(&self) -> Option<String> {
+        self.blockchain.get_last_block_hash()
+    }
+
+    #[napi(getter)]
+    pub fn genesis_hash(&self) -> Option<String> {
+        self.blockchain.genesis_hash()
+    }
+
+    #[napi(getter)]
+    pub fn chain_id(&self) -> String {
+        self.blockchain.chain_id().to_string()
+    }
+
+    #[napi]
+    pub fn get_block(&self, height: i64) -> Result<Option<Value>> {
+        if height < 0 {
+            return Ok(None);
+        }
+
+        match self.blockchain.get_block_by_height(height as u64) {
+            Some(block) => Ok(Some(to_json(BlockView::new(block))?)),
+            None => Ok(None),
+        }
+    }
+
+    #[napi]
+    pub fn get_account(&self, account_id: String) -> Result<Option<Value>> {
+        match self.blockchain.get_account_by_id(account_id.clone()) {
+            Some(account) => Ok(Some(to_json(AccountView::new(&account_id, account))?)),
+            None => Ok(None),
+        }
+    }
+
+    // Decimal string, for the `nonce` of the next request the account signs
+    #[napi]
+    pub fn next_nonce(&self, account_id: String) -> String {
+        self.blockchain.next_nonce(&account_id).to_string()
+    }
+
+    #[napi]
+    pub fn submit_transaction(&mut self, request: Value) -> Result<String> {
+        let transaction = parse_transaction(request)?;
+        self.blockchain.submit_transaction(transaction).map_err(Error::from_reason)
+    }
+
+    #[napi]
+    pub fn pending_transactions(&self) -> Result<Value> {
+        to_json(
+            self.blockchain
+                .pending_transactions()
+                .iter()
+                .map(TransactionView::from)
+                .collect::<Vec<TransactionView>>(),
+        )
+    }
+
+    // Mines a block with the given transactions on top of the tip, returns its hash. After
+    // genesis they go in canonical order rather than the order given.
+    #[napi]
+    pub fn append_block(&mut self, requests: Vec<Value>) -> Result<String> {
+        let mut block = self.blockchain.next_block();
+        let mut transactions = requests.into_iter().map(parse_transaction).collect::<Result<Vec<_>>>()?;
+        if !self.blockchain.is_empty() {
+            self.blockchain.sort_transactions(&mut transactions);
+        }
+        for transaction in transactions {
+            block.add_transaction(transaction);
+        }
+        if !self.blockchain.is_empty() {
+            utils::mine_block(&mut block, self.blockchain.target());
+        }
+
+        let hash = block.hash();
+        self.blockchain.append_block(block).map_err(Error::from_reason)?;
+        Ok(hash)
+    }
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
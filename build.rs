@@ -0,0 +1,11 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().expect("protoc is unavailable"));
+        // The generated client needs the 2021 prelude, clients are generated per language anyway
+        tonic_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/workshop.proto"], &["proto"])
+            .expect("Failed to compile proto/workshop.proto");
+    }
+}
@@ -0,0 +1,32 @@
+#![no_main]
+
+use arbitrary::{Arbitrary, Unstructured};
+use blockchain_workshop::types::{Block, Blockchain, Hash, Transaction};
+use libfuzzer_sys::fuzz_target;
+
+// Builds blocks on the tip, on nothing or on garbage parents, so append_block, orphan
+// handling and side branches all see arbitrary transactions
+fuzz_target!(|data: &[u8]| {
+    let u = &mut Unstructured::new(data);
+    let mut bc = Blockchain::with_max_target(u128::MAX);
+
+    while !u.is_empty() {
+        let prev_hash = match u.int_in_range(0..=3) {
+            Ok(0) => None,
+            Ok(1) => Hash::arbitrary(u).ok(),
+            _ => bc.get_last_block_hash(),
+        };
+        let mut block = Block::new(prev_hash);
+        block.set_height(u.int_in_range(0..=bc.len() as u64).unwrap_or_default());
+        for transaction in Vec::<Transaction>::arbitrary(u).unwrap_or_default() {
+            block.add_transaction(transaction);
+        }
+        if let Ok(nonce) = u.arbitrary() {
+            block.set_nonce(nonce);
+        }
+
+        let _ = bc.process_block(block);
+    }
+
+    assert!(bc.validate().is_ok());
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use arbitrary::Unstructured;
+use blockchain_workshop::testing::{Differential, MockClock, GENESIS_TIME};
+use libfuzzer_sys::fuzz_target;
+
+// Rolling back through the state overlays has to accept the same blocks and transactions
+// and end up in the same state as copying the whole state
+fuzz_target!(|data: &[u8]| {
+    let mut differential = Differential::new(u128::MAX, MockClock::new(GENESIS_TIME));
+
+    if let Err(difference) = differential.run_arbitrary(&mut Unstructured::new(data)) {
+        panic!("{}", difference);
+    }
+    assert!(differential.chain().validate().is_ok());
+});
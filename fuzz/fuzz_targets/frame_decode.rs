@@ -0,0 +1,14 @@
+#![no_main]
+
+use blockchain_workshop::network::FrameCodec;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let codec = FrameCodec::default();
+
+    if let Ok(Some((frame, used))) = codec.decode(data) {
+        assert!(used <= data.len());
+        assert_eq!(codec.decode_exact(&codec.encode(&frame).unwrap()).unwrap(), frame);
+    }
+    let _ = codec.read_frame(&mut &data[..]);
+});
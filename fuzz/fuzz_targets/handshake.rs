@@ -0,0 +1,10 @@
+#![no_main]
+
+use blockchain_workshop::network::Handshake;
+use libfuzzer_sys::fuzz_target;
+use std::io::{self, BufReader};
+
+fuzz_target!(|data: &[u8]| {
+    let local = Handshake::new("fuzz", None);
+    let _ = local.exchange(&mut BufReader::new(data), &mut io::sink());
+});
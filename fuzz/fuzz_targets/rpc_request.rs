@@ -0,0 +1,17 @@
+#![no_main]
+
+use blockchain_workshop::network::PeerScores;
+use blockchain_workshop::rpc::{RpcHandler, SubmitTransactionRequest, SubscriptionSession, Subscriptions};
+use libfuzzer_sys::fuzz_target;
+use std::sync::{Arc, Mutex};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(request) = serde_json::from_slice::<SubmitTransactionRequest>(data) {
+        let _ = request.to_transaction();
+    }
+
+    if let Ok(request) = std::str::from_utf8(data) {
+        RpcHandler::new(Arc::new(Mutex::new(PeerScores::new()))).handle(request);
+        SubscriptionSession::new(Arc::new(Mutex::new(Subscriptions::new()))).handle(request);
+    }
+});
@@ -0,0 +1,151 @@
+use blockchain_workshop::datadir::{ChainLayout, ChainName, NodeConfig};
+use blockchain_workshop::mining::Miner;
+use blockchain_workshop::network::{ConnectionManager, Discovery, DiscoveryConfig, Node};
+use blockchain_workshop::rpc::{serve_rpc, RpcHandler};
+use blockchain_workshop::storage::{BlockStore, StoredBlocks};
+use blockchain_workshop::traits::Hashable;
+use blockchain_workshop::types::{BlockStatus, Blockchain, Error, GenesisConfig};
+use clap::{value_parser, Arg, Command};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// How often the main loop looks for mined blocks and new work for the miner
+const TICK: Duration = Duration::from_millis(200);
+// How often peers are redialed, see Node::maintain
+const MAINTAIN_INTERVAL: Duration = Duration::from_secs(1);
+
+fn command() -> Command {
+    Command::new("blockchain-node")
+        .about("Runs a node: block storage, peer to peer networking, JSON-RPC and optionally a miner")
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("PATH")
+                .value_parser(value_parser!(PathBuf))
+                .help("TOML config, by default the devnet's config.toml in the data directory if there is one"),
+        )
+}
+
+fn load_config(path: Option<&PathBuf>) -> Result<NodeConfig, Error> {
+    match path {
+        Some(path) => NodeConfig::load(path),
+        None => {
+            let path = ChainLayout::with_default_root(ChainName::default()).config_file();
+            if path.exists() {
+                NodeConfig::load(path)
+            } else {
+                Ok(NodeConfig::default())
+            }
+        }
+    }
+}
+
+// The chain in the data directory, started from the genesis config if the directory is
+// empty. A data directory holding another chain than the genesis config is an error. Blocks
+// are read from the store as they are needed, so a long chain starts as fast as a short one.
+fn open_chain(config: &NodeConfig, store: BlockStore) -> Result<Blockchain<StoredBlocks>, Error> {
+    let path = match &config.genesis {
+        Some(path) => path,
+        None => return Blockchain::open(store, config.chain.max_target()),
+    };
+
+    let genesis = GenesisConfig::load(path)?;
+    if let Some((stored, _)) = store.load_block(0)? {
+        if stored.hash() != genesis.block().hash() {
+            return Err(format!(
+                "{} holds a chain with another genesis block than {} describes",
+                config.layout().dir().display(),
+                path.display()
+            ));
+        }
+    }
+    Blockchain::open_genesis(store, &genesis)
+}
+
+fn run(config: NodeConfig) -> Result<(), Error> {
+    let layout = config.layout();
+    layout.create()?;
+    let store = BlockStore::open(layout.blocks_dir())?;
+    let blockchain = Arc::new(Mutex::new(open_chain(&config, store.clone())?));
+    // Peers are told the chain id and genesis of the chain itself, the config's chain name
+    // only picks the data directory and defaults
+    let info = blockchain.lock().unwrap().info();
+    match info.chain_id.as_str() {
+        "" => println!("{} at height {} in {}", config.chain, info.height, layout.dir().display()),
+        chain_id => println!("{} ({}) at height {} in {}", config.chain, chain_id, info.height, layout.dir().display()),
+    }
+
+    let node = Node::new(blockchain.clone());
+    let listen = node.listen(&config.listen)?;
+    let mut discovery = Discovery::new(DiscoveryConfig::new(config.peers.clone(), config.mdns));
+    discovery.start(config.chain.as_str(), listen)?;
+    let mut connections = ConnectionManager::new();
+    println!("Listening for peers on {}", listen);
+
+    let handler = RpcHandler::new(node.scores().clone()).with_blockchain(blockchain.clone());
+    let listener = TcpListener::bind(&config.rpc).map_err(|error| format!("Can't listen on {}: {}", config.rpc, error))?;
+    println!("JSON-RPC on {}", listener.local_addr().map_err(|error| error.to_string())?);
+    serve_rpc(listener, Arc::new(handler));
+
+    let mut miner = if config.mining.enabled {
+        let (mut miner, solutions) = Miner::new(blockchain.clone());
+        miner.set_threads(config.mining.threads);
+        miner.set_beneficiary(config.mining.account.clone());
+        println!("Mining on {} worker threads", miner.threads());
+        Some((miner, solutions))
+    } else {
+        None
+    };
+
+    let (stop, stopped) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = stop.send(());
+    })
+    .map_err(|error| error.to_string())?;
+
+    let mut last_maintained: Option<Instant> = None;
+    // Until SIGINT
+    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(TICK) {
+        if !matches!(last_maintained, Some(at) if at.elapsed() < MAINTAIN_INTERVAL) {
+            node.maintain(&mut discovery, &mut connections);
+            last_maintained = Some(Instant::now());
+        }
+
+        if let Some((miner, solutions)) = &mut miner {
+            for block in solutions.try_iter() {
+                let height = block.height();
+                match node.submit_block(block) {
+                    Ok(BlockStatus::Appended { .. }) => println!("Mined block #{}", height),
+                    Ok(_) => {}
+                    Err(error) => eprintln!("Mined block #{} was rejected: {}", height, error),
+                }
+            }
+            let tip = blockchain.lock().unwrap().get_last_block_hash();
+            miner.update_template(tip);
+        }
+    }
+
+    // Nothing is appended once the miner is stopped and the chain is locked, so the store
+    // is flushed with everything the chain has
+    println!("Shutting down");
+    if let Some((miner, _)) = &mut miner {
+        miner.stop();
+    }
+    let blockchain = blockchain.lock().unwrap();
+    store.flush()?;
+    println!("Flushed {} blocks to {}", blockchain.len(), layout.blocks_dir().display());
+    Ok(())
+}
+
+fn main() {
+    let matches = command().get_matches();
+    let result = load_config(matches.get_one::<PathBuf>("config")).and_then(run);
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
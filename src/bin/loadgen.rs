@@ -0,0 +1,117 @@
+use blockchain_workshop::datadir::ChainName;
+use blockchain_workshop::loadgen::{local_node, run, LoadConfig, TransferGenerator, MAX_TRANSFER};
+use std::env;
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: loadgen [--accounts N] [--tps N] [--transactions N] [--seed N]
+               [--chain devnet|classnet] [--block-interval MS]
+               [--rest HOST:PORT --faucet ACCOUNT_ID --faucet-key SECRET_HEX]
+
+Without --rest the load goes to an in-process chain with the difficulty of --chain
+that seals a block every --block-interval. With it, the accounts are created on the
+node and funded from the faucet account first.";
+
+struct Args {
+    accounts: usize,
+    seed: u64,
+    chain: ChainName,
+    block_interval: Duration,
+    rest: Option<String>,
+    faucet: Option<String>,
+    faucet_key: Option<String>,
+    config: LoadConfig,
+}
+
+fn parse<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", flag))?;
+    value.parse().map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        accounts: 10,
+        seed: 0,
+        chain: ChainName::default(),
+        block_interval: Duration::from_secs(1),
+        rest: None,
+        faucet: None,
+        faucet_key: None,
+        config: LoadConfig::default(),
+    };
+
+    let mut argv = env::args().skip(1);
+    while let Some(flag) = argv.next() {
+        match flag.as_str() {
+            "--accounts" => args.accounts = parse(&flag, argv.next())?,
+            "--tps" => args.config.tps = parse(&flag, argv.next())?,
+            "--transactions" => args.config.transactions = parse(&flag, argv.next())?,
+            "--seed" => args.seed = parse(&flag, argv.next())?,
+            "--chain" => args.chain = parse(&flag, argv.next())?,
+            "--block-interval" => args.block_interval = Duration::from_millis(parse(&flag, argv.next())?),
+            "--rest" => args.rest = Some(parse(&flag, argv.next())?),
+            "--faucet" => args.faucet = Some(parse(&flag, argv.next())?),
+            "--faucet-key" => args.faucet_key = Some(parse(&flag, argv.next())?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("Unknown argument {}", flag)),
+        }
+    }
+
+    Ok(args)
+}
+
+// Enough for every account to send every transfer
+fn supply(args: &Args) -> u128 {
+    (args.config.transactions as u128 + 1) * MAX_TRANSFER * 2
+}
+
+#[cfg(feature = "rest")]
+fn run_rest(address: &str, args: &Args, generator: &mut TransferGenerator) -> Result<(), String> {
+use blockchain_workshop::loadgen::{prepare, RestNode};
+    use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+
+    let faucet = args.faucet.clone().ok_or("--rest needs --faucet")?;
+    let secret = args.faucet_key.as_ref().ok_or("--rest needs --faucet-key")?;
+    let secret = hex::decode(secret)
+        .ok()
+        .and_then(|bytes| SecretKey::from_bytes(&bytes).ok())
+        .ok_or("Invalid --faucet-key")?;
+    let public = PublicKey::from(&secret);
+    let keypair = Keypair { secret, public };
+
+    let mut node = RestNode::new(address);
+    prepare(&mut node, generator, &faucet, &keypair, supply(args), &args.config)?;
+    println!("{}", run(&mut node, generator, &args.config)?);
+    Ok(())
+}
+
+#[cfg(not(feature = "rest"))]
+fn run_rest(_address: &str, _args: &Args, _generator: &mut TransferGenerator) -> Result<(), String> {
+    Err("loadgen was built without the rest feature".to_string())
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+    let mut generator = TransferGenerator::new(args.accounts, args.seed);
+
+    let result = match &args.rest {
+        Some(address) => run_rest(address, &args, &mut generator),
+        None => local_node(&generator, supply(&args), args.chain.max_target(), args.block_interval)
+            .and_then(|mut node| run(&mut node, &mut generator, &args.config))
+            .map(|report| println!("{}", report)),
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
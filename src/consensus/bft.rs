@@ -0,0 +1,524 @@
+use crate::traits::{CanonicalEncode, Hashable};
+#[cfg(feature = "std")]
+use crate::{traits::WorldState, types::Blockchain};
+use crate::types::{AccountId, Block, Error, Hash};
+use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VoteKind {
+    Prevote,
+    Precommit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Propose,
+    Prevote,
+    Precommit,
+    Commit,
+}
+
+#[derive(Debug, Clone)]
+pub struct BftVote {
+    pub(crate) kind: VoteKind,
+    pub(crate) height: u64,
+    pub(crate) round: u32,
+    pub(crate) block_hash: Option<Hash>,
+    pub(crate) validator: AccountId,
+    pub(crate) signature: Option<Signature>,
+}
+
+impl BftVote {
+    pub fn new(
+        kind: VoteKind,
+        height: u64,
+        round: u32,
+        block_hash: Option<Hash>,
+        validator: AccountId,
+    ) -> Self {
+        Self {
+            kind,
+            height,
+            round,
+            block_hash,
+            validator,
+            signature: None,
+        }
+    }
+
+    pub fn sign(&mut self, signature: Option<Signature>) {
+        self.signature = signature;
+    }
+}
+
+impl Hashable for BftVote {
+    fn hash(&self) -> Hash {
+        let kind = match self.kind {
+            VoteKind::Prevote => 0u8,
+            VoteKind::Precommit => 1u8,
+        };
+
+        (kind, self.height, self.round, &self.block_hash, &self.validator).canonical_hash()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Proposal {
+    pub(crate) height: u64,
+    pub(crate) round: u32,
+    pub(crate) block: Block,
+    pub(crate) proposer: AccountId,
+    pub(crate) signature: Option<Signature>,
+}
+
+impl Proposal {
+    pub fn new(height: u64, round: u32, block: Block, proposer: AccountId) -> Self {
+        Self {
+            height,
+            round,
+            block,
+            proposer,
+            signature: None,
+        }
+    }
+
+    pub fn sign(&mut self, signature: Option<Signature>) {
+        self.signature = signature;
+    }
+}
+
+impl Hashable for Proposal {
+    fn hash(&self) -> Hash {
+        (self.height, self.round, self.block.hash(), &self.proposer).canonical_hash()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum BftMessage {
+    Proposal(Box<Proposal>),
+    Vote(BftVote),
+}
+
+// Round-based propose/prevote/precommit state machine for a single height. The engine
+// doesn't do any networking or timing: messages it returns have to be broadcast to the
+// other validators and `on_timeout` has to be called when a step takes too long.
+pub struct BftEngine {
+    validators: Vec<(AccountId, PublicKey)>,
+    signer: Option<(AccountId, Keypair)>,
+    height: u64,
+    round: u32,
+    step: Step,
+    candidate: Option<Block>,
+    proposals: HashMap<u32, Block>,
+    votes: HashMap<(VoteKind, u32), HashMap<AccountId, BftVote>>,
+    locked: Option<(u32, Block)>,
+    decision: Option<(Block, Vec<BftVote>)>,
+}
+
+impl BftEngine {
+    pub fn new(validators: Vec<(AccountId, PublicKey)>, height: u64) -> Self {
+        Self {
+            validators,
+            signer: None,
+            height,
+            round: 0,
+            step: Step::Propose,
+            candidate: None,
+            proposals: HashMap::new(),
+            votes: HashMap::new(),
+            locked: None,
+            decision: None,
+        }
+    }
+
+    // Validators are the chain's finality validators, using their account keys
+    #[cfg(feature = "std")]
+    pub fn from_blockchain(bc: &Blockchain) -> Result<Self, Error> {
+        let mut validators = vec![];
+        for account_id in bc.finality().validators() {
+            match bc.get_account_by_id(account_id.clone()) {
+                Some(account) => validators.push((account_id.clone(), account.public_key)),
+                None => return Err(format!("Invalid validator account: {}", account_id)),
+            }
+        }
+
+        Ok(Self::new(validators, bc.len() as u64))
+    }
+
+    pub fn set_signer(&mut self, account_id: AccountId, keypair: Keypair) {
+        self.signer = Some((account_id, keypair));
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    pub fn round(&self) -> u32 {
+        self.round
+    }
+
+    pub fn step(&self) -> Step {
+        self.step
+    }
+
+    pub fn quorum(&self) -> usize {
+        self.validators.len() * 2 / 3 + 1
+    }
+
+    pub fn proposer(&self, round: u32) -> Option<&AccountId> {
+        if self.validators.is_empty() {
+            return None;
+        }
+
+        let index = (self.height + round as u64) % self.validators.len() as u64;
+        Some(&self.validators[index as usize].0)
+    }
+
+    // The decided block together with the precommits certifying it
+    pub fn decision(&self) -> Option<&(Block, Vec<BftVote>)> {
+        self.decision.as_ref()
+    }
+
+    pub fn start_round(&mut self, round: u32, candidate: Option<Block>) -> Vec<BftMessage> {
+        self.round = round;
+        self.step = Step::Propose;
+        if candidate.is_some() {
+            self.candidate = candidate;
+        }
+
+        let signer_id = match &self.signer {
+            Some((account_id, _)) => account_id.clone(),
+            None => return vec![],
+        };
+        if self.proposer(round) != Some(&signer_id) {
+            return vec![];
+        }
+
+        // A locked validator has to re-propose the block it is locked on
+        let block = match self.locked.clone().map(|(_, block)| block).or_else(|| self.candidate.clone()) {
+            Some(block) => block,
+            None => return vec![],
+        };
+
+        let mut proposal = Proposal::new(self.height, round, block.clone(), signer_id);
+        proposal.sign(self.sign(&proposal.hash()));
+        self.proposals.insert(round, block);
+
+        let mut messages = vec![BftMessage::Proposal(Box::new(proposal))];
+        messages.extend(self.prevote_proposal());
+        messages
+    }
+
+    pub fn handle_message(&mut self, message: BftMessage) -> Result<Vec<BftMessage>, Error> {
+        if self.step == Step::Commit {
+            return Ok(vec![]);
+        }
+
+        match message {
+            BftMessage::Proposal(proposal) => self.handle_proposal(*proposal),
+            BftMessage::Vote(vote) => self.handle_vote(vote),
+        }
+    }
+
+    pub fn on_timeout(&mut self) -> Vec<BftMessage> {
+        match self.step {
+            Step::Propose => {
+                self.step = Step::Prevote;
+                self.cast(VoteKind::Prevote, None)
+            }
+            Step::Prevote => {
+                self.step = Step::Precommit;
+                self.cast(VoteKind::Precommit, None)
+            }
+            Step::Precommit => self.start_round(self.round + 1, None),
+            Step::Commit => vec![],
+        }
+    }
+
+    fn handle_proposal(&mut self, proposal: Proposal) -> Result<Vec<BftMessage>, Error> {
+        if proposal.height != self.height {
+            return Err(format!("Proposal for height {} while at {}", proposal.height, self.height));
+        }
+        if self.proposer(proposal.round) != Some(&proposal.proposer) {
+            return Err(format!("{} is not the proposer of round {}", proposal.proposer, proposal.round));
+        }
+        self.verify(&proposal.proposer, &proposal.hash(), &proposal.signature)?;
+
+        if self.proposals.contains_key(&proposal.round) {
+            return Ok(vec![]);
+        }
+        self.proposals.insert(proposal.round, proposal.block);
+
+        if proposal.round != self.round || self.step != Step::Propose {
+            return Ok(self.check_thresholds());
+        }
+
+        Ok(self.prevote_proposal())
+    }
+
+    fn handle_vote(&mut self, vote: BftVote) -> Result<Vec<BftMessage>, Error> {
+        if vote.height != self.height {
+            return Err(format!("Vote for height {} while at {}", vote.height, self.height));
+        }
+        self.verify(&vote.validator, &vote.hash(), &vote.signature)?;
+
+        let votes = self.votes.entry((vote.kind, vote.round)).or_default();
+        if let Some(previous) = votes.get(&vote.validator) {
+            if previous.block_hash != vote.block_hash {
+                return Err(format!("Validator {} voted twice in round {}", vote.validator, vote.round));
+            }
+            return Ok(vec![]);
+        }
+        votes.insert(vote.validator.clone(), vote);
+
+        Ok(self.check_thresholds())
+    }
+
+    fn prevote_proposal(&mut self) -> Vec<BftMessage> {
+        let hash = match self.proposals.get(&self.round) {
+            Some(block) => block.hash(),
+            None => return vec![],
+        };
+        let block_hash = match &self.locked {
+            Some((_, locked)) if locked.hash() != hash => None,
+            _ => Some(hash),
+        };
+
+        self.step = Step::Prevote;
+        self.cast(VoteKind::Prevote, block_hash)
+    }
+
+    fn check_thresholds(&mut self) -> Vec<BftMessage> {
+        let mut messages = vec![];
+
+        if self.step == Step::Propose || self.step == Step::Prevote {
+            match self.majority(VoteKind::Prevote, self.round) {
+                Some(Some(hash)) => {
+                    if let Some(block) = self.proposal_with_hash(&hash) {
+                        self.locked = Some((self.round, block));
+                        self.step = Step::Precommit;
+                        messages.extend(self.cast(VoteKind::Precommit, Some(hash)));
+                    }
+                }
+                Some(None) => {
+                    self.step = Step::Precommit;
+                    messages.extend(self.cast(VoteKind::Precommit, None));
+                }
+                None => {}
+            }
+        }
+
+        if self.step != Step::Commit {
+            match self.majority(VoteKind::Precommit, self.round) {
+                Some(Some(hash)) => {
+                    if let Some(block) = self.proposal_with_hash(&hash) {
+                        let commit = self.votes[&(VoteKind::Precommit, self.round)]
+                            .values()
+                            .filter(|vote| vote.block_hash.as_ref() == Some(&hash))
+                            .cloned()
+                            .collect();
+                        self.decision = Some((block, commit));
+                        self.step = Step::Commit;
+                    }
+                }
+                Some(None) => messages.extend(self.start_round(self.round + 1, None)),
+                None => {}
+            }
+        }
+
+        messages
+    }
+
+    // The value (block hash or nil) more than 2/3 of the validators voted for
+    fn majority(&self, kind: VoteKind, round: u32) -> Option<Option<Hash>> {
+        let votes = self.votes.get(&(kind, round))?;
+        let mut counts: HashMap<&Option<Hash>, usize> = HashMap::new();
+        for vote in votes.values() {
+            *counts.entry(&vote.block_hash).or_default() += 1;
+        }
+
+        counts
+            .into_iter()
+            .find(|(_, count)| *count >= self.quorum())
+            .map(|(block_hash, _)| block_hash.clone())
+    }
+
+    fn proposal_with_hash(&self, hash: &Hash) -> Option<Block> {
+        self.proposals.values().find(|block| &block.hash() == hash).cloned()
+    }
+
+    fn cast(&mut self, kind: VoteKind, block_hash: Option<Hash>) -> Vec<BftMessage> {
+        let signer_id = match &self.signer {
+            Some((account_id, _)) => account_id.clone(),
+            None => return vec![],
+        };
+
+        let mut vote = BftVote::new(kind, self.height, self.round, block_hash, signer_id.clone());
+        vote.sign(self.sign(&vote.hash()));
+        self.votes
+            .entry((kind, self.round))
+            .or_default()
+            .insert(signer_id, vote.clone());
+
+        let mut messages = vec![BftMessage::Vote(vote)];
+        messages.extend(self.check_thresholds());
+        messages
+    }
+
+    fn sign(&self, hash: &Hash) -> Option<Signature> {
+        self.signer
+            .as_ref()
+            .map(|(_, keypair)| keypair.sign(hash.as_bytes()))
+    }
+
+    fn verify(&self, account_id: &AccountId, hash: &Hash, signature: &Option<Signature>) -> Result<(), Error> {
+        let public_key = match self.validators.iter().find(|(id, _)| id == account_id) {
+            Some((_, public_key)) => public_key,
+            None => return Err(format!("{} is not a validator", account_id)),
+        };
+
+        match signature {
+            Some(signature) if public_key.verify(hash.as_bytes(), signature).is_ok() => Ok(()),
+            Some(_) => Err("Invalid signature.".to_string()),
+            None => Err("Not sign.".to_string()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::types::Transaction;
+    use crate::utils::append_block_with_tx;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const VALIDATORS: [&str; 4] = ["alice", "bob", "carol", "dave"];
+
+    fn setup() -> (Blockchain, Vec<BftEngine>) {
+        let mut bc = Blockchain::new();
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let keypairs: Vec<Keypair> = VALIDATORS
+            .iter()
+            .map(|_| Keypair::generate(&mut rand::rngs::OsRng {}))
+            .collect();
+        let txs = VALIDATORS
+            .iter()
+            .zip(keypairs.iter())
+            .map(|(id, keypair)| {
+                Transaction::create_account(id.to_string(), keypair, time)
+            })
+            .collect();
+        assert!(append_block_with_tx(&mut bc, 1, txs).is_ok());
+        bc.set_validators(VALIDATORS.iter().map(|id| id.to_string()).collect(), 1);
+
+        let engines = VALIDATORS
+            .iter()
+            .zip(keypairs)
+            .map(|(id, keypair)| {
+                let mut engine = BftEngine::from_blockchain(&bc).unwrap();
+                engine.set_signer(id.to_string(), keypair);
+                engine
+            })
+            .collect();
+
+        (bc, engines)
+    }
+
+    fn candidate(bc: &Blockchain) -> Block {
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let mut block = bc.next_block();
+        block.add_transaction(Transaction::create_account("erin".to_string(), &keypair, time));
+        block
+    }
+
+    // Delivers every message to every engine but the ones in `offline` until nothing is left
+    fn deliver(engines: &mut [BftEngine], mut messages: Vec<BftMessage>, offline: &[usize]) {
+        while let Some(message) = messages.pop() {
+            for (i, engine) in engines.iter_mut().enumerate() {
+                if !offline.contains(&i) {
+                    messages.extend(engine.handle_message(message.clone()).unwrap_or_default());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_commit_in_first_round() {
+        let (mut bc, mut engines) = setup();
+        let block = candidate(&bc);
+
+        let messages = engines
+            .iter_mut()
+            .flat_map(|engine| engine.start_round(0, Some(block.clone())))
+            .collect();
+        deliver(&mut engines, messages, &[]);
+
+        for engine in &engines {
+            let (decided, commit) = engine.decision().unwrap();
+            assert_eq!(decided.hash(), block.hash());
+            assert!(commit.len() >= engine.quorum());
+        }
+
+        let (decided, commit) = engines[0].decision().unwrap().clone();
+        assert!(bc.append_committed_block(decided, &commit).is_ok());
+        assert_eq!(bc.finalized_height(), Some(1));
+    }
+
+    #[test]
+    fn test_offline_proposer_moves_to_next_round() {
+        let (bc, mut engines) = setup();
+        let block = candidate(&bc);
+        let proposer = engines[0].proposer(0).unwrap().clone();
+        let offline = vec![VALIDATORS.iter().position(|id| *id == proposer).unwrap()];
+
+        for (i, engine) in engines.iter_mut().enumerate() {
+            if !offline.contains(&i) {
+                engine.start_round(0, Some(block.clone()));
+            }
+        }
+
+        let mut messages = vec![];
+        for _ in 0..3 {
+            for (i, engine) in engines.iter_mut().enumerate() {
+                if !offline.contains(&i) && engine.step() != Step::Commit {
+                    messages.extend(engine.on_timeout());
+                }
+            }
+            deliver(&mut engines, std::mem::take(&mut messages), &offline);
+        }
+
+        for (i, engine) in engines.iter().enumerate() {
+            if !offline.contains(&i) {
+                assert_eq!(engine.decision().map(|(b, _)| b.hash()), Some(block.hash()));
+                assert!(engine.round() >= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_commit_without_quorum_fails() {
+        let (mut bc, mut engines) = setup();
+        let block = candidate(&bc);
+
+        let messages = engines
+            .iter_mut()
+            .flat_map(|engine| engine.start_round(0, Some(block.clone())))
+            .collect();
+        deliver(&mut engines, messages, &[]);
+
+        let (decided, commit) = engines[0].decision().unwrap().clone();
+        assert!(bc.append_committed_block(decided.clone(), &commit[..1]).is_err());
+
+        let forged: Vec<BftVote> = commit.iter().map(|vote| vote_with_hash(vote, "other")).collect();
+        assert!(bc.append_committed_block(decided, &forged).is_err());
+        assert_eq!(bc.len(), 1);
+    }
+
+    fn vote_with_hash(vote: &BftVote, hash: &str) -> BftVote {
+        let mut forged = vote.clone();
+        forged.block_hash = Some(hash.to_string());
+        forged
+    }
+}
@@ -0,0 +1,79 @@
+use crate::types::Error;
+
+// Bitcoin-style compact ("nBits") target encoding: the top byte is the length of the
+// target in bytes, the low 23 bits its most significant digits. Bit 23 is a sign bit,
+// so a mantissa that would set it is shifted down a byte instead.
+const SIGN_BIT: u32 = 0x0080_0000;
+const MANTISSA: u32 = 0x007f_ffff;
+
+pub fn encode_bits(target: u128) -> u32 {
+    let mut size = (128 - target.leading_zeros()).div_ceil(8);
+    let mut mantissa = if size <= 3 {
+        (target << (8 * (3 - size))) as u32
+    } else {
+        (target >> (8 * (size - 3))) as u32
+    };
+    if mantissa & SIGN_BIT != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+
+    mantissa | (size << 24)
+}
+
+pub fn decode_bits(bits: u32) -> Result<u128, Error> {
+    let size = bits >> 24;
+    let mantissa = bits & MANTISSA;
+    if bits & SIGN_BIT != 0 && mantissa != 0 {
+        return Err(format!("Negative target in bits {:08x}", bits));
+    }
+
+    if size <= 3 {
+        return Ok((mantissa >> (8 * (3 - size))) as u128);
+    }
+    let shift = 8 * (size - 3);
+    if mantissa != 0 && (32 - mantissa.leading_zeros()) + shift > 128 {
+        return Err(format!("Target in bits {:08x} overflows", bits));
+    }
+
+    Ok((mantissa as u128).checked_shl(shift).unwrap_or(0))
+}
+
+// The closest target at or below `target` that survives a trip through bits
+pub fn round_target(target: u128) -> u128 {
+    decode_bits(encode_bits(target)).unwrap_or(target)
+}
+
+// Expected hashes to meet `target`, what a block adds to the work of its chain
+pub fn target_work(target: u128) -> u128 {
+    u128::MAX / target.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bits() {
+        assert_eq!(encode_bits(0xffff << 96), 0x0f00_ffff);
+        assert_eq!(decode_bits(0x0f00_ffff), Ok(0xffff << 96));
+        assert_eq!(encode_bits(0), 0);
+        assert_eq!(decode_bits(0), Ok(0));
+        assert_eq!(encode_bits(0x12), 0x0112_0000);
+        assert_eq!(encode_bits(0x80), 0x0200_8000);
+        assert_eq!(decode_bits(0x0200_8000), Ok(0x80));
+        assert_eq!(decode_bits(0x0112_3456), Ok(0x12));
+
+        assert_eq!(encode_bits(u128::MAX), 0x1100_ffff);
+        assert_eq!(decode_bits(0x1100_ffff), Ok(0xffff << 112));
+        assert!(decode_bits(0x1101_0000).is_err());
+        assert!(decode_bits(0x0480_0001).is_err());
+
+        for target in [1, 0x7f_ffff, 1 << 100, u128::MAX >> 8, u128::MAX] {
+            let rounded = round_target(target);
+            assert!(rounded <= target);
+            assert_eq!(encode_bits(rounded), encode_bits(target));
+            assert_eq!(round_target(rounded), rounded);
+        }
+    }
+}
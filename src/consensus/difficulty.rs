@@ -0,0 +1,275 @@
+use crate::types::Timestamp;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub const BLOCK_TIME: Timestamp = 10 * 60;
+pub const EPOCH_LENGTH: u64 = 10;
+pub const MAX_ADJUSTMENT: u128 = 4;
+pub const LWMA_WINDOW: u64 = 45;
+// LWMA caps a single solve time at this many block times so one stalled block can't
+// make the next ones trivial
+pub const MAX_SOLVE_TIME_FACTOR: u128 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DifficultyAlgorithm {
+    // Bitcoin-style: one adjustment at the end of every epoch
+    Epoch,
+    // Linear Weighted Moving Average: adjusts every block from the last `window` solve
+    // times, recent blocks weigh the most. Reacts quickly to hashrate joining or leaving.
+    Lwma { window: u64 },
+}
+
+// Configs name the algorithm in a "type" field. Binary formats can't read internally
+// tagged enums, so they get the plain form.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TaggedAlgorithm {
+    Epoch,
+    Lwma { window: u64 },
+}
+
+#[derive(Serialize, Deserialize)]
+enum PlainAlgorithm {
+    Epoch,
+    Lwma { window: u64 },
+}
+
+impl Serialize for DifficultyAlgorithm {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match (*self, serializer.is_human_readable()) {
+            (DifficultyAlgorithm::Epoch, true) => TaggedAlgorithm::Epoch.serialize(serializer),
+            (DifficultyAlgorithm::Lwma { window }, true) => TaggedAlgorithm::Lwma { window }.serialize(serializer),
+            (DifficultyAlgorithm::Epoch, false) => PlainAlgorithm::Epoch.serialize(serializer),
+            (DifficultyAlgorithm::Lwma { window }, false) => PlainAlgorithm::Lwma { window }.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DifficultyAlgorithm {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            Ok(match TaggedAlgorithm::deserialize(deserializer)? {
+                TaggedAlgorithm::Epoch => DifficultyAlgorithm::Epoch,
+                TaggedAlgorithm::Lwma { window } => DifficultyAlgorithm::Lwma { window },
+            })
+        } else {
+            Ok(match PlainAlgorithm::deserialize(deserializer)? {
+                PlainAlgorithm::Epoch => DifficultyAlgorithm::Epoch,
+                PlainAlgorithm::Lwma { window } => DifficultyAlgorithm::Lwma { window },
+            })
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DifficultyConfig {
+    pub algorithm: DifficultyAlgorithm,
+    // Blocks per retarget epoch
+    pub epoch_length: u64,
+    // Seconds the chain aims for between blocks
+    pub block_time: Timestamp,
+}
+
+impl DifficultyConfig {
+    pub fn new(epoch_length: u64, block_time: Timestamp) -> Self {
+        Self {
+            algorithm: DifficultyAlgorithm::Epoch,
+            epoch_length: epoch_length.max(1),
+            block_time: block_time.max(1),
+        }
+    }
+
+    pub fn lwma(window: u64, block_time: Timestamp) -> Self {
+        Self {
+            algorithm: DifficultyAlgorithm::Lwma { window: window.max(1) },
+            ..Self::new(EPOCH_LENGTH, block_time)
+        }
+    }
+
+    pub fn is_retarget_height(&self, height: u64) -> bool {
+        height > 0 && height.is_multiple_of(self.epoch_length.max(1))
+    }
+
+    // How many of the latest blocks next_target looks at
+    pub fn history_len(&self) -> usize {
+        match self.algorithm {
+            DifficultyAlgorithm::Epoch => self.epoch_length as usize + 1,
+            DifficultyAlgorithm::Lwma { window } => window as usize + 1,
+        }
+    }
+
+    // Target for the block after `height`. `recent` holds the timestamp and target of up
+    // to history_len() blocks, oldest first, ending with the block at `height`.
+    pub fn next_target(&self, height: u64, recent: &[(Timestamp, u128)], max_target: u128) -> u128 {
+        let (last_timestamp, last_target) = match recent.last() {
+            Some(last) => *last,
+            None => return max_target,
+        };
+
+        match self.algorithm {
+            DifficultyAlgorithm::Epoch => {
+                if !self.is_retarget_height(height) || recent.len() <= self.epoch_length as usize {
+                    return last_target;
+                }
+                let (epoch_start, _) = recent[recent.len() - 1 - self.epoch_length as usize];
+                self.retarget(last_target, max_target, last_timestamp.saturating_sub(epoch_start))
+            }
+            DifficultyAlgorithm::Lwma { window } => self.lwma_target(window, recent, max_target),
+        }
+    }
+
+    // New target after an epoch that took `actual` seconds. The target scales with the
+    // time taken, at most MAX_ADJUSTMENT times up or down, and never exceeds `max_target`.
+    pub fn retarget(&self, target: u128, max_target: u128, actual: Timestamp) -> u128 {
+        let expected = self.block_time.saturating_mul(self.epoch_length as Timestamp).max(1);
+        let actual = actual.clamp(expected / MAX_ADJUSTMENT, expected.saturating_mul(MAX_ADJUSTMENT));
+
+        scale(target, actual, expected).clamp(1, max_target)
+    }
+
+    fn lwma_target(&self, window: u64, recent: &[(Timestamp, u128)], max_target: u128) -> u128 {
+        let blocks = &recent[recent.len().saturating_sub(window as usize + 1)..];
+        let solved = blocks.len() as u128 - 1;
+        if solved == 0 {
+            return blocks[0].1;
+        }
+
+        let mut weighted_time: u128 = 0;
+        let mut target_sum: u128 = 0;
+        for (weight, pair) in blocks.windows(2).enumerate() {
+            let solve_time = pair[1]
+                .0
+                .saturating_sub(pair[0].0)
+                .clamp(1, self.block_time.max(1).saturating_mul(MAX_SOLVE_TIME_FACTOR));
+            weighted_time = weighted_time.saturating_add((weight as u128 + 1) * solve_time);
+            target_sum = target_sum.saturating_add(pair[1].1 / solved);
+        }
+
+        // Had every block taken exactly block_time, weighted_time would be this
+        let expected = (solved * (solved + 1) / 2).saturating_mul(self.block_time.max(1));
+        scale(target_sum, weighted_time, expected).clamp(1, max_target)
+    }
+}
+
+impl Default for DifficultyConfig {
+    fn default() -> Self {
+        Self::new(EPOCH_LENGTH, BLOCK_TIME)
+    }
+}
+
+// target * numerator / denominator, rounded down, with the product taken in 256 bits so
+// nothing is lost on the way. Saturates if the result doesn't fit, or the denominator is 0.
+fn scale(target: u128, numerator: u128, denominator: u128) -> u128 {
+    let (high, low) = widening_mul(target, numerator);
+    if high >= denominator {
+        return u128::MAX;
+    }
+
+    // Long division of the 256-bit product, one bit of `low` at a time. The remainder stays
+    // below the denominator, so only the bit shifted out of it needs carrying.
+    let mut remainder = high;
+    let mut quotient = 0u128;
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        if carry == 1 || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            quotient |= 1 << bit;
+        }
+    }
+    quotient
+}
+
+// The full product of a and b, as its high and low 128 bits
+fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a_high, a_low) = (a >> 64, a & MASK);
+    let (b_high, b_low) = (b >> 64, b & MASK);
+
+    let low = a_low * b_low;
+    let cross = a_high * b_low + (low >> 64);
+    let middle = (cross & MASK) + a_low * b_high;
+    let high = a_high * b_high + (cross >> 64) + (middle >> 64);
+    (high, (middle << 64) | (low & MASK))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retarget() {
+        let config = DifficultyConfig::new(10, 600);
+        let expected = 6000;
+
+        assert_eq!(config.retarget(1000, u128::MAX, expected), 1000);
+        assert_eq!(config.retarget(1000, u128::MAX, expected / 2), 500);
+        assert_eq!(config.retarget(1000, u128::MAX, expected * 2), 2000);
+        // Clamped to 4x either way
+        assert_eq!(config.retarget(1000, u128::MAX, 0), 250);
+        assert_eq!(config.retarget(1000, u128::MAX, expected * 100), 4000);
+        assert_eq!(config.retarget(1000, 3000, expected * 100), 3000);
+        assert_eq!(config.retarget(u128::MAX, u128::MAX, expected * 2), u128::MAX);
+        assert_eq!(config.retarget(1, u128::MAX, 0), 1);
+
+        assert!(!config.is_retarget_height(0));
+        assert!(!config.is_retarget_height(9));
+        assert!(config.is_retarget_height(10));
+    }
+
+    #[test]
+    fn test_scale() {
+        assert_eq!(scale(1000, 3, 4), 750);
+        assert_eq!(scale(u128::MAX, 2, 3), u128::MAX / 3 * 2);
+        // Remainders whose product with the numerator doesn't fit in 128 bits
+        assert_eq!(scale(u128::MAX / 2 - 1, 3, u128::MAX / 2), 2);
+        assert_eq!(scale(u128::MAX - 1, 4, u128::MAX / 3), 11);
+        assert_eq!(scale(u128::MAX / 2 + 7, 5, u128::MAX / 4 + 3), 10);
+        // Too big for 128 bits
+        assert_eq!(scale(u128::MAX, 2, 1), u128::MAX);
+        assert_eq!(scale(1, 1, 0), u128::MAX);
+        assert_eq!(widening_mul(u128::MAX, u128::MAX), (u128::MAX - 1, 1));
+    }
+
+    #[test]
+    fn test_next_target_from_block_times() {
+        let config = DifficultyConfig::new(4, 600);
+        let history = |spacing: Timestamp| -> Vec<(Timestamp, u128)> { (0..5).map(|i| (i * spacing, 1 << 64)).collect() };
+
+        // Faster chain gets harder, slower chain easier, both by the time ratio
+        assert_eq!(config.next_target(4, &history(300), u128::MAX), 1 << 63);
+        assert_eq!(config.next_target(4, &history(1200), u128::MAX), 1 << 65);
+        // No more than 4x, and never down to zero however fast
+        assert_eq!(config.next_target(4, &history(0), u128::MAX), 1 << 62);
+        assert_eq!(config.next_target(4, &history(60_000), u128::MAX), 1 << 66);
+        assert_eq!(config.next_target(4, &[(0, 1), (0, 1), (0, 1), (0, 1), (0, 1)], u128::MAX), 1);
+        // Between retargets the target carries over
+        assert_eq!(config.next_target(3, &history(300), u128::MAX), 1 << 64);
+    }
+
+    #[test]
+    fn test_lwma() {
+        let config = DifficultyConfig::lwma(4, 600);
+        let blocks = |solve_times: &[Timestamp]| {
+            let mut time = 0;
+            let mut recent = vec![(time, 1_000_000)];
+            for solve_time in solve_times {
+                time += solve_time;
+                recent.push((time, 1_000_000));
+            }
+            recent
+        };
+
+        assert_eq!(config.next_target(0, &blocks(&[]), u128::MAX), 1_000_000);
+        assert_eq!(config.next_target(4, &blocks(&[600, 600, 600, 600]), u128::MAX), 1_000_000);
+        assert_eq!(config.next_target(4, &blocks(&[300, 300, 300, 300]), u128::MAX), 500_000);
+        // Only the window counts, and the latest block weighs the most
+        assert_eq!(config.next_target(5, &blocks(&[1, 600, 600, 600, 600]), u128::MAX), 1_000_000);
+        assert!(
+            config.next_target(4, &blocks(&[600, 600, 600, 60]), u128::MAX)
+                < config.next_target(4, &blocks(&[60, 600, 600, 600]), u128::MAX)
+        );
+        // A single stalled block is capped
+        assert_eq!(config.next_target(4, &blocks(&[600, 600, 600, 1_000_000]), u128::MAX), 3_000_000);
+        assert_eq!(config.next_target(4, &blocks(&[600, 600, 600, 1_000_000]), 2_000_000), 2_000_000);
+    }
+}
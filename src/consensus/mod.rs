@@ -0,0 +1,14 @@
+mod bft;
+mod compact;
+mod difficulty;
+mod pow;
+mod target;
+
+pub use bft::{BftEngine, BftMessage, BftVote, Proposal, Step, VoteKind};
+pub use compact::{decode_bits, encode_bits, round_target, target_work};
+pub use difficulty::{
+    DifficultyAlgorithm, DifficultyConfig, BLOCK_TIME, EPOCH_LENGTH, LWMA_WINDOW, MAX_ADJUSTMENT,
+    MAX_SOLVE_TIME_FACTOR,
+};
+pub use pow::{Argon2Params, Argon2Pow, Blake2sPow, Pow, PowAlgorithm, Sha256dPow, ARGON2_SALT};
+pub use target::{meets_target, Target};
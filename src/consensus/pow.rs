@@ -0,0 +1,199 @@
+use crate::consensus::{decode_bits, encode_bits, meets_target};
+use crate::traits::Hashable;
+use crate::types::{Block, Error, Hash};
+use argon2::{Algorithm, Argon2, Params, Version};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+// A proof-of-work puzzle over the block header. The block hash commits to the header and
+// the transactions, the puzzle turns it into the digest that has to meet the target.
+pub trait Pow: Debug + Send + Sync {
+    // The digest for a block hash, all a header-only client has to go on
+    fn digest(&self, block_hash: &Hash) -> Hash;
+
+    fn pow_hash(&self, block: &Block) -> Hash {
+        self.digest(&block.hash())
+    }
+
+    // Stamps the target into the block's bits and searches nonces until the block meets it.
+    // The search is against the target as the bits state it, which is what verify checks.
+    fn seal(&self, block: &mut Block, target: u128) {
+        let bits = encode_bits(target);
+        block.set_bits(bits);
+        let target = decode_bits(bits).unwrap_or(target);
+        while !meets_target(&self.pow_hash(block), target) {
+            block.set_nonce(block.nonce() + 1);
+        }
+    }
+
+    // Whether the block meets the target stated in its own bits
+    fn verify(&self, block: &Block) -> bool {
+        match decode_bits(block.bits()) {
+            Ok(target) => meets_target(&self.pow_hash(block), target),
+            Err(_) => false,
+        }
+    }
+}
+
+// The block hash itself, the puzzle every chain used so far
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Blake2sPow;
+
+impl Pow for Blake2sPow {
+    fn digest(&self, block_hash: &Hash) -> Hash {
+        block_hash.clone()
+    }
+}
+
+// Bitcoin's double SHA-256 over the block hash
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Sha256dPow;
+
+impl Pow for Sha256dPow {
+    fn digest(&self, block_hash: &Hash) -> Hash {
+        hex::encode(Sha256::digest(&Sha256::digest(block_hash.as_bytes())))
+    }
+}
+
+pub const ARGON2_SALT: &[u8] = b"blockchain_workshop";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Argon2Params {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for Argon2Params {
+    fn default() -> Self {
+        Self {
+            memory_kib: 4096,
+            iterations: 1,
+            parallelism: 1,
+        }
+    }
+}
+
+// Argon2id over the block hash. Every attempt has to fill `memory_kib` of memory, which
+// evens out the gap between commodity hardware and specialised miners, at the price of
+// slower verification for every node.
+#[derive(Debug, Clone)]
+pub struct Argon2Pow {
+    argon2: Argon2<'static>,
+}
+
+impl Argon2Pow {
+    pub fn new(params: Argon2Params) -> Result<Self, Error> {
+        let params = Params::new(params.memory_kib, params.iterations, params.parallelism, Some(32))
+            .map_err(|error| format!("Invalid Argon2 parameters: {}", error))?;
+
+        Ok(Self {
+            argon2: Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+        })
+    }
+}
+
+impl Pow for Argon2Pow {
+    fn digest(&self, block_hash: &Hash) -> Hash {
+        let mut digest = [0u8; 32];
+        // Only fails for out of range lengths, the lengths here are fixed
+        self.argon2
+            .hash_password_into(block_hash.as_bytes(), ARGON2_SALT, &mut digest)
+            .expect("Argon2 input lengths are valid");
+
+        hex::encode(digest)
+    }
+}
+
+// Which puzzle a chain uses, as it appears in chain configuration
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PowAlgorithm {
+    #[default]
+    Blake2s,
+    Sha256d,
+    Argon2id(Argon2Params),
+}
+
+impl PowAlgorithm {
+    pub fn pow(&self) -> Result<Arc<dyn Pow>, Error> {
+        Ok(match self {
+            PowAlgorithm::Blake2s => Arc::new(Blake2sPow),
+            PowAlgorithm::Sha256d => Arc::new(Sha256dPow),
+            PowAlgorithm::Argon2id(params) => Arc::new(Argon2Pow::new(*params)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_ARGON2: Argon2Params = Argon2Params {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn test_seal_and_verify() {
+        let target = u128::MAX >> 8;
+
+        for algorithm in [PowAlgorithm::Blake2s, PowAlgorithm::Sha256d, PowAlgorithm::Argon2id(TEST_ARGON2)] {
+            let pow = algorithm.pow().unwrap();
+            let mut block = Block::new(None);
+            pow.seal(&mut block, target);
+
+            assert_eq!(block.bits(), encode_bits(target));
+            assert!(pow.verify(&block));
+            assert!(meets_target(&pow.pow_hash(&block), target));
+
+            block.set_bits(encode_bits(1));
+            assert!(!pow.verify(&block));
+        }
+
+        let mut block = Block::new(None);
+        Sha256dPow.seal(&mut block, u128::MAX >> 16);
+        assert_ne!(Sha256dPow.pow_hash(&block), Blake2sPow.pow_hash(&block));
+
+        // Nonce 0 meets u128::MAX but not the target its bits round it down to
+        let mut block = Block::new(None);
+        block.set_timestamp(84738);
+        Blake2sPow.seal(&mut block, u128::MAX);
+        assert_ne!(block.nonce(), 0);
+        assert!(Blake2sPow.verify(&block));
+    }
+
+    #[test]
+    fn test_argon2_against_blake2s() {
+        let argon2 = Argon2Pow::new(TEST_ARGON2).unwrap();
+        let target = u128::MAX >> 4;
+
+        // Deterministic, and a different puzzle from the default one
+        let mut block = Block::new(None);
+        argon2.seal(&mut block, target);
+        assert_eq!(argon2.pow_hash(&block), Argon2Pow::new(TEST_ARGON2).unwrap().pow_hash(&block));
+        assert_ne!(argon2.pow_hash(&block), Blake2sPow.pow_hash(&block));
+
+        // Work for one algorithm is not work for the other
+        let mut sealed = 0;
+        for nonce in 0..32 {
+            let mut block = Block::new(None);
+            block.set_nonce(nonce * 1000);
+            Blake2sPow.seal(&mut block, target);
+            sealed += argon2.verify(&block) as u32;
+        }
+        assert!(sealed < 32);
+
+        // Memory parameters change the digest
+        let heavier = Argon2Pow::new(Argon2Params { memory_kib: 128, ..TEST_ARGON2 }).unwrap();
+        assert_ne!(heavier.pow_hash(&block), argon2.pow_hash(&block));
+        assert!(Argon2Pow::new(Argon2Params { memory_kib: 1, ..TEST_ARGON2 }).is_err());
+        assert_eq!(
+            serde_json::to_value(PowAlgorithm::Argon2id(TEST_ARGON2)).unwrap(),
+            serde_json::json!({ "type": "argon2id", "memory_kib": 64, "iterations": 1, "parallelism": 1 })
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use std::convert::TryInto;
+
+// A 256-bit proof of work target, big-endian like the digests it is compared with. The
+// u128 targets the chain keeps stand for the top half, the bottom half of their Target is
+// zero, so a digest meets one exactly when its top 128 bits are below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Target([u8; 32]);
+
+impl Target {
+    pub const MAX: Target = Target([0xff; 32]);
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    // Whether `digest`, 64 hex digits, is below the target. Digests compare byte by byte
+    // over their full length, anything that isn't one never meets a target.
+    pub fn is_met_by(&self, digest: &str) -> bool {
+        match decode_digest(digest) {
+            Some(digest) => digest < self.0,
+            None => false,
+        }
+    }
+}
+
+impl From<u128> for Target {
+    fn from(target: u128) -> Self {
+        let mut bytes = [0; 32];
+        bytes[..16].copy_from_slice(&target.to_be_bytes());
+        Self(bytes)
+    }
+}
+
+// Compares the whole digest against the 256-bit target `target` stands for
+pub fn meets_target(hash: &str, target: u128) -> bool {
+    Target::from(target).is_met_by(hash)
+}
+
+fn decode_digest(digest: &str) -> Option<[u8; 32]> {
+    hex::decode(digest).ok()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target() {
+        let digest = |top: u128, bottom: u128| format!("{:032x}{:032x}", top, bottom);
+
+        // The bottom half of a digest counts for nothing against a chain target
+        assert!(Target::from(6).is_met_by(&digest(5, u128::MAX)));
+        assert!(!Target::from(5).is_met_by(&digest(5, 0)));
+
+        // It does against a full one
+        let mut bytes = *Target::from(5).as_bytes();
+        bytes[31] = 0x10;
+        let target = Target::from_bytes(bytes);
+        assert!(target.is_met_by(&digest(5, 0x0f)));
+        assert!(!target.is_met_by(&digest(5, 0x10)));
+        assert!(target > Target::from(5) && target < Target::from(6));
+
+        assert!(Target::MAX.is_met_by(&digest(u128::MAX, u128::MAX - 1)));
+        assert!(!Target::MAX.is_met_by(&"f".repeat(64)));
+        assert!(!Target::MAX.is_met_by(&digest(0, 0)[..62]));
+        assert!(!Target::MAX.is_met_by("not a hash"));
+    }
+
+    #[test]
+    fn test_meets_target() {
+        let hash = format!("{:032x}{}", 5u128, "ff".repeat(16));
+
+        assert!(meets_target(&hash, 6));
+        assert!(!meets_target(&hash, 5));
+        assert!(!meets_target("not a hash", u128::MAX));
+        assert!(!meets_target(&hash[..32], 6));
+    }
+}
@@ -0,0 +1,51 @@
+use crate::types::{Error, MAX_TARGET};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+// The chains a node can run side by side: a private one to experiment on and the one the
+// whole class shares
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChainName {
+    #[default]
+    Devnet,
+    Classnet,
+}
+
+impl ChainName {
+    pub const ALL: [ChainName; 2] = [ChainName::Devnet, ChainName::Classnet];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChainName::Devnet => "devnet",
+            ChainName::Classnet => "classnet",
+        }
+    }
+
+    // Devnet blocks are sealed in a blink on a laptop
+    pub fn max_target(&self) -> u128 {
+        match self {
+            ChainName::Devnet => u128::MAX >> 8,
+            ChainName::Classnet => MAX_TARGET,
+        }
+    }
+}
+
+impl fmt::Display for ChainName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ChainName {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ChainName::ALL
+            .iter()
+            .find(|chain| chain.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("Unknown chain {}, expected devnet or classnet", s))
+    }
+}
@@ -0,0 +1,148 @@
+use crate::datadir::{default_root, ChainLayout, ChainName};
+use crate::types::{AccountId, Error};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_LISTEN_ADDR: &str = "0.0.0.0:7878";
+pub const DEFAULT_RPC_ADDR: &str = "127.0.0.1:8545";
+
+// What blockchain-node runs, read from a TOML file like
+//
+//   chain = "classnet"
+//   data_dir = "/var/lib/blockchain_workshop"
+//   listen = "0.0.0.0:7878"
+//   rpc = "127.0.0.1:8545"
+//   peers = ["10.0.0.5:7878"]
+//   genesis = "genesis.toml"
+//
+//   [mining]
+//   enabled = true
+//   account = "alice"
+//
+// Everything is optional. Relative paths are relative to the file, unknown keys are an
+// error so a typo doesn't silently leave a setting at its default.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    pub chain: ChainName,
+    // Root the chain's directory goes under, see ChainLayout
+    pub data_dir: PathBuf,
+    // Peer to peer address
+    pub listen: String,
+    // JSON-RPC address
+    pub rpc: String,
+    pub peers: Vec<String>,
+    pub mdns: bool,
+    // GenesisConfig to start the chain from when the data directory is empty, and to
+    // check it against otherwise. Without one the genesis block has to come from a peer.
+    pub genesis: Option<PathBuf>,
+    pub mining: MiningConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MiningConfig {
+    pub enabled: bool,
+    // Paid the block reward, without one blocks are mined without a coinbase
+    pub account: Option<AccountId>,
+    pub threads: usize,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            chain: ChainName::default(),
+            data_dir: default_root(),
+            listen: DEFAULT_LISTEN_ADDR.to_string(),
+            rpc: DEFAULT_RPC_ADDR.to_string(),
+            peers: vec![],
+            mdns: false,
+            genesis: None,
+            mining: MiningConfig::default(),
+        }
+    }
+}
+
+impl Default for MiningConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            account: None,
+            threads: 1,
+        }
+    }
+}
+
+impl NodeConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let toml = fs::read_to_string(path).map_err(|error| format!("Can't read {}: {}", path.display(), error))?;
+        let config = Self::from_toml(&toml).map_err(|error| format!("Invalid config {}: {}", path.display(), error))?;
+        Ok(config.relative_to(path.parent().unwrap_or_else(|| Path::new(""))))
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, Error> {
+        let config: Self = toml::from_str(toml).map_err(|error| error.message().to_string())?;
+        if config.mining.threads == 0 {
+            return Err("Mining needs at least one thread".to_string());
+        }
+        Ok(config)
+    }
+
+    // With relative paths taken as relative to `dir`
+    pub fn relative_to(mut self, dir: &Path) -> Self {
+        self.data_dir = dir.join(&self.data_dir);
+        self.genesis = self.genesis.map(|genesis| dir.join(genesis));
+        self
+    }
+
+    pub fn layout(&self) -> ChainLayout {
+        ChainLayout::new(&self.data_dir, self.chain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_config() {
+        let config = NodeConfig::from_toml(
+            r#"
+            chain = "classnet"
+            data_dir = "data"
+            peers = ["10.0.0.5:7878"]
+            genesis = "genesis.json"
+
+            [mining]
+            enabled = true
+            account = "alice"
+            "#,
+        )
+        .unwrap()
+        .relative_to(Path::new("/etc/workshop"));
+        assert_eq!(config.layout().blocks_dir(), Path::new("/etc/workshop/data/classnet/blocks"));
+        assert_eq!(config.genesis.as_deref(), Some(Path::new("/etc/workshop/genesis.json")));
+        assert_eq!(config.listen, DEFAULT_LISTEN_ADDR);
+        assert_eq!(config.peers, vec!["10.0.0.5:7878"]);
+        assert_eq!(
+            config.mining,
+            MiningConfig {
+                enabled: true,
+                account: Some("alice".to_string()),
+                threads: 1,
+            }
+        );
+
+        // Absolute paths stay as they are
+        let config = NodeConfig::from_toml("data_dir = \"/srv/chain\"").unwrap().relative_to(Path::new("/etc"));
+        assert_eq!(config.data_dir, Path::new("/srv/chain"));
+        assert_eq!(config.chain, ChainName::Devnet);
+        assert!(!config.mining.enabled);
+
+        assert!(NodeConfig::from_toml("chian = \"devnet\"").unwrap_err().contains("unknown field `chian`"));
+        assert!(NodeConfig::from_toml("chain = \"mainnet\"").is_err());
+        assert!(NodeConfig::from_toml("[mining]\nthreads = 0").is_err());
+    }
+}
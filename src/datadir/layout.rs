@@ -0,0 +1,121 @@
+use crate::datadir::ChainName;
+use crate::types::Error;
+use crate::wallet::KEYSTORE_FILE;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Overrides the default data directory root
+pub const DATA_DIR_ENV: &str = "BLOCKCHAIN_WORKSHOP_HOME";
+
+// $BLOCKCHAIN_WORKSHOP_HOME, otherwise ~/.blockchain_workshop, otherwise the working
+// directory
+pub fn default_root() -> PathBuf {
+    if let Some(root) = env::var_os(DATA_DIR_ENV) {
+        return PathBuf::from(root);
+    }
+    match env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".blockchain_workshop"),
+        None => PathBuf::from(".blockchain_workshop"),
+    }
+}
+
+// Where one chain keeps its files. Every chain gets its own directory under the root so
+// their blocks, keys and settings never mix:
+//
+//   <root>/<chain>/blocks/
+//   <root>/<chain>/keystore/keystore.json
+//   <root>/<chain>/config.toml
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainLayout {
+    chain: ChainName,
+    root: PathBuf,
+}
+
+impl ChainLayout {
+    pub fn new<P: AsRef<Path>>(root: P, chain: ChainName) -> Self {
+        Self {
+            chain,
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn with_default_root(chain: ChainName) -> Self {
+        Self::new(default_root(), chain)
+    }
+
+    pub fn chain(&self) -> ChainName {
+        self.chain
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        self.root.join(self.chain.as_str())
+    }
+
+    pub fn blocks_dir(&self) -> PathBuf {
+        self.dir().join("blocks")
+    }
+
+    pub fn keystore_dir(&self) -> PathBuf {
+        self.dir().join("keystore")
+    }
+
+    pub fn keystore_file(&self) -> PathBuf {
+        self.keystore_dir().join(KEYSTORE_FILE)
+    }
+
+    pub fn config_file(&self) -> PathBuf {
+        self.dir().join("config.toml")
+    }
+
+    // Creates the chain's directories if they don't exist yet
+    pub fn create(&self) -> Result<(), Error> {
+        for dir in [self.blocks_dir(), self.keystore_dir()] {
+            fs::create_dir_all(&dir).map_err(|error| format!("Can't create {}: {}", dir.display(), error))?;
+        }
+        Ok(())
+    }
+
+    // Chains that have a directory under `root`
+    pub fn existing<P: AsRef<Path>>(root: P) -> Vec<ChainLayout> {
+        ChainName::ALL
+            .iter()
+            .map(|chain| ChainLayout::new(&root, *chain))
+            .filter(|layout| layout.dir().is_dir())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn test_layout() {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let root = env::temp_dir().join(format!("datadir-{}-{}", std::process::id(), nanos));
+        let devnet = ChainLayout::new(&root, ChainName::Devnet);
+        let classnet = ChainLayout::new(&root, "classnet".parse().unwrap());
+
+        assert_eq!(devnet.blocks_dir(), root.join("devnet").join("blocks"));
+        assert_eq!(classnet.keystore_dir(), root.join("classnet").join("keystore"));
+        assert_eq!(classnet.config_file(), root.join("classnet").join("config.toml"));
+        assert!("mainnet".parse::<ChainName>().is_err());
+
+        assert!(ChainLayout::existing(&root).is_empty());
+        devnet.create().unwrap();
+        assert!(devnet.blocks_dir().is_dir());
+        assert!(devnet.keystore_dir().is_dir());
+        assert_eq!(ChainLayout::existing(&root), vec![devnet.clone()]);
+
+        classnet.create().unwrap();
+        assert_eq!(ChainLayout::existing(&root).len(), 2);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
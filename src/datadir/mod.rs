@@ -0,0 +1,7 @@
+mod chain_name;
+mod config;
+mod layout;
+
+pub use chain_name::ChainName;
+pub use config::{MiningConfig, NodeConfig, DEFAULT_LISTEN_ADDR, DEFAULT_RPC_ADDR};
+pub use layout::{default_root, ChainLayout, DATA_DIR_ENV};
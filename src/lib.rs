@@ -1,3 +1,24 @@
+pub mod consensus;
+#[cfg(feature = "std")]
+pub mod datadir;
+pub mod light;
+#[cfg(feature = "std")]
+pub mod loadgen;
+#[cfg(feature = "std")]
+pub mod mining;
+#[cfg(feature = "std")]
+pub mod network;
+#[cfg(feature = "std")]
+pub mod rpc;
+#[cfg(feature = "std")]
+pub mod storage;
+#[cfg(feature = "std")]
+pub mod sync;
+#[cfg(feature = "std")]
+pub mod testing;
 pub mod traits;
 pub mod types;
+#[cfg(feature = "std")]
 pub mod utils;
+#[cfg(feature = "std")]
+pub mod wallet;
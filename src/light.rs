@@ -0,0 +1,212 @@
+// Header verification for light clients. Nothing here touches the clock, threads, storage
+// or the world state.
+use crate::consensus::{decode_bits, meets_target, target_work, PowAlgorithm};
+use crate::traits::Hashable;
+use crate::types::{BlockHeader, Error, Hash};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// The consensus rules headers are checked against from `height` on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fork {
+    pub height: u64,
+    pub pow: PowAlgorithm,
+    // The easiest target a header may claim
+    pub max_target: u128,
+}
+
+// The rules of a chain over its lifetime, one Fork per change, ordered by height
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForkSchedule {
+    forks: Vec<Fork>,
+}
+
+impl ForkSchedule {
+    pub fn new(pow: PowAlgorithm, max_target: u128) -> Self {
+        Self {
+            forks: vec![Fork {
+                height: 0,
+                pow,
+                max_target,
+            }],
+        }
+    }
+
+    // Switches to `pow` and `max_target` from `height` on, replacing later forks
+    pub fn with_fork(mut self, height: u64, pow: PowAlgorithm, max_target: u128) -> Self {
+        self.forks.retain(|fork| fork.height < height);
+        self.forks.push(Fork { height, pow, max_target });
+        self
+    }
+
+    pub fn at(&self, height: u64) -> &Fork {
+        self.forks
+            .iter()
+            .rev()
+            .find(|fork| fork.height <= height)
+            .unwrap_or(&self.forks[0])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifiedTip {
+    pub hash: Hash,
+    pub height: u64,
+    // Expected hashes to produce the chain up to the tip, genesis counts for nothing
+    pub work: u128,
+}
+
+fn verify_header(header: &BlockHeader, fork: &Fork) -> Result<u128, Error> {
+    let target = decode_bits(header.bits)?;
+    if target > fork.max_target {
+        return Err(format!("Header bits {:08x} are easier than the pow limit", header.bits));
+    }
+
+    let pow = fork.pow.pow()?;
+    if !meets_target(&pow.digest(&header.hash()), target) {
+        return Err("The hash of header more than target.".to_string());
+    }
+    Ok(target_work(target))
+}
+
+// Checks linkage and proof of work of `headers` on top of the trusted `genesis_hash` and
+// returns the tip with the most work. Headers have to come after their parent and be one
+// above it. Those that don't link up or fail their checks are left out along with
+// everything built on them.
+// Retargeting is not replayed, a header only has to meet its own bits within the pow limit
+// of its fork, so the best tip rather than the longest is what counts.
+pub fn verify_header_chain(headers: &[BlockHeader], genesis_hash: &Hash, fork_schedule: &ForkSchedule) -> VerifiedTip {
+    let mut best = VerifiedTip {
+        hash: genesis_hash.clone(),
+        height: 0,
+        work: 0,
+    };
+    let mut verified: BTreeMap<Hash, (u64, u128)> = BTreeMap::new();
+    verified.insert(genesis_hash.clone(), (0, 0));
+
+    for header in headers {
+        let hash = header.hash();
+        if verified.contains_key(&hash) {
+            continue;
+        }
+        let (parent_height, parent_work) = match header.prev_hash.as_ref().and_then(|prev| verified.get(prev)) {
+            Some(parent) => *parent,
+            None => continue,
+        };
+
+        let height = parent_height + 1;
+        if header.height != height {
+            continue;
+        }
+        if let Ok(header_work) = verify_header(header, fork_schedule.at(height)) {
+            let work = parent_work.saturating_add(header_work);
+            verified.insert(hash.clone(), (height, work));
+            if work > best.work {
+                best = VerifiedTip { hash, height, work };
+            }
+        }
+    }
+
+    best
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::consensus::{encode_bits, Blake2sPow, Pow, Sha256dPow};
+    use crate::testing::Fixtures;
+    use crate::types::{Block, Blockchain, Transaction};
+    use crate::utils::{append_block, mine_block};
+
+    const TEST_TARGET: u128 = u128::MAX >> 8;
+
+    fn mined_header(fixtures: &mut Fixtures, prev_hash: &Hash, height: u64, pow: &dyn Pow, target: u128) -> BlockHeader {
+        let mut block = Block::new(Some(prev_hash.clone()));
+        block.set_height(height);
+        block.add_transaction(Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), fixtures.timestamp()));
+        pow.seal(&mut block, target);
+        block.header().clone()
+    }
+
+    #[test]
+    fn test_verify_header_chain() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET);
+        append_block(&mut bc, fixtures, 0);
+        for nonce in 1..4 {
+            append_block(&mut bc, fixtures, nonce);
+        }
+        let genesis_hash = bc.get_block_by_height(0).unwrap().hash();
+        let mut headers: Vec<BlockHeader> = (1..4).map(|height| bc.get_block_by_height(height).unwrap().header().clone()).collect();
+        let schedule = ForkSchedule::new(PowAlgorithm::Blake2s, TEST_TARGET);
+
+        let tip = verify_header_chain(&headers, &genesis_hash, &schedule);
+        assert_eq!(tip.hash, bc.get_last_block_hash().unwrap());
+        assert_eq!(tip.height, 3);
+
+        // Nor does a header claiming to be anywhere but above its parent
+        let mut misplaced = headers.clone();
+        misplaced[2].height = 4;
+        assert_eq!(verify_header_chain(&misplaced, &genesis_hash, &schedule).height, 2);
+
+        // Nothing past a header with a broken proof of work counts
+        headers[1].nonce += 1;
+        while meets_target(&headers[1].hash(), TEST_TARGET) {
+            headers[1].nonce += 1;
+        }
+        let tip = verify_header_chain(&headers, &genesis_hash, &schedule);
+        assert_eq!(tip.hash, headers[0].hash());
+        assert_eq!(tip.height, 1);
+
+        // Easier than the pow limit
+        let mut easy = Block::new(Some(genesis_hash.clone()));
+        easy.set_height(1);
+        easy.add_transaction(Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), fixtures.timestamp()));
+        mine_block(&mut easy, u128::MAX);
+        assert_eq!(easy.header().bits, encode_bits(u128::MAX));
+        assert_eq!(verify_header_chain(&[easy.header().clone()], &genesis_hash, &schedule).height, 0);
+    }
+
+    #[test]
+    fn test_most_work_wins() {
+        let fixtures = &mut Fixtures::default();
+        let genesis_hash = Block::new(None).hash();
+        let schedule = ForkSchedule::new(PowAlgorithm::Blake2s, TEST_TARGET);
+
+        // Two easy headers against one forty times harder
+        let first = mined_header(fixtures, &genesis_hash, 1, &Blake2sPow, TEST_TARGET);
+        let second = mined_header(fixtures, &first.hash(), 2, &Blake2sPow, TEST_TARGET);
+        let heavy = mined_header(fixtures, &genesis_hash, 1, &Blake2sPow, TEST_TARGET / 40);
+
+        let tip = verify_header_chain(&[first, second, heavy.clone()], &genesis_hash, &schedule);
+        assert_eq!(tip.hash, heavy.hash());
+        assert_eq!(tip.height, 1);
+    }
+
+    #[test]
+    fn test_fork_schedule() {
+        let fixtures = &mut Fixtures::default();
+        let genesis_hash = Block::new(None).hash();
+        let schedule = ForkSchedule::new(PowAlgorithm::Blake2s, TEST_TARGET).with_fork(2, PowAlgorithm::Sha256d, TEST_TARGET);
+        assert_eq!(schedule.at(1).pow, PowAlgorithm::Blake2s);
+        assert_eq!(schedule.at(5).pow, PowAlgorithm::Sha256d);
+
+        let first = mined_header(fixtures, &genesis_hash, 1, &Blake2sPow, TEST_TARGET);
+        let sha256d = mined_header(fixtures, &first.hash(), 2, &Sha256dPow, TEST_TARGET);
+        // Blake2s work doesn't count after the fork
+        let mut blake2s = Block::new(Some(first.hash()));
+        blake2s.set_height(2);
+        blake2s.add_transaction(Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), fixtures.timestamp()));
+        mine_block(&mut blake2s, TEST_TARGET);
+        while Sha256dPow.verify(&blake2s) {
+            blake2s.set_nonce(blake2s.nonce() + 1);
+            mine_block(&mut blake2s, TEST_TARGET);
+        }
+
+        let tip = verify_header_chain(&[first.clone(), blake2s.header().clone()], &genesis_hash, &schedule);
+        assert_eq!(tip.hash, first.hash());
+        let tip = verify_header_chain(&[first, sha256d.clone()], &genesis_hash, &schedule);
+        assert_eq!(tip.hash, sha256d.hash());
+        assert_eq!(tip.height, 2);
+    }
+}
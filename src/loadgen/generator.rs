@@ -0,0 +1,85 @@
+use crate::types::{AccountId, Balance, Timestamp, Transaction, TransactionData};
+use ed25519_dalek::{Keypair, Signer};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Most a generated transfer moves, accounts are funded for many of them
+pub const MAX_TRANSFER: Balance = 100;
+
+// Signs random transfers between a fixed set of accounts
+pub struct TransferGenerator {
+    accounts: Vec<(AccountId, Keypair)>,
+    // Last nonce each account used, the accounts are the generator's own so nothing else
+    // sends from them
+    nonces: Vec<u128>,
+    rng: StdRng,
+}
+
+impl TransferGenerator {
+    pub fn new(accounts: usize, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let accounts: Vec<(AccountId, Keypair)> = (0..accounts.max(2))
+            .map(|index| (format!("load{}", index), Keypair::generate(&mut rng)))
+            .collect();
+
+        Self {
+            nonces: vec![0; accounts.len()],
+            accounts,
+            rng,
+        }
+    }
+
+    pub fn accounts(&self) -> &[(AccountId, Keypair)] {
+        &self.accounts
+    }
+
+    // Self-signed account creations
+    pub fn create_accounts(&self, timestamp: Timestamp) -> Vec<Transaction> {
+        self.accounts
+            .iter()
+            .map(|(account_id, keypair)| Transaction::create_account(account_id.clone(), keypair, timestamp))
+            .collect()
+    }
+
+    // Transfers of `amount` from `faucet` to every account, numbered from `first_nonce`
+    pub fn fund_accounts(
+        &self,
+        faucet: &AccountId,
+        faucet_keypair: &Keypair,
+        first_nonce: u128,
+        amount: Balance,
+        timestamp: Timestamp,
+    ) -> Vec<Transaction> {
+        self.accounts
+            .iter()
+            .zip(first_nonce..)
+            .map(|((account_id, _), nonce)| {
+                let data = TransactionData::Transfer {
+                    to: account_id.clone(),
+                    amount,
+                };
+                sign(Transaction::new(data, Some(faucet.clone()), timestamp), nonce, faucet_keypair)
+            })
+            .collect()
+    }
+
+    pub fn next_transfer(&mut self, timestamp: Timestamp) -> Transaction {
+        let from = self.rng.gen_range(0, self.accounts.len());
+        let to = (from + self.rng.gen_range(1, self.accounts.len())) % self.accounts.len();
+        let amount = self.rng.gen_range(1, MAX_TRANSFER + 1);
+
+        let data = TransactionData::Transfer {
+            to: self.accounts[to].0.clone(),
+            amount,
+        };
+        self.nonces[from] += 1;
+        let transaction = Transaction::new(data, Some(self.accounts[from].0.clone()), timestamp);
+        sign(transaction, self.nonces[from], &self.accounts[from].1)
+    }
+}
+
+fn sign(mut transaction: Transaction, nonce: u128, keypair: &Keypair) -> Transaction {
+    transaction.set_nonce(nonce);
+    transaction.sign(Some(keypair.sign(&transaction.signing_message())));
+    transaction
+}
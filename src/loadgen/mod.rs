@@ -0,0 +1,13 @@
+mod generator;
+mod node;
+mod report;
+#[cfg(feature = "rest")]
+mod rest_node;
+mod runner;
+
+pub use generator::{TransferGenerator, MAX_TRANSFER};
+pub use node::{LoadTarget, LocalNode};
+pub use report::LoadReport;
+#[cfg(feature = "rest")]
+pub use rest_node::RestNode;
+pub use runner::{local_node, prepare, run, LoadConfig};
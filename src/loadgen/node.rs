@@ -0,0 +1,82 @@
+use crate::types::{AccountId, Blockchain, Error, Hash, Timestamp, Transaction};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Where the load goes
+pub trait LoadTarget {
+    fn submit(&mut self, transaction: &Transaction) -> Result<Hash, Error>;
+
+    fn is_confirmed(&mut self, hash: &Hash) -> Result<bool, Error>;
+
+    // Nonce for the next transaction `account_id` signs
+    fn next_nonce(&mut self, account_id: &AccountId) -> Result<u128, Error>;
+
+    // The node's clock, transactions are stamped with it
+    fn now(&mut self) -> Timestamp {
+        SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as Timestamp
+    }
+
+    // Called between submissions, for targets that have to drive the chain themselves
+    fn tick(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+// An in-process node that seals a block with the pending transactions every
+// `block_interval`. Mining happens on the calling thread.
+pub struct LocalNode {
+    blockchain: Arc<Mutex<Blockchain>>,
+    block_interval: Duration,
+    last_block: Instant,
+}
+
+impl LocalNode {
+    pub fn new(blockchain: Arc<Mutex<Blockchain>>, block_interval: Duration) -> Self {
+        Self {
+            blockchain,
+            block_interval,
+            last_block: Instant::now(),
+        }
+    }
+
+    pub fn blockchain(&self) -> &Arc<Mutex<Blockchain>> {
+        &self.blockchain
+    }
+
+    pub fn mine_block(&mut self) -> Result<(), Error> {
+        self.last_block = Instant::now();
+        let mut blockchain = self.blockchain.lock().unwrap();
+        let mut block = blockchain.block_template();
+        if block.body.transactions.is_empty() {
+            return Ok(());
+        }
+
+        blockchain.pow().seal(&mut block, blockchain.target());
+        blockchain.append_block(block).map_err(|error| error.to_string())
+    }
+}
+
+impl LoadTarget for LocalNode {
+    fn submit(&mut self, transaction: &Transaction) -> Result<Hash, Error> {
+        self.blockchain.lock().unwrap().submit_transaction(transaction.clone())
+    }
+
+    fn is_confirmed(&mut self, hash: &Hash) -> Result<bool, Error> {
+        Ok(matches!(self.blockchain.lock().unwrap().confirmations(hash), Some(confirmations) if confirmations > 0))
+    }
+
+    fn next_nonce(&mut self, account_id: &AccountId) -> Result<u128, Error> {
+        Ok(self.blockchain.lock().unwrap().next_nonce(account_id))
+    }
+
+    fn now(&mut self) -> Timestamp {
+        self.blockchain.lock().unwrap().now()
+    }
+
+    fn tick(&mut self) -> Result<(), Error> {
+        if self.last_block.elapsed() >= self.block_interval {
+            self.mine_block()?;
+        }
+        Ok(())
+    }
+}
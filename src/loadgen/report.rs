@@ -0,0 +1,76 @@
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadReport {
+    pub submitted: usize,
+    pub rejected: usize,
+    pub confirmed: usize,
+    pub last_error: Option<String>,
+    // Time spent submitting, and until the last confirmation or the timeout
+    pub submit_elapsed: Duration,
+    pub elapsed: Duration,
+    // From submission to the first poll that saw the transaction in a block, sorted
+    pub latencies: Vec<Duration>,
+}
+
+impl LoadReport {
+    pub fn unconfirmed(&self) -> usize {
+        self.submitted - self.confirmed
+    }
+
+    // Accepted submissions per second
+    pub fn achieved_tps(&self) -> f64 {
+        per_second(self.submitted, self.submit_elapsed)
+    }
+
+    pub fn confirmed_tps(&self) -> f64 {
+        per_second(self.confirmed, self.elapsed)
+    }
+
+    // Nearest rank, `percentile` in 0..=100
+    pub fn latency_percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        Some(self.latencies[rank.max(1) - 1])
+    }
+}
+
+fn per_second(count: usize, elapsed: Duration) -> f64 {
+    if elapsed.as_secs_f64() == 0.0 {
+        return 0.0;
+    }
+    count as f64 / elapsed.as_secs_f64()
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "submitted {} ({} rejected), confirmed {}, unconfirmed {}",
+            self.submitted,
+            self.rejected,
+            self.confirmed,
+            self.unconfirmed()
+        )?;
+        writeln!(
+            f,
+            "achieved {:.1} tps, confirmed {:.1} tps",
+            self.achieved_tps(),
+            self.confirmed_tps()
+        )?;
+        let percentiles: Vec<String> = [50.0, 90.0, 99.0, 100.0]
+            .iter()
+            .filter_map(|p| self.latency_percentile(*p).map(|latency| format!("p{} {:?}", p, latency)))
+            .collect();
+        if !percentiles.is_empty() {
+            writeln!(f, "confirmation latency {}", percentiles.join(", "))?;
+        }
+        if let Some(error) = &self.last_error {
+            writeln!(f, "last rejection: {}", error)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use crate::loadgen::LoadTarget;
+use crate::rpc::{AccountView, ConfirmationsView, ErrorResponse, SubmitTransactionRequest, SubmitTransactionResponse};
+use crate::types::{AccountId, Error, Hash, Transaction};
+use serde::de::DeserializeOwned;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+// A node serving the REST API, spoken to over plain HTTP/1.1
+pub struct RestNode {
+    address: String,
+}
+
+impl RestNode {
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+        }
+    }
+
+    fn request<T: DeserializeOwned>(&self, method: &str, path: &str, body: Option<String>) -> Result<T, Error> {
+        let mut stream = TcpStream::connect(&self.address).map_err(|e| e.to_string())?;
+        let body = body.unwrap_or_default();
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            path,
+            self.address,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).map_err(|e| e.to_string())?;
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| "Malformed HTTP response".to_string())?;
+        let status = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse::<u16>().ok())
+            .ok_or_else(|| "Malformed HTTP response".to_string())?;
+
+        if !(200..300).contains(&status) {
+            return Err(match serde_json::from_str::<ErrorResponse>(body) {
+                Ok(response) => response.error,
+                Err(_) => format!("HTTP {}", status),
+            });
+        }
+        serde_json::from_str(body).map_err(|e| e.to_string())
+    }
+}
+
+impl LoadTarget for RestNode {
+    fn submit(&mut self, transaction: &Transaction) -> Result<Hash, Error> {
+        let body = serde_json::to_string(&SubmitTransactionRequest::from(transaction)).map_err(|e| e.to_string())?;
+        let response: SubmitTransactionResponse = self.request("POST", "/transactions", Some(body))?;
+        Ok(response.hash)
+    }
+
+    fn is_confirmed(&mut self, hash: &Hash) -> Result<bool, Error> {
+        let path = format!("/transactions/{}/confirmations", hash);
+        let view: ConfirmationsView = self.request("GET", &path, None)?;
+        Ok(view.confirmations > 0)
+    }
+
+    // What the account has pending isn't counted
+    fn next_nonce(&mut self, account_id: &AccountId) -> Result<u128, Error> {
+        let view: AccountView = self.request("GET", &format!("/accounts/{}", account_id), None)?;
+        let nonce: u128 = view.nonce.parse().map_err(|_| format!("Invalid nonce {}", view.nonce))?;
+        Ok(nonce.saturating_add(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::{DifficultyConfig, BLOCK_TIME};
+    use crate::loadgen::{prepare, run, LoadConfig, LocalNode, TransferGenerator};
+    use crate::rpc::{serve_rest, RestState, Subscriptions};
+    use crate::types::{Blockchain, TransactionData};
+    use crate::utils::append_block_with_tx;
+    use ed25519_dalek::Keypair;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_load_over_rest() {
+        let faucet = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut blockchain = Blockchain::with_max_target(u128::MAX >> 4);
+        blockchain.set_difficulty(DifficultyConfig::new(u32::MAX as u64, BLOCK_TIME));
+        let now = blockchain.now();
+        let genesis = vec![
+            Transaction::create_account("faucet".to_string(), &faucet, now),
+            Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: "faucet".to_string(),
+                    amount: 1_000_000,
+                },
+                None,
+                now,
+            ),
+        ];
+        assert!(append_block_with_tx(&mut blockchain, 0, genesis).is_ok());
+        let blockchain = Arc::new(Mutex::new(blockchain));
+
+        // The node mines on its own, the load generator only talks HTTP to it
+        let running = Arc::new(AtomicBool::new(true));
+        let miner = {
+            let mut node = LocalNode::new(blockchain.clone(), Duration::from_millis(20));
+            let running = running.clone();
+            thread::spawn(move || {
+                while running.load(Ordering::SeqCst) {
+                    node.tick().unwrap();
+                    thread::sleep(Duration::from_millis(5));
+                }
+            })
+        };
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let state = RestState {
+            blockchain,
+            subscriptions: Arc::new(Mutex::new(Subscriptions::new())),
+        };
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async move {
+                listener.set_nonblocking(true).unwrap();
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                serve_rest(listener, state).await.unwrap();
+            });
+        });
+
+        let mut node = RestNode::new(&address);
+        let mut generator = TransferGenerator::new(2, 7);
+        let config = LoadConfig {
+            transactions: 20,
+            tps: 100,
+            confirm_timeout: Duration::from_secs(30),
+            poll_interval: Duration::from_millis(10),
+        };
+        prepare(&mut node, &generator, &"faucet".to_string(), &faucet, 10_000, &config).unwrap();
+        let report = run(&mut node, &mut generator, &config).unwrap();
+        running.store(false, Ordering::SeqCst);
+        miner.join().unwrap();
+
+        assert_eq!(report.rejected, 0, "{:?}", report.last_error);
+        assert_eq!(report.confirmed, 20);
+        assert!(node.submit(&generator.create_accounts(0)[0]).is_err());
+    }
+}
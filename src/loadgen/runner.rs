@@ -0,0 +1,193 @@
+use crate::consensus::{DifficultyConfig, BLOCK_TIME};
+use crate::loadgen::{LoadReport, LoadTarget, LocalNode, TransferGenerator};
+use crate::types::{AccountId, Balance, Blockchain, Block, Error, Hash, Transaction, TransactionData};
+use ed25519_dalek::Keypair;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct LoadConfig {
+    pub transactions: usize,
+    pub tps: u64,
+    // How long to keep waiting for confirmations once everything is submitted
+    pub confirm_timeout: Duration,
+    pub poll_interval: Duration,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        Self {
+            transactions: 1000,
+            tps: 100,
+            confirm_timeout: Duration::from_secs(60),
+            poll_interval: Duration::from_millis(100),
+        }
+    }
+}
+
+// An in-process chain whose genesis creates the generator's accounts with `supply` each
+pub fn local_node(
+    generator: &TransferGenerator,
+    supply: Balance,
+    max_target: u128,
+    block_interval: Duration,
+) -> Result<LocalNode, Error> {
+    let mut blockchain = Blockchain::with_max_target(max_target);
+    // Blocks come every block_interval, far faster than BLOCK_TIME. Left to retarget the
+    // chain would soon be too hard to keep up, so the target stays put.
+    blockchain.set_difficulty(DifficultyConfig::new(u32::MAX as u64, BLOCK_TIME));
+    let now = blockchain.now();
+
+    let mut genesis = Block::new(None);
+    genesis.set_timestamp(now);
+    for transaction in generator.create_accounts(now) {
+        genesis.add_transaction(transaction);
+    }
+    for (account_id, _) in generator.accounts() {
+        let data = TransactionData::MintInitialSupply {
+            to: account_id.clone(),
+            amount: supply,
+        };
+        genesis.add_transaction(Transaction::new(data, None, now));
+    }
+    blockchain.append_block(genesis)?;
+
+    Ok(LocalNode::new(Arc::new(Mutex::new(blockchain)), block_interval))
+}
+
+// Creates the generator's accounts on a running node and funds them from `faucet`,
+// waiting for each step to be confirmed
+pub fn prepare<T: LoadTarget>(
+    target: &mut T,
+    generator: &TransferGenerator,
+    faucet: &AccountId,
+    faucet_keypair: &Keypair,
+    amount: Balance,
+    config: &LoadConfig,
+) -> Result<(), Error> {
+    let now = target.now();
+    let first_nonce = target.next_nonce(faucet)?;
+    // Funding checks the receivers exist, so they have to be in a block first
+    for step in [
+        generator.create_accounts(now),
+        generator.fund_accounts(faucet, faucet_keypair, first_nonce, amount, now),
+    ] {
+        let mut pending = vec![];
+        for transaction in &step {
+            pending.push((target.submit(transaction)?, Instant::now()));
+        }
+
+        let deadline = Instant::now() + config.confirm_timeout;
+        while !pending.is_empty() {
+            if Instant::now() > deadline {
+                return Err(format!("{} setup transactions weren't confirmed in time", pending.len()));
+            }
+            target.tick()?;
+            poll(target, &mut pending, &mut vec![]);
+            thread::sleep(config.poll_interval);
+        }
+    }
+
+    Ok(())
+}
+
+// Submits `config.transactions` random transfers at `config.tps` and waits for them to
+// be confirmed
+pub fn run<T: LoadTarget>(
+    target: &mut T,
+    generator: &mut TransferGenerator,
+    config: &LoadConfig,
+) -> Result<LoadReport, Error> {
+    let mut report = LoadReport::default();
+    let mut pending: Vec<(Hash, Instant)> = vec![];
+    let start = Instant::now();
+    let mut last_poll = start;
+    let interval = Duration::from_secs_f64(1.0 / config.tps.max(1) as f64);
+
+    for index in 0..config.transactions {
+        let due = start + interval * index as u32;
+        loop {
+            target.tick()?;
+            if last_poll.elapsed() >= config.poll_interval {
+                poll(target, &mut pending, &mut report.latencies);
+                last_poll = Instant::now();
+            }
+            let now = Instant::now();
+            if now >= due {
+                break;
+            }
+            thread::sleep((due - now).min(Duration::from_millis(1)));
+        }
+
+        let transaction = generator.next_transfer(target.now());
+        match target.submit(&transaction) {
+            Ok(hash) => {
+                report.submitted += 1;
+                pending.push((hash, Instant::now()));
+            }
+            Err(error) => {
+                report.rejected += 1;
+                report.last_error = Some(error);
+            }
+        }
+    }
+    report.submit_elapsed = start.elapsed();
+
+    let deadline = Instant::now() + config.confirm_timeout;
+    while !pending.is_empty() && Instant::now() < deadline {
+        target.tick()?;
+        poll(target, &mut pending, &mut report.latencies);
+        if !pending.is_empty() {
+            thread::sleep(config.poll_interval);
+        }
+    }
+    report.elapsed = start.elapsed();
+    report.confirmed = report.latencies.len();
+    report.latencies.sort();
+
+    Ok(report)
+}
+
+// Moves confirmed transactions out of `pending`, recording how long they took. Errors
+// count as not confirmed yet.
+fn poll<T: LoadTarget>(target: &mut T, pending: &mut Vec<(Hash, Instant)>, latencies: &mut Vec<Duration>) {
+    pending.retain(|(hash, submitted)| match target.is_confirmed(hash) {
+        Ok(true) => {
+            latencies.push(submitted.elapsed());
+            false
+        }
+        _ => true,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_run() {
+        let mut generator = TransferGenerator::new(3, 1);
+        let mut node = local_node(&generator, 10_000, u128::MAX >> 4, Duration::from_millis(50)).unwrap();
+        let config = LoadConfig {
+            transactions: 30,
+            tps: 200,
+            confirm_timeout: Duration::from_secs(30),
+            poll_interval: Duration::from_millis(10),
+        };
+
+        let report = run(&mut node, &mut generator, &config).unwrap();
+        assert_eq!(report.submitted, 30);
+        assert_eq!(report.rejected, 0);
+        assert_eq!(report.confirmed, 30);
+        assert!(report.latency_percentile(50.0) <= report.latency_percentile(99.0));
+
+        // Everything that was sent is in the chain, next to the genesis transactions
+        let blockchain = node.blockchain().lock().unwrap();
+        let transactions: usize = (0..blockchain.len() as u64)
+            .filter_map(|height| blockchain.get_block_by_height(height))
+            .map(|block| block.body.transactions.len())
+            .sum();
+        assert_eq!(transactions, 30 + 6);
+    }
+}
@@ -1,44 +1,369 @@
-use blake2::{Blake2s, Digest};
-use blockchain_workshop::traits::Hashable;
-use blockchain_workshop::types::{Transaction, TransactionData};
-use ed25519_dalek::{Keypair, Signature, Signer, Verifier};
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::ops::Add;
+use blockchain_workshop::datadir::{ChainLayout, ChainName, DEFAULT_RPC_ADDR};
+use blockchain_workshop::rpc::{transaction_to_json, BlockView, RpcClient, SubmitTransactionRequest};
+use blockchain_workshop::traits::Clock;
+use blockchain_workshop::types::{short_hash, short_key, Amount, ChainInfo, GenesisConfig, Transaction, TransactionData};
+use blockchain_workshop::utils::SystemClock;
+use blockchain_workshop::wallet::{derive_workshop_key, derive_workshop_keys, Keystore};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use ed25519_dalek::{Keypair, Signer};
+use serde_json::{json, Value};
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use std::process;
 
-fn main() {
-    let keypair_bob = Keypair::generate(&mut rand::rngs::OsRng {});
-    let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-    let mut tx = Transaction::new(
+fn command() -> Command {
+    Command::new("blockchain-cli")
+        .about("Keys, transactions and chain queries against a local node")
+        .subcommand_required(true)
+        .arg(
+            Arg::new("rpc")
+                .long("rpc")
+                .global(true)
+                .value_name("HOST:PORT")
+                .default_value(DEFAULT_RPC_ADDR)
+                .help("JSON-RPC address of the node"),
+        )
+        .arg(
+            Arg::new("chain")
+                .long("chain")
+                .global(true)
+                .value_name("devnet|classnet")
+                .default_value("devnet")
+                .help("Chain whose keystore to use"),
+        )
+        .arg(
+            Arg::new("keystore")
+                .long("keystore")
+                .global(true)
+                .value_name("PATH")
+                .value_parser(value_parser!(PathBuf))
+                .help("Keystore file, instead of the one in the chain's data directory"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("Print results as JSON"),
+        )
+        .subcommand(
+            Command::new("keygen")
+                .about("Generates a keypair, kept in the keystore under --account if given")
+                .arg(Arg::new("account").long("account").value_name("ACCOUNT_ID"))
+                .arg(
+                    Arg::new("seed")
+                        .long("seed")
+                        .value_name("SEED")
+                        .requires("index")
+                        .help("Derives the workshop key of attendee --index instead, kept under its account"),
+                )
+                .arg(
+                    Arg::new("index")
+                        .long("index")
+                        .value_name("N")
+                        .requires("seed")
+                        .value_parser(value_parser!(u32)),
+                ),
+        )
+        .subcommand(
+            Command::new("workshop-genesis")
+                .about("Prints a genesis config funding the workshop accounts of every attendee")
+                .arg(Arg::new("chain-id").long("chain-id").required(true).value_name("ID"))
+                .arg(Arg::new("seed").long("seed").required(true).value_name("SEED"))
+                .arg(
+                    Arg::new("attendees")
+                        .long("attendees")
+                        .required(true)
+                        .value_name("N")
+                        .value_parser(value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("balance")
+                        .long("balance")
+                        .required(true)
+                        .value_name("AMOUNT")
+                        .value_parser(value_parser!(Amount))
+                        .help("Given to each attendee"),
+                ),
+        )
+        .subcommand(
+            Command::new("create-account")
+                .about("Creates an account with its key from the keystore, generating one if there's none")
+                .arg(Arg::new("account").required(true).value_name("ACCOUNT_ID")),
+        )
+        .subcommand(
+            Command::new("transfer")
+                .about("Signs a transfer with the sender's key from the keystore and submits it")
+                .arg(Arg::new("from").long("from").required(true).value_name("ACCOUNT_ID"))
+                .arg(Arg::new("to").long("to").required(true).value_name("ACCOUNT_ID|@ALIAS"))
+                .arg(
+                    Arg::new("amount")
+                        .long("amount")
+                        .required(true)
+                        .value_name("AMOUNT")
+                        .value_parser(value_parser!(Amount))
+                        .help("\"1.5 COIN\" or base units"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .long("nonce")
+                        .value_name("N")
+                        .value_parser(value_parser!(u128))
+                        .help("Defaults to one above the account's nonce on the node"),
+                ),
+        )
+        .subcommand(
+            Command::new("balance")
+                .about("Prints an account")
+                .arg(Arg::new("account").required(true).value_name("ACCOUNT_ID|@ALIAS")),
+        )
+        .subcommand(Command::new("info").about("Prints the chain id, genesis block and tip of the node's chain"))
+        .subcommand(
+            Command::new("block")
+                .about("Prints a main chain block")
+                .arg(Arg::new("block").required(true).value_name("HEIGHT|HASH")),
+        )
+}
+
+struct Context {
+    rpc: String,
+    keystore: PathBuf,
+    layout: ChainLayout,
+    json: bool,
+}
+
+impl Context {
+    fn new(matches: &ArgMatches) -> Result<Self, String> {
+        let chain: ChainName = matches.get_one::<String>("chain").unwrap().parse()?;
+        let layout = ChainLayout::with_default_root(chain);
+        Ok(Self {
+            rpc: matches.get_one::<String>("rpc").unwrap().clone(),
+            keystore: matches
+                .get_one::<PathBuf>("keystore")
+                .cloned()
+                .unwrap_or_else(|| layout.keystore_file()),
+            layout,
+            json: matches.get_flag("json"),
+        })
+    }
+
+    fn client(&self) -> Result<RpcClient, String> {
+        RpcClient::connect(&self.rpc)
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        self.client()?.call(method, params).map_err(|error| error.message)
+    }
+
+    fn chain_info(&self) -> Result<ChainInfo, String> {
+        serde_json::from_value(self.call("get_chain_info", Value::Null)?).map_err(|error| error.to_string())
+    }
+
+    // Transactions are signed for the node's chain
+    fn chain_id(&self) -> Result<String, String> {
+        Ok(self.chain_info()?.chain_id)
+    }
+
+    fn load_keystore(&self) -> Result<Keystore, String> {
+        Keystore::load(&self.keystore)
+    }
+
+    fn save_keystore(&self, keystore: &Keystore) -> Result<(), String> {
+        if self.keystore == self.layout.keystore_file() {
+            self.layout.create()?;
+        }
+        keystore.save(&self.keystore)
+    }
+
+    fn print(&self, value: Value, human: String) {
+        if self.json {
+            println!("{}", value);
+        } else {
+            println!("{}", human);
+        }
+    }
+}
+
+fn keygen(context: &Context, matches: &ArgMatches) -> Result<(), String> {
+    let (keypair, account) = match (matches.get_one::<String>("seed"), matches.get_one::<u32>("index")) {
+        (Some(seed), Some(index)) => {
+            let key = derive_workshop_key(seed, *index);
+            let account = matches.get_one::<String>("account").cloned().unwrap_or(key.account_id);
+            (key.keypair, Some(account))
+        }
+        _ => (
+            Keypair::generate(&mut rand::rngs::OsRng {}),
+            matches.get_one::<String>("account").cloned(),
+        ),
+    };
+    let public_key = hex::encode(keypair.public.as_bytes());
+
+    match &account {
+        Some(account_id) => {
+            let mut keystore = context.load_keystore()?;
+            if keystore.keypair(account_id)?.is_some() {
+                return Err(format!("{} already has a key in {}", account_id, context.keystore.display()));
+            }
+            keystore.add_keypair(account_id.clone(), &keypair);
+            context.save_keystore(&keystore)?;
+            context.print(
+                json!({ "account_id": account_id, "public_key": public_key }),
+                format!("Key {} for {} saved to {}", short_key(&keypair.public), account_id, context.keystore.display()),
+            );
+        }
+        // Nowhere to keep it, so the secret has to be shown
+        None => {
+            let secret_key = hex::encode(keypair.secret.as_bytes());
+            context.print(
+                json!({ "public_key": public_key, "secret_key": secret_key }),
+                format!("Public key: {}\nSecret key: {}", public_key, secret_key),
+            );
+        }
+    }
+    Ok(())
+}
+
+// TOML for blockchain-node's genesis setting, the same for the same arguments
+fn workshop_genesis(matches: &ArgMatches) -> Result<(), String> {
+    let mut config = GenesisConfig::new(matches.get_one::<String>("chain-id").unwrap());
+    let balance = *matches.get_one::<Amount>("balance").unwrap();
+    let seed = matches.get_one::<String>("seed").unwrap();
+    for key in derive_workshop_keys(seed, *matches.get_one::<u32>("attendees").unwrap()) {
+        config.add_account(key.account_id, key.keypair.public, balance);
+    }
+    config.validate()?;
+    print!("{}", config.to_toml()?);
+    Ok(())
+}
+
+fn create_account(context: &Context, matches: &ArgMatches) -> Result<(), String> {
+    let account_id = matches.get_one::<String>("account").unwrap();
+    let mut keystore = context.load_keystore()?;
+    let keypair = match keystore.keypair(account_id)? {
+        Some(keypair) => keypair,
+        None => {
+            let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+            keystore.add_keypair(account_id.clone(), &keypair);
+            context.save_keystore(&keystore)?;
+            keypair
+        }
+    };
+
+    let transaction = Transaction::create_account_on(&context.chain_id()?, account_id.clone(), &keypair, SystemClock.now());
+    submit(context, &transaction)
+}
+
+fn transfer(context: &Context, matches: &ArgMatches) -> Result<(), String> {
+    let from = matches.get_one::<String>("from").unwrap();
+    let keypair = context
+        .load_keystore()?
+        .keypair(from)?
+        .ok_or_else(|| format!("No key for {} in {}", from, context.keystore.display()))?;
+    let nonce = match matches.get_one::<u128>("nonce") {
+        Some(nonce) => *nonce,
+        None => {
+            let account = context.call("get_account", json!([from]))?;
+            let nonce: u128 = account["nonce"].as_str().and_then(|nonce| nonce.parse().ok()).unwrap_or_default();
+            nonce + 1
+        }
+    };
+
+    let mut transaction = Transaction::new(
         TransactionData::Transfer {
-            to: "alice".to_string(),
-            amount: 100,
+            to: matches.get_one::<String>("to").unwrap().clone(),
+            amount: matches.get_one::<Amount>("amount").unwrap().base_units(),
         },
-        Some("bob".to_string()),
-        time,
+        Some(from.clone()),
+        SystemClock.now(),
+    );
+    transaction.set_nonce(nonce);
+    transaction.set_chain_id(&context.chain_id()?);
+    transaction.sign(Some(keypair.sign(&transaction.signing_message())));
+    submit(context, &transaction)
+}
+
+fn submit(context: &Context, transaction: &Transaction) -> Result<(), String> {
+    let request = serde_json::to_value(SubmitTransactionRequest::from(transaction)).map_err(|error| error.to_string())?;
+    let result = context.call("submit_transaction", request)?;
+    context.print(
+        json!({ "hash": result["hash"], "transaction": transaction_to_json(transaction) }),
+        format!("Submitted {}", transaction),
     );
-    let pub_key_bob = keypair_bob.public;
+    Ok(())
+}
 
-    let signature_bytes = keypair_bob.sign(tx.hash().as_bytes()).to_bytes();
+fn balance(context: &Context, matches: &ArgMatches) -> Result<(), String> {
+    let account = context.call("get_account", json!([matches.get_one::<String>("account").unwrap()]))?;
+    let field = |name: &str| account[name].as_str().unwrap_or_default().to_string();
+    let human = format!(
+        "{}: {}, {}, key {}…, nonce {}",
+        field("account_id"),
+        field("account_type"),
+        field("formatted_balance"),
+        short_hash(&field("public_key")),
+        field("nonce")
+    );
+    context.print(account, human);
+    Ok(())
+}
 
-    // Blockchain
-    time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-    let mut tx_invalid = Transaction::new(
-        TransactionData::Transfer {
-            to: "alice".to_string(),
-            amount: 1,
-        },
-        Some("bob".to_string()),
-        time,
+fn info(context: &Context) -> Result<(), String> {
+    let info = context.chain_info()?;
+    let hash = |hash: &Option<String>| hash.as_deref().map(short_hash).unwrap_or("none").to_string();
+    let human = format!(
+        "Chain {}, genesis {}, height {}, tip {}",
+        if info.chain_id.is_empty() { "without id" } else { &info.chain_id },
+        hash(&info.genesis_hash),
+        info.height,
+        hash(&info.tip)
     );
+    context.print(serde_json::to_value(&info).map_err(|error| error.to_string())?, human);
+    Ok(())
+}
 
-    dbg!(pub_key_bob
-        .verify(
-            tx_invalid.hash().as_bytes(),
-            &Signature::from(signature_bytes)
-        )
-        .is_ok());
+fn block(context: &Context, matches: &ArgMatches) -> Result<(), String> {
+    let block = matches.get_one::<String>("block").unwrap();
+    let result = match block.parse::<u64>() {
+        Ok(height) => context.call("get_block_by_height", json!([height]))?,
+        Err(_) => context.call("get_block_by_hash", json!([block]))?,
+    };
+    let view: BlockView = serde_json::from_value(result.clone()).map_err(|error| error.to_string())?;
+
+    let mut human = format!("#{} {}", view.height, short_hash(&view.hash));
+    match &view.prev_hash {
+        Some(parent) => human.push_str(&format!(" on {}", short_hash(parent))),
+        None => human.push_str(" (genesis)"),
+    }
+    human.push_str(&format!(", {} transactions", view.transactions.len()));
+    for transaction in &view.transactions {
+        let data = TransactionData::try_from(&transaction.data)
+            .map(|data| data.to_string())
+            .unwrap_or_else(|error| error);
+        let from = transaction.from.as_ref().map(|from| format!(" by {}", from)).unwrap_or_default();
+        human.push_str(&format!("\n  {}{}: {}", short_hash(&transaction.hash), from, data));
+    }
+    context.print(result, human);
+    Ok(())
+}
 
-    dbg!(pub_key_bob
-        .verify(tx.hash().as_bytes(), &Signature::from(signature_bytes))
-        .is_ok());
+fn run(matches: &ArgMatches) -> Result<(), String> {
+    let context = Context::new(matches)?;
+    match matches.subcommand() {
+        Some(("keygen", matches)) => keygen(&context, matches),
+        Some(("workshop-genesis", matches)) => workshop_genesis(matches),
+        Some(("create-account", matches)) => create_account(&context, matches),
+        Some(("transfer", matches)) => transfer(&context, matches),
+        Some(("balance", matches)) => balance(&context, matches),
+        Some(("info", _)) => info(&context),
+        Some(("block", matches)) => block(&context, matches),
+        _ => unreachable!("clap requires a subcommand"),
+    }
+}
+
+fn main() {
+    let matches = command().get_matches();
+    if let Err(error) = run(&matches) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
 }
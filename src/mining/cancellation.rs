@@ -0,0 +1,22 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Shared flag a mining job polls between nonces. Clones observe the same cancellation.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
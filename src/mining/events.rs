@@ -0,0 +1,77 @@
+use crate::types::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Nonces a job searches between progress events
+pub const NONCE_RANGE: u128 = 1 << 16;
+pub const HASHRATE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MiningEvent {
+    // `hashes` tried over the last `elapsed`, reported every HASHRATE_INTERVAL and once
+    // more when a job ends
+    Hashrate { hashes: u64, elapsed: Duration },
+    NonceRangeExhausted { tip: Option<Hash>, start: u128, end: u128 },
+    SolutionFound { tip: Option<Hash>, hash: Hash, nonce: u128 },
+}
+
+impl MiningEvent {
+    pub fn hashes_per_second(&self) -> Option<f64> {
+        match self {
+            MiningEvent::Hashrate { hashes, elapsed } if !elapsed.is_zero() => {
+                Some(*hashes as f64 / elapsed.as_secs_f64())
+            }
+            _ => None,
+        }
+    }
+}
+
+pub type MiningCallback = Arc<dyn Fn(&MiningEvent) + Send + Sync>;
+
+// Totals over every job a miner ran, for metrics
+#[derive(Debug, Default)]
+pub struct MiningStats {
+    hashes: AtomicU64,
+    hashes_per_second: AtomicU64,
+    ranges_exhausted: AtomicU64,
+    solutions: AtomicU64,
+}
+
+impl MiningStats {
+    pub fn hashes(&self) -> u64 {
+        self.hashes.load(Ordering::Relaxed)
+    }
+
+    // As of the latest full report
+    pub fn hashes_per_second(&self) -> u64 {
+        self.hashes_per_second.load(Ordering::Relaxed)
+    }
+
+    pub fn ranges_exhausted(&self) -> u64 {
+        self.ranges_exhausted.load(Ordering::Relaxed)
+    }
+
+    pub fn solutions(&self) -> u64 {
+        self.solutions.load(Ordering::Relaxed)
+    }
+
+    pub fn record(&self, event: &MiningEvent) {
+        match event {
+            MiningEvent::Hashrate { hashes, elapsed } => {
+                self.hashes.fetch_add(*hashes, Ordering::Relaxed);
+                // Partial reports at the end of a job are too short to be representative
+                if *elapsed >= HASHRATE_INTERVAL {
+                    let rate = event.hashes_per_second().unwrap_or_default();
+                    self.hashes_per_second.store(rate as u64, Ordering::Relaxed);
+                }
+            }
+            MiningEvent::NonceRangeExhausted { .. } => {
+                self.ranges_exhausted.fetch_add(1, Ordering::Relaxed);
+            }
+            MiningEvent::SolutionFound { .. } => {
+                self.solutions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
@@ -0,0 +1,593 @@
+use crate::consensus::{decode_bits, encode_bits, Blake2sPow, Pow};
+use crate::mining::{CancellationToken, MiningCallback, MiningEvent, MiningStats, HASHRATE_INTERVAL, NONCE_RANGE};
+use crate::traits::{ChainStore, Hashable};
+use crate::types::{AccountId, Block, Blockchain, Chain, Hash};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum MiningError {
+    #[error("No hash is below a target of 0")]
+    UnreachableTarget,
+    #[error("No nonce meets the target")]
+    NonceSpaceExhausted,
+}
+
+struct Job {
+    tip: Option<Hash>,
+    // Of the template, coinbase aside
+    transactions: Vec<Hash>,
+    token: CancellationToken,
+    handle: JoinHandle<()>,
+}
+
+// Mines block templates on background worker threads. Sealed blocks come out of the receiver
+// returned by `new`; appending them, and pointing the miner at the new tip afterwards,
+// is up to the caller.
+pub struct Miner<S = Chain<Block>> {
+    blockchain: Arc<Mutex<Blockchain<S>>>,
+    job: Option<Job>,
+    solutions: Sender<Block>,
+    callbacks: Vec<MiningCallback>,
+    stats: Arc<MiningStats>,
+    threads: usize,
+    beneficiary: Option<AccountId>,
+}
+
+impl Miner {
+    // Stamps `target` into the block's bits and searches nonces, starting from the block's own,
+    // until the block meets it. Blocks the caller, `new` is for mining in the background.
+    pub fn mine(block: &mut Block, target: u128) -> Result<u128, MiningError> {
+        Self::mine_with(&Blake2sPow, block, target)
+    }
+
+    pub fn mine_with(pow: &dyn Pow, block: &mut Block, target: u128) -> Result<u128, MiningError> {
+        let bits = encode_bits(target);
+        // The search is against the target as the bits state it, which is what verify checks
+        let target = decode_bits(bits).unwrap_or(target);
+        if target == 0 {
+            return Err(MiningError::UnreachableTarget);
+        }
+        block.set_bits(bits);
+
+        let start = block.nonce();
+        while !pow.verify(block) {
+            let nonce = block.nonce().wrapping_add(1);
+            if nonce == start {
+                return Err(MiningError::NonceSpaceExhausted);
+            }
+            block.set_nonce(nonce);
+        }
+        Ok(block.nonce())
+    }
+}
+
+impl<S: ChainStore> Miner<S> {
+    pub fn new(blockchain: Arc<Mutex<Blockchain<S>>>) -> (Self, Receiver<Block>) {
+        let (solutions, receiver) = mpsc::channel();
+
+        (
+            Self {
+                blockchain,
+                job: None,
+                solutions,
+                callbacks: Vec::new(),
+                stats: Default::default(),
+                threads: 1,
+                beneficiary: None,
+            },
+            receiver,
+        )
+    }
+
+    // Called with every event of jobs started from now on, on the mining thread
+    pub fn on_event<F>(&mut self, callback: F)
+    where
+        F: Fn(&MiningEvent) + Send + Sync + 'static,
+    {
+        self.callbacks.push(Arc::new(callback));
+    }
+
+    // Worker threads of jobs started from now on, one by default
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    // Account the coinbase of jobs started from now on pays the block reward to. Without
+    // one blocks are mined without a coinbase.
+    pub fn set_beneficiary(&mut self, beneficiary: Option<AccountId>) {
+        self.beneficiary = beneficiary;
+    }
+
+    pub fn stats(&self) -> Arc<MiningStats> {
+        self.stats.clone()
+    }
+
+    // Called when `tip` became the best block or the mempool changed: the running job is
+    // abandoned unless it already builds on `tip` with the transactions the template has
+    // now. Returns whether a new job started.
+    pub fn update_template(&mut self, tip: Option<Hash>) -> bool {
+        let (template, pow) = self.template();
+        let transactions = template_transactions(&template);
+        if matches!(&self.job, Some(job) if job.tip == tip && job.transactions == transactions && !job.handle.is_finished())
+        {
+            return false;
+        }
+
+        self.start(template, pow)
+    }
+
+    // Abandons the running job and starts over on the current tip and mempool. With a
+    // beneficiary blocks are mined even when the mempool is empty, they pay the block
+    // reward; without one nothing is mined until there's a transaction for a block.
+    pub fn restart(&mut self) -> bool {
+        let (template, pow) = self.template();
+        self.start(template, pow)
+    }
+
+    fn template(&self) -> (Block, Arc<dyn Pow>) {
+        let mut blockchain = self.blockchain.lock().unwrap();
+        (blockchain.block_template(), blockchain.pow())
+    }
+
+    fn start(&mut self, mut template: Block, pow: Arc<dyn Pow>) -> bool {
+        self.stop();
+
+        let transactions = template_transactions(&template);
+        if let Some(beneficiary) = &self.beneficiary {
+            self.blockchain.lock().unwrap().add_coinbase(&mut template, beneficiary.clone());
+        }
+        if template.body.transactions.is_empty() {
+            return false;
+        }
+
+        let token = CancellationToken::new();
+        let job_token = token.clone();
+        let solutions = self.solutions.clone();
+        let threads = self.threads;
+        let reporter = Reporter {
+            callbacks: self.callbacks.clone(),
+            stats: self.stats.clone(),
+        };
+        self.job = Some(Job {
+            tip: template.header.prev_hash.clone(),
+            transactions,
+            token,
+            handle: thread::spawn(move || mine(template, pow, threads, job_token, solutions, reporter)),
+        });
+        true
+    }
+}
+
+impl<S> Miner<S> {
+    pub fn stop(&mut self) {
+        if let Some(job) = self.job.take() {
+            job.token.cancel();
+            let _ = job.handle.join();
+        }
+    }
+
+    pub fn is_mining(&self) -> bool {
+        matches!(&self.job, Some(job) if !job.handle.is_finished())
+    }
+
+    // The tip the current job builds on
+    pub fn tip(&self) -> Option<&Hash> {
+        self.job.as_ref().and_then(|job| job.tip.as_ref())
+    }
+}
+
+impl<S> Drop for Miner<S> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn template_transactions(template: &Block) -> Vec<Hash> {
+    template.body.transactions.iter().map(|transaction| transaction.hash()).collect()
+}
+
+struct Reporter {
+    callbacks: Vec<MiningCallback>,
+    stats: Arc<MiningStats>,
+}
+
+impl Reporter {
+    fn emit(&self, event: MiningEvent) {
+        self.stats.record(&event);
+        for callback in &self.callbacks {
+            callback(&event);
+        }
+    }
+}
+
+// Hashes a worker counts before adding them to the job's total
+const HASH_BATCH: u64 = 1024;
+// How often a job checks on its workers between hashrate reports
+const WORKER_POLL: Duration = Duration::from_millis(10);
+
+// Spreads the nonces from the block's own on across `threads` workers, each taking every
+// `threads`th range of NONCE_RANGE nonces. The first solution ends the job; hashrate is
+// reported for all workers together.
+fn mine(block: Block, pow: Arc<dyn Pow>, threads: usize, token: CancellationToken, solutions: Sender<Block>, reporter: Reporter) {
+    let tip = block.header.prev_hash.clone();
+    let hashes = AtomicU64::new(0);
+    let solved = AtomicBool::new(false);
+    let solution = Mutex::new(None);
+    let search = Search {
+        pow: &*pow,
+        threads: threads.max(1) as u128,
+        token: &token,
+        solved: &solved,
+        solution: &solution,
+        hashes: &hashes,
+        reporter: &reporter,
+    };
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..search.threads)
+            .map(|worker| {
+                let block = block.clone();
+                scope.spawn(move || search.run(block, worker))
+            })
+            .collect();
+
+        let mut reported = 0;
+        let mut since = Instant::now();
+        while !workers.iter().all(|worker| worker.is_finished()) {
+            thread::sleep(WORKER_POLL);
+            if since.elapsed() >= HASHRATE_INTERVAL {
+                let total = hashes.load(Ordering::Relaxed);
+                reporter.emit(MiningEvent::Hashrate {
+                    hashes: total - reported,
+                    elapsed: since.elapsed(),
+                });
+                reported = total;
+                since = Instant::now();
+            }
+        }
+
+        let total = hashes.load(Ordering::Relaxed);
+        if total > reported {
+            reporter.emit(MiningEvent::Hashrate {
+                hashes: total - reported,
+                elapsed: since.elapsed(),
+            });
+        }
+    });
+
+    if let Some(block) = solution.into_inner().unwrap() {
+        reporter.emit(MiningEvent::SolutionFound {
+            tip,
+            hash: block.hash(),
+            nonce: block.nonce(),
+        });
+        let _ = solutions.send(block);
+    }
+}
+
+// What the workers of a job share
+#[derive(Clone, Copy)]
+struct Search<'a> {
+    pow: &'a dyn Pow,
+    threads: u128,
+    token: &'a CancellationToken,
+    solved: &'a AtomicBool,
+    solution: &'a Mutex<Option<Block>>,
+    hashes: &'a AtomicU64,
+    reporter: &'a Reporter,
+}
+
+impl Search<'_> {
+    fn is_over(&self) -> bool {
+        self.token.is_cancelled() || self.solved.load(Ordering::Relaxed)
+    }
+
+    fn run(&self, mut block: Block, worker: u128) {
+        let tip = block.header.prev_hash.clone();
+        let start = block.nonce();
+        let mut hashes = 0;
+
+        let mut range = worker;
+        'ranges: while !self.is_over() {
+            let range_start = start.wrapping_add(range.wrapping_mul(NONCE_RANGE));
+            for offset in 0..NONCE_RANGE {
+                if self.is_over() {
+                    break 'ranges;
+                }
+                block.set_nonce(range_start.wrapping_add(offset));
+                hashes += 1;
+                if hashes == HASH_BATCH {
+                    self.hashes.fetch_add(hashes, Ordering::Relaxed);
+                    hashes = 0;
+                }
+
+                if self.pow.verify(&block) {
+                    // Only the first of simultaneous solutions goes out
+                    if !self.solved.swap(true, Ordering::Relaxed) {
+                        *self.solution.lock().unwrap() = Some(block);
+                    }
+                    break 'ranges;
+                }
+            }
+
+            self.reporter.emit(MiningEvent::NonceRangeExhausted {
+                tip: tip.clone(),
+                start: range_start,
+                end: range_start.wrapping_add(NONCE_RANGE),
+            });
+            range = range.wrapping_add(self.threads);
+        }
+
+        self.hashes.fetch_add(hashes, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::Blake2sPow;
+    use crate::traits::WorldState;
+    use crate::types::{Transaction, BLOCK_REWARD};
+    use crate::utils::append_block_with_tx;
+    use ed25519_dalek::Keypair;
+    use std::time::Duration;
+
+    // A puzzle nobody solves, keeps a job running until it's cancelled
+    #[derive(Debug)]
+    struct Unsolvable;
+
+    impl Pow for Unsolvable {
+        fn digest(&self, _block_hash: &Hash) -> Hash {
+            "f".repeat(64)
+        }
+    }
+
+    fn create_account(account_id: &str) -> Transaction {
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let time = Blockchain::new().now();
+        Transaction::create_account(account_id.to_string(), &keypair, time)
+    }
+
+    fn blockchain() -> Arc<Mutex<Blockchain>> {
+        let mut bc = Blockchain::with_max_target(u128::MAX >> 8);
+        assert!(append_block_with_tx(&mut bc, 1, vec![create_account("alice")]).is_ok());
+        Arc::new(Mutex::new(bc))
+    }
+
+    #[test]
+    fn test_mine() {
+        let mut block = Block::new(None);
+        block.add_transaction(create_account("alice"));
+        let nonce = Miner::mine(&mut block, u128::MAX >> 8).unwrap();
+        assert_eq!(block.nonce(), nonce);
+        assert_eq!(block.bits(), encode_bits(u128::MAX >> 8));
+        assert!(Blake2sPow.verify(&block));
+
+        // Already sealed blocks stay as they are
+        assert_eq!(Miner::mine(&mut block, u128::MAX >> 8), Ok(nonce));
+
+        assert_eq!(Miner::mine(&mut block, 0), Err(MiningError::UnreachableTarget));
+    }
+
+    #[test]
+    fn test_mines_pending_transactions() {
+        let blockchain = blockchain();
+        let (mut miner, solutions) = Miner::new(blockchain.clone());
+        let tip = blockchain.lock().unwrap().get_last_block_hash();
+        assert!(!miner.update_template(tip.clone()));
+
+        blockchain.lock().unwrap().submit_transaction(create_account("bob")).unwrap();
+        assert!(miner.update_template(tip.clone()));
+
+        let block = solutions.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(block.header.prev_hash, tip);
+        assert_eq!(block.body.transactions.len(), 1);
+        assert!(blockchain.lock().unwrap().append_block(block).is_ok());
+        assert!(blockchain.lock().unwrap().pending_transactions().is_empty());
+
+        // With a beneficiary the blocks pay it the block reward
+        miner.set_beneficiary(Some("alice".to_string()));
+        let tip = blockchain.lock().unwrap().get_last_block_hash();
+        blockchain.lock().unwrap().submit_transaction(create_account("carol")).unwrap();
+        assert!(miner.update_template(tip));
+
+        let block = solutions.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(block.body.transactions.len(), 2);
+        assert_eq!(block.beneficiary(), Some(&"alice".to_string()));
+        let mut blockchain = blockchain.lock().unwrap();
+        assert!(blockchain.append_block(block).is_ok());
+        assert_eq!(blockchain.get_account_by_id("alice".to_string()).unwrap().balance, BLOCK_REWARD);
+    }
+
+    #[test]
+    fn test_mines_coinbase_only_blocks() {
+        let blockchain = blockchain();
+        let (mut miner, solutions) = Miner::new(blockchain.clone());
+        let tip = blockchain.lock().unwrap().get_last_block_hash();
+        assert!(!miner.update_template(tip.clone()));
+
+        // A quiet network still gets blocks from miners with a beneficiary
+        miner.set_beneficiary(Some("alice".to_string()));
+        assert!(miner.update_template(tip.clone()));
+        let block = solutions.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(block.header.prev_hash, tip);
+        assert_eq!(block.body.transactions.len(), 1);
+        assert!(block.body.transactions[0].is_coinbase());
+        let mut blockchain = blockchain.lock().unwrap();
+        assert!(blockchain.append_block(block).is_ok());
+        assert_eq!(blockchain.get_account_by_id("alice".to_string()).unwrap().balance, BLOCK_REWARD);
+    }
+
+    #[test]
+    fn test_mempool_change_restarts_job() {
+        let blockchain = blockchain();
+        let (mut miner, solutions) = Miner::new(blockchain.clone());
+        let tip = {
+            let mut bc = blockchain.lock().unwrap();
+            bc.submit_transaction(create_account("bob")).unwrap();
+            bc.set_pow(Arc::new(Unsolvable));
+            bc.get_last_block_hash()
+        };
+        assert!(miner.update_template(tip.clone()));
+        assert!(!miner.update_template(tip.clone()));
+
+        // Same tip, but carol's account arrived while the job ran
+        {
+            let mut bc = blockchain.lock().unwrap();
+            bc.submit_transaction(create_account("carol")).unwrap();
+            bc.set_pow(Arc::new(Blake2sPow));
+        }
+        assert!(miner.update_template(tip.clone()));
+        let block = solutions.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(block.header.prev_hash, tip);
+        assert_eq!(block.body.transactions.len(), 2);
+    }
+
+    #[test]
+    fn test_new_tip_preempts_job() {
+        let blockchain = blockchain();
+        let (mut miner, solutions) = Miner::new(blockchain.clone());
+        let tip = {
+            let mut bc = blockchain.lock().unwrap();
+            bc.submit_transaction(create_account("bob")).unwrap();
+            bc.set_pow(Arc::new(Unsolvable));
+            bc.get_last_block_hash()
+        };
+        assert!(miner.update_template(tip.clone()));
+        assert!(!miner.update_template(tip));
+        assert!(miner.is_mining());
+
+        // Someone else mines bob's account first
+        let new_tip = {
+            let mut bc = blockchain.lock().unwrap();
+            bc.set_pow(Arc::new(Blake2sPow));
+            assert!(append_block_with_tx(&mut bc, 2, vec![create_account("bob")]).is_ok());
+            bc.submit_transaction(create_account("carol")).unwrap();
+            bc.set_pow(Arc::new(Unsolvable));
+            bc.get_last_block_hash()
+        };
+        assert!(miner.update_template(new_tip.clone()));
+        assert_eq!(miner.tip(), new_tip.as_ref());
+
+        miner.stop();
+        assert!(!miner.is_mining());
+        assert!(solutions.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_workers_split_nonces() {
+        let blockchain = blockchain();
+        let (mut miner, solutions) = Miner::new(blockchain.clone());
+        miner.set_threads(4);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        miner.on_event(move |event| recorded.lock().unwrap().push(event.clone()));
+
+        let tip = {
+            let mut bc = blockchain.lock().unwrap();
+            bc.submit_transaction(create_account("bob")).unwrap();
+            bc.get_last_block_hash()
+        };
+        assert!(miner.update_template(tip));
+        let block = solutions.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(blockchain.lock().unwrap().append_block(block).is_ok());
+        let stats = miner.stats();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while miner.is_mining() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(stats.solutions(), 1);
+        assert!(solutions.try_recv().is_err());
+
+        // Every worker starts on a range of its own
+        events.lock().unwrap().clear();
+        blockchain.lock().unwrap().submit_transaction(create_account("carol")).unwrap();
+        blockchain.lock().unwrap().set_pow(Arc::new(Unsolvable));
+        assert!(miner.restart());
+        let deadline = Instant::now() + Duration::from_secs(120);
+        while stats.ranges_exhausted() < 5 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        miner.stop();
+
+        let mut starts: Vec<u128> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|event| match event {
+                MiningEvent::NonceRangeExhausted { start, .. } => Some(*start),
+                _ => None,
+            })
+            .collect();
+        starts.sort_unstable();
+        let ranges = starts.len();
+        starts.dedup();
+        assert_eq!(starts.len(), ranges);
+        assert_eq!(starts[..4], [0, NONCE_RANGE, 2 * NONCE_RANGE, 3 * NONCE_RANGE]);
+    }
+
+    #[test]
+    fn test_events() {
+        let blockchain = blockchain();
+        let (mut miner, solutions) = Miner::new(blockchain.clone());
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        miner.on_event(move |event| recorded.lock().unwrap().push(event.clone()));
+
+        let tip = {
+            let mut bc = blockchain.lock().unwrap();
+            bc.submit_transaction(create_account("bob")).unwrap();
+            bc.get_last_block_hash()
+        };
+        assert!(miner.update_template(tip.clone()));
+        let block = solutions.recv_timeout(Duration::from_secs(10)).unwrap();
+        miner.stop();
+
+        // Every nonce up to the solution counts
+        let stats = miner.stats();
+        assert_eq!(stats.hashes() as u128, block.nonce() + 1);
+        assert_eq!(stats.solutions(), 1);
+        assert_eq!(
+            events.lock().unwrap().last(),
+            Some(&MiningEvent::SolutionFound {
+                tip: tip.clone(),
+                hash: block.hash(),
+                nonce: block.nonce(),
+            })
+        );
+
+        // A job that never succeeds keeps reporting progress
+        events.lock().unwrap().clear();
+        blockchain.lock().unwrap().submit_transaction(create_account("carol")).unwrap();
+        blockchain.lock().unwrap().set_pow(Arc::new(Unsolvable));
+        assert!(miner.restart());
+        // A whole range takes seconds in debug builds, more when other tests hog the cores
+        let deadline = Instant::now() + Duration::from_secs(120);
+        while stats.ranges_exhausted() == 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        miner.stop();
+
+        let events = events.lock().unwrap();
+        assert_eq!(
+            events.iter().find(|event| matches!(event, MiningEvent::NonceRangeExhausted { .. })),
+            Some(&MiningEvent::NonceRangeExhausted {
+                tip,
+                start: 0,
+                end: NONCE_RANGE,
+            })
+        );
+        assert!(matches!(events.last(), Some(MiningEvent::Hashrate { .. })));
+        assert_eq!(stats.solutions(), 1);
+        assert!(stats.hashes() as u128 > NONCE_RANGE);
+    }
+}
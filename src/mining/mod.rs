@@ -0,0 +1,11 @@
+mod cancellation;
+mod events;
+mod miner;
+mod pool;
+mod stratum;
+
+pub use cancellation::CancellationToken;
+pub use events::{MiningCallback, MiningEvent, MiningStats, HASHRATE_INTERVAL, NONCE_RANGE};
+pub use miner::{Miner, MiningError};
+pub use pool::{Pool, PoolJob, ShareStatus, SHARE_TARGET_MULTIPLIER};
+pub use stratum::{serve_pool, StratumSession, POOL_POLL_INTERVAL};
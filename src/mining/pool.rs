@@ -0,0 +1,302 @@
+use crate::consensus::{decode_bits, meets_target};
+use crate::traits::Hashable;
+use crate::types::{AccountId, Balance, Block, Blockchain, Error, Hash, Transaction, TransactionData};
+use ed25519_dalek::{Keypair, Signer};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+// Shares are this many times easier than blocks
+pub const SHARE_TARGET_MULTIPLIER: u128 = 16;
+
+#[derive(Debug, Clone)]
+pub struct PoolJob {
+    pub id: u64,
+    pub block: Block,
+    pub target: u128,
+    pub share_target: u128,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShareStatus {
+    Accepted,
+    BlockFound { hash: Hash },
+}
+
+// A mining pool on top of a node. Miners work on the pool's jobs, whose coinbase pays the
+// pool account, and prove their effort with shares: solutions to an easier share target.
+// When one of them also meets the block target the block is appended and the reward is
+// split between the miners in proportion to their shares in the round, then paid out
+// from the pool account.
+pub struct Pool {
+    blockchain: Arc<Mutex<Blockchain>>,
+    account_id: AccountId,
+    keypair: Keypair,
+    reward: Balance,
+    next_job_id: u64,
+    job: Option<PoolJob>,
+    shares: HashMap<AccountId, u64>,
+    submitted: HashSet<(u64, u128)>,
+    owed: HashMap<AccountId, Balance>,
+}
+
+impl Pool {
+    pub fn new(blockchain: Arc<Mutex<Blockchain>>, account_id: AccountId, keypair: Keypair) -> Self {
+        let reward = blockchain.lock().unwrap().block_reward();
+        Self {
+            blockchain,
+            account_id,
+            keypair,
+            reward,
+            next_job_id: 0,
+            job: None,
+            shares: HashMap::new(),
+            submitted: HashSet::new(),
+            owed: HashMap::new(),
+        }
+    }
+
+    // What is split between the miners per found block, the chain's block reward by
+    // default
+    pub fn set_reward(&mut self, reward: Balance) {
+        self.reward = reward;
+    }
+
+    // Shares of the current round by miner
+    pub fn shares(&self) -> &HashMap<AccountId, u64> {
+        &self.shares
+    }
+
+    // Earnings that haven't made it into the mempool yet
+    pub fn owed(&self) -> &HashMap<AccountId, Balance> {
+        &self.owed
+    }
+
+    // The job miners should be working on, replaced once the chain moved on
+    pub fn job(&mut self) -> Result<PoolJob, Error> {
+        let mut blockchain = self.blockchain.lock().unwrap();
+        if let Some(job) = &self.job {
+            if job.block.header.prev_hash == blockchain.get_last_block_hash() {
+                return Ok(job.clone());
+            }
+        }
+
+        let mut block = blockchain.block_template();
+        if block.body.transactions.is_empty() {
+            return Err("No transactions to mine".to_string());
+        }
+        blockchain.add_coinbase(&mut block, self.account_id.clone());
+        let target = decode_bits(block.bits())?;
+
+        self.next_job_id += 1;
+        self.submitted.clear();
+        let job = PoolJob {
+            id: self.next_job_id,
+            block,
+            target,
+            share_target: target.saturating_mul(SHARE_TARGET_MULTIPLIER),
+        };
+        self.job = Some(job.clone());
+        Ok(job)
+    }
+
+    pub fn submit_share(&mut self, miner: &AccountId, job_id: u64, nonce: u128) -> Result<ShareStatus, Error> {
+        let mut block = match &self.job {
+            Some(job) if job.id == job_id => job.block.clone(),
+            _ => return Err("Stale job".to_string()),
+        };
+        block.set_nonce(nonce);
+
+        let pow = self.blockchain.lock().unwrap().pow();
+        let share_target = self.job.as_ref().map(|job| job.share_target).unwrap_or_default();
+        if !meets_target(&pow.pow_hash(&block), share_target) {
+            return Err("Share above the share target".to_string());
+        }
+        if !self.submitted.insert((job_id, nonce)) {
+            return Err("Duplicate share".to_string());
+        }
+        *self.shares.entry(miner.clone()).or_default() += 1;
+
+        if !pow.verify(&block) {
+            return Ok(ShareStatus::Accepted);
+        }
+        let hash = block.hash();
+        self.blockchain.lock().unwrap().append_block(block)?;
+        self.job = None;
+        self.credit();
+        self.pay_out();
+
+        Ok(ShareStatus::BlockFound { hash })
+    }
+
+    // Splits the reward by shares and starts a new round. Rounding leftovers stay with
+    // the pool.
+    fn credit(&mut self) {
+        let total: u64 = self.shares.values().sum();
+        for (miner, shares) in self.shares.drain() {
+            let earned = self.reward.saturating_mul(shares as Balance) / total.max(1) as Balance;
+            *self.owed.entry(miner).or_default() += earned;
+        }
+    }
+
+    // Queues a transfer for everything owed. Whatever the node refuses stays owed for the
+    // next block.
+    fn pay_out(&mut self) {
+        let mut blockchain = self.blockchain.lock().unwrap();
+        let timestamp = blockchain.now();
+
+        let mut owed: Vec<(AccountId, Balance)> = self.owed.drain().collect();
+        owed.sort();
+        for (miner, amount) in owed {
+            if amount == 0 || miner == self.account_id {
+                continue;
+            }
+            let mut transaction = Transaction::new(
+                TransactionData::Transfer {
+                    to: miner.clone(),
+                    amount,
+                },
+                Some(self.account_id.clone()),
+                timestamp,
+            );
+            transaction.set_nonce(blockchain.next_nonce(&self.account_id));
+            transaction.sign(Some(self.keypair.sign(&transaction.signing_message())));
+            if blockchain.submit_transaction(transaction).is_err() {
+                self.owed.insert(miner, amount);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::WorldState;
+    use crate::types::BLOCK_REWARD;
+    use crate::utils::append_block_with_tx;
+
+    fn pool() -> (Pool, Arc<Mutex<Blockchain>>) {
+        let keypairs: Vec<Keypair> = (0..4).map(|_| Keypair::generate(&mut rand::rngs::OsRng {})).collect();
+        let mut bc = Blockchain::with_max_target(u128::MAX >> 8);
+        let now = bc.now();
+        let mut genesis: Vec<Transaction> = ["pool", "alice", "bob", "carol"]
+            .iter()
+            .zip(keypairs.iter())
+            .map(|(account_id, keypair)| {
+                Transaction::create_account(account_id.to_string(), keypair, now)
+            })
+            .collect();
+        genesis.push(Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: "pool".to_string(),
+                amount: 1000,
+            },
+            None,
+            now,
+        ));
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+
+        let blockchain = Arc::new(Mutex::new(bc));
+        let pool_keypair = Keypair::from_bytes(&keypairs[0].to_bytes()).unwrap();
+        (Pool::new(blockchain.clone(), "pool".to_string(), pool_keypair), blockchain)
+    }
+
+    fn queue_transaction(blockchain: &Arc<Mutex<Blockchain>>, account_id: &str) {
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut blockchain = blockchain.lock().unwrap();
+        let transaction =
+            Transaction::create_account(account_id.to_string(), &keypair, blockchain.now());
+        blockchain.submit_transaction(transaction).unwrap();
+    }
+
+    // Nonces from `start` that make a share, and whether they also make a block
+    fn find_share(job: &PoolJob, start: u128) -> (u128, bool) {
+        let mut block = job.block.clone();
+        (start..)
+            .find_map(|nonce| {
+                block.set_nonce(nonce);
+                let hash = block.hash();
+                if meets_target(&hash, job.share_target) {
+                    Some((nonce, meets_target(&hash, job.target)))
+                } else {
+                    None
+                }
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_shares_and_payouts() {
+        let (mut pool, blockchain) = pool();
+        assert!(pool.job().is_err());
+        queue_transaction(&blockchain, "dave");
+
+        let job = pool.job().unwrap();
+        assert_eq!(job.share_target, job.target * SHARE_TARGET_MULTIPLIER);
+        assert_eq!(job.block.beneficiary(), Some(&"pool".to_string()));
+        assert!(job.block.body.transactions[0].is_coinbase());
+        assert_eq!(pool.job().unwrap().id, job.id);
+
+        // Alice and bob take turns until one of them finds the block
+        let miners = ["alice".to_string(), "bob".to_string()];
+        let mut nonce = 0;
+        let mut turn = 0;
+        let found = loop {
+            let (share, is_block) = find_share(&job, nonce);
+            let miner = &miners[turn % 2];
+            nonce = share + 1;
+            turn += 1;
+            if !is_block {
+                assert_eq!(pool.submit_share(miner, job.id, share), Ok(ShareStatus::Accepted));
+                assert_eq!(pool.submit_share(miner, job.id, share), Err("Duplicate share".to_string()));
+                continue;
+            }
+            let shares = pool.shares().clone();
+            match pool.submit_share(miner, job.id, share).unwrap() {
+                ShareStatus::BlockFound { hash } => break (hash, shares),
+                status => panic!("Expected a block, got {:?}", status),
+            }
+        };
+
+        let (hash, mut shares) = found;
+        *shares.entry(miners[(turn - 1) % 2].clone()).or_default() += 1;
+        let total: u64 = shares.values().sum();
+        let blockchain = blockchain.lock().unwrap();
+        assert_eq!(blockchain.get_last_block_hash(), Some(hash));
+        assert!(pool.shares().is_empty());
+        assert!(pool.owed().is_empty());
+
+        // One payout per miner, in proportion to their shares
+        let payouts: HashMap<AccountId, Balance> = blockchain
+            .pending_transactions()
+            .iter()
+            .filter_map(|transaction| match transaction.data() {
+                TransactionData::Transfer { to, amount } => Some((to.clone(), *amount)),
+                _ => None,
+            })
+            .collect();
+        for (miner, count) in shares {
+            assert_eq!(payouts[&miner], BLOCK_REWARD * count as Balance / total as Balance);
+        }
+        // The coinbase paid the pool, the payouts are still pending
+        assert_eq!(blockchain.get_account_by_id("pool".to_string()).unwrap().balance, 1000 + BLOCK_REWARD);
+    }
+
+    #[test]
+    fn test_invalid_shares() {
+        let (mut pool, blockchain) = pool();
+        queue_transaction(&blockchain, "dave");
+        let job = pool.job().unwrap();
+        let alice = "alice".to_string();
+
+        let mut block = job.block.clone();
+        let weak = (0..)
+            .find(|nonce| {
+                block.set_nonce(*nonce);
+                !meets_target(&block.hash(), job.share_target)
+            })
+            .unwrap();
+        assert!(pool.submit_share(&alice, job.id, weak).is_err());
+        assert!(pool.submit_share(&alice, job.id + 1, find_share(&job, 0).0).is_err());
+        assert!(pool.shares().is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::mining::{Pool, PoolJob, ShareStatus};
+use crate::rpc::{block_to_json, RpcError, RpcRequest, RpcResponse, CHAIN_ERROR, INVALID_PARAMS, INVALID_REQUEST};
+use crate::rpc::{METHOD_NOT_FOUND, PARSE_ERROR};
+use crate::types::AccountId;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+pub const POOL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// One miner's connection to the pool, speaking line-delimited JSON-RPC in the spirit of
+// Stratum: "mining.authorize" with the account to be paid, "mining.get_job" and
+// "mining.submit" with a job id and nonce. New jobs are pushed as "mining.notify".
+pub struct StratumSession {
+    pool: Arc<Mutex<Pool>>,
+    miner: Option<AccountId>,
+    notified_job: Option<u64>,
+}
+
+impl StratumSession {
+    pub fn new(pool: Arc<Mutex<Pool>>) -> Self {
+        Self {
+            pool,
+            miner: None,
+            notified_job: None,
+        }
+    }
+
+    pub fn handle(&mut self, request: &str) -> String {
+        let response = match serde_json::from_str::<RpcRequest>(request) {
+            Ok(request) if request.jsonrpc != "2.0" => {
+                RpcResponse::new(request.id, Err(RpcError::new(INVALID_REQUEST, "Invalid Request")))
+            }
+            Ok(request) => RpcResponse::new(request.id.clone(), self.dispatch(&request.method, &request.params)),
+            Err(_) => RpcResponse::new(Value::Null, Err(RpcError::new(PARSE_ERROR, "Parse error"))),
+        };
+
+        serde_json::to_string(&response).unwrap_or_default()
+    }
+
+    // A mining.notify for an authorized miner whenever the pool moved to a new job
+    pub fn poll(&mut self) -> Option<Value> {
+        self.miner.as_ref()?;
+        let job = self.pool.lock().unwrap().job().ok()?;
+        if self.notified_job == Some(job.id) {
+            return None;
+        }
+        self.notified_job = Some(job.id);
+
+        Some(json!({ "jsonrpc": "2.0", "method": "mining.notify", "params": job_to_json(&job) }))
+    }
+
+    fn dispatch(&mut self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "mining.authorize" => {
+                let miner = params
+                    .get(0)
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected an account id"))?;
+                self.miner = Some(miner.to_string());
+                Ok(json!(true))
+            }
+            "mining.get_job" => {
+                let job = self
+                    .pool
+                    .lock()
+                    .unwrap()
+                    .job()
+                    .map_err(|error| RpcError::new(CHAIN_ERROR, &error))?;
+                self.notified_job = Some(job.id);
+                Ok(job_to_json(&job))
+            }
+            "mining.submit" => {
+                let miner = self
+                    .miner
+                    .clone()
+                    .ok_or_else(|| RpcError::new(INVALID_REQUEST, "Not authorized"))?;
+                let job_id = params.get(0).and_then(Value::as_u64);
+                let nonce = params.get(1).and_then(Value::as_str).and_then(|nonce| nonce.parse().ok());
+                let (job_id, nonce) = job_id
+                    .zip(nonce)
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected a job id and a nonce"))?;
+
+                match self.pool.lock().unwrap().submit_share(&miner, job_id, nonce) {
+                    Ok(ShareStatus::Accepted) => Ok(json!({ "accepted": true, "block": null })),
+                    Ok(ShareStatus::BlockFound { hash }) => Ok(json!({ "accepted": true, "block": hash })),
+                    Err(error) => Err(RpcError::new(CHAIN_ERROR, &error)),
+                }
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, "Method not found")),
+        }
+    }
+}
+
+fn job_to_json(job: &PoolJob) -> Value {
+    json!({
+        "job_id": job.id,
+        "target": format!("{:032x}", job.target),
+        "share_target": format!("{:032x}", job.share_target),
+        "block": block_to_json(&job.block),
+    })
+}
+
+// Pool endpoint, one thread per miner. Each thread alternates between reading requests
+// (with a short timeout) and pushing new jobs.
+pub fn serve_pool(listener: TcpListener, pool: Arc<Mutex<Pool>>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let pool = pool.clone();
+            thread::spawn(move || handle_connection(stream, pool));
+        }
+    })
+}
+
+fn handle_connection(stream: TcpStream, pool: Arc<Mutex<Pool>>) {
+    if stream.set_read_timeout(Some(POOL_POLL_INTERVAL)).is_err() {
+        return;
+    }
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    let mut session = StratumSession::new(pool);
+    // Bytes read before a timeout stay here until the rest of the line arrives
+    let mut line = Vec::new();
+
+    loop {
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => break,
+            Ok(_) => {
+                let response = session.handle(&String::from_utf8_lossy(&line));
+                line.clear();
+                if writeln!(writer, "{}", response).is_err() {
+                    break;
+                }
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::TimedOut => {}
+            Err(_) => break,
+        }
+
+        if let Some(notification) = session.poll() {
+            if writeln!(writer, "{}", notification).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::Hashable;
+    use crate::types::{Blockchain, Transaction};
+    use crate::consensus::meets_target;
+    use crate::utils::append_block_with_tx;
+    use ed25519_dalek::Keypair;
+
+    fn create_account(blockchain: &Blockchain, account_id: &str, keypair: &Keypair) -> Transaction {
+        Transaction::create_account(account_id.to_string(), keypair, blockchain.now())
+    }
+
+    #[test]
+    fn test_mine_through_the_pool() {
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut bc = Blockchain::with_max_target(u128::MAX >> 8);
+        let genesis = vec![
+            create_account(&bc, "pool", &keypair),
+            create_account(&bc, "alice", &Keypair::generate(&mut rand::rngs::OsRng {})),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        let bob = create_account(&bc, "bob", &Keypair::generate(&mut rand::rngs::OsRng {}));
+        bc.submit_transaction(bob).unwrap();
+        let blockchain = Arc::new(Mutex::new(bc));
+        let pool = Arc::new(Mutex::new(Pool::new(blockchain.clone(), "pool".to_string(), keypair)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        serve_pool(listener, pool.clone());
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut call = |method: &str, params: Value| -> Value {
+            let request = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 });
+            writeln!(writer, "{}", request).unwrap();
+            // Skip job notifications on the way to the response
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let message: Value = serde_json::from_str(&line).unwrap();
+                if message["method"] != "mining.notify" {
+                    return message;
+                }
+            }
+        };
+
+        assert_eq!(call("mining.submit", json!([1, "0"]))["error"]["code"], INVALID_REQUEST);
+        assert_eq!(call("mining.authorize", json!(["alice"]))["result"], true);
+        let job = call("mining.get_job", Value::Null)["result"].clone();
+        assert_eq!(job["block"]["beneficiary"], "pool");
+
+        // Mine the job's block the way a remote miner would, from the job alone
+        let job_id = job["job_id"].as_u64().unwrap();
+        let mut block = pool.lock().unwrap().job().unwrap().block;
+        assert_eq!(block.hash(), job["block"]["hash"]);
+        let target = u128::from_str_radix(job["target"].as_str().unwrap(), 16).unwrap();
+        let nonce = (0..)
+            .find(|nonce| {
+                block.set_nonce(*nonce);
+                meets_target(&block.hash(), target)
+            })
+            .unwrap();
+
+        let response = call("mining.submit", json!([job_id, nonce.to_string()]));
+        assert_eq!(response["result"]["block"], block.hash());
+        assert_eq!(blockchain.lock().unwrap().get_last_block_hash(), Some(block.hash()));
+        assert_eq!(call("mining.submit", json!([job_id, nonce.to_string()]))["error"]["code"], CHAIN_ERROR);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::traits::CanonicalEncode;
+use crate::types::Hash;
+use blake2::digest::FixedOutput;
+use blake2::{Blake2s, Digest};
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+pub const MAGIC: [u8; 4] = *b"WKSP";
+pub const HEADER_SIZE: usize = 13;
+pub const MAX_PAYLOAD_SIZE: u32 = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    Handshake = 1,
+    TxAnnounce = 2,
+    TxRequest = 3,
+    TxDeliver = 4,
+    Block = 5,
+    Consensus = 6,
+    GetBlocks = 7,
+    Blocks = 8,
+}
+
+impl TryFrom<u8> for MessageType {
+    type Error = DecodeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(MessageType::Handshake),
+            2 => Ok(MessageType::TxAnnounce),
+            3 => Ok(MessageType::TxRequest),
+            4 => Ok(MessageType::TxDeliver),
+            5 => Ok(MessageType::Block),
+            6 => Ok(MessageType::Consensus),
+            7 => Ok(MessageType::GetBlocks),
+            8 => Ok(MessageType::Blocks),
+            _ => Err(DecodeError::UnknownType(value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub message_type: MessageType,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    BadMagic([u8; 4]),
+    UnknownType(u8),
+    TooLarge(u32),
+    BadChecksum,
+    LengthMismatch,
+    Io(io::Error),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::BadMagic(magic) => write!(f, "Bad magic bytes {}", hex::encode(magic)),
+            DecodeError::UnknownType(value) => write!(f, "Unknown message type {}", value),
+            DecodeError::TooLarge(length) => write!(f, "Payload of {} bytes is too large", length),
+            DecodeError::BadChecksum => write!(f, "Checksum mismatch"),
+            DecodeError::LengthMismatch => write!(f, "Frame length doesn't match the message"),
+            DecodeError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<io::Error> for DecodeError {
+    fn from(error: io::Error) -> Self {
+        DecodeError::Io(error)
+    }
+}
+
+pub fn checksum(payload: &[u8]) -> [u8; 4] {
+    let mut hasher = Blake2s::new();
+    hasher.update(payload);
+    let digest = hasher.finalize_fixed();
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+// Magic of the network made of `chain_id` and `genesis_hash`. Nodes of other networks,
+// even ones that picked the same chain id, fail to decode our frames and we theirs, so
+// their blocks and transactions never get in.
+pub fn network_magic(chain_id: &str, genesis_hash: Option<&Hash>) -> [u8; 4] {
+    checksum(&(chain_id, genesis_hash).canonical_bytes())
+}
+
+// Frame layout: magic (4) | type (1) | payload length (4, big endian) | checksum (4) | payload.
+// The header is validated before the payload is read, so a peer can't make us allocate
+// more than `max_payload_size` or feed us garbage.
+#[derive(Debug, Clone)]
+pub struct FrameCodec {
+    magic: [u8; 4],
+    max_payload_size: u32,
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        Self::new(MAGIC)
+    }
+}
+
+impl FrameCodec {
+    pub fn new(magic: [u8; 4]) -> Self {
+        Self {
+            magic,
+            max_payload_size: MAX_PAYLOAD_SIZE,
+        }
+    }
+
+    pub fn for_network(chain_id: &str, genesis_hash: Option<&Hash>) -> Self {
+        Self::new(network_magic(chain_id, genesis_hash))
+    }
+
+    pub fn magic(&self) -> [u8; 4] {
+        self.magic
+    }
+
+    pub fn set_max_payload_size(&mut self, max_payload_size: u32) {
+        self.max_payload_size = max_payload_size;
+    }
+
+    pub fn encode(&self, frame: &Frame) -> Result<Vec<u8>, DecodeError> {
+        let length = u32::try_from(frame.payload.len()).unwrap_or(u32::MAX);
+        if length > self.max_payload_size {
+            return Err(DecodeError::TooLarge(length));
+        }
+
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + frame.payload.len());
+        bytes.extend_from_slice(&self.magic);
+        bytes.push(frame.message_type as u8);
+        bytes.extend_from_slice(&length.to_be_bytes());
+        bytes.extend_from_slice(&checksum(&frame.payload));
+        bytes.extend_from_slice(&frame.payload);
+        Ok(bytes)
+    }
+
+    // Decodes the first frame of `buf`, returns the frame and the number of bytes it took,
+    // or None if more bytes are needed
+    pub fn decode(&self, buf: &[u8]) -> Result<Option<(Frame, usize)>, DecodeError> {
+        if buf.len() < HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let (message_type, length, expected) = self.decode_header(&buf[..HEADER_SIZE])?;
+        let end = HEADER_SIZE + length as usize;
+        if buf.len() < end {
+            return Ok(None);
+        }
+
+        let payload = buf[HEADER_SIZE..end].to_vec();
+        if checksum(&payload) != expected {
+            return Err(DecodeError::BadChecksum);
+        }
+
+        Ok(Some((Frame { message_type, payload }, end)))
+    }
+
+    // Decodes a buffer holding exactly one frame, e.g. a message of a transport that
+    // does its own framing
+    pub fn decode_exact(&self, buf: &[u8]) -> Result<Frame, DecodeError> {
+        match self.decode(buf)? {
+            Some((frame, used)) if used == buf.len() => Ok(frame),
+            _ => Err(DecodeError::LengthMismatch),
+        }
+    }
+
+    pub fn read_frame<R: Read>(&self, reader: &mut R) -> Result<Frame, DecodeError> {
+        let mut header = [0u8; HEADER_SIZE];
+        reader.read_exact(&mut header)?;
+        let (message_type, length, expected) = self.decode_header(&header)?;
+
+        let mut payload = vec![0u8; length as usize];
+        reader.read_exact(&mut payload)?;
+        if checksum(&payload) != expected {
+            return Err(DecodeError::BadChecksum);
+        }
+
+        Ok(Frame { message_type, payload })
+    }
+
+    pub fn write_frame<W: Write>(&self, writer: &mut W, frame: &Frame) -> Result<(), DecodeError> {
+        writer.write_all(&self.encode(frame)?)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn decode_header(&self, header: &[u8]) -> Result<(MessageType, u32, [u8; 4]), DecodeError> {
+        let magic = [header[0], header[1], header[2], header[3]];
+        if magic != self.magic {
+            return Err(DecodeError::BadMagic(magic));
+        }
+
+        let message_type = MessageType::try_from(header[4])?;
+        let length = u32::from_be_bytes([header[5], header[6], header[7], header[8]]);
+        if length > self.max_payload_size {
+            return Err(DecodeError::TooLarge(length));
+        }
+
+        Ok((message_type, length, [header[9], header[10], header[11], header[12]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame() -> Frame {
+        Frame {
+            message_type: MessageType::TxAnnounce,
+            payload: b"payload".to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let codec = FrameCodec::default();
+        let mut bytes = codec.encode(&frame()).unwrap();
+        assert_eq!(bytes.len(), HEADER_SIZE + 7);
+
+        assert!(codec.decode(&bytes[..HEADER_SIZE + 3]).unwrap().is_none());
+        bytes.extend_from_slice(&codec.encode(&frame()).unwrap());
+        let (decoded, used) = codec.decode(&bytes).unwrap().unwrap();
+        assert_eq!(decoded, frame());
+        assert_eq!(used, HEADER_SIZE + 7);
+
+        assert_eq!(codec.read_frame(&mut &bytes[used..]).unwrap(), frame());
+        assert_eq!(codec.decode_exact(&bytes[used..]).unwrap(), frame());
+        assert!(matches!(codec.decode_exact(&bytes), Err(DecodeError::LengthMismatch)));
+    }
+
+    #[test]
+    fn test_malformed_frames() {
+        let mut codec = FrameCodec::default();
+        let bytes = codec.encode(&frame()).unwrap();
+
+        let mut corrupted = bytes.clone();
+        corrupted[HEADER_SIZE] ^= 1;
+        assert!(matches!(codec.decode(&corrupted), Err(DecodeError::BadChecksum)));
+
+        let mut unknown = bytes.clone();
+        unknown[4] = 0xff;
+        assert!(matches!(codec.decode(&unknown), Err(DecodeError::UnknownType(0xff))));
+
+        assert!(matches!(
+            FrameCodec::new(*b"TEST").decode(&bytes),
+            Err(DecodeError::BadMagic(_))
+        ));
+
+        // Same chain id, different genesis
+        let genesis = "genesis".to_string();
+        let other = "other".to_string();
+        let classnet = FrameCodec::for_network("classnet", Some(&genesis));
+        let encoded = classnet.encode(&frame()).unwrap();
+        assert_eq!(classnet.decode_exact(&encoded).unwrap(), frame());
+        assert!(matches!(
+            FrameCodec::for_network("classnet", Some(&other)).decode(&encoded),
+            Err(DecodeError::BadMagic(_))
+        ));
+        assert!(matches!(
+            FrameCodec::for_network("devnet", Some(&genesis)).decode(&encoded),
+            Err(DecodeError::BadMagic(_))
+        ));
+
+        // A huge length is refused before anything is allocated
+        let mut huge = bytes[..HEADER_SIZE].to_vec();
+        huge[5..9].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(codec.read_frame(&mut &huge[..]), Err(DecodeError::TooLarge(_))));
+
+        codec.set_max_payload_size(3);
+        assert!(matches!(codec.encode(&frame()), Err(DecodeError::TooLarge(7))));
+        assert!(matches!(codec.read_frame(&mut &bytes[..5]), Err(DecodeError::Io(_))));
+    }
+}
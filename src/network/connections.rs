@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+// Before the first redial, doubling with every failed attempt after
+pub const DIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+pub const MAX_DIAL_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone)]
+struct Candidate {
+    connected: bool,
+    failures: u32,
+    // None for right away
+    next_attempt: Option<Instant>,
+}
+
+// Addresses we want to be connected to, e.g. everything discovery turns up, and when each
+// is due to be dialed. Failed dials back off exponentially, so a peer that went away
+// isn't hammered while one that merely restarted is back soon.
+#[derive(Debug)]
+pub struct ConnectionManager {
+    candidates: BTreeMap<SocketAddr, Candidate>,
+    retry_delay: Duration,
+    max_retry_delay: Duration,
+}
+
+impl Default for ConnectionManager {
+    fn default() -> Self {
+        Self::with_retry_delay(DIAL_RETRY_DELAY, MAX_DIAL_RETRY_DELAY)
+    }
+}
+
+impl ConnectionManager {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_retry_delay(retry_delay: Duration, max_retry_delay: Duration) -> Self {
+        Self {
+            candidates: BTreeMap::new(),
+            retry_delay,
+            max_retry_delay,
+        }
+    }
+
+    // New addresses are due right away
+    pub fn add(&mut self, addr: SocketAddr) -> bool {
+        if self.candidates.contains_key(&addr) {
+            return false;
+        }
+
+        self.candidates.insert(
+            addr,
+            Candidate {
+                connected: false,
+                failures: 0,
+                next_attempt: None,
+            },
+        );
+        true
+    }
+
+    pub fn remove(&mut self, addr: &SocketAddr) {
+        self.candidates.remove(addr);
+    }
+
+    pub fn is_connected(&self, addr: &SocketAddr) -> bool {
+        matches!(self.candidates.get(addr), Some(candidate) if candidate.connected)
+    }
+
+    pub fn connected(&self) -> Vec<SocketAddr> {
+        self.candidates
+            .iter()
+            .filter(|(_, candidate)| candidate.connected)
+            .map(|(addr, _)| *addr)
+            .collect()
+    }
+
+    // Dial attempts in a row that failed
+    pub fn failures(&self, addr: &SocketAddr) -> u32 {
+        self.candidates.get(addr).map_or(0, |candidate| candidate.failures)
+    }
+
+    // When `addr` is to be dialed next, None while connected or if it's due right away
+    pub fn next_attempt(&self, addr: &SocketAddr) -> Option<Instant> {
+        self.candidates
+            .get(addr)
+            .filter(|candidate| !candidate.connected)
+            .and_then(|candidate| candidate.next_attempt)
+    }
+
+    // Addresses to dial at `now`
+    pub fn due(&self, now: Instant) -> Vec<SocketAddr> {
+        self.candidates
+            .iter()
+            .filter(|(_, candidate)| !candidate.connected && !matches!(candidate.next_attempt, Some(at) if at > now))
+            .map(|(addr, _)| *addr)
+            .collect()
+    }
+
+    pub fn on_connected(&mut self, addr: SocketAddr) {
+        let candidate = self.entry(addr);
+        candidate.connected = true;
+        candidate.failures = 0;
+    }
+
+    pub fn on_failed(&mut self, addr: SocketAddr, now: Instant) {
+        let (retry_delay, max_retry_delay) = (self.retry_delay, self.max_retry_delay);
+        let candidate = self.entry(addr);
+        candidate.connected = false;
+        candidate.failures = candidate.failures.saturating_add(1);
+
+        let backoff = 2u32.saturating_pow(candidate.failures - 1);
+        candidate.next_attempt = Some(now + retry_delay.saturating_mul(backoff).min(max_retry_delay));
+    }
+
+    // A connection that worked is worth a quick redial
+    pub fn on_disconnected(&mut self, addr: SocketAddr, now: Instant) {
+        let next_attempt = now + self.retry_delay;
+        let candidate = self.entry(addr);
+        candidate.connected = false;
+        candidate.failures = 0;
+        candidate.next_attempt = Some(next_attempt);
+    }
+
+    fn entry(&mut self, addr: SocketAddr) -> &mut Candidate {
+        self.candidates.entry(addr).or_insert_with(|| Candidate {
+            connected: false,
+            failures: 0,
+            next_attempt: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let mut connections = ConnectionManager::with_retry_delay(Duration::from_secs(1), Duration::from_secs(5));
+        let addr: SocketAddr = "10.0.0.1:7000".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(connections.add(addr));
+        assert!(!connections.add(addr));
+        assert_eq!(connections.next_attempt(&addr), None);
+        assert_eq!(connections.due(now), vec![addr]);
+
+        // 1s, 2s, 4s, then capped at 5s
+        for delay in [1, 2, 4, 5, 5].iter() {
+            connections.on_failed(addr, now);
+            assert_eq!(connections.next_attempt(&addr), Some(now + Duration::from_secs(*delay)));
+        }
+        assert_eq!(connections.failures(&addr), 5);
+        assert!(connections.due(now).is_empty());
+        assert_eq!(connections.due(now + Duration::from_secs(5)), vec![addr]);
+
+        connections.on_connected(addr);
+        assert!(connections.is_connected(&addr));
+        assert_eq!(connections.next_attempt(&addr), None);
+        assert!(connections.due(now + Duration::from_secs(60)).is_empty());
+
+        connections.on_disconnected(addr, now);
+        assert_eq!(connections.failures(&addr), 0);
+        assert_eq!(connections.next_attempt(&addr), Some(now + Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::types::Error;
+use std::collections::BTreeSet;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+pub const MDNS_SERVICE_TYPE: &str = "_blockchain-ws._tcp.local.";
+
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveryConfig {
+    pub bootstrap_peers: Vec<String>,
+    pub mdns: bool,
+}
+
+impl DiscoveryConfig {
+    pub fn new(bootstrap_peers: Vec<String>, mdns: bool) -> Self {
+        Self {
+            bootstrap_peers,
+            mdns,
+        }
+    }
+
+    // Accepts "host:port" entries separated by commas or whitespace
+    pub fn parse_bootstrap_peers(list: &str) -> Vec<String> {
+        list.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|peer| !peer.is_empty())
+            .map(|peer| peer.to_string())
+            .collect()
+    }
+}
+
+// Collects peer addresses from the static bootstrap list and, with the `mdns` feature,
+// from other nodes announcing themselves on the local network
+pub struct Discovery {
+    config: DiscoveryConfig,
+    peers: BTreeSet<SocketAddr>,
+    new_peers: Vec<SocketAddr>,
+    #[cfg(feature = "mdns")]
+    mdns: Option<super::mdns::Mdns>,
+}
+
+impl Discovery {
+    pub fn new(config: DiscoveryConfig) -> Self {
+        Self {
+            config,
+            peers: BTreeSet::new(),
+            new_peers: vec![],
+            #[cfg(feature = "mdns")]
+            mdns: None,
+        }
+    }
+
+    pub fn config(&self) -> &DiscoveryConfig {
+        &self.config
+    }
+
+    pub fn start(&mut self, node_id: &str, listen_addr: SocketAddr) -> Result<(), Error> {
+        for peer in self.config.bootstrap_peers.clone() {
+            let addrs = match peer.to_socket_addrs() {
+                Ok(addrs) => addrs,
+                Err(error) => return Err(format!("Invalid bootstrap peer {}: {}", peer, error)),
+            };
+            for addr in addrs {
+                if addr != listen_addr {
+                    self.add_peer(addr);
+                }
+            }
+        }
+
+        if self.config.mdns {
+            self.start_mdns(node_id, listen_addr)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn add_peer(&mut self, addr: SocketAddr) -> bool {
+        if !self.peers.insert(addr) {
+            return false;
+        }
+
+        self.new_peers.push(addr);
+        true
+    }
+
+    pub fn peers(&self) -> Vec<SocketAddr> {
+        self.peers.iter().cloned().collect()
+    }
+
+    // Addresses discovered since the last call
+    pub fn poll(&mut self) -> Vec<SocketAddr> {
+        #[cfg(feature = "mdns")]
+        {
+            let found = self.mdns.as_ref().map(|mdns| mdns.poll()).unwrap_or_default();
+            for addr in found {
+                self.add_peer(addr);
+            }
+        }
+
+        std::mem::take(&mut self.new_peers)
+    }
+
+    #[cfg(feature = "mdns")]
+    fn start_mdns(&mut self, node_id: &str, listen_addr: SocketAddr) -> Result<(), Error> {
+        self.mdns = Some(super::mdns::Mdns::start(node_id, listen_addr)?);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "mdns"))]
+    fn start_mdns(&mut self, _node_id: &str, _listen_addr: SocketAddr) -> Result<(), Error> {
+        Err("mDNS discovery requires the `mdns` feature".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bootstrap_peers() {
+        assert_eq!(
+            DiscoveryConfig::parse_bootstrap_peers("10.0.0.1:7000, 10.0.0.2:7000\nlocalhost:7001"),
+            vec!["10.0.0.1:7000", "10.0.0.2:7000", "localhost:7001"]
+        );
+        assert!(DiscoveryConfig::parse_bootstrap_peers(" , ").is_empty());
+    }
+
+    #[test]
+    fn test_bootstrap_peers() {
+        let listen_addr: SocketAddr = "127.0.0.1:7000".parse().unwrap();
+        let config = DiscoveryConfig::new(
+            vec!["127.0.0.1:7000".to_string(), "127.0.0.1:7001".to_string(), "127.0.0.1:7001".to_string()],
+            false,
+        );
+        let mut discovery = Discovery::new(config);
+
+        assert!(discovery.start("alice", listen_addr).is_ok());
+        assert_eq!(discovery.poll(), vec!["127.0.0.1:7001".parse().unwrap()]);
+        assert!(discovery.poll().is_empty());
+
+        assert!(discovery.add_peer("127.0.0.1:7002".parse().unwrap()));
+        assert!(!discovery.add_peer("127.0.0.1:7002".parse().unwrap()));
+        assert_eq!(discovery.peers().len(), 2);
+    }
+
+    #[test]
+    fn test_invalid_bootstrap_peer() {
+        let mut discovery = Discovery::new(DiscoveryConfig::new(vec!["no-port".to_string()], false));
+
+        assert!(discovery.start("alice", "127.0.0.1:7000".parse().unwrap()).is_err());
+    }
+
+    #[cfg(not(feature = "mdns"))]
+    #[test]
+    fn test_mdns_requires_feature() {
+        let mut discovery = Discovery::new(DiscoveryConfig::new(vec![], true));
+
+        assert!(discovery.start("alice", "127.0.0.1:7000".parse().unwrap()).is_err());
+    }
+}
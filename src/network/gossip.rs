@@ -0,0 +1,230 @@
+use crate::network::Misbehavior;
+use crate::traits::Hashable;
+use crate::types::{Hash, Transaction};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+pub const MAX_INVENTORY: usize = 1000;
+pub const MAX_SEEN: usize = 50_000;
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub enum GossipMessage {
+    Announce(Vec<Hash>),
+    Request(Vec<Hash>),
+    Deliver(Vec<Transaction>),
+}
+
+// Transactions travel as announce (hashes) -> request -> deliver. Every hash is handled
+// once thanks to the seen-set, so a transaction relayed around the LAN doesn't echo forever.
+#[derive(Debug, Default)]
+pub struct TxGossip {
+    seen: HashSet<Hash>,
+    order: VecDeque<Hash>,
+    transactions: HashMap<Hash, Transaction>,
+    peers: HashMap<SocketAddr, HashSet<Hash>>,
+    requested: HashMap<Hash, (SocketAddr, Instant)>,
+}
+
+impl TxGossip {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_peer(&mut self, addr: SocketAddr) {
+        self.peers.entry(addr).or_default();
+    }
+
+    pub fn remove_peer(&mut self, addr: &SocketAddr) {
+        self.peers.remove(addr);
+        self.requested.retain(|_, (peer, _)| peer != addr);
+    }
+
+    pub fn has_seen(&self, hash: &Hash) -> bool {
+        self.seen.contains(hash)
+    }
+
+    pub fn get(&self, hash: &Hash) -> Option<&Transaction> {
+        self.transactions.get(hash)
+    }
+
+    // Announces a valid transaction, either our own or one just received, to every peer
+    // that doesn't have it yet
+    pub fn broadcast(&mut self, transaction: Transaction) -> Vec<(SocketAddr, GossipMessage)> {
+        let hash = transaction.hash();
+        self.mark_seen(hash.clone());
+        self.transactions.insert(hash.clone(), transaction);
+
+        self.peers
+            .iter_mut()
+            .filter(|(_, known)| !known.contains(&hash))
+            .map(|(addr, known)| {
+                known.insert(hash.clone());
+                (*addr, GossipMessage::Announce(vec![hash.clone()]))
+            })
+            .collect()
+    }
+
+    // Requests announced transactions we haven't seen and aren't already fetching elsewhere
+    pub fn on_announce(&mut self, from: SocketAddr, hashes: Vec<Hash>) -> Result<Option<GossipMessage>, Misbehavior> {
+        if hashes.len() > MAX_INVENTORY {
+            return Err(Misbehavior::Spam);
+        }
+
+        let now = Instant::now();
+        let mut wanted = vec![];
+        for hash in hashes {
+            self.peers.entry(from).or_default().insert(hash.clone());
+
+            let in_flight = matches!(self.requested.get(&hash), Some((_, at)) if now < *at + REQUEST_TIMEOUT);
+            if self.seen.contains(&hash) || in_flight || wanted.contains(&hash) {
+                continue;
+            }
+
+            self.requested.insert(hash.clone(), (from, now));
+            wanted.push(hash);
+        }
+
+        Ok(if wanted.is_empty() {
+            None
+        } else {
+            Some(GossipMessage::Request(wanted))
+        })
+    }
+
+    pub fn on_request(&mut self, from: SocketAddr, hashes: Vec<Hash>) -> Result<Option<GossipMessage>, Misbehavior> {
+        if hashes.len() > MAX_INVENTORY {
+            return Err(Misbehavior::Spam);
+        }
+
+        let transactions: Vec<Transaction> = hashes
+            .iter()
+            .filter_map(|hash| self.transactions.get(hash))
+            .cloned()
+            .collect();
+
+        let known = self.peers.entry(from).or_default();
+        for transaction in transactions.iter() {
+            known.insert(transaction.hash());
+        }
+
+        Ok(if transactions.is_empty() {
+            None
+        } else {
+            Some(GossipMessage::Deliver(transactions))
+        })
+    }
+
+    // Returns the delivered transactions that are new, the caller validates them and
+    // `broadcast`s the valid ones further. Deliveries we never asked for are spam.
+    pub fn on_deliver(&mut self, from: SocketAddr, transactions: Vec<Transaction>) -> Result<Vec<Transaction>, Misbehavior> {
+        if transactions.len() > MAX_INVENTORY {
+            return Err(Misbehavior::Spam);
+        }
+
+        let mut received = vec![];
+        for transaction in transactions {
+            let hash = transaction.hash();
+            match self.requested.get(&hash) {
+                Some((peer, _)) if *peer == from => {}
+                _ if self.seen.contains(&hash) => continue,
+                _ => return Err(Misbehavior::Spam),
+            }
+
+            self.requested.remove(&hash);
+            self.peers.entry(from).or_default().insert(hash.clone());
+            self.mark_seen(hash);
+            received.push(transaction);
+        }
+
+        Ok(received)
+    }
+
+    fn mark_seen(&mut self, hash: Hash) {
+        if !self.seen.insert(hash.clone()) {
+            return;
+        }
+        self.order.push_back(hash);
+
+        while self.order.len() > MAX_SEEN {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+                self.transactions.remove(&oldest);
+                for known in self.peers.values_mut() {
+                    known.remove(&oldest);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TransactionData;
+
+    fn transfer(amount: u128) -> Transaction {
+        Transaction::new(
+            TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount,
+            },
+            Some("alice".to_string()),
+            0,
+        )
+    }
+
+    #[test]
+    fn test_announce_request_deliver() {
+        let (alice, bob, carol): (SocketAddr, SocketAddr, SocketAddr) = (
+            "10.0.0.1:7000".parse().unwrap(),
+            "10.0.0.2:7000".parse().unwrap(),
+            "10.0.0.3:7000".parse().unwrap(),
+        );
+        let mut sender = TxGossip::new();
+        sender.add_peer(bob);
+        let mut receiver = TxGossip::new();
+        receiver.add_peer(alice);
+        receiver.add_peer(carol);
+
+        let tx = transfer(10);
+        let hash = tx.hash();
+        let announces = sender.broadcast(tx);
+        assert_eq!(announces.len(), 1);
+
+        let request = match &announces[0].1 {
+            GossipMessage::Announce(hashes) => receiver.on_announce(alice, hashes.clone()).unwrap().unwrap(),
+            _ => panic!("expected announce"),
+        };
+        // Carol announcing the same hash doesn't trigger a second request
+        assert!(receiver.on_announce(carol, vec![hash.clone()]).unwrap().is_none());
+
+        let delivery = match request {
+            GossipMessage::Request(hashes) => sender.on_request(bob, hashes).unwrap().unwrap(),
+            _ => panic!("expected request"),
+        };
+        let received = match delivery {
+            GossipMessage::Deliver(txs) => receiver.on_deliver(alice, txs).unwrap(),
+            _ => panic!("expected deliver"),
+        };
+        assert_eq!(received.len(), 1);
+        assert!(receiver.has_seen(&hash));
+
+        // Relaying skips both peers that already have it
+        assert!(receiver.broadcast(received[0].clone()).is_empty());
+        assert!(receiver.on_announce(alice, vec![hash]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_unsolicited_delivery() {
+        let mut gossip = TxGossip::new();
+        let peer: SocketAddr = "10.0.0.1:7000".parse().unwrap();
+
+        assert_eq!(gossip.on_deliver(peer, vec![transfer(1)]).unwrap_err(), Misbehavior::Spam);
+        assert_eq!(
+            gossip.on_announce(peer, vec!["hash".to_string(); MAX_INVENTORY + 1]).unwrap_err(),
+            Misbehavior::Spam
+        );
+    }
+}
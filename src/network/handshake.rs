@@ -0,0 +1,243 @@
+use crate::network::FrameCodec;
+use crate::traits::ChainStore;
+use crate::types::{Blockchain, ChainInfo, Error, Hash};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Read, Write};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+pub const MAX_HANDSHAKE_SIZE: u64 = 4096;
+pub const SUPPORTED_FEATURES: &[&str] = &["orphans", "side-branches", "ommers", "finality", "bft"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Handshake {
+    pub version: u32,
+    pub chain_id: String,
+    pub genesis_hash: Option<Hash>,
+    pub features: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HandshakeMessage {
+    Hello(Handshake),
+    Accept { version: u32, features: Vec<String> },
+    Reject { reason: String },
+}
+
+// What both sides agreed on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub version: u32,
+    pub features: Vec<String>,
+    pub remote: Handshake,
+}
+
+impl Handshake {
+    pub fn new(chain_id: &str, genesis_hash: Option<Hash>) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            chain_id: chain_id.to_string(),
+            genesis_hash,
+            features: SUPPORTED_FEATURES.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    // Announces the chain the info describes, its chain id and genesis being what peers
+    // have to share with us
+    pub fn from_info(info: &ChainInfo) -> Self {
+        Self::new(&info.chain_id, info.genesis_hash.clone())
+    }
+
+    pub fn from_blockchain<S: ChainStore>(bc: &Blockchain<S>) -> Self {
+        Self::from_info(&bc.info())
+    }
+
+    // Codec for the messages after the handshake, only understood within this network
+    pub fn frame_codec(&self) -> FrameCodec {
+        FrameCodec::for_network(&self.chain_id, self.genesis_hash.as_ref())
+    }
+
+    pub fn negotiate(&self, remote: &Handshake) -> Result<Session, Error> {
+        if remote.version < MIN_PROTOCOL_VERSION {
+            return Err(format!("Unsupported protocol version {}", remote.version));
+        }
+        if remote.chain_id != self.chain_id {
+            return Err(format!("Chain id {} doesn't match {}", remote.chain_id, self.chain_id));
+        }
+        // A node without genesis yet can still connect to sync it
+        if let (Some(local), Some(remote)) = (&self.genesis_hash, &remote.genesis_hash) {
+            if local != remote {
+                return Err(format!("Genesis hash {} doesn't match {}", remote, local));
+            }
+        }
+
+        Ok(Session {
+            version: self.version.min(remote.version),
+            features: self
+                .features
+                .iter()
+                .filter(|feature| remote.features.contains(feature))
+                .cloned()
+                .collect(),
+            remote: remote.clone(),
+        })
+    }
+
+    // Exchanges hellos and verdicts over a stream, one JSON message per line. Both sides
+    // learn why a connection was refused instead of failing later on undecodable messages.
+    pub fn exchange<R: BufRead, W: Write>(&self, reader: &mut R, writer: &mut W) -> Result<Session, Error> {
+        write_message(writer, &HandshakeMessage::Hello(self.clone()))?;
+
+        let remote = match read_message(reader)? {
+            HandshakeMessage::Hello(remote) => remote,
+            _ => return Err("Expected hello".to_string()),
+        };
+
+        let session = self.negotiate(&remote);
+        let verdict = match &session {
+            Ok(session) => HandshakeMessage::Accept {
+                version: session.version,
+                features: session.features.clone(),
+            },
+            Err(reason) => HandshakeMessage::Reject { reason: reason.clone() },
+        };
+        write_message(writer, &verdict)?;
+        let session = session?;
+
+        match read_message(reader)? {
+            HandshakeMessage::Accept { .. } => Ok(session),
+            HandshakeMessage::Reject { reason } => Err(format!("Rejected by peer: {}", reason)),
+            HandshakeMessage::Hello(_) => Err("Unexpected hello".to_string()),
+        }
+    }
+}
+
+fn write_message<W: Write>(writer: &mut W, message: &HandshakeMessage) -> Result<(), Error> {
+    let mut line = serde_json::to_vec(message).map_err(|error| error.to_string())?;
+    line.push(b'\n');
+    writer
+        .write_all(&line)
+        .and_then(|_| writer.flush())
+        .map_err(|error| format!("Handshake failed: {}", error))
+}
+
+fn read_message<R: BufRead>(reader: &mut R) -> Result<HandshakeMessage, Error> {
+    let mut line = String::new();
+    reader
+        .take(MAX_HANDSHAKE_SIZE)
+        .read_line(&mut line)
+        .map_err(|error| format!("Handshake failed: {}", error))?;
+
+    if !line.ends_with('\n') {
+        return Err("Handshake message is too long or truncated".to_string());
+    }
+
+    serde_json::from_str(&line).map_err(|error| format!("Malformed handshake: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Amount, GenesisConfig};
+    use ed25519_dalek::Keypair;
+    use std::io::BufReader;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    fn connect(local: Handshake, remote: Handshake) -> (Result<Session, Error>, Result<Session, Error>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            remote.exchange(&mut reader, &mut stream)
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let client = local.exchange(&mut reader, &mut stream);
+
+        (client, server.join().unwrap())
+    }
+
+    #[test]
+    fn test_negotiate_features() {
+        let local = Handshake::new("classnet", Some("genesis".to_string()));
+        let mut remote = Handshake::new("classnet", None);
+        remote.version = PROTOCOL_VERSION + 1;
+        remote.features = vec!["orphans".to_string(), "compact-blocks".to_string()];
+
+        let session = local.negotiate(&remote).unwrap();
+        assert_eq!(session.version, PROTOCOL_VERSION);
+        assert_eq!(session.features, vec!["orphans".to_string()]);
+    }
+
+    #[test]
+    fn test_incompatible_peers() {
+        let local = Handshake::new("classnet", Some("genesis".to_string()));
+
+        assert!(local.negotiate(&Handshake::new("devnet", Some("genesis".to_string()))).is_err());
+        assert!(local.negotiate(&Handshake::new("classnet", Some("other".to_string()))).is_err());
+
+        let mut old = Handshake::new("classnet", None);
+        old.version = 0;
+        assert!(local.negotiate(&old).is_err());
+    }
+
+    #[test]
+    fn test_from_chain_info() {
+        let alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        let chain = |chain_id: &str| {
+            let mut config = GenesisConfig::new(chain_id);
+            config.add_account("alice".to_string(), alice.public, Amount::from_base_units(100));
+            Blockchain::from_genesis(&config).unwrap()
+        };
+        let (class, test) = (chain("class"), chain("test"));
+        let local = Handshake::from_blockchain(&class);
+        assert_eq!(local.chain_id, "class");
+        assert_eq!(local.genesis_hash, class.genesis_hash());
+
+        // Genesis transactions are for one chain, so the genesis blocks differ too
+        let remote = Handshake::from_info(&test.info());
+        assert_ne!(remote.genesis_hash, local.genesis_hash);
+        assert!(local.negotiate(&remote).is_err());
+        // The chain ids alone keep them apart
+        assert!(local.negotiate(&Handshake::new("test", local.genesis_hash.clone())).is_err());
+    }
+
+    #[test]
+    fn test_exchange() {
+        let (client, server) = connect(
+            Handshake::new("classnet", Some("genesis".to_string())),
+            Handshake::new("classnet", Some("genesis".to_string())),
+        );
+
+        assert_eq!(client.unwrap().remote.chain_id, "classnet");
+        assert!(server.is_ok());
+    }
+
+    #[test]
+    fn test_exchange_rejected() {
+        let (client, server) = connect(
+            Handshake::new("classnet", Some("genesis".to_string())),
+            Handshake::new("devnet", Some("genesis".to_string())),
+        );
+
+        assert!(client.unwrap_err().contains("doesn't match"));
+        assert!(server.is_err());
+    }
+
+    #[test]
+    fn test_malformed_handshake() {
+        let local = Handshake::new("classnet", None);
+        let mut output = vec![];
+
+        let mut garbage = BufReader::new(&b"\x00\x01garbage\n"[..]);
+        assert!(local.exchange(&mut garbage, &mut output).is_err());
+
+        let long = vec![b'a'; MAX_HANDSHAKE_SIZE as usize * 2];
+        assert!(local.exchange(&mut BufReader::new(&long[..]), &mut output).is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use crate::network::MDNS_SERVICE_TYPE;
+use crate::types::Error;
+use mdns_sd::{Receiver, ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::net::SocketAddr;
+
+pub struct Mdns {
+    daemon: ServiceDaemon,
+    receiver: Receiver<ServiceEvent>,
+    fullname: String,
+}
+
+impl Mdns {
+    pub fn start(node_id: &str, listen_addr: SocketAddr) -> Result<Self, Error> {
+        let daemon = ServiceDaemon::new().map_err(|error| format!("mDNS error: {}", error))?;
+
+        let host_name = format!("{}.local.", node_id);
+        let properties = [("node_id", node_id)];
+        // Announce every interface address when listening on 0.0.0.0
+        let service = if listen_addr.ip().is_unspecified() {
+            ServiceInfo::new(MDNS_SERVICE_TYPE, node_id, &host_name, "", listen_addr.port(), &properties[..])
+                .map(|service| service.enable_addr_auto())
+        } else {
+            ServiceInfo::new(MDNS_SERVICE_TYPE, node_id, &host_name, listen_addr.ip(), listen_addr.port(), &properties[..])
+        }
+        .map_err(|error| format!("mDNS error: {}", error))?;
+
+        let fullname = service.get_fullname().to_string();
+        daemon
+            .register(service)
+            .map_err(|error| format!("mDNS error: {}", error))?;
+        let receiver = daemon
+            .browse(MDNS_SERVICE_TYPE)
+            .map_err(|error| format!("mDNS error: {}", error))?;
+
+        Ok(Self {
+            daemon,
+            receiver,
+            fullname,
+        })
+    }
+
+    pub fn poll(&self) -> Vec<SocketAddr> {
+        let mut found = vec![];
+        while let Ok(event) = self.receiver.try_recv() {
+            if let ServiceEvent::ServiceResolved(service) = event {
+                if service.get_fullname() == self.fullname {
+                    continue;
+                }
+                for ip in service.get_addresses() {
+                    found.push(SocketAddr::new(*ip, service.get_port()));
+                }
+            }
+        }
+
+        found
+    }
+}
+
+impl Drop for Mdns {
+    fn drop(&mut self) {
+        let _ = self.daemon.shutdown();
+    }
+}
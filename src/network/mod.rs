@@ -0,0 +1,33 @@
+mod codec;
+mod connections;
+mod discovery;
+mod gossip;
+mod handshake;
+#[cfg(feature = "mdns")]
+mod mdns;
+mod node;
+#[cfg(feature = "p2p")]
+mod p2p;
+#[cfg(feature = "p2p")]
+mod p2p_node;
+mod peers;
+
+pub use codec::{
+    checksum, network_magic, DecodeError, Frame, FrameCodec, MessageType, HEADER_SIZE, MAGIC, MAX_PAYLOAD_SIZE,
+};
+pub use connections::{ConnectionManager, DIAL_RETRY_DELAY, MAX_DIAL_RETRY_DELAY};
+pub use discovery::{Discovery, DiscoveryConfig, MDNS_SERVICE_TYPE};
+pub use gossip::{GossipMessage, TxGossip, MAX_INVENTORY, MAX_SEEN, REQUEST_TIMEOUT};
+pub use handshake::{
+    Handshake, HandshakeMessage, Session, MAX_HANDSHAKE_SIZE, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+    SUPPORTED_FEATURES,
+};
+pub use node::{Node, CONNECT_TIMEOUT};
+#[cfg(feature = "p2p")]
+pub use p2p::{P2pEvent, P2pTransport, BLOCKS_TOPIC, SYNC_PROTOCOL, TRANSACTIONS_TOPIC};
+#[cfg(feature = "p2p")]
+pub use p2p_node::P2pNode;
+pub use peers::{
+    Misbehavior, PeerAction, PeerScore, PeerScores, BAN_THRESHOLD, DEFAULT_BAN_DURATION,
+    DISCONNECT_THRESHOLD, SCORE_RECOVERY_INTERVAL,
+};
@@ -0,0 +1,542 @@
+use crate::network::{
+    ConnectionManager, DecodeError, Discovery, Frame, FrameCodec, GossipMessage, Handshake, Misbehavior, MessageType,
+    PeerAction, PeerScores, TxGossip, MAX_INVENTORY,
+};
+use crate::traits::{ChainStore, Hashable};
+use crate::types::{Block, BlockStatus, Blockchain, Chain, Error, Hash, Transaction};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// For dialing a peer and for its side of the handshake
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// A peer that doesn't take a frame within this long is dropped
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Peer {
+    // For shutting the connection down while a write may be stuck
+    stream: TcpStream,
+    // Frames are written one at a time per peer, outside the lock on all peers, so a slow
+    // peer only holds up what is sent to it
+    writer: Arc<Mutex<TcpStream>>,
+    codec: FrameCodec,
+}
+
+struct Inner<S> {
+    blockchain: Arc<Mutex<Blockchain<S>>>,
+    peers: Mutex<HashMap<SocketAddr, Peer>>,
+    gossip: Mutex<TxGossip>,
+    scores: Arc<Mutex<PeerScores>>,
+}
+
+// A node on the TCP network. Connections start with the handshake, then carry frames with
+// JSON payloads: new blocks and transactions are relayed to every other peer, and a block
+// whose parent we don't have makes us ask the peer that sent it for the parent, until the
+// orphans connect. Every connection is read on its own thread; the handle is cheap to
+// clone and share with them.
+pub struct Node<S = Chain<Block>> {
+    inner: Arc<Inner<S>>,
+}
+
+impl<S> Clone for Node<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S: ChainStore + Send + 'static> Node<S> {
+    pub fn new(blockchain: Arc<Mutex<Blockchain<S>>>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                blockchain,
+                peers: Mutex::new(HashMap::new()),
+                gossip: Mutex::new(TxGossip::new()),
+                scores: Arc::new(Mutex::new(PeerScores::new())),
+            }),
+        }
+    }
+
+    pub fn blockchain(&self) -> &Arc<Mutex<Blockchain<S>>> {
+        &self.inner.blockchain
+    }
+
+    pub fn peers(&self) -> Vec<SocketAddr> {
+        let mut peers: Vec<SocketAddr> = self.inner.peers.lock().unwrap().keys().copied().collect();
+        peers.sort();
+        peers
+    }
+
+    // Shared, e.g. with the RpcHandler serving list_peers
+    pub fn scores(&self) -> &Arc<Mutex<PeerScores>> {
+        &self.inner.scores
+    }
+
+    // Accepts peers on `addr` in the background, returns the address actually bound
+    pub fn listen<A: ToSocketAddrs>(&self, addr: A) -> Result<SocketAddr, Error> {
+        let listener = TcpListener::bind(addr).map_err(|error| error.to_string())?;
+        let local = listener.local_addr().map_err(|error| error.to_string())?;
+
+        let node = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let node = node.clone();
+                thread::spawn(move || {
+                    let _ = node.start(stream);
+                });
+            }
+        });
+        Ok(local)
+    }
+
+    // Connects to a peer and returns once the handshake is done
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> Result<SocketAddr, Error> {
+        let addr = addr
+            .to_socket_addrs()
+            .map_err(|error| error.to_string())?
+            .next()
+            .ok_or("Address doesn't resolve")?;
+        let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).map_err(|error| error.to_string())?;
+        self.start(stream)
+    }
+
+    // One round of staying connected: takes in the addresses discovery found since the
+    // last round, notices peers that dropped and dials whatever `connections` says is
+    // due. Meant to be called every second or so.
+    pub fn maintain(&self, discovery: &mut Discovery, connections: &mut ConnectionManager) {
+        let now = Instant::now();
+        for addr in discovery.poll() {
+            connections.add(addr);
+        }
+
+        let peers = self.peers();
+        for addr in connections.connected() {
+            if !peers.contains(&addr) {
+                connections.on_disconnected(addr, now);
+            }
+        }
+
+        for addr in connections.due(now) {
+            if peers.contains(&addr) {
+                connections.on_connected(addr);
+                continue;
+            }
+            match self.connect(addr) {
+                Ok(_) => connections.on_connected(addr),
+                Err(_) => connections.on_failed(addr, now),
+            }
+        }
+    }
+
+    pub fn disconnect(&self, addr: &SocketAddr) {
+        let removed = self.inner.peers.lock().unwrap().remove(addr);
+        if let Some(peer) = removed {
+            let _ = peer.stream.shutdown(Shutdown::Both);
+        }
+        self.inner.gossip.lock().unwrap().remove_peer(addr);
+        self.inner.scores.lock().unwrap().remove_peer(addr);
+    }
+
+    // Queues a transaction of our own and announces it
+    pub fn submit_transaction(&self, transaction: Transaction) -> Result<Hash, Error> {
+        let hash = self.inner.blockchain.lock().unwrap().submit_transaction(transaction.clone())?;
+        self.relay_transaction(transaction);
+        Ok(hash)
+    }
+
+    // Processes a block of our own, e.g. one just mined, and sends it to every peer
+    pub fn submit_block(&self, block: Block) -> Result<BlockStatus, Error> {
+        let (status, relay) = {
+            let mut blockchain = self.inner.blockchain.lock().unwrap();
+            let status = blockchain.process_block(block.clone())?;
+            let relay = relayed_blocks(&blockchain, block, &status);
+            (status, relay)
+        };
+
+        for block in relay {
+            self.broadcast(MessageType::Block, &block, None);
+        }
+        Ok(status)
+    }
+
+    fn start(&self, stream: TcpStream) -> Result<SocketAddr, Error> {
+        let addr = stream.peer_addr().map_err(|error| error.to_string())?;
+        if self.inner.scores.lock().unwrap().is_banned(&addr) {
+            let _ = stream.shutdown(Shutdown::Both);
+            return Err(format!("Peer {} is banned", addr));
+        }
+
+        let handshake = Handshake::from_blockchain(&self.inner.blockchain.lock().unwrap());
+        let mut writer = stream.try_clone().map_err(|error| error.to_string())?;
+        let mut reader = BufReader::new(stream);
+        // A peer that never answers doesn't get to hold the thread
+        reader.get_ref().set_read_timeout(Some(CONNECT_TIMEOUT)).map_err(|error| error.to_string())?;
+        let session = handshake.exchange(&mut reader, &mut writer)?;
+        reader.get_ref().set_read_timeout(None).map_err(|error| error.to_string())?;
+
+        // A side without genesis yet takes the other's, so both end up with the same magic
+        let genesis_hash = handshake.genesis_hash.or(session.remote.genesis_hash);
+        let codec = FrameCodec::for_network(&handshake.chain_id, genesis_hash.as_ref());
+        writer.set_write_timeout(Some(WRITE_TIMEOUT)).map_err(|error| error.to_string())?;
+        let stream = writer.try_clone().map_err(|error| error.to_string())?;
+        self.inner.peers.lock().unwrap().insert(
+            addr,
+            Peer {
+                stream,
+                writer: Arc::new(Mutex::new(writer)),
+                codec: codec.clone(),
+            },
+        );
+        self.inner.gossip.lock().unwrap().add_peer(addr);
+        self.inner.scores.lock().unwrap().add_peer(addr);
+
+        let node = self.clone();
+        thread::spawn(move || node.read_frames(addr, reader, codec));
+        Ok(addr)
+    }
+
+    fn read_frames(&self, addr: SocketAddr, mut reader: BufReader<TcpStream>, codec: FrameCodec) {
+        loop {
+            let misbehavior = match codec.read_frame(&mut reader) {
+                Ok(frame) => self.on_frame(addr, frame).err(),
+                Err(DecodeError::Io(_)) => break,
+                Err(DecodeError::BadChecksum) => Some(Misbehavior::MalformedMessage),
+                // The rest of the frame is still in the stream, there's no finding the next one
+                Err(_) => {
+                    self.inner.scores.lock().unwrap().report(addr, Misbehavior::MalformedMessage);
+                    break;
+                }
+            };
+
+            if let Some(misbehavior) = misbehavior {
+                if self.inner.scores.lock().unwrap().report(addr, misbehavior) != PeerAction::Keep {
+                    break;
+                }
+            }
+        }
+
+        self.disconnect(&addr);
+    }
+
+    fn on_frame(&self, from: SocketAddr, frame: Frame) -> Result<(), Misbehavior> {
+        match frame.message_type {
+            MessageType::TxAnnounce => {
+                let hashes = decode(&frame.payload)?;
+                let reply = self.inner.gossip.lock().unwrap().on_announce(from, hashes)?;
+                self.send_gossip(from, reply);
+            }
+            MessageType::TxRequest => {
+                let hashes = decode(&frame.payload)?;
+                let reply = self.inner.gossip.lock().unwrap().on_request(from, hashes)?;
+                self.send_gossip(from, reply);
+            }
+            MessageType::TxDeliver => {
+                let transactions = decode(&frame.payload)?;
+                let received = self.inner.gossip.lock().unwrap().on_deliver(from, transactions)?;
+                for transaction in received {
+                    // A rejected transaction may just have lost a race with a block, so
+                    // it isn't held against the peer; it's not relayed either
+                    let accepted = self.inner.blockchain.lock().unwrap().submit_transaction(transaction.clone());
+                    if accepted.is_ok() {
+                        self.relay_transaction(transaction);
+                    }
+                }
+            }
+            MessageType::Block => self.on_block(from, decode(&frame.payload)?)?,
+            MessageType::GetBlocks => {
+                let hashes: Vec<Hash> = decode(&frame.payload)?;
+                if hashes.len() > MAX_INVENTORY {
+                    return Err(Misbehavior::Spam);
+                }
+
+                let blocks: Vec<Block> = {
+                    let blockchain = self.inner.blockchain.lock().unwrap();
+                    hashes
+                        .iter()
+                        .filter_map(|hash| {
+                            blockchain
+                                .get_block_by_hash(hash)
+                                .or_else(|| blockchain.side_branches().get(hash))
+                        })
+                        .cloned()
+                        .collect()
+                };
+                if !blocks.is_empty() {
+                    self.send(from, MessageType::Blocks, &blocks);
+                }
+            }
+            MessageType::Blocks => {
+                let blocks: Vec<Block> = decode(&frame.payload)?;
+                if blocks.len() > MAX_INVENTORY {
+                    return Err(Misbehavior::Spam);
+                }
+                for block in blocks {
+                    self.on_block(from, block)?;
+                }
+            }
+            // Consensus messages belong to the BFT engine, which runs its own transport
+            MessageType::Consensus => {}
+            MessageType::Handshake => return Err(Misbehavior::MalformedMessage),
+        }
+
+        Ok(())
+    }
+
+    fn on_block(&self, from: SocketAddr, block: Block) -> Result<(), Misbehavior> {
+        let hash = block.hash();
+        let (status, relay) = {
+            let mut blockchain = self.inner.blockchain.lock().unwrap();
+            // Relayed to us by more than one peer
+            if blockchain.get_block_by_hash(&hash).is_some() || blockchain.side_branches().contains(&hash) {
+                return Ok(());
+            }
+
+            let status = match blockchain.process_block(block.clone()) {
+                Ok(status) => status,
+                Err(error) if error.is_invalid_block() => return Err(Misbehavior::InvalidBlock),
+                // Stale, too far behind, past a checkpoint or ahead of our clock, which an
+                // honest peer can't always tell
+                Err(_) => return Ok(()),
+            };
+            let relay = relayed_blocks(&blockchain, block, &status);
+            (status, relay)
+        };
+
+        if let BlockStatus::Orphan { missing_parent } = status {
+            self.send(from, MessageType::GetBlocks, &vec![missing_parent]);
+        }
+        for block in relay {
+            self.broadcast(MessageType::Block, &block, Some(from));
+        }
+        Ok(())
+    }
+
+    fn relay_transaction(&self, transaction: Transaction) {
+        let messages = self.inner.gossip.lock().unwrap().broadcast(transaction);
+        for (addr, message) in messages {
+            self.send_gossip(addr, Some(message));
+        }
+    }
+
+    fn send_gossip(&self, to: SocketAddr, message: Option<GossipMessage>) {
+        match message {
+            Some(GossipMessage::Announce(hashes)) => self.send(to, MessageType::TxAnnounce, &hashes),
+            Some(GossipMessage::Request(hashes)) => self.send(to, MessageType::TxRequest, &hashes),
+            Some(GossipMessage::Deliver(transactions)) => self.send(to, MessageType::TxDeliver, &transactions),
+            None => {}
+        }
+    }
+
+    fn broadcast<T: Serialize>(&self, message_type: MessageType, message: &T, except: Option<SocketAddr>) {
+        for addr in self.peers() {
+            if Some(addr) != except {
+                self.send(addr, message_type, message);
+            }
+        }
+    }
+
+    // A peer we can't write to is dropped, its reader thread notices the closed socket
+    fn send<T: Serialize>(&self, to: SocketAddr, message_type: MessageType, message: &T) {
+        let payload = match serde_json::to_vec(message) {
+            Ok(payload) => payload,
+            Err(_) => return,
+        };
+        let frame = Frame { message_type, payload };
+
+        let peer = self
+            .inner
+            .peers
+            .lock()
+            .unwrap()
+            .get(&to)
+            .map(|peer| (peer.writer.clone(), peer.codec.clone()));
+        let (writer, codec) = match peer {
+            Some(peer) => peer,
+            None => return,
+        };
+        let sent = codec.write_frame(&mut *writer.lock().unwrap(), &frame).is_ok();
+        if !sent {
+            self.disconnect(&to);
+        }
+    }
+}
+
+fn decode<T: DeserializeOwned>(payload: &[u8]) -> Result<T, Misbehavior> {
+    serde_json::from_slice(payload).map_err(|_| Misbehavior::MalformedMessage)
+}
+
+// The blocks worth passing on after `block` was processed: itself and the orphans it let
+// in, unless it is an orphan too
+fn relayed_blocks<S: ChainStore>(blockchain: &Blockchain<S>, block: Block, status: &BlockStatus) -> Vec<Block> {
+    let attached_orphans = match status {
+        BlockStatus::Appended { attached_orphans }
+        | BlockStatus::SideBranch { attached_orphans, .. }
+        | BlockStatus::Reorganized { attached_orphans, .. } => attached_orphans,
+        BlockStatus::Orphan { .. } => return vec![],
+    };
+
+    let mut blocks = vec![block];
+    blocks.extend(attached_orphans.iter().filter_map(|hash| {
+        blockchain
+            .get_block_by_hash(hash)
+            .or_else(|| blockchain.side_branches().get(hash))
+            .cloned()
+    }));
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Fixtures;
+    use crate::network::DiscoveryConfig;
+    use crate::types::MAX_FUTURE_DRIFT;
+    use crate::consensus::meets_target;
+    use crate::utils::{append_block, mine_block};
+
+    const TEST_TARGET: u128 = u128::MAX >> 8;
+
+    fn wait_for<F: Fn() -> bool>(condition: F) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn node(fixtures: &Fixtures) -> Node {
+        let blockchain = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        Node::new(Arc::new(Mutex::new(blockchain)))
+    }
+
+    #[test]
+    fn test_blocks_and_transactions_propagate() {
+        let mut fixtures = Fixtures::new(1);
+        let miner = node(&fixtures);
+        let mut source = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        for nonce in 0..2 {
+            let block = append_block(&mut source, &mut fixtures, nonce);
+            assert!(miner.submit_block(block).is_ok());
+        }
+
+        // Joins without genesis and has to fetch both blocks under the next one
+        let follower = node(&fixtures);
+        let addr = miner.listen("127.0.0.1:0").unwrap();
+        follower.connect(addr).unwrap();
+        wait_for(|| miner.peers().len() == 1);
+
+        let block = append_block(&mut source, &mut fixtures, 2);
+        assert!(matches!(miner.submit_block(block), Ok(BlockStatus::Appended { .. })));
+        wait_for(|| follower.blockchain().lock().unwrap().len() == 3);
+        assert_eq!(
+            follower.blockchain().lock().unwrap().get_last_block_hash(),
+            source.get_last_block_hash()
+        );
+
+        let keypair = fixtures.keypair();
+        let transaction = Transaction::create_account(fixtures.account_id(), &keypair, fixtures.timestamp());
+        let hash = follower.submit_transaction(transaction).unwrap();
+        wait_for(|| {
+            let blockchain = miner.blockchain().lock().unwrap();
+            blockchain.pending_transactions().iter().any(|pending| pending.hash() == hash)
+        });
+    }
+
+    #[test]
+    fn test_invalid_block_drops_peer() {
+        let mut fixtures = Fixtures::new(1);
+        let honest = node(&fixtures);
+        let mut source = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        assert!(honest.submit_block(append_block(&mut source, &mut fixtures, 0)).is_ok());
+        let addr = honest.listen("127.0.0.1:0").unwrap();
+
+        // Talks the protocol by hand to send a block without its proof of work
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let handshake = Handshake::from_blockchain(&source);
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        handshake.exchange(&mut reader, &mut stream).unwrap();
+        let local = stream.local_addr().unwrap();
+        wait_for(|| honest.peers() == vec![local]);
+
+        let mut block = source.next_block();
+        block.set_timestamp(source.now());
+        block.add_transaction(Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), fixtures.timestamp()));
+        while meets_target(&block.hash(), TEST_TARGET) {
+            block.set_nonce(block.header.nonce + 1);
+        }
+        let frame = Frame {
+            message_type: MessageType::Block,
+            payload: serde_json::to_vec(&block).unwrap(),
+        };
+        handshake.frame_codec().write_frame(&mut stream, &frame).unwrap();
+
+        // Costs the peer enough to be dropped
+        wait_for(|| honest.peers().is_empty());
+        assert_eq!(honest.blockchain().lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_block_ahead_of_clock_keeps_peer() {
+        let mut fixtures = Fixtures::new(1);
+        let honest = node(&fixtures);
+        let mut source = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        assert!(honest.submit_block(append_block(&mut source, &mut fixtures, 0)).is_ok());
+        let addr = honest.listen("127.0.0.1:0").unwrap();
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let handshake = Handshake::from_blockchain(&source);
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        handshake.exchange(&mut reader, &mut stream).unwrap();
+        let local = stream.local_addr().unwrap();
+        wait_for(|| honest.peers() == vec![local]);
+
+        // Valid but too far ahead of the clock, which could as well be ours being behind
+        let mut block = source.next_block();
+        block.set_timestamp(source.now() + MAX_FUTURE_DRIFT + 1);
+        block.add_transaction(Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), fixtures.timestamp()));
+        mine_block(&mut block, source.target());
+        let next = append_block(&mut source, &mut fixtures, 1);
+        for block in &[block, next] {
+            let frame = Frame {
+                message_type: MessageType::Block,
+                payload: serde_json::to_vec(block).unwrap(),
+            };
+            handshake.frame_codec().write_frame(&mut stream, &frame).unwrap();
+        }
+
+        wait_for(|| honest.blockchain().lock().unwrap().len() == 2);
+        assert_eq!(honest.peers(), vec![local]);
+    }
+
+    #[test]
+    fn test_maintain_dials_discovered_peers() {
+        let fixtures = Fixtures::new(1);
+        let (alice, bob) = (node(&fixtures), node(&fixtures));
+        let addr = alice.listen("127.0.0.1:0").unwrap();
+        // Nobody listens here any more
+        let gone = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+        let listen_addr = bob.listen("127.0.0.1:0").unwrap();
+        let mut discovery = Discovery::new(DiscoveryConfig::new(vec![addr.to_string(), gone.to_string()], false));
+        discovery.start("bob", listen_addr).unwrap();
+        let mut connections = ConnectionManager::new();
+
+        bob.maintain(&mut discovery, &mut connections);
+        assert_eq!(bob.peers(), vec![addr]);
+        assert!(connections.is_connected(&addr));
+        assert_eq!(connections.failures(&gone), 1);
+        assert!(connections.next_attempt(&gone).unwrap() > Instant::now());
+
+        // Dropped peers are dialed again once due
+        bob.disconnect(&addr);
+        bob.maintain(&mut discovery, &mut connections);
+        assert!(!connections.is_connected(&addr));
+        assert!(connections.next_attempt(&addr).is_some());
+    }
+}
@@ -0,0 +1,258 @@
+use crate::network::{checksum, DecodeError, Frame, FrameCodec, MessageType};
+use crate::types::Error;
+use libp2p::futures::StreamExt;
+use libp2p::gossipsub::{self, IdentTopic, MessageAcceptance, MessageAuthenticity, MessageId, ValidationMode};
+use libp2p::request_response::{self, OutboundRequestId, ProtocolSupport, ResponseChannel};
+use libp2p::swarm::{NetworkBehaviour, SwarmEvent};
+use libp2p::{noise, tcp, yamux, Multiaddr, PeerId, StreamProtocol, Swarm, SwarmBuilder};
+use std::time::Duration;
+
+pub const BLOCKS_TOPIC: &str = "workshop/blocks";
+pub const TRANSACTIONS_TOPIC: &str = "workshop/transactions";
+pub const SYNC_PROTOCOL: &str = "/workshop/sync/1";
+
+#[derive(NetworkBehaviour)]
+struct Behaviour {
+    gossipsub: gossipsub::Behaviour,
+    sync: request_response::json::Behaviour<Vec<u8>, Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub enum P2pEvent {
+    Listening(Multiaddr),
+    Connected(PeerId),
+    Disconnected(PeerId),
+    // Not forwarded to other peers until reported valid with `validate`
+    Gossip { peer: PeerId, message_id: MessageId, frame: Frame },
+    Request { peer: PeerId, frame: Frame, channel: ResponseChannel<Vec<u8>> },
+    Response { peer: PeerId, frame: Frame },
+    RequestFailed { peer: PeerId, error: Error },
+    Malformed { peer: PeerId, error: DecodeError },
+}
+
+// libp2p alternative to a plain TCP layer: blocks and transactions are broadcast over
+// gossipsub, sync goes over request-response. Both carry the same frames as FrameCodec
+// produces, so messages stay identical whichever transport a node runs. Topics are
+// suffixed with the codec's magic, so networks sharing a LAN don't hear each other.
+pub struct P2pTransport {
+    swarm: Swarm<Behaviour>,
+    codec: FrameCodec,
+    blocks: IdentTopic,
+    transactions: IdentTopic,
+}
+
+impl P2pTransport {
+    pub fn new(codec: FrameCodec) -> Result<Self, Error> {
+        let swarm = SwarmBuilder::with_new_identity()
+            .with_tokio()
+            .with_tcp(tcp::Config::default(), noise::Config::new, yamux::Config::default)
+            .map_err(|error| error.to_string())?
+            .with_behaviour(|key| {
+                let config = gossipsub::ConfigBuilder::default()
+                    .validation_mode(ValidationMode::Strict)
+                    .validate_messages()
+                    .message_id_fn(|message| MessageId::from(hex::encode(checksum(&message.data))))
+                    .build()?;
+                let gossipsub = gossipsub::Behaviour::new(MessageAuthenticity::Signed(key.clone()), config)?;
+                let sync = request_response::json::Behaviour::new(
+                    [(StreamProtocol::new(SYNC_PROTOCOL), ProtocolSupport::Full)],
+                    request_response::Config::default(),
+                );
+                Ok(Behaviour { gossipsub, sync })
+            })
+            .map_err(|error| error.to_string())?
+            .with_swarm_config(|config| config.with_idle_connection_timeout(Duration::from_secs(60)))
+            .build();
+
+        let mut transport = Self {
+            swarm,
+            blocks: network_topic(BLOCKS_TOPIC, &codec),
+            transactions: network_topic(TRANSACTIONS_TOPIC, &codec),
+            codec,
+        };
+        for topic in [transport.blocks.clone(), transport.transactions.clone()].iter() {
+            transport
+                .swarm
+                .behaviour_mut()
+                .gossipsub
+                .subscribe(topic)
+                .map_err(|error| error.to_string())?;
+        }
+
+        Ok(transport)
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        *self.swarm.local_peer_id()
+    }
+
+    pub fn listen(&mut self, addr: &str) -> Result<(), Error> {
+        let addr: Multiaddr = addr.parse().map_err(|_| format!("Invalid address {}", addr))?;
+        self.swarm.listen_on(addr).map(|_| ()).map_err(|error| error.to_string())
+    }
+
+    pub fn dial(&mut self, addr: &str) -> Result<(), Error> {
+        let addr: Multiaddr = addr.parse().map_err(|_| format!("Invalid address {}", addr))?;
+        self.swarm.dial(addr).map_err(|error| error.to_string())
+    }
+
+    pub fn publish(&mut self, frame: &Frame) -> Result<(), Error> {
+        let topic = match frame.message_type {
+            MessageType::TxAnnounce | MessageType::TxRequest | MessageType::TxDeliver => self.transactions.clone(),
+            MessageType::Block | MessageType::Consensus => self.blocks.clone(),
+            MessageType::Handshake => return Err("Handshakes aren't gossiped".to_string()),
+            MessageType::GetBlocks | MessageType::Blocks => return Err("Block requests go over the sync protocol".to_string()),
+        };
+        let bytes = self.codec.encode(frame).map_err(|error| error.to_string())?;
+
+        self.swarm
+            .behaviour_mut()
+            .gossipsub
+            .publish(topic, bytes)
+            .map(|_| ())
+            .map_err(|error| error.to_string())
+    }
+
+    // Verdict on a gossiped message: accepted ones are forwarded, rejected ones count
+    // against the peer's gossipsub score, ignored ones are just dropped
+    pub fn validate(&mut self, message_id: &MessageId, peer: &PeerId, acceptance: MessageAcceptance) {
+        let _ = self
+            .swarm
+            .behaviour_mut()
+            .gossipsub
+            .report_message_validation_result(message_id, peer, acceptance);
+    }
+
+    pub fn request(&mut self, peer: &PeerId, frame: &Frame) -> Result<OutboundRequestId, Error> {
+        let bytes = self.codec.encode(frame).map_err(|error| error.to_string())?;
+        Ok(self.swarm.behaviour_mut().sync.send_request(peer, bytes))
+    }
+
+    pub fn respond(&mut self, channel: ResponseChannel<Vec<u8>>, frame: &Frame) -> Result<(), Error> {
+        let bytes = self.codec.encode(frame).map_err(|error| error.to_string())?;
+        self.swarm
+            .behaviour_mut()
+            .sync
+            .send_response(channel, bytes)
+            .map_err(|_| "Peer closed the sync request".to_string())
+    }
+
+    pub async fn next_event(&mut self) -> P2pEvent {
+        loop {
+            let event = match self.swarm.select_next_some().await {
+                SwarmEvent::NewListenAddr { address, .. } => Some(P2pEvent::Listening(address)),
+                SwarmEvent::ConnectionEstablished { peer_id, .. } => Some(P2pEvent::Connected(peer_id)),
+                SwarmEvent::ConnectionClosed { peer_id, .. } => Some(P2pEvent::Disconnected(peer_id)),
+                SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                    propagation_source,
+                    message_id,
+                    message,
+                })) => {
+                    let event = self.decode(propagation_source, &message.data, |peer, frame| P2pEvent::Gossip {
+                        peer,
+                        message_id: message_id.clone(),
+                        frame,
+                    });
+                    // Garbage is never worth forwarding
+                    if let P2pEvent::Malformed { .. } = event {
+                        self.validate(&message_id, &propagation_source, MessageAcceptance::Reject);
+                    }
+                    Some(event)
+                }
+                SwarmEvent::Behaviour(BehaviourEvent::Sync(request_response::Event::Message { peer, message })) => {
+                    Some(match message {
+                        request_response::Message::Request { request, channel, .. } => {
+                            match self.codec.decode_exact(&request) {
+                                Ok(frame) => P2pEvent::Request { peer, frame, channel },
+                                Err(error) => P2pEvent::Malformed { peer, error },
+                            }
+                        }
+                        request_response::Message::Response { response, .. } => {
+                            self.decode(peer, &response, |peer, frame| P2pEvent::Response { peer, frame })
+                        }
+                    })
+                }
+                SwarmEvent::Behaviour(BehaviourEvent::Sync(request_response::Event::OutboundFailure {
+                    peer,
+                    error,
+                    ..
+                })) => Some(P2pEvent::RequestFailed {
+                    peer,
+                    error: error.to_string(),
+                }),
+                _ => None,
+            };
+
+            if let Some(event) = event {
+                return event;
+            }
+        }
+    }
+
+    fn decode<F: FnOnce(PeerId, Frame) -> P2pEvent>(&self, peer: PeerId, bytes: &[u8], event: F) -> P2pEvent {
+        match self.codec.decode_exact(bytes) {
+            Ok(frame) => event(peer, frame),
+            Err(error) => P2pEvent::Malformed { peer, error },
+        }
+    }
+}
+
+fn network_topic(topic: &str, codec: &FrameCodec) -> IdentTopic {
+    IdentTopic::new(format!("{}/{}", topic, hex::encode(codec.magic())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sync_request_response() {
+        let mut server = P2pTransport::new(FrameCodec::default()).unwrap();
+        let mut client = P2pTransport::new(FrameCodec::default()).unwrap();
+        server.listen("/ip4/127.0.0.1/tcp/0").unwrap();
+
+        let addr = loop {
+            if let P2pEvent::Listening(addr) = server.next_event().await {
+                break addr;
+            }
+        };
+        client.dial(&addr.to_string()).unwrap();
+        let server_id = server.peer_id();
+
+        let response = Frame {
+            message_type: MessageType::TxDeliver,
+            payload: b"transactions".to_vec(),
+        };
+        let served = tokio::spawn(async move {
+            loop {
+                if let P2pEvent::Request { frame, channel, .. } = server.next_event().await {
+                    assert_eq!(frame.message_type, MessageType::TxRequest);
+                    server.respond(channel, &response).unwrap();
+                    // Keep driving the swarm so the response gets flushed
+                    let _ = tokio::time::timeout(Duration::from_millis(500), server.next_event()).await;
+                    break;
+                }
+            }
+        });
+
+        let request = Frame {
+            message_type: MessageType::TxRequest,
+            payload: b"hashes".to_vec(),
+        };
+        let received = tokio::time::timeout(Duration::from_secs(20), async {
+            while !matches!(client.next_event().await, P2pEvent::Connected(peer) if peer == server_id) {}
+            client.request(&server_id, &request).unwrap();
+
+            loop {
+                if let P2pEvent::Response { frame, .. } = client.next_event().await {
+                    break frame;
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(received.payload, b"transactions".to_vec());
+        served.await.unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+use crate::network::{Frame, Handshake, MessageType, P2pEvent, P2pTransport, MAX_INVENTORY};
+use crate::traits::Hashable;
+use crate::types::{Block, BlockStatus, Blockchain, Error, Hash, Transaction};
+use libp2p::gossipsub::MessageAcceptance;
+use libp2p::request_response::ResponseChannel;
+use libp2p::PeerId;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+// The libp2p counterpart of Node: our blocks and transactions are published on the
+// gossipsub topics, and what comes in is applied to the chain first and only forwarded by
+// gossipsub once the chain took it. Orphans make us request their parent from the peer
+// that relayed them over the sync protocol.
+pub struct P2pNode {
+    transport: P2pTransport,
+    blockchain: Arc<Mutex<Blockchain>>,
+}
+
+impl P2pNode {
+    pub fn new(blockchain: Arc<Mutex<Blockchain>>) -> Result<Self, Error> {
+        let codec = Handshake::from_blockchain(&blockchain.lock().unwrap()).frame_codec();
+        Ok(Self {
+            transport: P2pTransport::new(codec)?,
+            blockchain,
+        })
+    }
+
+    pub fn blockchain(&self) -> &Arc<Mutex<Blockchain>> {
+        &self.blockchain
+    }
+
+    // For listening, dialing and the peer id
+    pub fn transport(&mut self) -> &mut P2pTransport {
+        &mut self.transport
+    }
+
+    // Processes a block of our own and publishes it. Without subscribed peers the block
+    // still goes into the chain.
+    pub fn publish_block(&mut self, block: Block) -> Result<BlockStatus, Error> {
+        let status = self.blockchain.lock().unwrap().process_block(block.clone())?;
+        if !matches!(status, BlockStatus::Orphan { .. }) {
+            let _ = self.publish(MessageType::Block, &block);
+        }
+        Ok(status)
+    }
+
+    pub fn publish_transaction(&mut self, transaction: Transaction) -> Result<Hash, Error> {
+        let hash = self.blockchain.lock().unwrap().submit_transaction(transaction.clone())?;
+        let _ = self.publish(MessageType::TxDeliver, &vec![transaction]);
+        Ok(hash)
+    }
+
+    // Drives the swarm, applying gossip and answering block requests on the way. Other
+    // events are handed back for logging and peer management.
+    pub async fn next_event(&mut self) -> P2pEvent {
+        loop {
+            let event = match self.transport.next_event().await {
+                P2pEvent::Request { frame, channel, .. } if frame.message_type == MessageType::GetBlocks => {
+                    let blocks = self.blocks(&frame.payload);
+                    let _ = self.respond(channel, &blocks);
+                    continue;
+                }
+                event => event,
+            };
+
+            match &event {
+                P2pEvent::Gossip { peer, message_id, frame } => {
+                    let acceptance = self.apply(peer, frame);
+                    self.transport.validate(message_id, peer, acceptance);
+                }
+                P2pEvent::Response { peer, frame } if frame.message_type == MessageType::Blocks => {
+                    let blocks: Vec<Block> = serde_json::from_slice(&frame.payload).unwrap_or_default();
+                    for block in blocks.into_iter().take(MAX_INVENTORY) {
+                        self.apply_block(peer, block);
+                    }
+                }
+                _ => {}
+            }
+            return event;
+        }
+    }
+
+    fn apply(&mut self, peer: &PeerId, frame: &Frame) -> MessageAcceptance {
+        match frame.message_type {
+            MessageType::Block => match serde_json::from_slice(&frame.payload) {
+                Ok(block) => self.apply_block(peer, block),
+                Err(_) => MessageAcceptance::Reject,
+            },
+            MessageType::TxDeliver => match serde_json::from_slice::<Vec<Transaction>>(&frame.payload) {
+                Ok(transactions) if transactions.len() <= MAX_INVENTORY => {
+                    let mut blockchain = self.blockchain.lock().unwrap();
+                    // A rejected transaction may just have lost a race with a block, so
+                    // it's dropped without holding it against the peer
+                    let accepted = transactions
+                        .into_iter()
+                        .filter(|transaction| blockchain.submit_transaction(transaction.clone()).is_ok())
+                        .count();
+                    if accepted > 0 {
+                        MessageAcceptance::Accept
+                    } else {
+                        MessageAcceptance::Ignore
+                    }
+                }
+                _ => MessageAcceptance::Reject,
+            },
+            _ => MessageAcceptance::Reject,
+        }
+    }
+
+    fn apply_block(&mut self, peer: &PeerId, block: Block) -> MessageAcceptance {
+        let status = {
+            let mut blockchain = self.blockchain.lock().unwrap();
+            let hash = block.hash();
+            if blockchain.get_block_by_hash(&hash).is_some() || blockchain.side_branches().contains(&hash) {
+                return MessageAcceptance::Ignore;
+            }
+            blockchain.process_block(block)
+        };
+
+        match status {
+            // Can't tell yet whether it's valid, so it isn't forwarded
+            Ok(BlockStatus::Orphan { missing_parent }) => {
+                let _ = self.request_blocks(peer, vec![missing_parent]);
+                MessageAcceptance::Ignore
+            }
+            Ok(_) => MessageAcceptance::Accept,
+            Err(error) if error.is_invalid_block() => MessageAcceptance::Reject,
+            // Not the sender's fault, like a block that's stale or ahead of our clock
+            Err(_) => MessageAcceptance::Ignore,
+        }
+    }
+
+    fn blocks(&self, payload: &[u8]) -> Vec<Block> {
+        let hashes: Vec<Hash> = serde_json::from_slice(payload).unwrap_or_default();
+        let blockchain = self.blockchain.lock().unwrap();
+        hashes
+            .iter()
+            .take(MAX_INVENTORY)
+            .filter_map(|hash| {
+                blockchain
+                    .get_block_by_hash(hash)
+                    .or_else(|| blockchain.side_branches().get(hash))
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn request_blocks(&mut self, peer: &PeerId, hashes: Vec<Hash>) -> Result<(), Error> {
+        let frame = frame(MessageType::GetBlocks, &hashes)?;
+        self.transport.request(peer, &frame).map(|_| ())
+    }
+
+    fn respond(&mut self, channel: ResponseChannel<Vec<u8>>, blocks: &[Block]) -> Result<(), Error> {
+        let frame = frame(MessageType::Blocks, blocks)?;
+        self.transport.respond(channel, &frame)
+    }
+
+    fn publish<T: Serialize + ?Sized>(&mut self, message_type: MessageType, message: &T) -> Result<(), Error> {
+        let frame = frame(message_type, message)?;
+        self.transport.publish(&frame)
+    }
+}
+
+fn frame<T: Serialize + ?Sized>(message_type: MessageType, message: &T) -> Result<Frame, Error> {
+    let payload = serde_json::to_vec(message).map_err(|error| error.to_string())?;
+    Ok(Frame { message_type, payload })
+}
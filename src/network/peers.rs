@@ -0,0 +1,214 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+pub const DISCONNECT_THRESHOLD: i32 = -50;
+pub const BAN_THRESHOLD: i32 = -100;
+pub const DEFAULT_BAN_DURATION: Duration = Duration::from_secs(60 * 60);
+// A point of misbehavior forgiven this often, so a peer that slipped once is back to a
+// clean record after a while but one that keeps at it still crosses the thresholds
+pub const SCORE_RECOVERY_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Misbehavior {
+    InvalidBlock,
+    InvalidTransaction,
+    MalformedMessage,
+    Spam,
+}
+
+impl Misbehavior {
+    pub fn penalty(&self) -> i32 {
+        match self {
+            Misbehavior::InvalidBlock => 50,
+            Misbehavior::InvalidTransaction => 10,
+            Misbehavior::MalformedMessage => 20,
+            Misbehavior::Spam => 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PeerAction {
+    Keep,
+    Disconnect,
+    Ban,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PeerScore {
+    pub score: i32,
+    pub invalid_blocks: u32,
+    pub invalid_transactions: u32,
+    pub malformed_messages: u32,
+    pub spam: u32,
+}
+
+// Misbehavior reports per peer; bans apply to the whole IP so a banned peer can't just
+// reconnect from another port
+#[derive(Debug)]
+pub struct PeerScores {
+    peers: HashMap<SocketAddr, PeerScore>,
+    bans: HashMap<IpAddr, Instant>,
+    ban_duration: Duration,
+    recovery_interval: Duration,
+    last_decay: Instant,
+}
+
+impl Default for PeerScores {
+    fn default() -> Self {
+        Self::with_ban_duration(DEFAULT_BAN_DURATION)
+    }
+}
+
+impl PeerScores {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_ban_duration(ban_duration: Duration) -> Self {
+        Self {
+            peers: HashMap::new(),
+            bans: HashMap::new(),
+            ban_duration,
+            recovery_interval: SCORE_RECOVERY_INTERVAL,
+            last_decay: Instant::now(),
+        }
+    }
+
+    pub fn set_recovery_interval(&mut self, recovery_interval: Duration) {
+        self.recovery_interval = recovery_interval;
+    }
+
+    // Forgives a point of every negative score per recovery interval passed by `now`
+    pub fn decay(&mut self, now: Instant) {
+        let interval = self.recovery_interval.as_nanos().max(1);
+        let steps = now.saturating_duration_since(self.last_decay).as_nanos() / interval;
+        if steps == 0 {
+            return;
+        }
+
+        self.last_decay += self.recovery_interval * steps.min(u32::MAX as u128) as u32;
+        let recovered = steps.min(i32::MAX as u128) as i32;
+        for peer in self.peers.values_mut() {
+            peer.score = peer.score.saturating_add(recovered).min(0);
+        }
+    }
+
+    pub fn add_peer(&mut self, addr: SocketAddr) {
+        self.peers.entry(addr).or_default();
+    }
+
+    pub fn remove_peer(&mut self, addr: &SocketAddr) {
+        self.peers.remove(addr);
+    }
+
+    pub fn get(&self, addr: &SocketAddr) -> Option<&PeerScore> {
+        self.peers.get(addr)
+    }
+
+    pub fn peers(&self) -> Vec<(SocketAddr, PeerScore)> {
+        let mut peers: Vec<(SocketAddr, PeerScore)> = self
+            .peers
+            .iter()
+            .map(|(addr, score)| (*addr, score.clone()))
+            .collect();
+        peers.sort_by_key(|(addr, _)| *addr);
+        peers
+    }
+
+    pub fn report(&mut self, addr: SocketAddr, misbehavior: Misbehavior) -> PeerAction {
+        self.decay(Instant::now());
+        let peer = self.peers.entry(addr).or_default();
+        peer.score -= misbehavior.penalty();
+        match misbehavior {
+            Misbehavior::InvalidBlock => peer.invalid_blocks += 1,
+            Misbehavior::InvalidTransaction => peer.invalid_transactions += 1,
+            Misbehavior::MalformedMessage => peer.malformed_messages += 1,
+            Misbehavior::Spam => peer.spam += 1,
+        }
+
+        if peer.score <= BAN_THRESHOLD {
+            self.bans.insert(addr.ip(), Instant::now() + self.ban_duration);
+            self.peers.remove(&addr);
+            PeerAction::Ban
+        } else if peer.score <= DISCONNECT_THRESHOLD {
+            PeerAction::Disconnect
+        } else {
+            PeerAction::Keep
+        }
+    }
+
+    pub fn is_banned(&self, addr: &SocketAddr) -> bool {
+        matches!(self.bans.get(&addr.ip()), Some(until) if *until > Instant::now())
+    }
+
+    pub fn banned(&self) -> Vec<(IpAddr, Duration)> {
+        let now = Instant::now();
+        let mut banned: Vec<(IpAddr, Duration)> = self
+            .bans
+            .iter()
+            .filter(|(_, until)| **until > now)
+            .map(|(ip, until)| (*ip, *until - now))
+            .collect();
+        banned.sort_by_key(|(ip, _)| *ip);
+        banned
+    }
+
+    pub fn unban(&mut self, ip: &IpAddr) {
+        self.bans.remove(ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_thresholds() {
+        let mut scores = PeerScores::new();
+        let addr: SocketAddr = "10.0.0.1:7000".parse().unwrap();
+        scores.add_peer(addr);
+
+        assert_eq!(scores.report(addr, Misbehavior::MalformedMessage), PeerAction::Keep);
+        assert_eq!(scores.report(addr, Misbehavior::MalformedMessage), PeerAction::Keep);
+        assert_eq!(scores.report(addr, Misbehavior::InvalidTransaction), PeerAction::Disconnect);
+        assert_eq!(scores.get(&addr).unwrap().score, -50);
+        assert_eq!(scores.get(&addr).unwrap().malformed_messages, 2);
+        assert!(!scores.is_banned(&addr));
+
+        assert_eq!(scores.report(addr, Misbehavior::InvalidBlock), PeerAction::Ban);
+        assert!(scores.is_banned(&addr));
+        assert!(scores.is_banned(&"10.0.0.1:7001".parse().unwrap()));
+        assert!(scores.get(&addr).is_none());
+        assert_eq!(scores.banned().len(), 1);
+    }
+
+    #[test]
+    fn test_scores_decay() {
+        let mut scores = PeerScores::new();
+        let start = Instant::now();
+        let addr: SocketAddr = "10.0.0.1:7000".parse().unwrap();
+        scores.report(addr, Misbehavior::MalformedMessage);
+        assert_eq!(scores.get(&addr).unwrap().score, -20);
+
+        scores.decay(start + SCORE_RECOVERY_INTERVAL * 15);
+        assert_eq!(scores.get(&addr).unwrap().score, -5);
+        // Counts stay for the record, scores don't go positive
+        scores.decay(start + SCORE_RECOVERY_INTERVAL * 100);
+        assert_eq!(scores.get(&addr).unwrap().score, 0);
+        assert_eq!(scores.get(&addr).unwrap().malformed_messages, 1);
+    }
+
+    #[test]
+    fn test_ban_expires() {
+        let mut scores = PeerScores::with_ban_duration(Duration::from_secs(0));
+        let addr: SocketAddr = "10.0.0.1:7000".parse().unwrap();
+        scores.report(addr, Misbehavior::InvalidBlock);
+        scores.report(addr, Misbehavior::InvalidBlock);
+
+        assert!(!scores.is_banned(&addr));
+        assert!(scores.banned().is_empty());
+    }
+}
@@ -0,0 +1,81 @@
+use crate::rpc::{RpcError, PARSE_ERROR};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+// Long enough for a node busy sealing a block
+pub const RPC_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Talks to serve_rpc: one request line out, one response line back
+pub struct RpcClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    next_id: u64,
+}
+
+impl RpcClient {
+    pub fn connect(address: &str) -> Result<Self, String> {
+        let stream = TcpStream::connect(address).map_err(|error| format!("Can't reach {}: {}", address, error))?;
+        stream
+            .set_read_timeout(Some(RPC_TIMEOUT))
+            .and_then(|_| stream.try_clone())
+            .map(|writer| Self {
+                reader: BufReader::new(stream),
+                writer,
+                next_id: 0,
+            })
+            .map_err(|error| error.to_string())
+    }
+
+    // The result, or the error the node answered with. Failing to talk to the node at all
+    // comes back as a PARSE_ERROR.
+    pub fn call(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        self.next_id += 1;
+        let request = json!({ "jsonrpc": "2.0", "id": self.next_id, "method": method, "params": params });
+        let transport = |error: std::io::Error| RpcError::new(PARSE_ERROR, &error.to_string());
+        writeln!(self.writer, "{}", request).map_err(transport)?;
+        let mut line = String::new();
+        self.reader.read_line(&mut line).map_err(transport)?;
+
+        let mut response: Value =
+            serde_json::from_str(&line).map_err(|_| RpcError::new(PARSE_ERROR, "Invalid response from the node"))?;
+        match response.get("error") {
+            Some(error) => Err(serde_json::from_value(error.clone())
+                .unwrap_or_else(|_| RpcError::new(PARSE_ERROR, "Invalid response from the node"))),
+            None => Ok(response["result"].take()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::PeerScores;
+    use crate::rpc::{serve_rpc, RpcHandler, CHAIN_ERROR};
+    use crate::types::{Blockchain, Transaction};
+    use crate::utils::append_block_with_tx;
+    use ed25519_dalek::Keypair;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_call() {
+        let mut bc = Blockchain::with_max_target(u128::MAX >> 8);
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let create_alice = Transaction::create_account("alice".to_string(), &keypair, bc.now());
+        assert!(append_block_with_tx(&mut bc, 1, vec![create_alice]).is_ok());
+        let handler = RpcHandler::new(Arc::new(Mutex::new(PeerScores::new()))).with_blockchain(Arc::new(Mutex::new(bc)));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        serve_rpc(listener, Arc::new(handler));
+
+        let mut client = RpcClient::connect(&addr.to_string()).unwrap();
+        let account = client.call("get_account", json!(["alice"])).unwrap();
+        assert_eq!(account["nonce"], "0");
+        assert_eq!(account["public_key"], hex::encode(keypair.public.as_bytes()));
+        let error = client.call("get_account", json!(["bob"])).unwrap_err();
+        assert_eq!(error, RpcError::new(CHAIN_ERROR, "Unknown account"));
+        assert_eq!(client.call("get_chain_height", Value::Null).unwrap()["height"], 0);
+    }
+}
@@ -0,0 +1,76 @@
+use crate::rpc::block_to_json;
+use crate::types::{Block, Hash};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+// Templates a node keeps around for external miners, older ones are forgotten first
+pub const MAX_TEMPLATES: usize = 64;
+
+// Getwork-style mining for processes that don't link against the crate. A miner fetches
+// a template, searches nonces with the block hash rule (the default Blake2s puzzle)
+//
+//   blake2s(option(prev_hash) ++ u128(nonce) ++ u128(timestamp) ++ u32(bits)
+//           ++ option(beneficiary) ++ option(producer) ++ list(transaction hashes)
+//           ++ list(ommer hashes))
+//
+// in the canonical encoding (big endian integers; strings as a u32 length and their
+// bytes; options as 0, or 1 and the value; lists as a u32 count and their items; hashes
+// are hex strings) until the first 32 hex digits of the digest are below `target`, and
+// submits the template id with the nonce.
+#[derive(Debug, Default)]
+pub struct Templates {
+    next_id: u64,
+    templates: BTreeMap<u64, Block>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubmitBlockParams {
+    pub template_id: u64,
+    pub nonce: String,
+}
+
+impl Templates {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    pub fn issue(&mut self, block: Block) -> u64 {
+        self.next_id += 1;
+        self.templates.insert(self.next_id, block);
+        while self.templates.len() > MAX_TEMPLATES {
+            self.templates.pop_first();
+        }
+
+        self.next_id
+    }
+
+    // The template sealed with `nonce`
+    pub fn solve(&self, template_id: u64, nonce: u128) -> Option<Block> {
+        let mut block = self.templates.get(&template_id)?.clone();
+        block.set_nonce(nonce);
+
+        Some(block)
+    }
+
+    // Templates on top of anything but `tip` can't be appended anymore
+    pub fn prune(&mut self, tip: &Option<Hash>) {
+        self.templates.retain(|_, block| &block.header.prev_hash == tip);
+    }
+}
+
+pub fn template_to_json(template_id: u64, block: &Block, target: u128) -> Value {
+    json!({
+        "template_id": template_id,
+        "target": format!("{:032x}", target),
+        "block": block_to_json(block),
+    })
+}
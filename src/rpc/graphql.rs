@@ -0,0 +1,340 @@
+use crate::rpc::{AccountView, TransactionDataView};
+use crate::traits::{Hashable, WorldState};
+use crate::types::{AccountId, Block, Blockchain, Hash, Transaction};
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Json as GraphqlJson, Object, Result, Schema};
+use axum::extract::State;
+use axum::routing::post;
+use axum::{Json, Router};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::net::TcpListener;
+
+// Most blocks or transactions a single list field returns
+pub const MAX_PAGE: u64 = 100;
+// Deepest a query may nest, block -> transactions -> sender -> transactions -> block is 5
+pub const MAX_QUERY_DEPTH: usize = 12;
+
+pub type ChainSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn graphql_schema(blockchain: Arc<Mutex<Blockchain>>) -> ChainSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(blockchain)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .finish()
+}
+
+// POST /graphql with {"query": ..., "variables": ...}
+pub fn graphql_router(schema: ChainSchema) -> Router {
+    Router::new().route("/graphql", post(execute)).with_state(schema)
+}
+
+pub async fn serve_graphql(listener: TcpListener, schema: ChainSchema) -> std::io::Result<()> {
+    axum::serve(listener, graphql_router(schema)).await
+}
+
+async fn execute(State(schema): State<ChainSchema>, Json(request): Json<async_graphql::Request>) -> Json<async_graphql::Response> {
+    Json(schema.execute(request).await)
+}
+
+// Resolvers look things up one at a time rather than holding the lock for the whole query
+fn blockchain<'a>(ctx: &Context<'a>) -> Result<MutexGuard<'a, Blockchain>> {
+    Ok(ctx.data::<Arc<Mutex<Blockchain>>>()?.lock().unwrap())
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    // By height or hash, the tip without either
+    async fn block(&self, ctx: &Context<'_>, height: Option<u64>, hash: Option<Hash>) -> Result<Option<BlockObject>> {
+        let blockchain = blockchain(ctx)?;
+        let block = match (height, hash) {
+            (Some(height), _) => blockchain.get_block_by_height(height),
+            (None, Some(hash)) => blockchain.get_block_by_hash(&hash),
+            (None, None) => blockchain.get_last_block_hash().and_then(|hash| blockchain.get_block_by_hash(&hash)),
+        };
+
+        Ok(block.cloned().map(BlockObject))
+    }
+
+    // Main chain blocks from `from` up, at most MAX_PAGE
+    async fn blocks(&self, ctx: &Context<'_>, #[graphql(default)] from: u64, #[graphql(default = 20)] limit: u64) -> Result<Vec<BlockObject>> {
+        let blockchain = blockchain(ctx)?;
+
+        Ok((from..from.saturating_add(limit.min(MAX_PAGE)))
+            .map_while(|height| blockchain.get_block_by_height(height))
+            .cloned()
+            .map(BlockObject)
+            .collect())
+    }
+
+    // Main chain transactions only, pending ones have no block yet
+    async fn transaction(&self, ctx: &Context<'_>, hash: Hash) -> Result<Option<TransactionObject>> {
+        let blockchain = blockchain(ctx)?;
+
+        Ok(blockchain
+            .get_transaction(&hash)
+            .map(|(transaction, height)| TransactionObject::new(transaction.clone(), height)))
+    }
+
+    // `id` may also be an alias reference like "@alice"
+    async fn account(&self, ctx: &Context<'_>, id: AccountId) -> Result<Option<AccountObject>> {
+        let blockchain = blockchain(ctx)?;
+        let id = match blockchain.resolve_account(&id) {
+            Ok(id) => id,
+            Err(_) => return Ok(None),
+        };
+
+        Ok(blockchain.get_account_by_id(id.clone()).map(|account| AccountObject(AccountView::new(&id, account))))
+    }
+}
+
+pub struct BlockObject(Block);
+
+#[Object(name = "Block")]
+impl BlockObject {
+    async fn hash(&self) -> Hash {
+        self.0.hash()
+    }
+
+    async fn height(&self) -> u64 {
+        self.0.height()
+    }
+
+    async fn prev_hash(&self) -> Option<&Hash> {
+        self.0.prev_hash()
+    }
+
+    // Numbers past 2^53 don't survive JSON, so these are decimal strings as in BlockView
+    async fn nonce(&self) -> String {
+        self.0.nonce().to_string()
+    }
+
+    async fn timestamp(&self) -> String {
+        self.0.timestamp().to_string()
+    }
+
+    async fn bits(&self) -> u32 {
+        self.0.bits()
+    }
+
+    async fn beneficiary(&self) -> Option<&AccountId> {
+        self.0.beneficiary()
+    }
+
+    async fn producer(&self) -> Option<&AccountId> {
+        self.0.producer()
+    }
+
+    async fn tx_root(&self) -> &Hash {
+        &self.0.header().tx_root
+    }
+
+    async fn ommers(&self) -> Vec<Hash> {
+        self.0.ommers().iter().map(|ommer| ommer.hash()).collect()
+    }
+
+    async fn transactions(&self) -> Vec<TransactionObject> {
+        self.0
+            .transactions()
+            .iter()
+            .map(|transaction| TransactionObject::new(transaction.clone(), self.0.height()))
+            .collect()
+    }
+
+    async fn parent(&self, ctx: &Context<'_>) -> Result<Option<BlockObject>> {
+        let blockchain = blockchain(ctx)?;
+
+        Ok(self
+            .0
+            .prev_hash()
+            .and_then(|hash| blockchain.get_block_by_hash(hash))
+            .cloned()
+            .map(BlockObject))
+    }
+}
+
+pub struct TransactionObject {
+    transaction: Transaction,
+    height: u64,
+}
+
+impl TransactionObject {
+    fn new(transaction: Transaction, height: u64) -> Self {
+        Self { transaction, height }
+    }
+}
+
+#[Object(name = "Transaction")]
+impl TransactionObject {
+    async fn hash(&self) -> Hash {
+        self.transaction.hash()
+    }
+
+    async fn from(&self) -> Option<&AccountId> {
+        self.transaction.from()
+    }
+
+    async fn nonce(&self) -> String {
+        self.transaction.nonce().to_string()
+    }
+
+    async fn timestamp(&self) -> String {
+        self.transaction.timestamp.to_string()
+    }
+
+    // As in TransactionView, tagged by "type"
+    async fn data(&self) -> GraphqlJson<TransactionDataView> {
+        GraphqlJson(self.transaction.data().into())
+    }
+
+    async fn height(&self) -> u64 {
+        self.height
+    }
+
+    async fn block(&self, ctx: &Context<'_>) -> Result<Option<BlockObject>> {
+        let blockchain = blockchain(ctx)?;
+
+        Ok(blockchain.get_block_by_height(self.height).cloned().map(BlockObject))
+    }
+
+    // The account as it is now, not as it was when the transaction ran
+    async fn sender(&self, ctx: &Context<'_>) -> Result<Option<AccountObject>> {
+        let blockchain = blockchain(ctx)?;
+
+        Ok(self.transaction.from().and_then(|id| {
+            blockchain
+                .get_account_by_id(id.clone())
+                .map(|account| AccountObject(AccountView::new(id, account)))
+        }))
+    }
+}
+
+pub struct AccountObject(AccountView);
+
+#[Object(name = "Account")]
+impl AccountObject {
+    async fn id(&self) -> &AccountId {
+        &self.0.account_id
+    }
+
+    async fn account_type(&self) -> &str {
+        &self.0.account_type
+    }
+
+    async fn balance(&self) -> &str {
+        &self.0.balance
+    }
+
+    async fn formatted_balance(&self) -> &str {
+        &self.0.formatted_balance
+    }
+
+    async fn public_key(&self) -> &str {
+        &self.0.public_key
+    }
+
+    async fn nonce(&self) -> &str {
+        &self.0.nonce
+    }
+
+    // Main chain transactions sending from or to the account, newest first
+    async fn transactions(&self, ctx: &Context<'_>, #[graphql(default = 20)] limit: u64) -> Result<Vec<TransactionObject>> {
+        let blockchain = blockchain(ctx)?;
+
+        Ok(blockchain
+            .account_history(&self.0.account_id)
+            .iter()
+            .rev()
+            .take(limit.min(MAX_PAGE) as usize)
+            .filter_map(|hash| blockchain.get_transaction(hash))
+            .map(|(transaction, height)| TransactionObject::new(transaction.clone(), height))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockClock;
+    use crate::types::{Transaction, TransactionData};
+    use crate::utils::append_block_with_tx;
+    use ed25519_dalek::{Keypair, Signer};
+    use serde_json::json;
+
+    fn schema() -> (ChainSchema, Hash) {
+        let mut blockchain = Blockchain::with_max_target(u128::MAX >> 8).with_clock(MockClock::new(1));
+        let alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        let create_alice = Transaction::create_account("alice".to_string(), &alice, 0);
+        let create_bob = Transaction::create_account("bob".to_string(), &alice, 0);
+        let mut mint = Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: "alice".to_string(),
+                amount: 100,
+            },
+            None,
+            0,
+        );
+        mint.sign(Some(alice.sign(&mint.signing_message())));
+        assert!(append_block_with_tx(&mut blockchain, 1, vec![create_alice, create_bob, mint]).is_ok());
+
+        let mut transfer = Transaction::new(
+            TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 10,
+            },
+            Some("alice".to_string()),
+            1,
+        );
+        transfer.set_nonce(1);
+        transfer.sign(Some(alice.sign(&transfer.signing_message())));
+        let hash = transfer.hash();
+        assert!(append_block_with_tx(&mut blockchain, 2, vec![transfer]).is_ok());
+
+        (graphql_schema(Arc::new(Mutex::new(blockchain))), hash)
+    }
+
+    async fn query(schema: &ChainSchema, query: &str) -> serde_json::Value {
+        let response = schema.execute(query).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data.into_json().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_nested_queries() {
+        let (schema, hash) = schema();
+
+        let tip = query(&schema, "{ block { height parent { height } transactions { hash data sender { id balance } } } }").await;
+        assert_eq!(
+            tip["block"],
+            json!({
+                "height": 1,
+                "parent": { "height": 0 },
+                "transactions": [{
+                    "hash": hash,
+                    "data": { "type": "transfer", "to": "bob", "amount": "10" },
+                    "sender": { "id": "alice", "balance": "90" }
+                }]
+            })
+        );
+
+        let transaction = query(&schema, &format!("{{ transaction(hash: \"{}\") {{ height block {{ height }} }} }}", hash)).await;
+        assert_eq!(transaction["transaction"], json!({ "height": 1, "block": { "height": 1 } }));
+        assert_eq!(query(&schema, "{ transaction(hash: \"nope\") { height } }").await["transaction"], json!(null));
+
+        let bob = query(&schema, "{ account(id: \"bob\") { balance transactions { height from } } }").await;
+        assert_eq!(bob["account"]["balance"], "10");
+        assert_eq!(bob["account"]["transactions"][0], json!({ "height": 1, "from": "alice" }));
+        assert_eq!(query(&schema, "{ account(id: \"carol\") { id } }").await["account"], json!(null));
+
+        let blocks = query(&schema, "{ blocks(from: 1, limit: 5) { height } }").await;
+        assert_eq!(blocks["blocks"], json!([{ "height": 1 }]));
+    }
+
+    #[tokio::test]
+    async fn test_query_depth_is_limited() {
+        let (schema, _) = schema();
+        let nested = format!("{{ block {} height {} }}", "{ parent ".repeat(MAX_QUERY_DEPTH), "}".repeat(MAX_QUERY_DEPTH));
+
+        assert!(!schema.execute(nested.as_str()).await.errors.is_empty());
+    }
+}
@@ -0,0 +1,395 @@
+use crate::rpc::{AccountView, BlockView, ChainEvent, SubmitTransactionRequest, Subscriptions, Topic};
+use crate::rpc::{TransactionDataView, TransactionView};
+use crate::traits::WorldState;
+use crate::types::Blockchain;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("workshop");
+}
+
+use proto::chain_server::{Chain, ChainServer};
+
+pub const BLOCK_STREAM_BUFFER: usize = 16;
+
+impl From<TransactionDataView> for proto::transaction::Data {
+    fn from(data: TransactionDataView) -> Self {
+        match data {
+            TransactionDataView::CreateAccount { account_id, public_key } => {
+                proto::transaction::Data::CreateAccount(proto::CreateAccount { account_id, public_key })
+            }
+            TransactionDataView::MintInitialSupply { to, amount } => {
+                proto::transaction::Data::MintInitialSupply(proto::MintInitialSupply { to, amount })
+            }
+            TransactionDataView::Transfer { to, amount } => {
+                proto::transaction::Data::Transfer(proto::Transfer { to, amount })
+            }
+            TransactionDataView::RegisterAlias { alias, owner } => {
+                proto::transaction::Data::RegisterAlias(proto::RegisterAlias { alias, owner })
+            }
+            TransactionDataView::TransferAlias { alias, to } => {
+                proto::transaction::Data::TransferAlias(proto::TransferAlias { alias, to })
+            }
+            TransactionDataView::SetInheritance {
+                beneficiary,
+                inactive_blocks,
+            } => proto::transaction::Data::SetInheritance(proto::SetInheritance {
+                beneficiary,
+                inactive_blocks,
+            }),
+            TransactionDataView::ClaimInheritance { owner } => {
+                proto::transaction::Data::ClaimInheritance(proto::ClaimInheritance { owner })
+            }
+            TransactionDataView::CreateStandingOrder {
+                payee,
+                amount,
+                interval,
+                cap,
+            } => proto::transaction::Data::CreateStandingOrder(proto::CreateStandingOrder {
+                payee,
+                amount,
+                interval,
+                cap,
+            }),
+            TransactionDataView::CancelStandingOrder { payee } => {
+                proto::transaction::Data::CancelStandingOrder(proto::CancelStandingOrder { payee })
+            }
+            TransactionDataView::SetSpendingLimit {
+                account,
+                limit,
+                epoch_blocks,
+            } => proto::transaction::Data::SetSpendingLimit(proto::SetSpendingLimit {
+                account,
+                limit,
+                epoch_blocks,
+            }),
+            TransactionDataView::SetGuardians {
+                account,
+                guardians,
+                threshold,
+            } => proto::transaction::Data::SetGuardians(proto::SetGuardians {
+                account,
+                guardians,
+                threshold,
+            }),
+            TransactionDataView::ApproveRecovery { account, new_key } => {
+                proto::transaction::Data::ApproveRecovery(proto::ApproveRecovery { account, new_key })
+            }
+            TransactionDataView::CancelRecovery { account } => {
+                proto::transaction::Data::CancelRecovery(proto::CancelRecovery { account })
+            }
+            TransactionDataView::CompleteRecovery { account } => {
+                proto::transaction::Data::CompleteRecovery(proto::CompleteRecovery { account })
+            }
+            TransactionDataView::Coinbase { to, amount } => {
+                proto::transaction::Data::Coinbase(proto::Coinbase { to, amount })
+            }
+        }
+    }
+}
+
+impl From<proto::submit_transaction_request::Data> for TransactionDataView {
+    fn from(data: proto::submit_transaction_request::Data) -> Self {
+        match data {
+            proto::submit_transaction_request::Data::CreateAccount(proto::CreateAccount { account_id, public_key }) => {
+                TransactionDataView::CreateAccount { account_id, public_key }
+            }
+            proto::submit_transaction_request::Data::MintInitialSupply(proto::MintInitialSupply { to, amount }) => {
+                TransactionDataView::MintInitialSupply { to, amount }
+            }
+            proto::submit_transaction_request::Data::Transfer(proto::Transfer { to, amount }) => {
+                TransactionDataView::Transfer { to, amount }
+            }
+            proto::submit_transaction_request::Data::RegisterAlias(proto::RegisterAlias { alias, owner }) => {
+                TransactionDataView::RegisterAlias { alias, owner }
+            }
+            proto::submit_transaction_request::Data::TransferAlias(proto::TransferAlias { alias, to }) => {
+                TransactionDataView::TransferAlias { alias, to }
+            }
+            proto::submit_transaction_request::Data::SetInheritance(proto::SetInheritance {
+                beneficiary,
+                inactive_blocks,
+            }) => TransactionDataView::SetInheritance {
+                beneficiary,
+                inactive_blocks,
+            },
+            proto::submit_transaction_request::Data::ClaimInheritance(proto::ClaimInheritance { owner }) => {
+                TransactionDataView::ClaimInheritance { owner }
+            }
+            proto::submit_transaction_request::Data::CreateStandingOrder(proto::CreateStandingOrder {
+                payee,
+                amount,
+                interval,
+                cap,
+            }) => TransactionDataView::CreateStandingOrder {
+                payee,
+                amount,
+                interval,
+                cap,
+            },
+            proto::submit_transaction_request::Data::CancelStandingOrder(proto::CancelStandingOrder { payee }) => {
+                TransactionDataView::CancelStandingOrder { payee }
+            }
+            proto::submit_transaction_request::Data::SetSpendingLimit(proto::SetSpendingLimit {
+                account,
+                limit,
+                epoch_blocks,
+            }) => TransactionDataView::SetSpendingLimit {
+                account,
+                limit,
+                epoch_blocks,
+            },
+            proto::submit_transaction_request::Data::SetGuardians(proto::SetGuardians {
+                account,
+                guardians,
+                threshold,
+            }) => TransactionDataView::SetGuardians {
+                account,
+                guardians,
+                threshold,
+            },
+            proto::submit_transaction_request::Data::ApproveRecovery(proto::ApproveRecovery { account, new_key }) => {
+                TransactionDataView::ApproveRecovery { account, new_key }
+            }
+            proto::submit_transaction_request::Data::CancelRecovery(proto::CancelRecovery { account }) => {
+                TransactionDataView::CancelRecovery { account }
+            }
+            proto::submit_transaction_request::Data::CompleteRecovery(proto::CompleteRecovery { account }) => {
+                TransactionDataView::CompleteRecovery { account }
+            }
+            proto::submit_transaction_request::Data::Coinbase(proto::Coinbase { to, amount }) => {
+                TransactionDataView::Coinbase { to, amount }
+            }
+        }
+    }
+}
+
+impl From<TransactionView> for proto::Transaction {
+    fn from(view: TransactionView) -> Self {
+        Self {
+            hash: view.hash,
+            from: view.from,
+            nonce: view.nonce,
+            timestamp: view.timestamp,
+            data: Some(view.data.into()),
+        }
+    }
+}
+
+impl From<BlockView> for proto::Block {
+    fn from(view: BlockView) -> Self {
+        Self {
+            hash: view.hash,
+            height: Some(view.height),
+            prev_hash: view.prev_hash,
+            nonce: view.nonce,
+            beneficiary: view.beneficiary,
+            producer: view.producer,
+            transactions: view.transactions.into_iter().map(Into::into).collect(),
+            ommers: view.ommers,
+            timestamp: view.timestamp,
+            bits: view.bits,
+            tx_root: view.tx_root,
+            ommers_root: view.ommers_root,
+        }
+    }
+}
+
+impl From<AccountView> for proto::Account {
+    fn from(view: AccountView) -> Self {
+        Self {
+            account_id: view.account_id,
+            account_type: view.account_type,
+            balance: view.balance,
+            formatted_balance: view.formatted_balance,
+            public_key: view.public_key,
+            nonce: view.nonce,
+        }
+    }
+}
+
+// gRPC counterpart of the REST and JSON-RPC surfaces, generated from proto/workshop.proto
+pub struct ChainService {
+    blockchain: Arc<Mutex<Blockchain>>,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+}
+
+impl ChainService {
+    pub fn new(blockchain: Arc<Mutex<Blockchain>>, subscriptions: Arc<Mutex<Subscriptions>>) -> Self {
+        Self {
+            blockchain,
+            subscriptions,
+        }
+    }
+
+    pub fn into_server(self) -> ChainServer<Self> {
+        ChainServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl Chain for ChainService {
+    type SubscribeBlocksStream = Pin<Box<dyn Stream<Item = Result<proto::Block, Status>> + Send>>;
+
+    async fn get_chain_info(
+        &self,
+        _request: Request<proto::GetChainInfoRequest>,
+    ) -> Result<Response<proto::ChainInfo>, Status> {
+        let info = self.blockchain.lock().unwrap().info();
+
+        Ok(Response::new(proto::ChainInfo {
+            height: info.height,
+            tip_hash: info.tip,
+            genesis_hash: info.genesis_hash,
+            chain_id: info.chain_id,
+        }))
+    }
+
+    async fn get_block(&self, request: Request<proto::GetBlockRequest>) -> Result<Response<proto::Block>, Status> {
+        let height = request.into_inner().height;
+        let blockchain = self.blockchain.lock().unwrap();
+
+        match blockchain.get_block_by_height(height) {
+            Some(block) => Ok(Response::new(BlockView::new(block).into())),
+            None => Err(Status::not_found("Block not found")),
+        }
+    }
+
+    async fn get_account(
+        &self,
+        request: Request<proto::GetAccountRequest>,
+    ) -> Result<Response<proto::Account>, Status> {
+        let account_id = request.into_inner().account_id;
+        let blockchain = self.blockchain.lock().unwrap();
+
+        match blockchain.get_account_by_id(account_id.clone()) {
+            Some(account) => Ok(Response::new(AccountView::new(&account_id, account).into())),
+            None => Err(Status::not_found("Account not found")),
+        }
+    }
+
+    async fn submit_transaction(
+        &self,
+        request: Request<proto::SubmitTransactionRequest>,
+    ) -> Result<Response<proto::SubmitTransactionResponse>, Status> {
+        if self.blockchain.lock().unwrap().is_read_only() {
+            return Err(Status::permission_denied("Node is a read-only replica"));
+        }
+        let request = request.into_inner();
+        let data = request
+            .data
+            .ok_or_else(|| Status::invalid_argument("Missing transaction data"))?;
+        let transaction = SubmitTransactionRequest {
+            from: request.from,
+            nonce: request.nonce,
+            chain_id: request.chain_id,
+            timestamp: request.timestamp,
+            data: data.into(),
+            signature: request.signature,
+        }
+        .to_transaction()
+        .map_err(Status::invalid_argument)?;
+
+        let hash = self
+            .blockchain
+            .lock()
+            .unwrap()
+            .submit_transaction(transaction.clone())
+            .map_err(Status::failed_precondition)?;
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .publish(&ChainEvent::PendingTransaction(transaction));
+
+        Ok(Response::new(proto::SubmitTransactionResponse { hash }))
+    }
+
+    async fn subscribe_blocks(
+        &self,
+        _request: Request<proto::SubscribeBlocksRequest>,
+    ) -> Result<Response<Self::SubscribeBlocksStream>, Status> {
+        let (_, notifications) = self.subscriptions.lock().unwrap().subscribe(Topic::NewBlocks);
+        let (sender, receiver) = tokio::sync::mpsc::channel(BLOCK_STREAM_BUFFER);
+
+        // Subscriptions deliver over a blocking channel, forward until the client goes away
+        tokio::task::spawn_blocking(move || {
+            for notification in notifications.iter() {
+                let block = serde_json::from_value::<BlockView>(notification["params"]["result"].clone())
+                    .map(proto::Block::from)
+                    .map_err(|error| Status::internal(error.to_string()));
+                if sender.blocking_send(block).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Block, Transaction};
+    use crate::utils::append_block_with_tx;
+    use ed25519_dalek::Keypair;
+    use tokio_stream::StreamExt;
+
+    fn service() -> ChainService {
+        let mut blockchain = Blockchain::with_max_target(u128::MAX >> 8);
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let create_alice = Transaction::create_account("alice".to_string(), &keypair, 0);
+        assert!(append_block_with_tx(&mut blockchain, 1, vec![create_alice]).is_ok());
+
+        ChainService::new(
+            Arc::new(Mutex::new(blockchain)),
+            Arc::new(Mutex::new(Subscriptions::new())),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_queries() {
+        let service = service();
+
+        let info = service.get_chain_info(Request::new(proto::GetChainInfoRequest {})).await.unwrap();
+        assert_eq!(info.get_ref().height, 0);
+        assert_eq!(info.get_ref().tip_hash, info.get_ref().genesis_hash);
+        assert_eq!(info.get_ref().chain_id, "");
+
+        let block = service.get_block(Request::new(proto::GetBlockRequest { height: 0 })).await.unwrap();
+        assert!(matches!(
+            block.get_ref().transactions[0].data,
+            Some(proto::transaction::Data::CreateAccount(_))
+        ));
+        assert!(service.get_block(Request::new(proto::GetBlockRequest { height: 1 })).await.is_err());
+
+        let account = service
+            .get_account(Request::new(proto::GetAccountRequest {
+                account_id: "alice".to_string(),
+            }))
+            .await
+            .unwrap();
+        assert_eq!(account.get_ref().balance, "0");
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_blocks() {
+        let service = service();
+        let mut stream = service
+            .subscribe_blocks(Request::new(proto::SubscribeBlocksRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let mut block = Block::new(None);
+        block.set_height(7);
+        service.subscriptions.lock().unwrap().publish(&ChainEvent::NewBlock { height: 7, block });
+
+        let block = stream.next().await.unwrap().unwrap();
+        assert_eq!(block.height, Some(7));
+    }
+}
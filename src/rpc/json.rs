@@ -0,0 +1,497 @@
+use crate::traits::{ChainStore, Hashable};
+use crate::types::{
+    Account, AccountId, Amount, Balance, Block, Blockchain, BlockchainError, ChainStats, Error, Hash, SpendingLimit, Transaction,
+    TransactionData,
+};
+use ed25519_dalek::{PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::convert::TryFrom;
+
+// Wire representations shared by the RPC transports. u128 values go out as strings,
+// JSON numbers can't hold them in most clients.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransactionDataView {
+    CreateAccount { account_id: AccountId, public_key: String },
+    MintInitialSupply { to: AccountId, amount: String },
+    Transfer { to: AccountId, amount: String },
+    RegisterAlias { alias: String, owner: AccountId },
+    TransferAlias { alias: String, to: AccountId },
+    SetInheritance { beneficiary: AccountId, inactive_blocks: u64 },
+    ClaimInheritance { owner: AccountId },
+    CreateStandingOrder { payee: AccountId, amount: String, interval: u64, cap: String },
+    CancelStandingOrder { payee: AccountId },
+    // No limit lifts it, the epoch only counts with one
+    SetSpendingLimit { account: AccountId, limit: Option<String>, epoch_blocks: u64 },
+    SetGuardians { account: AccountId, guardians: Vec<String>, threshold: u64 },
+    ApproveRecovery { account: AccountId, new_key: String },
+    CancelRecovery { account: AccountId },
+    CompleteRecovery { account: AccountId },
+    Coinbase { to: AccountId, amount: String },
+}
+
+impl From<&TransactionData> for TransactionDataView {
+    fn from(data: &TransactionData) -> Self {
+        match data {
+            TransactionData::CreateAccount(account_id, public_key) => TransactionDataView::CreateAccount {
+                account_id: account_id.clone(),
+                public_key: hex::encode(public_key.as_bytes()),
+            },
+            TransactionData::MintInitialSupply { to, amount } => TransactionDataView::MintInitialSupply {
+                to: to.clone(),
+                amount: amount.to_string(),
+            },
+            TransactionData::Transfer { to, amount } => TransactionDataView::Transfer {
+                to: to.clone(),
+                amount: amount.to_string(),
+            },
+            TransactionData::RegisterAlias { alias, owner } => TransactionDataView::RegisterAlias {
+                alias: alias.clone(),
+                owner: owner.clone(),
+            },
+            TransactionData::TransferAlias { alias, to } => TransactionDataView::TransferAlias {
+                alias: alias.clone(),
+                to: to.clone(),
+            },
+            TransactionData::SetInheritance {
+                beneficiary,
+                inactive_blocks,
+            } => TransactionDataView::SetInheritance {
+                beneficiary: beneficiary.clone(),
+                inactive_blocks: *inactive_blocks,
+            },
+            TransactionData::ClaimInheritance { owner } => TransactionDataView::ClaimInheritance { owner: owner.clone() },
+            TransactionData::CreateStandingOrder {
+                payee,
+                amount,
+                interval,
+                cap,
+            } => TransactionDataView::CreateStandingOrder {
+                payee: payee.clone(),
+                amount: amount.to_string(),
+                interval: *interval,
+                cap: cap.to_string(),
+            },
+            TransactionData::CancelStandingOrder { payee } => {
+                TransactionDataView::CancelStandingOrder { payee: payee.clone() }
+            }
+            TransactionData::SetSpendingLimit { account, limit } => TransactionDataView::SetSpendingLimit {
+                account: account.clone(),
+                limit: limit.map(|limit| limit.amount.to_string()),
+                epoch_blocks: limit.map_or(0, |limit| limit.epoch_blocks),
+            },
+            TransactionData::SetGuardians {
+                account,
+                guardians,
+                threshold,
+            } => TransactionDataView::SetGuardians {
+                account: account.clone(),
+                guardians: guardians.iter().map(|key| hex::encode(key.as_bytes())).collect(),
+                threshold: *threshold as u64,
+            },
+            TransactionData::ApproveRecovery { account, new_key } => TransactionDataView::ApproveRecovery {
+                account: account.clone(),
+                new_key: hex::encode(new_key.as_bytes()),
+            },
+            TransactionData::CancelRecovery { account } => {
+                TransactionDataView::CancelRecovery { account: account.clone() }
+            }
+            TransactionData::CompleteRecovery { account } => {
+                TransactionDataView::CompleteRecovery { account: account.clone() }
+            }
+            TransactionData::Coinbase { to, amount } => TransactionDataView::Coinbase {
+                to: to.clone(),
+                amount: amount.to_string(),
+            },
+        }
+    }
+}
+
+impl TryFrom<&TransactionDataView> for TransactionData {
+    type Error = Error;
+
+    fn try_from(view: &TransactionDataView) -> Result<Self, Self::Error> {
+        // Base units, or coins when suffixed with the unit ("1.5 COIN")
+        let parse_amount = |amount: &str| amount.parse::<Amount>().map(Balance::from);
+        let parse_key = |key: &str| {
+            let bytes = hex::decode(key).map_err(|_| "Invalid public key".to_string())?;
+            PublicKey::from_bytes(&bytes).map_err(|_| "Invalid public key".to_string())
+        };
+
+        match view {
+            TransactionDataView::CreateAccount { account_id, public_key } => {
+                Ok(TransactionData::CreateAccount(account_id.clone(), parse_key(public_key)?))
+            }
+            TransactionDataView::MintInitialSupply { to, amount } => Ok(TransactionData::MintInitialSupply {
+                to: to.clone(),
+                amount: parse_amount(amount)?,
+            }),
+            TransactionDataView::Transfer { to, amount } => Ok(TransactionData::Transfer {
+                to: to.clone(),
+                amount: parse_amount(amount)?,
+            }),
+            TransactionDataView::RegisterAlias { alias, owner } => Ok(TransactionData::RegisterAlias {
+                alias: alias.clone(),
+                owner: owner.clone(),
+            }),
+            TransactionDataView::TransferAlias { alias, to } => Ok(TransactionData::TransferAlias {
+                alias: alias.clone(),
+                to: to.clone(),
+            }),
+            TransactionDataView::SetInheritance {
+                beneficiary,
+                inactive_blocks,
+            } => Ok(TransactionData::SetInheritance {
+                beneficiary: beneficiary.clone(),
+                inactive_blocks: *inactive_blocks,
+            }),
+            TransactionDataView::ClaimInheritance { owner } => {
+                Ok(TransactionData::ClaimInheritance { owner: owner.clone() })
+            }
+            TransactionDataView::CreateStandingOrder {
+                payee,
+                amount,
+                interval,
+                cap,
+            } => Ok(TransactionData::CreateStandingOrder {
+                payee: payee.clone(),
+                amount: parse_amount(amount)?,
+                interval: *interval,
+                cap: parse_amount(cap)?,
+            }),
+            TransactionDataView::CancelStandingOrder { payee } => {
+                Ok(TransactionData::CancelStandingOrder { payee: payee.clone() })
+            }
+            TransactionDataView::SetSpendingLimit {
+                account,
+                limit,
+                epoch_blocks,
+            } => Ok(TransactionData::SetSpendingLimit {
+                account: account.clone(),
+                limit: match limit {
+                    Some(amount) => Some(SpendingLimit {
+                        amount: parse_amount(amount)?,
+                        epoch_blocks: *epoch_blocks,
+                    }),
+                    None => None,
+                },
+            }),
+            TransactionDataView::SetGuardians {
+                account,
+                guardians,
+                threshold,
+            } => Ok(TransactionData::SetGuardians {
+                account: account.clone(),
+                guardians: guardians.iter().map(|key| parse_key(key)).collect::<Result<_, _>>()?,
+                threshold: *threshold as usize,
+            }),
+            TransactionDataView::ApproveRecovery { account, new_key } => Ok(TransactionData::ApproveRecovery {
+                account: account.clone(),
+                new_key: parse_key(new_key)?,
+            }),
+            TransactionDataView::CancelRecovery { account } => {
+                Ok(TransactionData::CancelRecovery { account: account.clone() })
+            }
+            TransactionDataView::CompleteRecovery { account } => {
+                Ok(TransactionData::CompleteRecovery { account: account.clone() })
+            }
+            TransactionDataView::Coinbase { to, amount } => Ok(TransactionData::Coinbase {
+                to: to.clone(),
+                amount: parse_amount(amount)?,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransactionView {
+    pub hash: Hash,
+    pub from: Option<AccountId>,
+    pub nonce: String,
+    pub timestamp: String,
+    pub data: TransactionDataView,
+}
+
+impl From<&Transaction> for TransactionView {
+    fn from(transaction: &Transaction) -> Self {
+        Self {
+            hash: transaction.hash(),
+            from: transaction.from().cloned(),
+            nonce: transaction.nonce().to_string(),
+            timestamp: transaction.timestamp.to_string(),
+            data: transaction.data().into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockView {
+    pub hash: Hash,
+    pub height: u64,
+    pub prev_hash: Option<Hash>,
+    pub nonce: String,
+    pub timestamp: String,
+    pub bits: u32,
+    pub beneficiary: Option<AccountId>,
+    pub producer: Option<AccountId>,
+    // Merkle roots the header commits to the transactions and ommers with
+    pub tx_root: Hash,
+    pub ommers_root: Hash,
+    pub transactions: Vec<TransactionView>,
+    pub ommers: Vec<Hash>,
+}
+
+impl BlockView {
+    pub fn new(block: &Block) -> Self {
+        Self {
+            hash: block.hash(),
+            height: block.height(),
+            prev_hash: block.prev_hash().cloned(),
+            nonce: block.nonce().to_string(),
+            timestamp: block.timestamp().to_string(),
+            bits: block.bits(),
+            beneficiary: block.beneficiary().cloned(),
+            producer: block.producer().cloned(),
+            tx_root: block.header().tx_root.clone(),
+            ommers_root: block.header().ommers_root.clone(),
+            transactions: block.transactions().iter().map(TransactionView::from).collect(),
+            ommers: block.ommers().iter().map(|ommer| ommer.hash()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountView {
+    pub account_id: AccountId,
+    pub account_type: String,
+    pub balance: String,
+    // The balance in coins, for display
+    pub formatted_balance: String,
+    pub public_key: String,
+    // Of the last transaction the account signed
+    pub nonce: String,
+}
+
+impl AccountView {
+    pub fn new(account_id: &AccountId, account: &Account) -> Self {
+        Self {
+            account_id: account_id.clone(),
+            account_type: account.account_type().name().to_string(),
+            balance: account.balance.to_string(),
+            formatted_balance: Amount::from(account.balance).to_string(),
+            public_key: hex::encode(account.public_key.as_bytes()),
+            nonce: account.nonce().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainStatsView {
+    pub blocks: u64,
+    pub transactions: u64,
+    pub average_block_interval: Option<f64>,
+    pub average_transactions_per_block: f64,
+    pub target: String,
+    pub bits: u32,
+    pub difficulty: f64,
+    pub supply: String,
+    pub formatted_supply: String,
+}
+
+impl From<&ChainStats> for ChainStatsView {
+    fn from(stats: &ChainStats) -> Self {
+        Self {
+            blocks: stats.blocks,
+            transactions: stats.transactions,
+            average_block_interval: stats.average_block_interval,
+            average_transactions_per_block: stats.average_transactions_per_block,
+            target: format!("{:032x}", stats.target),
+            bits: stats.bits,
+            difficulty: stats.difficulty,
+            supply: stats.supply.to_string(),
+            formatted_supply: Amount::from(stats.supply).to_string(),
+        }
+    }
+}
+
+// How settled a transaction is. `safe` once it has `safe_depth` confirmations or sits
+// below a finalized checkpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfirmationsView {
+    pub hash: Hash,
+    pub confirmations: u64,
+    pub safe_depth: u64,
+    pub safe: bool,
+}
+
+impl ConfirmationsView {
+    // None for transactions the chain doesn't know
+    pub fn new<S: ChainStore>(blockchain: &Blockchain<S>, hash: &Hash) -> Option<Self> {
+        Some(Self {
+            hash: hash.clone(),
+            confirmations: blockchain.confirmations(hash)?,
+            safe_depth: blockchain.safe_depth(),
+            safe: blockchain.is_safe(hash),
+        })
+    }
+}
+
+// A transaction as submitted by a client: the signature is hex, the hash is computed.
+// Without a nonce it is 0, which only transactions no account signs get away with, and
+// without a chain id it only executes on chains without one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubmitTransactionRequest {
+    pub from: Option<AccountId>,
+    #[serde(default)]
+    pub nonce: Option<String>,
+    #[serde(default)]
+    pub chain_id: String,
+    pub timestamp: String,
+    pub data: TransactionDataView,
+    pub signature: Option<String>,
+}
+
+impl SubmitTransactionRequest {
+    pub fn to_transaction(&self) -> Result<Transaction, Error> {
+        let timestamp = self
+            .timestamp
+            .parse()
+            .map_err(|_| format!("Invalid timestamp {}", self.timestamp))?;
+        let mut transaction = Transaction::new(TransactionData::try_from(&self.data)?, self.from.clone(), timestamp);
+        if let Some(nonce) = &self.nonce {
+            transaction.set_nonce(nonce.parse().map_err(|_| format!("Invalid nonce {}", nonce))?);
+        }
+        transaction.set_chain_id(&self.chain_id);
+
+        if let Some(signature) = &self.signature {
+            let bytes = hex::decode(signature).map_err(|_| "Invalid signature encoding".to_string())?;
+            let signature = Signature::try_from(&bytes[..]).map_err(|_| "Invalid signature encoding".to_string())?;
+            transaction.sign(Some(signature));
+        }
+        transaction.check_limits().map_err(|error| error.to_string())?;
+
+        Ok(transaction)
+    }
+}
+
+impl From<&Transaction> for SubmitTransactionRequest {
+    fn from(transaction: &Transaction) -> Self {
+        Self {
+            from: transaction.from().cloned(),
+            nonce: Some(transaction.nonce().to_string()),
+            chain_id: transaction.chain_id().to_string(),
+            timestamp: transaction.timestamp.to_string(),
+            data: transaction.data().into(),
+            signature: transaction.signature().map(|signature| hex::encode(signature.to_bytes())),
+        }
+    }
+}
+
+pub fn transaction_to_json(transaction: &Transaction) -> Value {
+    serde_json::to_value(TransactionView::from(transaction)).unwrap_or_default()
+}
+
+pub fn block_to_json(block: &Block) -> Value {
+    serde_json::to_value(BlockView::new(block)).unwrap_or_default()
+}
+
+// What a wallet can act on when the chain refuses a transaction, for the errors with more
+// to them than their message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ErrorDetailsView {
+    InsufficientBalance { needed: String, available: String },
+    Overflow,
+    SpendingLimitExceeded { available: String },
+}
+
+impl ErrorDetailsView {
+    pub fn of(error: &BlockchainError) -> Option<Self> {
+        match error {
+            BlockchainError::InsufficientBalance { needed, available } => Some(ErrorDetailsView::InsufficientBalance {
+                needed: needed.to_string(),
+                available: available.to_string(),
+            }),
+            BlockchainError::BalanceOverflow => Some(ErrorDetailsView::Overflow),
+            BlockchainError::SpendingLimitExceeded(available) => Some(ErrorDetailsView::SpendingLimitExceeded {
+                available: available.to_string(),
+            }),
+            BlockchainError::TransactionFailed(error) => Self::of(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Keypair, Signer};
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_submit_request_roundtrip() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let mut transaction = Transaction::new(
+            TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: u128::MAX,
+            },
+            Some("alice".to_string()),
+            42,
+        );
+        transaction.set_nonce(7);
+        transaction.sign(Some(keypair.sign(&transaction.signing_message())));
+
+        let view = TransactionView::from(&transaction);
+        assert_eq!(view.nonce, "7");
+        assert_eq!(
+            view.data,
+            TransactionDataView::Transfer {
+                to: "bob".to_string(),
+                amount: u128::MAX.to_string()
+            }
+        );
+
+        let request: SubmitTransactionRequest = serde_json::from_value(serde_json::json!({
+            "from": "alice",
+            "nonce": "7",
+            "timestamp": "42",
+            "data": { "type": "transfer", "to": "bob", "amount": u128::MAX.to_string() },
+            "signature": hex::encode(keypair.sign(&transaction.signing_message()).to_bytes()),
+        }))
+        .unwrap();
+        assert_eq!(request.to_transaction().unwrap().hash(), transaction.hash());
+        assert_eq!(SubmitTransactionRequest::from(&transaction), request);
+
+        let mut coins = request.clone();
+        coins.data = TransactionDataView::Transfer {
+            to: "bob".to_string(),
+            amount: "1.5 COIN".to_string(),
+        };
+        assert!(matches!(
+            coins.to_transaction().unwrap().data(),
+            TransactionData::Transfer { amount: 150_000_000, .. }
+        ));
+
+        // Keys decoded from hex hash the same as the ones they came from
+        let create = Transaction::create_account("carol".to_string(), &keypair, 42);
+        let request = SubmitTransactionRequest::from(&create);
+        assert_eq!(request.to_transaction().unwrap().hash(), create.hash());
+
+        let mut invalid = request;
+        invalid.data = TransactionDataView::Transfer {
+            to: "bob".to_string(),
+            amount: "-1".to_string(),
+        };
+        assert!(invalid.to_transaction().is_err());
+        invalid.data = TransactionDataView::Transfer {
+            to: String::new(),
+            amount: "1".to_string(),
+        };
+        assert_eq!(invalid.to_transaction().unwrap_err(), "Account id is empty");
+        invalid.nonce = Some("next".to_string());
+        assert_eq!(invalid.to_transaction().unwrap_err(), "Invalid nonce next");
+    }
+}
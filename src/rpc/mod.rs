@@ -0,0 +1,646 @@
+mod client;
+mod getwork;
+#[cfg(feature = "graphql")]
+mod graphql;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod json;
+#[cfg(feature = "rest")]
+mod rest;
+mod server;
+mod subscriptions;
+mod webhooks;
+#[cfg(feature = "ws")]
+mod ws;
+
+pub use client::{RpcClient, RPC_TIMEOUT};
+pub use getwork::{template_to_json, SubmitBlockParams, Templates, MAX_TEMPLATES};
+#[cfg(feature = "graphql")]
+pub use graphql::{
+    graphql_router, graphql_schema, serve_graphql, AccountObject, BlockObject, ChainSchema, QueryRoot, TransactionObject,
+    MAX_PAGE, MAX_QUERY_DEPTH,
+};
+#[cfg(feature = "grpc")]
+pub use grpc::{proto, ChainService, BLOCK_STREAM_BUFFER};
+pub use json::{
+    block_to_json, transaction_to_json, AccountView, BlockView, ChainStatsView, ConfirmationsView, ErrorDetailsView,
+    SubmitTransactionRequest, TransactionDataView, TransactionView,
+};
+#[cfg(feature = "rest")]
+pub use rest::{rest_router, serve_rest, ErrorResponse, RestState, SubmitTransactionResponse};
+pub use server::serve_rpc;
+pub use subscriptions::{ChainEvent, SubscriptionSession, Subscriptions, Topic, MAX_QUEUED_NOTIFICATIONS};
+pub use webhooks::{
+    sign_payload, verify_payload, WebhookConfig, Webhooks, MAX_ATTEMPTS, RETRY_DELAY, SIGNATURE_HEADER, WEBHOOK_TIMEOUT,
+};
+#[cfg(feature = "ws")]
+pub use ws::{serve_ws, POLL_INTERVAL};
+
+use crate::consensus::decode_bits;
+use crate::network::PeerScores;
+use crate::traits::{ChainStore, Hashable, WorldState};
+use crate::types::{Block, Blockchain, BlockchainError, Chain};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+// Requests the chain turned down, e.g. an invalid block
+pub const CHAIN_ERROR: i64 = -32000;
+// Submitting and mining on a read-only replica
+pub const READ_ONLY: i64 = -32001;
+
+#[derive(Debug, Deserialize)]
+pub struct RpcRequest {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+    #[serde(default)]
+    pub id: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<ErrorDetailsView>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: &str) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+
+    // A transaction the chain refused, with the details wallets can act on in `data`
+    pub fn chain(error: &BlockchainError) -> Self {
+        Self {
+            code: CHAIN_ERROR,
+            message: error.to_string(),
+            data: ErrorDetailsView::of(error),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+    pub id: Value,
+}
+
+impl RpcResponse {
+    pub(crate) fn new(id: Value, result: Result<Value, RpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result,
+            error,
+            id,
+        }
+    }
+}
+
+// JSON-RPC 2.0 dispatcher, independent of the transport carrying the requests. Serves a
+// chain with any block store, like one opened from disk with its blocks loaded lazily.
+pub struct RpcHandler<S = Chain<Block>> {
+    peers: Arc<Mutex<PeerScores>>,
+    blockchain: Option<Arc<Mutex<Blockchain<S>>>>,
+    templates: Mutex<Templates>,
+}
+
+impl RpcHandler {
+    pub fn new(peers: Arc<Mutex<PeerScores>>) -> Self {
+        Self {
+            peers,
+            blockchain: None,
+            templates: Default::default(),
+        }
+    }
+}
+
+impl<S: ChainStore> RpcHandler<S> {
+    // Serves the chain, account and mining methods from `blockchain`
+    pub fn with_blockchain<T: ChainStore>(self, blockchain: Arc<Mutex<Blockchain<T>>>) -> RpcHandler<T> {
+        RpcHandler {
+            peers: self.peers,
+            blockchain: Some(blockchain),
+            templates: self.templates,
+        }
+    }
+
+    pub fn handle(&self, request: &str) -> String {
+        let response = match serde_json::from_str::<RpcRequest>(request) {
+            Ok(request) if request.jsonrpc != "2.0" => {
+                RpcResponse::new(request.id, Err(RpcError::new(INVALID_REQUEST, "Invalid Request")))
+            }
+            Ok(request) => RpcResponse::new(request.id.clone(), self.dispatch(&request.method, &request.params)),
+            Err(_) => RpcResponse::new(Value::Null, Err(RpcError::new(PARSE_ERROR, "Parse error"))),
+        };
+
+        serde_json::to_string(&response).unwrap_or_default()
+    }
+
+    fn dispatch(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        let writes = matches!(method, "get_block_template" | "submit_block" | "submit_transaction");
+        if writes && self.is_read_only() {
+            return Err(RpcError::new(READ_ONLY, "Node is a read-only replica"));
+        }
+
+        match method {
+            "list_peers" => Ok(self.list_peers()),
+            "get_block_template" => self.get_block_template(),
+            "submit_block" => self.submit_block(params),
+            "get_confirmations" => self.get_confirmations(params),
+            "get_chain_info" => self.get_chain_info(),
+            "get_chain_stats" => self.get_chain_stats(),
+            "resolve_account" => self.resolve_account(params),
+            "submit_transaction" => self.submit_transaction(params),
+            "simulate_transaction" => self.simulate_transaction(params),
+            "get_block_by_hash" => self.get_block_by_hash(params),
+            "get_block_by_height" => self.get_block_by_height(params),
+            "get_balance" => self.get_balance(params),
+            "get_account" => self.get_account(params),
+            "get_chain_height" => self.get_chain_height(),
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, "Method not found")),
+        }
+    }
+
+    fn list_peers(&self) -> Value {
+        let mut peers = self.peers.lock().unwrap();
+        peers.decay(Instant::now());
+
+        json!({
+            "peers": peers
+                .peers()
+                .into_iter()
+                .map(|(addr, score)| json!({ "addr": addr, "score": score }))
+                .collect::<Vec<Value>>(),
+            "banned": peers
+                .banned()
+                .into_iter()
+                .map(|(ip, remaining)| json!({ "ip": ip, "remaining_secs": remaining.as_secs() }))
+                .collect::<Vec<Value>>(),
+        })
+    }
+
+    fn is_read_only(&self) -> bool {
+        matches!(&self.blockchain, Some(blockchain) if blockchain.lock().unwrap().is_read_only())
+    }
+
+    fn blockchain(&self) -> Result<&Arc<Mutex<Blockchain<S>>>, RpcError> {
+        self.blockchain
+            .as_ref()
+            .ok_or_else(|| RpcError::new(METHOD_NOT_FOUND, "Method not found"))
+    }
+
+    // ["<transaction hash>"]
+    fn get_confirmations(&self, params: &Value) -> Result<Value, RpcError> {
+        let hash = params
+            .get(0)
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected a transaction hash"))?;
+        let blockchain = self.blockchain()?.lock().unwrap();
+        let view = ConfirmationsView::new(&blockchain, &hash.to_string())
+            .ok_or_else(|| RpcError::new(CHAIN_ERROR, "Unknown transaction"))?;
+
+        Ok(serde_json::to_value(view).unwrap_or_default())
+    }
+
+    fn get_chain_info(&self) -> Result<Value, RpcError> {
+        let info = self.blockchain()?.lock().unwrap().info();
+
+        Ok(serde_json::to_value(info).unwrap_or_default())
+    }
+
+    fn get_chain_stats(&self) -> Result<Value, RpcError> {
+        let stats = self.blockchain()?.lock().unwrap().stats();
+
+        Ok(serde_json::to_value(ChainStatsView::from(&stats)).unwrap_or_default())
+    }
+
+    // ["<account id or @alias>"]
+    fn resolve_account(&self, params: &Value) -> Result<Value, RpcError> {
+        let account_id = params
+            .get(0)
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected an account id or alias"))?;
+        let account_id = self
+            .blockchain()?
+            .lock()
+            .unwrap()
+            .resolve_account(&account_id.to_string())
+            .map_err(|error| RpcError::new(CHAIN_ERROR, &error.to_string()))?;
+
+        Ok(json!({ "account_id": account_id }))
+    }
+
+    // A SubmitTransactionRequest object, as POSTed to the REST /transactions
+    fn submit_transaction(&self, params: &Value) -> Result<Value, RpcError> {
+        let request =
+            SubmitTransactionRequest::deserialize(params).map_err(|_| RpcError::new(INVALID_PARAMS, "Invalid params"))?;
+        let transaction = request
+            .to_transaction()
+            .map_err(|error| RpcError::new(INVALID_PARAMS, &error))?;
+        let hash = self
+            .blockchain()?
+            .lock()
+            .unwrap()
+            .admit_transaction(transaction)
+            .map_err(|error| RpcError::chain(&error))?;
+
+        Ok(json!({ "hash": hash }))
+    }
+
+    // Same params as submit_transaction, runs the transaction without queueing it
+    fn simulate_transaction(&self, params: &Value) -> Result<Value, RpcError> {
+        let request =
+            SubmitTransactionRequest::deserialize(params).map_err(|_| RpcError::new(INVALID_PARAMS, "Invalid params"))?;
+        let transaction = request
+            .to_transaction()
+            .map_err(|error| RpcError::new(INVALID_PARAMS, &error))?;
+        self.blockchain()?
+            .lock()
+            .unwrap()
+            .simulate_transaction(&transaction)
+            .map_err(|error| RpcError::chain(&error))?;
+
+        Ok(json!({ "hash": transaction.hash() }))
+    }
+
+    // ["<block hash>"]
+    fn get_block_by_hash(&self, params: &Value) -> Result<Value, RpcError> {
+        let hash = params
+            .get(0)
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected a block hash"))?
+            .to_string();
+        let blockchain = self.blockchain()?.lock().unwrap();
+        let block = blockchain
+            .get_block_by_hash(&hash)
+            .ok_or_else(|| RpcError::new(CHAIN_ERROR, "Unknown block"))?;
+
+        Ok(block_to_json(block))
+    }
+
+    // [<height>]
+    fn get_block_by_height(&self, params: &Value) -> Result<Value, RpcError> {
+        let height = params
+            .get(0)
+            .and_then(Value::as_u64)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected a block height"))?;
+        let blockchain = self.blockchain()?.lock().unwrap();
+        let block = blockchain
+            .get_block_by_height(height)
+            .ok_or_else(|| RpcError::new(CHAIN_ERROR, "Unknown block"))?;
+
+        Ok(block_to_json(block))
+    }
+
+    // ["<account id or @alias>"], the balance is a decimal string as in AccountView
+    fn get_balance(&self, params: &Value) -> Result<Value, RpcError> {
+        let account_id = params
+            .get(0)
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected an account id or alias"))?;
+        let blockchain = self.blockchain()?.lock().unwrap();
+        let account_id = blockchain
+            .resolve_account(&account_id.to_string())
+            .map_err(|error| RpcError::new(CHAIN_ERROR, &error.to_string()))?;
+        let account = blockchain
+            .get_account_by_id(account_id.clone())
+            .ok_or_else(|| RpcError::new(CHAIN_ERROR, "Unknown account"))?;
+
+        Ok(json!({ "account_id": account_id, "balance": account.balance.to_string() }))
+    }
+
+    // The whole account, with the nonce a wallet signs the next transaction with one above
+    fn get_account(&self, params: &Value) -> Result<Value, RpcError> {
+        let account_id = params
+            .get(0)
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected an account id or alias"))?;
+        let blockchain = self.blockchain()?.lock().unwrap();
+        let account_id = blockchain
+            .resolve_account(&account_id.to_string())
+            .map_err(|error| RpcError::new(CHAIN_ERROR, &error.to_string()))?;
+        let account = blockchain
+            .get_account_by_id(account_id.clone())
+            .ok_or_else(|| RpcError::new(CHAIN_ERROR, "Unknown account"))?;
+
+        Ok(serde_json::to_value(AccountView::new(&account_id, account)).unwrap_or_default())
+    }
+
+    fn get_chain_height(&self) -> Result<Value, RpcError> {
+        let blockchain = self.blockchain()?.lock().unwrap();
+        if blockchain.is_empty() {
+            return Err(RpcError::new(CHAIN_ERROR, "Empty chain"));
+        }
+
+        Ok(json!({
+            "height": blockchain.len() - 1,
+            "hash": blockchain.get_last_block_hash(),
+            "chain_id": blockchain.chain_id(),
+        }))
+    }
+
+    fn get_block_template(&self) -> Result<Value, RpcError> {
+        let (block, tip) = {
+            let mut blockchain = self.blockchain()?.lock().unwrap();
+            (blockchain.block_template(), blockchain.get_last_block_hash())
+        };
+        if block.body.transactions.is_empty() {
+            return Err(RpcError::new(CHAIN_ERROR, "No transactions to mine"));
+        }
+        let target = decode_bits(block.bits()).map_err(|error| RpcError::new(CHAIN_ERROR, &error.to_string()))?;
+
+        let mut templates = self.templates.lock().unwrap();
+        templates.prune(&tip);
+        let template_id = templates.issue(block.clone());
+
+        Ok(template_to_json(template_id, &block, target))
+    }
+
+    fn submit_block(&self, params: &Value) -> Result<Value, RpcError> {
+        let params = SubmitBlockParams::deserialize(params).map_err(|_| RpcError::new(INVALID_PARAMS, "Invalid params"))?;
+        let nonce = params
+            .nonce
+            .parse()
+            .map_err(|_| RpcError::new(INVALID_PARAMS, "Invalid nonce"))?;
+        let mut blockchain = self.blockchain()?.lock().unwrap();
+
+        let mut templates = self.templates.lock().unwrap();
+        templates.prune(&blockchain.get_last_block_hash());
+        let block = templates
+            .solve(params.template_id, nonce)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Unknown or stale template"))?;
+        let hash = block.hash();
+        blockchain
+            .append_block(block)
+            .map_err(|error| RpcError::new(CHAIN_ERROR, &error.to_string()))?;
+        templates.prune(&blockchain.get_last_block_hash());
+
+        Ok(json!({ "hash": hash, "height": blockchain.len() - 1 }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Misbehavior;
+    use crate::types::{Block, Transaction, TransactionData};
+    use crate::consensus::meets_target;
+    use crate::utils::append_block_with_tx;
+    use blake2::{Blake2s, Digest};
+    use ed25519_dalek::{Keypair, Signer};
+
+    fn handler() -> (RpcHandler, Arc<Mutex<PeerScores>>) {
+        let peers = Arc::new(Mutex::new(PeerScores::new()));
+        (RpcHandler::new(peers.clone()), peers)
+    }
+
+    #[test]
+    fn test_list_peers() {
+        let (handler, peers) = handler();
+        {
+            let mut peers = peers.lock().unwrap();
+            peers.report("10.0.0.1:7000".parse().unwrap(), Misbehavior::Spam);
+            peers.report("10.0.0.2:7000".parse().unwrap(), Misbehavior::InvalidBlock);
+            peers.report("10.0.0.2:7000".parse().unwrap(), Misbehavior::InvalidBlock);
+        }
+
+        let response: Value =
+            serde_json::from_str(&handler.handle(r#"{"jsonrpc":"2.0","method":"list_peers","id":1}"#)).unwrap();
+
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["peers"][0]["addr"], "10.0.0.1:7000");
+        assert_eq!(response["result"]["peers"][0]["score"]["score"], -5);
+        assert_eq!(response["result"]["peers"][0]["score"]["spam"], 1);
+        assert_eq!(response["result"]["banned"][0]["ip"], "10.0.0.2");
+    }
+
+    #[test]
+    fn test_errors() {
+        let (handler, _) = handler();
+
+        let response: Value = serde_json::from_str(&handler.handle("{")).unwrap();
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+
+        let response: Value =
+            serde_json::from_str(&handler.handle(r#"{"jsonrpc":"2.0","method":"nope","id":"a"}"#)).unwrap();
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(response["id"], "a");
+
+        let response: Value =
+            serde_json::from_str(&handler.handle(r#"{"jsonrpc":"1.0","method":"list_peers","id":2}"#)).unwrap();
+        assert_eq!(response["error"]["code"], INVALID_REQUEST);
+    }
+
+    fn create_account(blockchain: &Blockchain, account_id: &str) -> Transaction {
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        Transaction::create_account(account_id.to_string(), &keypair, blockchain.now())
+    }
+
+    fn encode_string(out: &mut Vec<u8>, value: &str) {
+        out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        out.extend_from_slice(value.as_bytes());
+    }
+
+    // What a miner outside the crate does with a template, by hand as in the getwork docs
+    fn mine_template(template: &Value) -> u128 {
+        let block = &template["block"];
+        let target = u128::from_str_radix(template["target"].as_str().unwrap(), 16).unwrap();
+        let timestamp: u128 = block["timestamp"].as_str().unwrap().parse().unwrap();
+        let bits = block["bits"].as_u64().unwrap() as u32;
+
+        (0..)
+            .find(|nonce: &u128| {
+                let mut bytes = vec![];
+                match block["prev_hash"].as_str() {
+                    Some(prev_hash) => {
+                        bytes.push(1);
+                        encode_string(&mut bytes, prev_hash);
+                    }
+                    None => bytes.push(0),
+                }
+                bytes.extend_from_slice(&block["height"].as_u64().unwrap().to_be_bytes());
+                bytes.extend_from_slice(&nonce.to_be_bytes());
+                bytes.extend_from_slice(&timestamp.to_be_bytes());
+                bytes.extend_from_slice(&bits.to_be_bytes());
+                // No beneficiary, no producer
+                bytes.push(0);
+                bytes.push(0);
+                encode_string(&mut bytes, block["tx_root"].as_str().unwrap());
+                encode_string(&mut bytes, block["ommers_root"].as_str().unwrap());
+
+                meets_target(&hex::encode(Blake2s::digest(&bytes)), target)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_getwork() {
+        let mut bc = Blockchain::with_max_target(u128::MAX >> 8);
+        let genesis = create_account(&bc, "alice");
+        assert!(append_block_with_tx(&mut bc, 1, vec![genesis]).is_ok());
+        let blockchain = Arc::new(Mutex::new(bc));
+        let (handler, _) = handler();
+        let handler = handler.with_blockchain(blockchain.clone());
+        let call = |method: &str, params: Value| -> Value {
+            let request = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 });
+            serde_json::from_str(&handler.handle(&request.to_string())).unwrap()
+        };
+
+        assert_eq!(call("get_block_template", Value::Null)["error"]["code"], CHAIN_ERROR);
+        let bob = create_account(&blockchain.lock().unwrap(), "bob");
+        blockchain.lock().unwrap().submit_transaction(bob).unwrap();
+
+        let stale = call("get_block_template", Value::Null)["result"].clone();
+        let template = call("get_block_template", Value::Null)["result"].clone();
+        assert_eq!(template["template_id"], 2);
+        let nonce = mine_template(&template);
+
+        let wrong = if nonce == 0 { 1 } else { nonce - 1 };
+        let response = call("submit_block", json!({ "template_id": 2, "nonce": wrong.to_string() }));
+        assert!(response["error"].is_object());
+        assert_eq!(call("submit_block", json!({ "template_id": 2 }))["error"]["code"], INVALID_PARAMS);
+
+        let response = call("submit_block", json!({ "template_id": 2, "nonce": nonce.to_string() }));
+        assert_eq!(response["result"]["height"], 1);
+        assert_eq!(blockchain.lock().unwrap().get_last_block_hash().unwrap(), response["result"]["hash"]);
+        assert!(blockchain.lock().unwrap().pending_transactions().is_empty());
+        let bob = blockchain.lock().unwrap().get_block_by_height(1).unwrap().body.transactions[0].hash();
+        let confirmations = call("get_confirmations", json!([bob]))["result"].clone();
+        assert_eq!(confirmations["confirmations"], 1);
+        assert_eq!(confirmations["safe"], false);
+        assert_eq!(call("get_confirmations", json!(["nope"]))["error"]["code"], CHAIN_ERROR);
+        let stats = call("get_chain_stats", json!([]))["result"].clone();
+        assert_eq!(stats["blocks"], 2);
+        assert_eq!(stats["transactions"], 2);
+
+        // Work on the old tip is no use anymore
+        let response = call("submit_block", json!({ "template_id": stale["template_id"], "nonce": nonce.to_string() }));
+        assert_eq!(response["error"]["message"], "Unknown or stale template");
+    }
+
+    #[test]
+    fn test_resolve_account() {
+        let mut bc = Blockchain::with_max_target(u128::MAX >> 8);
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let create_alice = Transaction::create_account("alice".to_string(), &keypair, bc.now());
+        assert!(append_block_with_tx(&mut bc, 1, vec![create_alice]).is_ok());
+        bc.set_alias_owner("ali".to_string(), "alice".to_string());
+        let (handler, _) = handler();
+        let handler = handler.with_blockchain(Arc::new(Mutex::new(bc)));
+        let call = |params: Value| -> Value {
+            let request = json!({ "jsonrpc": "2.0", "method": "resolve_account", "params": params, "id": 1 });
+            serde_json::from_str(&handler.handle(&request.to_string())).unwrap()
+        };
+
+        assert_eq!(call(json!(["@ali"]))["result"]["account_id"], "alice");
+        assert_eq!(call(json!(["alice"]))["result"]["account_id"], "alice");
+        assert_eq!(call(json!(["@bob"]))["error"]["message"], "Unknown alias: bob");
+        assert_eq!(call(json!([]))["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[test]
+    fn test_node_methods() {
+        let mut bc = Blockchain::with_max_target(u128::MAX >> 8);
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let create_alice = Transaction::create_account("alice".to_string(), &keypair, bc.now());
+        assert!(append_block_with_tx(&mut bc, 1, vec![create_alice]).is_ok());
+        let genesis_hash = bc.get_last_block_hash().unwrap();
+        let blockchain = Arc::new(Mutex::new(bc));
+        let (handler, _) = handler();
+        let handler = handler.with_blockchain(blockchain.clone());
+        let call = |method: &str, params: Value| -> Value {
+            let request = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 });
+            serde_json::from_str(&handler.handle(&request.to_string())).unwrap()
+        };
+
+        let response = call("get_chain_height", Value::Null);
+        assert_eq!(response["result"]["height"], 0);
+        assert_eq!(response["result"]["hash"], genesis_hash);
+        assert_eq!(response["result"]["chain_id"], "");
+        let info = call("get_chain_info", Value::Null)["result"].clone();
+        assert_eq!(info, json!({ "genesis_hash": genesis_hash, "chain_id": "", "height": 0, "tip": genesis_hash }));
+        assert_eq!(call("get_block_by_height", json!([0]))["result"]["hash"], genesis_hash);
+        assert_eq!(call("get_block_by_height", json!([1]))["error"]["code"], CHAIN_ERROR);
+        assert_eq!(call("get_block_by_hash", json!([genesis_hash]))["result"]["height"], 0);
+        assert_eq!(call("get_block_by_hash", json!(["nope"]))["error"]["code"], CHAIN_ERROR);
+        assert_eq!(call("get_balance", json!(["alice"]))["result"]["balance"], "0");
+        assert_eq!(call("get_balance", json!(["bob"]))["error"]["code"], CHAIN_ERROR);
+        assert_eq!(call("get_balance", json!([1]))["error"]["code"], INVALID_PARAMS);
+
+        let bob = create_account(&blockchain.lock().unwrap(), "bob");
+        let request = SubmitTransactionRequest::from(&bob);
+        let response = call("submit_transaction", serde_json::to_value(&request).unwrap());
+        assert_eq!(response["result"]["hash"], bob.hash());
+        assert_eq!(blockchain.lock().unwrap().pending_transactions().len(), 1);
+        assert_eq!(call("submit_transaction", json!({ "from": null }))["error"]["code"], INVALID_PARAMS);
+        // Already in the pool
+        let response = call("submit_transaction", serde_json::to_value(&request).unwrap());
+        assert_eq!(response["error"]["code"], CHAIN_ERROR);
+        assert!(response["error"].get("data").is_none());
+
+        // Wallets learn how much is missing
+        let mut transfer = Transaction::new(
+            TransactionData::Transfer {
+                to: "alice".to_string(),
+                amount: 5,
+            },
+            Some("alice".to_string()),
+            blockchain.lock().unwrap().now(),
+        );
+        transfer.set_nonce(1);
+        transfer.sign(Some(keypair.sign(&transfer.signing_message())));
+        let request = SubmitTransactionRequest::from(&transfer);
+        for method in ["simulate_transaction", "submit_transaction"].iter() {
+            let response = call(method, serde_json::to_value(&request).unwrap());
+            assert_eq!(response["error"]["message"], "Insufficient balance: 5 needed, 0 available");
+            assert_eq!(
+                response["error"]["data"],
+                json!({ "kind": "insufficient_balance", "needed": "5", "available": "0" })
+            );
+        }
+        let request = SubmitTransactionRequest::from(&bob);
+
+        // A replica only answers queries
+        blockchain.lock().unwrap().set_read_only(true);
+        assert!(blockchain.lock().unwrap().pending_transactions().is_empty());
+        for method in ["get_block_template", "submit_block", "submit_transaction"] {
+            assert_eq!(call(method, serde_json::to_value(&request).unwrap())["error"]["code"], READ_ONLY);
+        }
+        assert_eq!(call("get_chain_height", Value::Null)["result"]["height"], 0);
+    }
+
+    #[test]
+    fn test_templates_are_bounded() {
+        let mut templates = Templates::new();
+        for _ in 0..MAX_TEMPLATES + 1 {
+            templates.issue(Block::new(None));
+        }
+
+        assert_eq!(templates.len(), MAX_TEMPLATES);
+        assert!(templates.solve(1, 0).is_none());
+        assert!(templates.solve(2, 0).is_some());
+        templates.prune(&Some("tip".to_string()));
+        assert!(templates.is_empty());
+    }
+}
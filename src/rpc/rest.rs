@@ -0,0 +1,221 @@
+use crate::rpc::{
+    AccountView, BlockView, ChainEvent, ChainStatsView, ConfirmationsView, ErrorDetailsView, SubmitTransactionRequest,
+    Subscriptions,
+};
+use crate::traits::WorldState;
+use crate::types::{AccountId, Blockchain, BlockchainError, Hash};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubmitTransactionResponse {
+    pub hash: Hash,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<ErrorDetailsView>,
+}
+
+type ApiResult<T> = Result<Json<T>, (StatusCode, Json<ErrorResponse>)>;
+
+fn api_error(status: StatusCode, error: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: error.to_string(),
+            details: None,
+        }),
+    )
+}
+
+fn chain_error(status: StatusCode, error: &BlockchainError) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: error.to_string(),
+            details: ErrorDetailsView::of(error),
+        }),
+    )
+}
+
+#[derive(Clone)]
+pub struct RestState {
+    pub blockchain: Arc<Mutex<Blockchain>>,
+    pub subscriptions: Arc<Mutex<Subscriptions>>,
+}
+
+// GET /stats, GET /blocks/{height}, GET /accounts/{id}, POST /transactions,
+// GET /transactions/{hash}/confirmations
+pub fn rest_router(state: RestState) -> Router {
+    Router::new()
+        .route("/stats", get(get_stats))
+        .route("/blocks/{height}", get(get_block))
+        .route("/accounts/{id}", get(get_account))
+        .route("/transactions", post(submit_transaction))
+        .route("/transactions/{hash}/confirmations", get(get_confirmations))
+        .with_state(state)
+}
+
+pub async fn serve_rest(listener: TcpListener, state: RestState) -> std::io::Result<()> {
+    axum::serve(listener, rest_router(state)).await
+}
+
+async fn get_stats(State(state): State<RestState>) -> ApiResult<ChainStatsView> {
+    let stats = state.blockchain.lock().unwrap().stats();
+
+    Ok(Json(ChainStatsView::from(&stats)))
+}
+
+async fn get_block(State(state): State<RestState>, Path(height): Path<u64>) -> ApiResult<BlockView> {
+    let blockchain = state.blockchain.lock().unwrap();
+
+    match blockchain.get_block_by_height(height) {
+        Some(block) => Ok(Json(BlockView::new(block))),
+        None => Err(api_error(StatusCode::NOT_FOUND, "Block not found")),
+    }
+}
+
+// `id` may also be an alias reference like "@alice"
+async fn get_account(State(state): State<RestState>, Path(id): Path<AccountId>) -> ApiResult<AccountView> {
+    let blockchain = state.blockchain.lock().unwrap();
+    let id = blockchain
+        .resolve_account(&id)
+        .map_err(|error| api_error(StatusCode::NOT_FOUND, &error.to_string()))?;
+
+    match blockchain.get_account_by_id(id.clone()) {
+        Some(account) => Ok(Json(AccountView::new(&id, account))),
+        None => Err(api_error(StatusCode::NOT_FOUND, "Account not found")),
+    }
+}
+
+async fn get_confirmations(State(state): State<RestState>, Path(hash): Path<Hash>) -> ApiResult<ConfirmationsView> {
+    let blockchain = state.blockchain.lock().unwrap();
+
+    match ConfirmationsView::new(&blockchain, &hash) {
+        Some(view) => Ok(Json(view)),
+        None => Err(api_error(StatusCode::NOT_FOUND, "Transaction not found")),
+    }
+}
+
+async fn submit_transaction(
+    State(state): State<RestState>,
+    Json(request): Json<SubmitTransactionRequest>,
+) -> ApiResult<SubmitTransactionResponse> {
+    if state.blockchain.lock().unwrap().is_read_only() {
+        return Err(api_error(StatusCode::FORBIDDEN, "Node is a read-only replica"));
+    }
+    let transaction = request
+        .to_transaction()
+        .map_err(|error| api_error(StatusCode::BAD_REQUEST, &error))?;
+
+    let hash = state
+        .blockchain
+        .lock()
+        .unwrap()
+        .admit_transaction(transaction.clone())
+        .map_err(|error| chain_error(StatusCode::UNPROCESSABLE_ENTITY, &error))?;
+    state
+        .subscriptions
+        .lock()
+        .unwrap()
+        .publish(&ChainEvent::PendingTransaction(transaction));
+
+    Ok(Json(SubmitTransactionResponse { hash }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::{Topic, TransactionDataView};
+    use crate::testing::MockClock;
+    use crate::traits::Hashable;
+    use crate::types::Transaction;
+    use crate::utils::append_block_with_tx;
+    use ed25519_dalek::{Keypair, Signer};
+
+    fn state() -> RestState {
+        let mut blockchain = Blockchain::with_max_target(u128::MAX >> 8).with_clock(MockClock::new(1));
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let create_alice = Transaction::create_account("alice".to_string(), &keypair, 0);
+        assert!(append_block_with_tx(&mut blockchain, 1, vec![create_alice]).is_ok());
+
+        RestState {
+            blockchain: Arc::new(Mutex::new(blockchain)),
+            subscriptions: Arc::new(Mutex::new(Subscriptions::new())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queries() {
+        let state = state();
+
+        let block = get_block(State(state.clone()), Path(0)).await.unwrap();
+        assert_eq!(block.height, 0);
+        assert_eq!(block.transactions.len(), 1);
+        assert_eq!(
+            get_block(State(state.clone()), Path(1)).await.unwrap_err().0,
+            StatusCode::NOT_FOUND
+        );
+
+        let account = get_account(State(state.clone()), Path("alice".to_string())).await.unwrap();
+        assert_eq!(account.balance, "0");
+        assert_eq!(account.formatted_balance, "0 COIN");
+        assert!(get_account(State(state.clone()), Path("bob".to_string())).await.is_err());
+
+        let confirmations = get_confirmations(State(state.clone()), Path(block.transactions[0].hash.clone()))
+            .await
+            .unwrap();
+        assert_eq!(confirmations.confirmations, 1);
+        assert!(!confirmations.safe);
+        assert!(get_confirmations(State(state.clone()), Path("nope".to_string())).await.is_err());
+
+        let stats = get_stats(State(state)).await.unwrap();
+        assert_eq!(stats.blocks, 1);
+        assert_eq!(stats.transactions, 1);
+        assert_eq!(stats.average_block_interval, None);
+    }
+
+    #[tokio::test]
+    async fn test_submit_transaction() {
+        let state = state();
+        let (_, pending) = state.subscriptions.lock().unwrap().subscribe(Topic::PendingTransactions);
+        let bob = Keypair::generate(&mut rand::rngs::OsRng {});
+
+        let mut request = SubmitTransactionRequest {
+            from: None,
+            nonce: None,
+            chain_id: String::new(),
+            timestamp: "1".to_string(),
+            data: TransactionDataView::CreateAccount {
+                account_id: "bob".to_string(),
+                public_key: hex::encode(bob.public.as_bytes()),
+            },
+            signature: None,
+        };
+        assert_eq!(
+            submit_transaction(State(state.clone()), Json(request.clone())).await.unwrap_err().0,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+
+        let message = request.to_transaction().unwrap().signing_message();
+        request.signature = Some(hex::encode(bob.sign(&message).to_bytes()));
+        let response = submit_transaction(State(state.clone()), Json(request.clone())).await.unwrap();
+        assert_eq!(response.hash, request.to_transaction().unwrap().hash());
+        assert_eq!(pending.try_iter().count(), 1);
+
+        let mut invalid = request;
+        invalid.timestamp = "soon".to_string();
+        assert_eq!(
+            submit_transaction(State(state), Json(invalid)).await.unwrap_err().0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
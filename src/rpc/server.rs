@@ -0,0 +1,78 @@
+use crate::rpc::RpcHandler;
+use crate::traits::ChainStore;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+// JSON-RPC over TCP, one request per line and one response line per request, one thread
+// per client. Everything is answered from the shared handler, so wallets and miners see
+// the same chain.
+pub fn serve_rpc<S: ChainStore + Send + 'static>(listener: TcpListener, handler: Arc<RpcHandler<S>>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let handler = handler.clone();
+            thread::spawn(move || handle_connection(stream, &handler));
+        }
+    })
+}
+
+fn handle_connection<S: ChainStore>(stream: TcpStream, handler: &RpcHandler<S>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        if writeln!(writer, "{}", handler.handle(&line)).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::PeerScores;
+    use crate::types::{Blockchain, Transaction};
+    use crate::utils::append_block_with_tx;
+    use ed25519_dalek::Keypair;
+    use serde_json::Value;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_serve_rpc() {
+        let mut bc = Blockchain::with_max_target(u128::MAX >> 8);
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let create_alice = Transaction::create_account("alice".to_string(), &keypair, bc.now());
+        assert!(append_block_with_tx(&mut bc, 1, vec![create_alice]).is_ok());
+        let handler = RpcHandler::new(Arc::new(Mutex::new(PeerScores::new()))).with_blockchain(Arc::new(Mutex::new(bc)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        serve_rpc(listener, Arc::new(handler));
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut call = |request: &str| -> Value {
+            writeln!(writer, "{}", request).unwrap();
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            serde_json::from_str(&line).unwrap()
+        };
+
+        let response = call(r#"{"jsonrpc":"2.0","method":"get_chain_height","id":1}"#);
+        assert_eq!(response["result"]["height"], 0);
+        let response = call(r#"{"jsonrpc":"2.0","method":"get_balance","params":["alice"],"id":2}"#);
+        assert_eq!(response["id"], 2);
+        assert_eq!(response["result"]["balance"], "0");
+    }
+}
@@ -0,0 +1,459 @@
+use crate::rpc::{block_to_json, transaction_to_json, BlockView, RpcError, RpcRequest, RpcResponse};
+use crate::rpc::{INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR};
+use crate::traits::Hashable;
+use crate::types::{AccountId, Block, Reorg, Transaction};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    NewBlocks,
+    // New main chain blocks without their bodies
+    NewHeads,
+    PendingTransactions,
+    // Main chain blocks taken out again by a reorg
+    Reorgs,
+    Address(AccountId),
+    // Blocks that changed the account, once each, for clients that refetch its state
+    AccountChanged(AccountId),
+}
+
+impl Topic {
+    // ["newBlocks"], ["newHeads"], ["pendingTransactions"], ["reorgs"],
+    // ["address", "<account id>"] or ["accountChanged", "<account id>"]
+    pub fn parse(params: &Value) -> Result<Self, RpcError> {
+        match (params.get(0).and_then(Value::as_str), params.get(1).and_then(Value::as_str)) {
+            (Some("newBlocks"), None) => Ok(Topic::NewBlocks),
+            (Some("newHeads"), None) => Ok(Topic::NewHeads),
+            (Some("pendingTransactions"), None) => Ok(Topic::PendingTransactions),
+            (Some("reorgs"), None) => Ok(Topic::Reorgs),
+            (Some("address"), Some(account_id)) => Ok(Topic::Address(account_id.to_string())),
+            (Some("accountChanged"), Some(account_id)) => Ok(Topic::AccountChanged(account_id.to_string())),
+            _ => Err(RpcError::new(INVALID_PARAMS, "Unknown subscription")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    NewBlock { height: u64, block: Block },
+    PendingTransaction(Transaction),
+    // A main chain block a reorg took out again
+    BlockReverted { height: u64, block: Block },
+}
+
+impl ChainEvent {
+    // What a reorg looks like to subscribers: the blocks it took out, tip first, then the
+    // ones it put in, oldest first
+    pub fn from_reorg(reorg: &Reorg) -> Vec<Self> {
+        let reverted = reorg.reverted.iter().map(|block| ChainEvent::BlockReverted {
+            height: block.height(),
+            block: block.clone(),
+        });
+        let applied = reorg.applied.iter().map(|block| ChainEvent::NewBlock {
+            height: block.height(),
+            block: block.clone(),
+        });
+
+        reverted.chain(applied).collect()
+    }
+}
+
+// Notifications a subscriber may have waiting before it's considered too slow to keep
+pub const MAX_QUEUED_NOTIFICATIONS: usize = 1024;
+
+// Fans chain events out to subscribers as JSON-RPC notifications. Subscribers whose
+// receiving end is gone, or who let MAX_QUEUED_NOTIFICATIONS pile up, are dropped on the
+// next publish, so a stalled client can't make the node buffer without end.
+#[derive(Debug)]
+pub struct Subscriptions {
+    next_id: u64,
+    capacity: usize,
+    subscribers: HashMap<u64, (Topic, SyncSender<Value>)>,
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Self::with_capacity(MAX_QUEUED_NOTIFICATIONS)
+    }
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            next_id: 0,
+            capacity,
+            subscribers: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    pub fn subscribe(&mut self, topic: Topic) -> (u64, Receiver<Value>) {
+        let (sender, receiver) = sync_channel(self.capacity);
+        self.next_id += 1;
+        self.subscribers.insert(self.next_id, (topic, sender));
+        (self.next_id, receiver)
+    }
+
+    pub fn unsubscribe(&mut self, id: u64) -> bool {
+        self.subscribers.remove(&id).is_some()
+    }
+
+    pub fn publish(&mut self, event: &ChainEvent) {
+        self.subscribers.retain(|id, (topic, sender)| {
+            payloads(topic, event)
+                .into_iter()
+                .all(|payload| sender.try_send(notification(*id, payload)).is_ok())
+        });
+    }
+}
+
+fn notification(id: u64, result: Value) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "subscription",
+        "params": { "subscription": id, "result": result },
+    })
+}
+
+// Sent in place of further notifications once a subscription was dropped for falling behind
+fn dropped_notification(id: u64) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "subscription_dropped",
+        "params": { "subscription": id, "reason": "Too many notifications queued" },
+    })
+}
+
+// The block as in newBlocks, with the number of transactions in place of them
+fn head_to_json(block: &Block) -> Value {
+    let view = BlockView::new(block);
+    json!({
+        "hash": view.hash,
+        "height": view.height,
+        "prev_hash": view.prev_hash,
+        "nonce": view.nonce,
+        "timestamp": view.timestamp,
+        "bits": view.bits,
+        "beneficiary": view.beneficiary,
+        "producer": view.producer,
+        "tx_root": view.tx_root,
+        "ommers_root": view.ommers_root,
+        "transactions": view.transactions.len(),
+    })
+}
+
+fn account_changed(account_id: &AccountId, status: &str, height: u64, block: &Block) -> Vec<Value> {
+    let transactions: Vec<_> = block
+        .transactions()
+        .iter()
+        .filter(|transaction| transaction.involves(account_id))
+        .map(|transaction| transaction.hash())
+        .collect();
+    if transactions.is_empty() {
+        return vec![];
+    }
+
+    vec![json!({
+        "account_id": account_id,
+        "status": status,
+        "height": height,
+        "block_hash": block.hash(),
+        "transactions": transactions,
+    })]
+}
+
+pub(crate) fn payloads(topic: &Topic, event: &ChainEvent) -> Vec<Value> {
+    match (topic, event) {
+        (Topic::NewBlocks, ChainEvent::NewBlock { block, .. }) => vec![block_to_json(block)],
+        (Topic::NewHeads, ChainEvent::NewBlock { block, .. }) => vec![head_to_json(block)],
+        (Topic::AccountChanged(account_id), ChainEvent::NewBlock { height, block }) => {
+            account_changed(account_id, "confirmed", *height, block)
+        }
+        (Topic::AccountChanged(account_id), ChainEvent::BlockReverted { height, block }) => {
+            account_changed(account_id, "reverted", *height, block)
+        }
+        (Topic::Reorgs, ChainEvent::BlockReverted { block, .. }) => vec![block_to_json(block)],
+        (Topic::PendingTransactions, ChainEvent::PendingTransaction(transaction)) => {
+            vec![transaction_to_json(transaction)]
+        }
+        (Topic::Address(account_id), ChainEvent::NewBlock { height, block }) => block
+            .body.transactions
+            .iter()
+            .filter(|transaction| transaction.involves(account_id))
+            .map(|transaction| {
+                json!({
+                    "status": "confirmed",
+                    "height": height,
+                    "block_hash": block.hash(),
+                    "transaction": transaction_to_json(transaction),
+                })
+            })
+            .collect(),
+        (Topic::Address(account_id), ChainEvent::BlockReverted { height, block }) => block
+            .body.transactions
+            .iter()
+            .filter(|transaction| transaction.involves(account_id))
+            .map(|transaction| {
+                json!({
+                    "status": "reverted",
+                    "height": height,
+                    "block_hash": block.hash(),
+                    "transaction": transaction_to_json(transaction),
+                })
+            })
+            .collect(),
+        (Topic::Address(account_id), ChainEvent::PendingTransaction(transaction))
+            if transaction.involves(account_id) =>
+        {
+            vec![json!({ "status": "pending", "transaction": transaction_to_json(transaction) })]
+        }
+        _ => vec![],
+    }
+}
+
+// Subscriptions of one client connection, handling its subscribe/unsubscribe requests.
+// Everything it subscribed to is dropped with it, e.g. when the client disconnects.
+pub struct SubscriptionSession {
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    receivers: Vec<(u64, Receiver<Value>)>,
+}
+
+impl SubscriptionSession {
+    pub fn new(subscriptions: Arc<Mutex<Subscriptions>>) -> Self {
+        Self {
+            subscriptions,
+            receivers: vec![],
+        }
+    }
+
+    pub fn handle(&mut self, request: &str) -> String {
+        let response = match serde_json::from_str::<RpcRequest>(request) {
+            Ok(request) if request.jsonrpc != "2.0" => {
+                RpcResponse::new(request.id, Err(RpcError::new(INVALID_REQUEST, "Invalid Request")))
+            }
+            Ok(request) => RpcResponse::new(request.id.clone(), self.dispatch(&request.method, &request.params)),
+            Err(_) => RpcResponse::new(Value::Null, Err(RpcError::new(PARSE_ERROR, "Parse error"))),
+        };
+
+        serde_json::to_string(&response).unwrap_or_default()
+    }
+
+    // Notifications received since the last poll. A subscription dropped for falling
+    // behind ends with a subscription_dropped notification.
+    pub fn poll(&mut self) -> Vec<Value> {
+        let mut notifications = vec![];
+        self.receivers.retain(|(id, receiver)| loop {
+            match receiver.try_recv() {
+                Ok(notification) => notifications.push(notification),
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => {
+                    notifications.push(dropped_notification(*id));
+                    return false;
+                }
+            }
+        });
+        notifications
+    }
+
+    fn dispatch(&mut self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "subscribe" => {
+                let topic = Topic::parse(params)?;
+                let (id, receiver) = self.subscriptions.lock().unwrap().subscribe(topic);
+                self.receivers.push((id, receiver));
+                Ok(json!(id))
+            }
+            "unsubscribe" => {
+                let id = params
+                    .get(0)
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected a subscription id"))?;
+                let owned = self.receivers.iter().any(|(owned, _)| *owned == id);
+                self.receivers.retain(|(owned, _)| *owned != id);
+                Ok(json!(owned && self.subscriptions.lock().unwrap().unsubscribe(id)))
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, "Method not found")),
+        }
+    }
+}
+
+impl Drop for SubscriptionSession {
+    fn drop(&mut self) {
+        if let Ok(mut subscriptions) = self.subscriptions.lock() {
+            for (id, _) in self.receivers.iter() {
+                subscriptions.unsubscribe(*id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TransactionData;
+
+    fn transfer(from: &str, to: &str) -> Transaction {
+        Transaction::new(
+            TransactionData::Transfer {
+                to: to.to_string(),
+                amount: 5,
+            },
+            Some(from.to_string()),
+            0,
+        )
+    }
+
+    #[test]
+    fn test_publish_by_topic() {
+        let mut subscriptions = Subscriptions::new();
+        let (_, blocks) = subscriptions.subscribe(Topic::NewBlocks);
+        let (_, pending) = subscriptions.subscribe(Topic::PendingTransactions);
+        let (bob_id, bob) = subscriptions.subscribe(Topic::Address("bob".to_string()));
+        let (_, reorgs) = subscriptions.subscribe(Topic::Reorgs);
+
+        let mut block = Block::new(None);
+        block.add_transaction(transfer("alice", "bob"));
+        block.add_transaction(transfer("alice", "carol"));
+        subscriptions.publish(&ChainEvent::NewBlock { height: 0, block });
+        subscriptions.publish(&ChainEvent::PendingTransaction(transfer("carol", "alice")));
+
+        assert_eq!(blocks.try_iter().count(), 1);
+        assert_eq!(pending.try_iter().count(), 1);
+        let bob_events: Vec<Value> = bob.try_iter().collect();
+        assert_eq!(bob_events.len(), 1);
+        assert_eq!(bob_events[0]["params"]["subscription"], bob_id);
+        assert_eq!(bob_events[0]["params"]["result"]["status"], "confirmed");
+        assert_eq!(bob_events[0]["params"]["result"]["transaction"]["data"]["amount"], "5");
+
+        let mut reverted = Block::new(None);
+        reverted.add_transaction(transfer("bob", "carol"));
+        subscriptions.publish(&ChainEvent::BlockReverted { height: 0, block: reverted });
+        assert_eq!(blocks.try_iter().count(), 0);
+        assert_eq!(reorgs.try_iter().count(), 1);
+        assert_eq!(bob.try_iter().next().unwrap()["params"]["result"]["status"], "reverted");
+
+        let mut replacement = Block::new(None);
+        replacement.set_height(1);
+        let reorg = Reorg {
+            fork_height: 0,
+            reverted: vec![Block::new(None)],
+            applied: vec![replacement],
+        };
+        for event in ChainEvent::from_reorg(&reorg) {
+            subscriptions.publish(&event);
+        }
+        assert_eq!(reorgs.try_iter().count(), 1);
+        assert_eq!(blocks.try_iter().next().unwrap()["params"]["result"]["height"], 1);
+
+        drop(blocks);
+        subscriptions.publish(&ChainEvent::NewBlock {
+            height: 1,
+            block: Block::new(None),
+        });
+        assert_eq!(subscriptions.len(), 3);
+    }
+
+    #[test]
+    fn test_session() {
+        let subscriptions = Arc::new(Mutex::new(Subscriptions::new()));
+        let mut session = SubscriptionSession::new(subscriptions.clone());
+
+        let response: Value = serde_json::from_str(&session.handle(
+            r#"{"jsonrpc":"2.0","id":1,"method":"subscribe","params":["address","alice"]}"#,
+        ))
+        .unwrap();
+        let id = response["result"].as_u64().unwrap();
+
+        let response: Value = serde_json::from_str(&session.handle(
+            r#"{"jsonrpc":"2.0","id":2,"method":"subscribe","params":["oldBlocks"]}"#,
+        ))
+        .unwrap();
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+
+        subscriptions
+            .lock()
+            .unwrap()
+            .publish(&ChainEvent::PendingTransaction(transfer("alice", "bob")));
+        assert_eq!(session.poll()[0]["params"]["result"]["status"], "pending");
+
+        let response: Value = serde_json::from_str(&session.handle(&format!(
+            r#"{{"jsonrpc":"2.0","id":3,"method":"unsubscribe","params":[{}]}}"#,
+            id
+        )))
+        .unwrap();
+        assert_eq!(response["result"], true);
+        assert!(subscriptions.lock().unwrap().is_empty());
+
+        session.handle(r#"{"jsonrpc":"2.0","id":4,"method":"subscribe","params":["newBlocks"]}"#);
+        drop(session);
+        assert!(subscriptions.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_heads_account_changes_and_backpressure() {
+        let subscriptions = Arc::new(Mutex::new(Subscriptions::with_capacity(2)));
+        let mut session = SubscriptionSession::new(subscriptions.clone());
+        let subscribe = |session: &mut SubscriptionSession, params: Value| -> u64 {
+            let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "subscribe", "params": params });
+            let response: Value = serde_json::from_str(&session.handle(&request.to_string())).unwrap();
+            response["result"].as_u64().unwrap()
+        };
+        let heads = subscribe(&mut session, json!(["newHeads"]));
+        let bob = subscribe(&mut session, json!(["accountChanged", "bob"]));
+
+        let mut block = Block::new(None);
+        block.set_height(3);
+        block.add_transaction(transfer("alice", "bob"));
+        block.add_transaction(transfer("bob", "carol"));
+        let publish = |event: ChainEvent| subscriptions.lock().unwrap().publish(&event);
+        publish(ChainEvent::PendingTransaction(transfer("alice", "bob")));
+        publish(ChainEvent::NewBlock {
+            height: 3,
+            block: block.clone(),
+        });
+
+        let notifications = session.poll();
+        assert_eq!(notifications.len(), 2);
+        let head = &notifications[0]["params"];
+        assert_eq!(head["subscription"], heads);
+        assert_eq!(head["result"]["height"], 3);
+        assert_eq!(head["result"]["transactions"], 2);
+        let change = &notifications[1]["params"];
+        assert_eq!(change["subscription"], bob);
+        assert_eq!(change["result"]["status"], "confirmed");
+        assert_eq!(change["result"]["transactions"], json!([block.transactions()[0].hash(), block.transactions()[1].hash()]));
+
+        // Heads pile up past the capacity while nobody polls, the other subscription lives on
+        for height in 4..7 {
+            publish(ChainEvent::NewBlock {
+                height,
+                block: Block::new(None),
+            });
+        }
+        assert_eq!(subscriptions.lock().unwrap().len(), 1);
+        let notifications = session.poll();
+        assert_eq!(notifications.len(), 3);
+        assert_eq!(notifications[2]["method"], "subscription_dropped");
+        assert_eq!(notifications[2]["params"]["subscription"], heads);
+        assert!(session.poll().is_empty());
+
+        publish(ChainEvent::BlockReverted { height: 3, block });
+        assert_eq!(session.poll()[0]["params"]["result"]["status"], "reverted");
+    }
+}
@@ -0,0 +1,301 @@
+use crate::rpc::subscriptions::payloads;
+use crate::rpc::{ChainEvent, Topic};
+use crate::types::Error;
+use hmac::{Hmac, Mac, NewMac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// Attempts at delivering a payload before giving up on it, unless a hook sets its own
+pub const MAX_ATTEMPTS: u32 = 5;
+// Before the first retry, doubling with every one after
+pub const RETRY_DELAY: Duration = Duration::from_secs(1);
+// For connecting to a hook and for its response
+pub const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+// Header carrying "sha256=<hex HMAC-SHA256 of the body>" for hooks with a secret
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+fn default_max_attempts() -> u32 {
+    MAX_ATTEMPTS
+}
+
+// An endpoint the node POSTs the payloads of `topics` to, as {"topic": ..., "payload": ...}
+// with the same payloads subscribers get
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    // Plain http://host:port/path, there is no TLS
+    pub url: String,
+    pub topics: Vec<Topic>,
+    // Key the body is signed with, so the receiver can tell it came from the node
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+}
+
+pub fn sign_payload(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+struct Delivery {
+    hook: usize,
+    body: String,
+}
+
+// Fires the configured webhooks on chain events. Deliveries go out one at a time, in the
+// order the events were published, from a thread of their own so publishing never waits
+// on a slow endpoint. Hooks failing with anything but a 2xx are retried with backoff.
+pub struct Webhooks {
+    hooks: Arc<Vec<WebhookConfig>>,
+    queue: Sender<Delivery>,
+    delivered: Arc<AtomicU64>,
+    failed: Arc<AtomicU64>,
+}
+
+impl Webhooks {
+    pub fn start(hooks: Vec<WebhookConfig>) -> Self {
+        Self::with_retry_delay(hooks, RETRY_DELAY)
+    }
+
+    pub fn with_retry_delay(hooks: Vec<WebhookConfig>, retry_delay: Duration) -> Self {
+        let hooks = Arc::new(hooks);
+        let delivered = Arc::new(AtomicU64::new(0));
+        let failed = Arc::new(AtomicU64::new(0));
+        let (queue, deliveries) = channel::<Delivery>();
+
+        let (worker_hooks, worker_delivered, worker_failed) = (hooks.clone(), delivered.clone(), failed.clone());
+        thread::spawn(move || {
+            for delivery in deliveries {
+                let hook = &worker_hooks[delivery.hook];
+                if deliver(hook, &delivery.body, retry_delay) {
+                    worker_delivered.fetch_add(1, Ordering::SeqCst);
+                } else {
+                    worker_failed.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+
+        Self {
+            hooks,
+            queue,
+            delivered,
+            failed,
+        }
+    }
+
+    pub fn hooks(&self) -> &[WebhookConfig] {
+        &self.hooks
+    }
+
+    // Payloads the endpoints accepted so far
+    pub fn delivered(&self) -> u64 {
+        self.delivered.load(Ordering::SeqCst)
+    }
+
+    // Payloads given up on after running out of attempts
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::SeqCst)
+    }
+
+    pub fn publish(&self, event: &ChainEvent) {
+        for (index, hook) in self.hooks.iter().enumerate() {
+            for topic in &hook.topics {
+                for payload in payloads(topic, event) {
+                    let body = json!({ "topic": topic, "payload": payload }).to_string();
+                    // The worker only stops once this is dropped
+                    let _ = self.queue.send(Delivery { hook: index, body });
+                }
+            }
+        }
+    }
+}
+
+fn deliver(hook: &WebhookConfig, body: &str, retry_delay: Duration) -> bool {
+    let mut delay = retry_delay;
+    for attempt in 1..=hook.max_attempts {
+        if post(hook, body).is_ok() {
+            return true;
+        }
+        if attempt < hook.max_attempts {
+            thread::sleep(delay);
+            delay = delay.saturating_mul(2);
+        }
+    }
+    false
+}
+
+fn post(hook: &WebhookConfig, body: &str) -> Result<(), Error> {
+    let rest = hook
+        .url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("Unsupported webhook URL: {}", hook.url))?;
+    let (address, path) = match rest.find('/') {
+        Some(slash) => (&rest[..slash], &rest[slash..]),
+        None => (rest, "/"),
+    };
+
+    let mut stream = TcpStream::connect(address).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(WEBHOOK_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(WEBHOOK_TIMEOUT)).map_err(|e| e.to_string())?;
+    let signature = match &hook.secret {
+        Some(secret) => format!("{}: sha256={}\r\n", SIGNATURE_HEADER, sign_payload(secret, body.as_bytes())),
+        None => String::new(),
+    };
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+        path,
+        address,
+        body.len(),
+        signature,
+        body
+    );
+    stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(|e| e.to_string())?;
+    let status = response
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| "Malformed HTTP response".to_string())?;
+    if !(200..300).contains(&status) {
+        return Err(format!("HTTP {}", status));
+    }
+    Ok(())
+}
+
+// The payload of a webhook request, checked against its signature with `secret`
+pub fn verify_payload(secret: &str, body: &[u8], signature: &str) -> Option<Value> {
+    let expected = format!("sha256={}", sign_payload(secret, body));
+    if expected != signature {
+        return None;
+    }
+    serde_json::from_slice(body).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Block, Transaction, TransactionData};
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::mpsc::Receiver;
+    use std::time::Instant;
+
+    // Answers with `statuses` in turn, passing on the signature header and body of each request
+    fn endpoint(statuses: Vec<u16>) -> (String, Receiver<(Option<String>, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (requests, received) = channel();
+
+        thread::spawn(move || {
+            for (stream, status) in listener.incoming().zip(statuses) {
+                let mut reader = BufReader::new(stream.unwrap());
+                let (mut signature, mut length) = (None, 0);
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(": ") {
+                        match name {
+                            SIGNATURE_HEADER => signature = Some(value.to_string()),
+                            "Content-Length" => length = value.parse().unwrap(),
+                            _ => {}
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let response = format!("HTTP/1.1 {} Whatever\r\nContent-Length: 0\r\n\r\n", status);
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                requests.send((signature, String::from_utf8(body).unwrap())).unwrap();
+            }
+        });
+
+        (url, received)
+    }
+
+    fn block_with_transfer(to: &str) -> Block {
+        let mut block = Block::new(None);
+        block.add_transaction(Transaction::new(
+            TransactionData::Transfer {
+                to: to.to_string(),
+                amount: 5,
+            },
+            Some("alice".to_string()),
+            0,
+        ));
+        block
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < Duration::from_secs(10), "Timed out");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_signed_delivery_with_retries() {
+        let (url, received) = endpoint(vec![500, 200]);
+        let hook = WebhookConfig {
+            url,
+            topics: vec![Topic::NewBlocks, Topic::Address("carol".to_string())],
+            secret: Some("hunter2".to_string()),
+            max_attempts: 3,
+        };
+        let webhooks = Webhooks::with_retry_delay(vec![hook], Duration::from_millis(10));
+
+        // Nothing for carol in the block, so one delivery for the new block
+        webhooks.publish(&ChainEvent::NewBlock {
+            height: 0,
+            block: block_with_transfer("bob"),
+        });
+        wait_for(|| webhooks.delivered() == 1);
+        assert_eq!(webhooks.failed(), 0);
+
+        let requests: Vec<_> = received.try_iter().collect();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0], requests[1]);
+        let (signature, body) = &requests[1];
+        let payload = verify_payload("hunter2", body.as_bytes(), signature.as_ref().unwrap()).unwrap();
+        assert_eq!(payload["topic"], "new_blocks");
+        assert_eq!(payload["payload"]["transactions"][0]["data"]["to"], "bob");
+        assert!(verify_payload("hunter3", body.as_bytes(), signature.as_ref().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_gives_up_after_max_attempts() {
+        let (url, received) = endpoint(vec![503, 503]);
+        let hook: WebhookConfig = serde_json::from_value(json!({
+            "url": url,
+            "topics": ["reorgs", { "address": "bob" }],
+            "max_attempts": 2
+        }))
+        .unwrap();
+        assert_eq!(hook.secret, None);
+        let webhooks = Webhooks::with_retry_delay(vec![hook], Duration::from_millis(10));
+
+        // The reorg counts once for the topic and once for bob
+        webhooks.publish(&ChainEvent::BlockReverted {
+            height: 3,
+            block: block_with_transfer("bob"),
+        });
+        wait_for(|| webhooks.failed() == 2);
+        assert_eq!(webhooks.delivered(), 0);
+        assert!(received.try_iter().all(|(signature, _)| signature.is_none()));
+    }
+}
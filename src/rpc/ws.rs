@@ -0,0 +1,88 @@
+use crate::rpc::{SubscriptionSession, Subscriptions};
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tungstenite::Message;
+
+pub const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// WebSocket endpoint for subscriptions, one thread per client. Each thread alternates
+// between reading requests (with a short timeout) and pushing pending notifications. A
+// client reading too slowly holds its thread up in send until its queues fill and
+// Subscriptions drops them; everything is unsubscribed when the client disconnects.
+pub fn serve_ws(listener: TcpListener, subscriptions: Arc<Mutex<Subscriptions>>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let subscriptions = subscriptions.clone();
+            thread::spawn(move || handle_connection(stream, subscriptions));
+        }
+    })
+}
+
+fn handle_connection(stream: TcpStream, subscriptions: Arc<Mutex<Subscriptions>>) {
+    let mut socket = match tungstenite::accept(stream) {
+        Ok(socket) => socket,
+        Err(_) => return,
+    };
+    if socket.get_ref().set_read_timeout(Some(POLL_INTERVAL)).is_err() {
+        return;
+    }
+
+    let mut session = SubscriptionSession::new(subscriptions);
+    loop {
+        match socket.read() {
+            Ok(Message::Text(request)) => {
+                if socket.send(Message::Text(session.handle(&request))).is_err() {
+                    break;
+                }
+            }
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(error))
+                if error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::TimedOut => {}
+            Err(_) => break,
+        }
+
+        for notification in session.poll() {
+            if socket.send(Message::Text(notification.to_string())).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::ChainEvent;
+    use crate::types::Block;
+    use serde_json::Value;
+
+    #[test]
+    fn test_subscribe_over_websocket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let subscriptions = Arc::new(Mutex::new(Subscriptions::new()));
+        serve_ws(listener, subscriptions.clone());
+
+        let (mut client, _) = tungstenite::connect(format!("ws://{}", addr)).unwrap();
+        client
+            .send(Message::Text(
+                r#"{"jsonrpc":"2.0","id":1,"method":"subscribe","params":["newBlocks"]}"#.to_string(),
+            ))
+            .unwrap();
+        let response: Value = serde_json::from_str(&client.read().unwrap().into_text().unwrap()).unwrap();
+        assert_eq!(response["result"], 1);
+
+        subscriptions.lock().unwrap().publish(&ChainEvent::NewBlock {
+            height: 0,
+            block: Block::new(None),
+        });
+        let event: Value = serde_json::from_str(&client.read().unwrap().into_text().unwrap()).unwrap();
+        assert_eq!(event["params"]["result"]["height"], 0);
+
+        client.close(None).unwrap();
+    }
+}
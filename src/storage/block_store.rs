@@ -0,0 +1,454 @@
+use crate::storage::StoredBlocks;
+use crate::traits::Hashable;
+use crate::types::{Account, AccountId, Block, Error, Hash, StandingOrder};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+const BLOCK_PREFIX: &[u8] = b"block/";
+// Hash and target of each block, enough to open the chain without decoding the blocks.
+// Kept META_CHUNK blocks to a value, so opening reads a few hundred values, not a key
+// per block.
+const META_PREFIX: &[u8] = b"meta/";
+const META_CHUNK: u64 = 256;
+// The world state is kept as an entry per account, alias and standing order, so a block only writes
+// what it changed. Standing orders are keyed by their big endian position, which keeps
+// them in the order they are settled in.
+const ACCOUNT_PREFIX: &[u8] = b"account/";
+const ALIAS_PREFIX: &[u8] = b"alias/";
+const STANDING_ORDER_PREFIX: &[u8] = b"order/";
+// Where stores written before there were entries keep the whole state, moved to entries
+// on the first load
+const ACCOUNTS_KEY: &[u8] = b"accounts";
+const ALIASES_KEY: &[u8] = b"aliases";
+const STANDING_ORDERS_KEY: &[u8] = b"standing_orders";
+
+// What a store holds: the main chain, oldest block first, with the target each block
+// had to meet, and the accounts, aliases and standing orders as of the tip. Without the
+// blocks when only the state was loaded.
+#[derive(Debug, Default)]
+pub struct StoredChain {
+    pub blocks: Vec<(Block, u128)>,
+    pub accounts: HashMap<AccountId, Account>,
+    pub aliases: HashMap<String, AccountId>,
+    pub standing_orders: Vec<StandingOrder>,
+}
+
+// The world state entries a block changed, as they are after it, None for those that are
+// gone. Standing orders by position, see set_standing_orders.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StateChanges {
+    pub accounts: HashMap<AccountId, Option<Account>>,
+    pub aliases: HashMap<String, Option<AccountId>>,
+    pub standing_orders: BTreeMap<u64, Option<StandingOrder>>,
+}
+
+impl StateChanges {
+    // All of a world state, for a store that has none yet
+    pub fn everything(
+        accounts: &HashMap<AccountId, Account>,
+        aliases: &HashMap<String, AccountId>,
+        standing_orders: &[StandingOrder],
+    ) -> Self {
+        let mut changes = Self {
+            accounts: accounts.iter().map(|(id, account)| (id.clone(), Some(account.clone()))).collect(),
+            aliases: aliases.iter().map(|(alias, owner)| (alias.clone(), Some(owner.clone()))).collect(),
+            standing_orders: BTreeMap::new(),
+        };
+        changes.set_standing_orders(&[], standing_orders);
+        changes
+    }
+
+    // The positions whose order differs between `before` and `after`. Standing orders are
+    // few and rarely change, so comparing them is cheap.
+    pub fn set_standing_orders(&mut self, before: &[StandingOrder], after: &[StandingOrder]) {
+        self.standing_orders.clear();
+        for position in 0..before.len().max(after.len()) {
+            if before.get(position) != after.get(position) {
+                self.standing_orders.insert(position as u64, after.get(position).cloned());
+            }
+        }
+    }
+
+    fn write(&self, batch: &mut sled::Batch) -> Result<(), Error> {
+        for (account_id, account) in &self.accounts {
+            write_entry(batch, [ACCOUNT_PREFIX, account_id.as_bytes()].concat(), account.as_ref())?;
+        }
+        for (alias, owner) in &self.aliases {
+            write_entry(batch, [ALIAS_PREFIX, alias.as_bytes()].concat(), owner.as_ref())?;
+        }
+        for (position, order) in &self.standing_orders {
+            write_entry(batch, [STANDING_ORDER_PREFIX, &position.to_be_bytes()].concat(), order.as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+fn write_entry<T: serde::Serialize>(batch: &mut sled::Batch, key: Vec<u8>, value: Option<&T>) -> Result<(), Error> {
+    match value {
+        Some(value) => batch.insert(key, serde_json::to_vec(value).map_err(storage_error)?),
+        None => batch.remove(key),
+    }
+    Ok(())
+}
+
+// sled database holding the main chain and the world state. Blocks are keyed by their
+// big endian height so they come back in order, and every block is written in one batch
+// with the world state it produced, so a crash never leaves them out of step.
+// The world state is only ever read back by load, on startup. Blocks execute against the
+// copy the chain keeps in memory, so no balance check waits on the disk.
+#[derive(Debug, Clone)]
+pub struct BlockStore {
+    db: sled::Db,
+}
+
+fn storage_error(error: impl std::fmt::Display) -> Error {
+    format!("Storage error: {}", error)
+}
+
+fn block_key(height: u64) -> Vec<u8> {
+    [BLOCK_PREFIX, &height.to_be_bytes()].concat()
+}
+
+fn meta_key(chunk: u64) -> Vec<u8> {
+    [META_PREFIX, &chunk.to_be_bytes()].concat()
+}
+
+// Each block as its target, big endian, then its hash prefixed with its length
+fn encode_meta(hash: &Hash, target: u128, out: &mut Vec<u8>) {
+    out.extend_from_slice(&target.to_be_bytes());
+    out.push(hash.len() as u8);
+    out.extend_from_slice(hash.as_bytes());
+}
+
+fn decode_meta(mut chunk: &[u8]) -> Result<Vec<(Hash, u128)>, Error> {
+    let invalid = || storage_error("invalid block metadata");
+    let mut meta = vec![];
+    while !chunk.is_empty() {
+        if chunk.len() < 17 {
+            return Err(invalid());
+        }
+        let mut target = [0; 16];
+        target.copy_from_slice(&chunk[..16]);
+        let end = 17 + chunk[16] as usize;
+        let hash = chunk.get(17..end).ok_or_else(invalid)?;
+        let hash = String::from_utf8(hash.to_vec()).map_err(|_| invalid())?;
+        meta.push((hash, u128::from_be_bytes(target)));
+        chunk = &chunk[end..];
+    }
+    Ok(meta)
+}
+
+impl BlockStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        sled::open(path).map(|db| Self { db }).map_err(storage_error)
+    }
+
+    // Backed by a temporary directory that is removed on drop
+    pub fn temporary() -> Result<Self, Error> {
+        sled::Config::new()
+            .temporary(true)
+            .open()
+            .map(|db| Self { db })
+            .map_err(storage_error)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.db.scan_prefix(BLOCK_PREFIX).next().is_none()
+    }
+
+    // Adds the block at `height` along with the state entries it changed
+    pub fn append(&self, height: u64, block: &Block, target: u128, changes: &StateChanges) -> Result<(), Error> {
+        let mut batch = sled::Batch::default();
+        batch.insert(block_key(height), serde_json::to_vec(&(block, target)).map_err(storage_error)?);
+        // Only added to when it covers every block below, so the metadata always
+        // describes the bottom of the chain
+        let chunk_key = meta_key(height / META_CHUNK);
+        let mut chunk = self.db.get(&chunk_key).map_err(storage_error)?.map(|chunk| chunk.to_vec()).unwrap_or_default();
+        if decode_meta(&chunk)?.len() as u64 == height % META_CHUNK {
+            encode_meta(&block.hash(), target, &mut chunk);
+            batch.insert(chunk_key, chunk);
+        }
+        changes.write(&mut batch)?;
+
+        self.db.apply_batch(batch).map_err(storage_error)
+    }
+
+    // Drops the blocks from `height` on. `changes` takes the entries they changed back to
+    // what the block before left behind.
+    pub fn truncate(&self, height: u64, changes: &StateChanges) -> Result<(), Error> {
+        let mut batch = sled::Batch::default();
+        let chunk = height / META_CHUNK;
+        for (prefix, start) in [(BLOCK_PREFIX, block_key(height)), (META_PREFIX, meta_key(chunk))].iter() {
+            for key in self.db.range(start.as_slice()..).keys() {
+                let key = key.map_err(storage_error)?;
+                if !key.starts_with(prefix) {
+                    break;
+                }
+                batch.remove(key);
+            }
+        }
+        // The chunk holding `height` keeps the blocks before it
+        if let Some(value) = self.db.get(meta_key(chunk)).map_err(storage_error)? {
+            let kept = (height % META_CHUNK) as usize;
+            let meta = decode_meta(&value)?;
+            if kept > 0 && meta.len() >= kept {
+                let mut value = vec![];
+                for (hash, target) in &meta[..kept] {
+                    encode_meta(hash, *target, &mut value);
+                }
+                batch.insert(meta_key(chunk), value);
+            }
+        }
+        changes.write(&mut batch)?;
+
+        self.db.apply_batch(batch).map_err(storage_error)
+    }
+
+    pub fn load(&self) -> Result<StoredChain, Error> {
+        let mut stored = self.load_state()?;
+        for (expected, entry) in self.db.scan_prefix(BLOCK_PREFIX).enumerate() {
+            let (key, value) = entry.map_err(storage_error)?;
+            if key.as_ref() != block_key(expected as u64).as_slice() {
+                return Err(format!("Storage error: block {} is missing", expected));
+            }
+            stored.blocks.push(serde_json::from_slice(&value).map_err(storage_error)?);
+        }
+        Ok(stored)
+    }
+
+    // The main chain without decoding a single block, they are read when first asked
+    // for. Along with the target of each block, oldest first.
+    pub fn load_blocks(&self) -> Result<(StoredBlocks, Vec<u128>), Error> {
+        let mut hashes = vec![];
+        let mut targets = vec![];
+        for entry in self.db.scan_prefix(META_PREFIX) {
+            let (key, value) = entry.map_err(storage_error)?;
+            // Anything after a partial chunk doesn't follow on from it
+            let chunk = hashes.len() as u64 / META_CHUNK;
+            if chunk * META_CHUNK != hashes.len() as u64 || key.as_ref() != meta_key(chunk).as_slice() {
+                break;
+            }
+            for (hash, target) in decode_meta(&value)? {
+                hashes.push(hash);
+                targets.push(target);
+            }
+        }
+
+        // Blocks without metadata, from stores written before there was any, are decoded
+        // and their metadata written, so that happens only once
+        let described = hashes.len();
+        while let Some((block, target)) = self.load_block(hashes.len() as u64)? {
+            hashes.push(block.hash());
+            targets.push(target);
+        }
+        if hashes.len() > described {
+            let mut batch = sled::Batch::default();
+            let first = described as u64 / META_CHUNK * META_CHUNK;
+            for chunk_start in (first..hashes.len() as u64).step_by(META_CHUNK as usize) {
+                let mut value = vec![];
+                for height in chunk_start..(chunk_start + META_CHUNK).min(hashes.len() as u64) {
+                    encode_meta(&hashes[height as usize], targets[height as usize], &mut value);
+                }
+                batch.insert(meta_key(chunk_start / META_CHUNK), value);
+            }
+            self.db.apply_batch(batch).map_err(storage_error)?;
+        }
+
+        Ok((StoredBlocks::new(self.clone(), hashes), targets))
+    }
+
+    pub fn load_block(&self, height: u64) -> Result<Option<(Block, u128)>, Error> {
+        match self.db.get(block_key(height)).map_err(storage_error)? {
+            Some(value) => serde_json::from_slice(&value).map(Some).map_err(storage_error),
+            None => Ok(None),
+        }
+    }
+
+    // The accounts, aliases and standing orders, without the blocks
+    pub fn load_state(&self) -> Result<StoredChain, Error> {
+        self.migrate_state()?;
+
+        let mut stored = StoredChain::default();
+        for entry in self.db.scan_prefix(ACCOUNT_PREFIX) {
+            let (key, value) = entry.map_err(storage_error)?;
+            let account_id = String::from_utf8(key[ACCOUNT_PREFIX.len()..].to_vec()).map_err(storage_error)?;
+            stored.accounts.insert(account_id, serde_json::from_slice(&value).map_err(storage_error)?);
+        }
+        for entry in self.db.scan_prefix(ALIAS_PREFIX) {
+            let (key, value) = entry.map_err(storage_error)?;
+            let alias = String::from_utf8(key[ALIAS_PREFIX.len()..].to_vec()).map_err(storage_error)?;
+            stored.aliases.insert(alias, serde_json::from_slice(&value).map_err(storage_error)?);
+        }
+        for entry in self.db.scan_prefix(STANDING_ORDER_PREFIX) {
+            let (_, value) = entry.map_err(storage_error)?;
+            stored.standing_orders.push(serde_json::from_slice(&value).map_err(storage_error)?);
+        }
+        Ok(stored)
+    }
+
+    // Moves the state of stores from before there were entries to entries, so that
+    // happens only once
+    fn migrate_state(&self) -> Result<(), Error> {
+        let legacy = |key: &[u8]| self.db.get(key).map_err(storage_error);
+        let (accounts, aliases, standing_orders) = match (legacy(ACCOUNTS_KEY)?, legacy(ALIASES_KEY)?, legacy(STANDING_ORDERS_KEY)?) {
+            (None, None, None) => return Ok(()),
+            (accounts, aliases, standing_orders) => (accounts, aliases, standing_orders),
+        };
+
+        let mut stored = StoredChain::default();
+        if let Some(accounts) = accounts {
+            stored.accounts = serde_json::from_slice(&accounts).map_err(storage_error)?;
+        }
+        if let Some(aliases) = aliases {
+            stored.aliases = serde_json::from_slice(&aliases).map_err(storage_error)?;
+        }
+        if let Some(standing_orders) = standing_orders {
+            stored.standing_orders = serde_json::from_slice(&standing_orders).map_err(storage_error)?;
+        }
+        let mut batch = sled::Batch::default();
+        StateChanges::everything(&stored.accounts, &stored.aliases, &stored.standing_orders).write(&mut batch)?;
+        for key in [ACCOUNTS_KEY, ALIASES_KEY, STANDING_ORDERS_KEY] {
+            batch.remove(key);
+        }
+        self.db.apply_batch(batch).map_err(storage_error)
+    }
+
+    // Writes everything to disk, sled otherwise does it in the background
+    pub fn flush(&self) -> Result<(), Error> {
+        self.db.flush().map(|_| ()).map_err(storage_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::ChainStore;
+    use crate::types::AccountType;
+    use ed25519_dalek::Keypair;
+
+    #[test]
+    fn test_append_and_load() {
+        let store = BlockStore::temporary().unwrap();
+        assert!(store.is_empty());
+
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let aliases = HashMap::from([("ali".to_string(), "account0".to_string())]);
+        let standing_orders = vec![StandingOrder {
+            payer: "account0".to_string(),
+            payee: "account1".to_string(),
+            amount: 10,
+            interval: 2,
+            cap: 30,
+            paid: 0,
+            next_due: 3,
+        }];
+        let mut blocks = vec![];
+        let mut prev_hash = None;
+        for height in 0..3u64 {
+            let mut block = Block::new(prev_hash.clone());
+            block.set_height(height);
+            block.set_nonce(height as u128);
+            // Past the first block, each only carries the account it added
+            let mut changes = match height {
+                0 => StateChanges::everything(&HashMap::new(), &aliases, &standing_orders),
+                _ => StateChanges::default(),
+            };
+            let account = Account::new(AccountType::User, keypair.public);
+            changes.accounts.insert(format!("account{}", height), Some(account));
+            store.append(height, &block, u128::MAX >> height, &changes).unwrap();
+            prev_hash = Some(block.hash());
+            blocks.push(block);
+        }
+        assert!(!store.is_empty());
+
+        let stored = store.load().unwrap();
+        assert_eq!(stored.accounts.len(), 3);
+        assert_eq!(stored.aliases, aliases);
+        assert_eq!(stored.standing_orders, standing_orders);
+        for (height, (block, target)) in stored.blocks.iter().enumerate() {
+            assert_eq!(block.hash(), blocks[height].hash());
+            assert_eq!(*target, u128::MAX >> height);
+        }
+
+        // A gap in the heights means the store is damaged
+        store.append(4, &blocks[0], 0, &StateChanges::default()).unwrap();
+        assert_eq!(store.load().unwrap_err(), "Storage error: block 3 is missing");
+
+        let mut changes = StateChanges::default();
+        changes.accounts.insert("account2".to_string(), None);
+        changes.set_standing_orders(&standing_orders, &[]);
+        store.truncate(2, &changes).unwrap();
+        let stored = store.load().unwrap();
+        assert_eq!(stored.blocks.len(), 2);
+        assert_eq!(stored.accounts.len(), 2);
+        assert_eq!(stored.aliases, aliases);
+        assert!(stored.standing_orders.is_empty());
+    }
+
+    #[test]
+    fn test_set_standing_orders() {
+        let order = |amount| StandingOrder {
+            payer: "account0".to_string(),
+            payee: "account1".to_string(),
+            amount,
+            interval: 2,
+            cap: 30,
+            paid: 0,
+            next_due: 3,
+        };
+        let mut changes = StateChanges::default();
+        changes.set_standing_orders(&[order(1), order(2), order(3)], &[order(1), order(3)]);
+        assert_eq!(changes.standing_orders, BTreeMap::from([(1, Some(order(3))), (2, None)]));
+    }
+
+    #[test]
+    fn test_migrate_state() {
+        let store = BlockStore::temporary().unwrap();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let accounts = HashMap::from([("account0".to_string(), Account::new(AccountType::User, keypair.public))]);
+        let aliases = HashMap::from([("ali".to_string(), "account0".to_string())]);
+        store.db.insert(ACCOUNTS_KEY, serde_json::to_vec(&accounts).unwrap()).unwrap();
+        store.db.insert(ALIASES_KEY, serde_json::to_vec(&aliases).unwrap()).unwrap();
+
+        // A store from before there were entries gets them on the first load
+        let stored = store.load_state().unwrap();
+        assert_eq!(stored.accounts.len(), 1);
+        assert_eq!(stored.aliases, aliases);
+        assert!(store.db.get(ACCOUNTS_KEY).unwrap().is_none());
+        assert!(store.db.get([ALIAS_PREFIX, b"ali"].concat()).unwrap().is_some());
+        assert_eq!(store.load_state().unwrap().accounts.len(), 1);
+    }
+
+    #[test]
+    fn test_load_blocks() {
+        let store = BlockStore::temporary().unwrap();
+        let mut hashes = vec![];
+        let mut prev_hash = None;
+        for height in 0..300u64 {
+            let mut block = Block::new(prev_hash);
+            block.set_height(height);
+            store.append(height, &block, height as u128, &StateChanges::default()).unwrap();
+            prev_hash = Some(block.hash());
+            hashes.push(block.hash());
+        }
+
+        let (blocks, targets) = store.load_blocks().unwrap();
+        assert_eq!(blocks.len(), 300);
+        assert_eq!(blocks.loaded(), 0);
+        assert_eq!(blocks.hash_at(299), Some(&hashes[299]));
+        assert_eq!(targets[257], 257);
+
+        store.truncate(257, &StateChanges::default()).unwrap();
+        let (blocks, targets) = store.load_blocks().unwrap();
+        assert_eq!((blocks.len(), targets.len()), (257, 257));
+        assert_eq!(blocks.get_block_by_hash(&hashes[256]).unwrap().height(), 256);
+
+        // A store from before there was metadata gets it on the first load
+        for chunk in 0..2 {
+            store.db.remove(meta_key(chunk)).unwrap();
+        }
+        let (blocks, _) = store.load_blocks().unwrap();
+        assert_eq!(blocks.hash_at(100), Some(&hashes[100]));
+        assert_eq!(decode_meta(&store.db.get(meta_key(1)).unwrap().unwrap()).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,5 @@
+mod block_store;
+mod stored_blocks;
+
+pub use block_store::{BlockStore, StateChanges, StoredChain};
+pub use stored_blocks::StoredBlocks;
@@ -0,0 +1,116 @@
+use crate::storage::BlockStore;
+use crate::traits::{ChainStore, Hashable};
+use crate::types::{Block, Hash};
+use std::cell::OnceCell;
+use std::collections::HashMap;
+
+#[derive(Debug)]
+struct Slot {
+    hash: Hash,
+    // Boxed, as most slots stay empty
+    block: OnceCell<Box<Block>>,
+}
+
+// Main chain blocks that stay in the BlockStore until they are first asked for, so
+// opening a long chain only reads their hashes. Blocks appended after opening are kept
+// as they come. Persisting them is up to the Blockchain, as with any other ChainStore.
+#[derive(Debug, Default)]
+pub struct StoredBlocks {
+    store: Option<BlockStore>,
+    slots: Vec<Slot>,
+    // Built on the first lookup by hash
+    heights: OnceCell<HashMap<Hash, u64>>,
+}
+
+impl StoredBlocks {
+    // The blocks with `hashes`, oldest first, to be read from `store`
+    pub fn new(store: BlockStore, hashes: Vec<Hash>) -> Self {
+        let slots = hashes
+            .into_iter()
+            .map(|hash| Slot {
+                hash,
+                block: OnceCell::new(),
+            })
+            .collect();
+        Self {
+            store: Some(store),
+            slots,
+            heights: OnceCell::new(),
+        }
+    }
+
+    // Blocks read from the store or appended so far
+    pub fn loaded(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.block.get().is_some()).count()
+    }
+
+    pub fn hash_at(&self, height: u64) -> Option<&Hash> {
+        self.slots.get(height as usize).map(|slot| &slot.hash)
+    }
+
+    fn heights(&self) -> &HashMap<Hash, u64> {
+        self.heights.get_or_init(|| {
+            self.slots
+                .iter()
+                .enumerate()
+                .map(|(height, slot)| (slot.hash.clone(), height as u64))
+                .collect()
+        })
+    }
+
+    fn read(&self, height: u64) -> Option<Block> {
+        // A block that can't be read back is as good as missing
+        match self.store.as_ref()?.load_block(height) {
+            Ok(Some((block, _))) => Some(block),
+            _ => None,
+        }
+    }
+}
+
+impl ChainStore for StoredBlocks {
+    fn put_block(&mut self, block: Block) {
+        let hash = block.hash();
+        let height = self.slots.len() as u64;
+        if let Some(heights) = self.heights.get_mut() {
+            heights.insert(hash.clone(), height);
+        }
+        self.slots.push(Slot {
+            hash,
+            block: OnceCell::from(Box::new(block)),
+        });
+    }
+
+    fn pop_block(&mut self) -> Option<Block> {
+        let height = self.slots.len().checked_sub(1)? as u64;
+        let block = match self.slots.last_mut()?.block.take() {
+            Some(block) => *block,
+            None => self.read(height)?,
+        };
+        let slot = self.slots.pop()?;
+        if let Some(heights) = self.heights.get_mut() {
+            heights.remove(&slot.hash);
+        }
+        Some(block)
+    }
+
+    fn get_block_by_hash(&self, hash: &Hash) -> Option<&Block> {
+        self.get_block_by_height(*self.heights().get(hash)?)
+    }
+
+    fn get_block_by_height(&self, height: u64) -> Option<&Block> {
+        let slot = self.slots.get(height as usize)?;
+        if let Some(block) = slot.block.get() {
+            return Some(block);
+        }
+        let block = self.read(height)?;
+        Some(slot.block.get_or_init(|| Box::new(block)))
+    }
+
+    fn head(&self) -> Option<&Block> {
+        self.get_block_by_height(self.slots.len().checked_sub(1)? as u64)
+    }
+
+    fn len(&self) -> usize {
+        self.slots.len()
+    }
+}
@@ -0,0 +1,200 @@
+// Headers-first synchronization: a node catching up first fetches the headers of the
+// chain it is missing and checks their linkage and proof of work, which costs a hash per
+// block, and only then fetches the bodies and executes them in order. A peer sending a
+// bogus chain is caught before any of it is downloaded in full.
+use crate::consensus::{decode_bits, meets_target};
+use crate::traits::{ChainStore, Hashable};
+use crate::types::{Block, BlockHeader, Blockchain, Error, Hash};
+
+// Most headers asked for at once
+pub const MAX_HEADERS: u64 = 2000;
+// Most bodies asked for at once
+pub const MAX_BODIES: usize = 128;
+
+// Where a syncing node gets the chain from
+pub trait SyncPeer {
+    // Main chain headers from `from` up, oldest first, at most `max` and fewer only once
+    // the peer's tip is reached
+    fn headers(&mut self, from: u64, max: u64) -> Result<Vec<BlockHeader>, Error>;
+
+    // The blocks with `hashes`, in the same order
+    fn blocks(&mut self, hashes: &[Hash]) -> Result<Vec<Block>, Error>;
+}
+
+// A chain in the same process serves as a peer, e.g. to bootstrap from a local copy
+impl<S: ChainStore> SyncPeer for Blockchain<S> {
+    fn headers(&mut self, from: u64, max: u64) -> Result<Vec<BlockHeader>, Error> {
+        Ok((from..from.saturating_add(max))
+            .map_while(|height| self.get_block_by_height(height))
+            .map(|block| block.header().clone())
+            .collect())
+    }
+
+    fn blocks(&mut self, hashes: &[Hash]) -> Result<Vec<Block>, Error> {
+        hashes
+            .iter()
+            .map(|hash| self.get_block_by_hash(hash).cloned().ok_or_else(|| format!("Unknown block {}", hash)))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncReport {
+    pub headers: u64,
+    pub blocks: u64,
+}
+
+// Checks that `header` goes on top of the one before it and meets the bits it states
+// within the pow limit of `blockchain`. Retargeting is left to executing the block.
+fn verify_header<S: ChainStore>(blockchain: &Blockchain<S>, header: &BlockHeader, prev_hash: Option<&Hash>, height: u64) -> Result<(), Error> {
+    if header.prev_hash.as_ref() != prev_hash {
+        return Err(format!("Header {} doesn't build on the one before", height));
+    }
+    if header.height != height {
+        return Err(format!("Header {} claims height {}", height, header.height));
+    }
+    // Genesis is not mined
+    if height == 0 {
+        return Ok(());
+    }
+
+    let target = decode_bits(header.bits)?;
+    if target > blockchain.max_target() {
+        return Err(format!("Header {} has bits easier than the pow limit", height));
+    }
+    if !meets_target(&blockchain.pow().digest(&header.hash()), target) {
+        return Err(format!("Header {} doesn't meet its target", height));
+    }
+    Ok(())
+}
+
+// Brings `blockchain` up to the tip of `peer`, whose chain has to extend it. Fails on the
+// first header or block that doesn't check out, keeping the blocks executed until then.
+pub fn sync<S: ChainStore, P: SyncPeer>(blockchain: &mut Blockchain<S>, peer: &mut P) -> Result<SyncReport, Error> {
+    let start = blockchain.len() as u64;
+    let mut headers: Vec<BlockHeader> = vec![];
+    loop {
+        let from = start + headers.len() as u64;
+        let batch = peer.headers(from, MAX_HEADERS)?;
+        let complete = (batch.len() as u64) < MAX_HEADERS;
+
+        for header in batch.into_iter().take(MAX_HEADERS as usize) {
+            let height = start + headers.len() as u64;
+            let prev_hash = match headers.last() {
+                Some(prev) => Some(prev.hash()),
+                None => blockchain.get_last_block_hash(),
+            };
+            verify_header(blockchain, &header, prev_hash.as_ref(), height)?;
+            headers.push(header);
+        }
+        if complete {
+            break;
+        }
+    }
+
+    let hashes: Vec<Hash> = headers.iter().map(|header| header.hash()).collect();
+    let mut report = SyncReport {
+        headers: headers.len() as u64,
+        blocks: 0,
+    };
+    for batch in hashes.chunks(MAX_BODIES) {
+        let blocks = peer.blocks(batch)?;
+        if blocks.len() != batch.len() {
+            return Err(format!("Peer sent {} of {} blocks", blocks.len(), batch.len()));
+        }
+
+        for (block, hash) in blocks.into_iter().zip(batch) {
+            if &block.hash() != hash {
+                return Err(format!("Block {} doesn't match its header", block.height()));
+            }
+            blockchain.append_block(block).map_err(|error| error.to_string())?;
+            report.blocks += 1;
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Fixtures;
+    use crate::utils::append_block;
+
+    const TEST_TARGET: u128 = u128::MAX >> 8;
+
+    fn chain(seed: u64, blocks: u128) -> (Blockchain, Fixtures) {
+        let mut fixtures = Fixtures::new(seed);
+        let mut source = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        for nonce in 0..blocks {
+            append_block(&mut source, &mut fixtures, nonce);
+        }
+
+        (source, fixtures)
+    }
+
+    // Serves `source` with the header at the height of `forged` swapped for it
+    struct TestPeer {
+        source: Blockchain,
+        forged: Option<(u64, BlockHeader)>,
+        requests: usize,
+    }
+
+    impl SyncPeer for TestPeer {
+        fn headers(&mut self, from: u64, max: u64) -> Result<Vec<BlockHeader>, Error> {
+            self.requests += 1;
+            let mut headers = self.source.headers(from, max)?;
+            if let Some((height, forged)) = &self.forged {
+                if let Some(header) = height.checked_sub(from).and_then(|index| headers.get_mut(index as usize)) {
+                    *header = forged.clone();
+                }
+            }
+            Ok(headers)
+        }
+
+        fn blocks(&mut self, hashes: &[Hash]) -> Result<Vec<Block>, Error> {
+            self.source.blocks(hashes)
+        }
+    }
+
+    #[test]
+    fn test_sync_from_scratch_and_from_behind() {
+        let (mut source, fixtures) = chain(1, 5);
+        let mut node = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+
+        assert_eq!(sync(&mut node, &mut source), Ok(SyncReport { headers: 5, blocks: 5 }));
+        assert_eq!(node.get_last_block_hash(), source.get_last_block_hash());
+        assert_eq!(node.state_fingerprint(), source.state_fingerprint());
+
+        // Nothing new
+        assert_eq!(sync(&mut node, &mut source), Ok(SyncReport { headers: 0, blocks: 0 }));
+    }
+
+    #[test]
+    fn test_invalid_headers_stop_before_bodies() {
+        let (source, fixtures) = chain(1, 4);
+        let mut forged = source.get_block_by_height(2).unwrap().header().clone();
+        // Still linked, but without the proof of work
+        forged.timestamp += 1;
+        while meets_target(&forged.hash(), TEST_TARGET) {
+            forged.timestamp += 1;
+        }
+        let mut peer = TestPeer {
+            source,
+            forged: Some((2, forged)),
+            requests: 0,
+        };
+
+        let mut node = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        assert_eq!(sync(&mut node, &mut peer), Err("Header 2 doesn't meet its target".to_string()));
+        assert!(node.is_empty());
+        assert_eq!(peer.requests, 1);
+
+        // A chain that doesn't extend the node's
+        let (mut other, _) = chain(2, 3);
+        peer.forged = None;
+        let mut node = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        assert!(sync(&mut node, &mut other).is_ok());
+        assert_eq!(sync(&mut node, &mut peer), Err("Header 3 doesn't build on the one before".to_string()));
+    }
+}
@@ -0,0 +1,48 @@
+use crate::traits::Clock;
+use crate::types::Timestamp;
+use std::sync::{Arc, Mutex};
+
+// A clock that only moves when told to. Clones share the same time, so a test can keep
+// one and fast-forward whatever it handed the other to.
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    now: Arc<Mutex<Timestamp>>,
+}
+
+impl MockClock {
+    pub fn new(now: Timestamp) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: Timestamp) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, seconds: Timestamp) {
+        *self.now.lock().unwrap() += seconds;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Timestamp {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_time() {
+        let clock = MockClock::new(100);
+        let shared = clock.clone();
+
+        clock.advance(20);
+        assert_eq!(shared.now(), 120);
+        shared.set(5);
+        assert_eq!(clock.now(), 5);
+    }
+}
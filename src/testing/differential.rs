@@ -0,0 +1,172 @@
+use crate::testing::MockClock;
+use crate::traits::Hashable;
+use crate::types::{Block, BlockStatus, Blockchain, BlockchainError, Error, Hash, Transaction};
+
+// Two chains fed the same blocks and transactions that differ only in how they roll back,
+// one through the state overlays and one by copying the whole state as it used to. Any
+// difference in what they accept or the state they end up in is a bug in the overlays.
+pub struct Differential {
+    overlay: Blockchain,
+    copying: Blockchain,
+}
+
+impl Differential {
+    pub fn new(max_target: u128, clock: MockClock) -> Self {
+        let overlay = Blockchain::with_max_target(max_target).with_clock(clock.clone());
+        let mut copying = Blockchain::with_max_target(max_target).with_clock(clock);
+        copying.set_rollback_by_copy(true);
+
+        Self { overlay, copying }
+    }
+
+    // The chain rolling back through the overlays
+    pub fn chain(&self) -> &Blockchain {
+        &self.overlay
+    }
+
+    // What the chains made of `block`, or how they disagree
+    pub fn process_block(&mut self, block: Block) -> Result<Result<BlockStatus, BlockchainError>, String> {
+        let hash = block.hash();
+        let overlay = self.overlay.process_block(block.clone());
+        let copying = self.copying.process_block(block);
+        if overlay != copying {
+            return Err(format!("Block {}: {:?} with overlays, {:?} by copy", hash, overlay, copying));
+        }
+
+        self.compare(&hash)?;
+        Ok(overlay)
+    }
+
+    pub fn submit_transaction(&mut self, transaction: Transaction) -> Result<Result<Hash, Error>, String> {
+        let hash = transaction.hash();
+        let overlay = self.overlay.submit_transaction(transaction.clone());
+        let copying = self.copying.submit_transaction(transaction);
+        if overlay != copying {
+            return Err(format!("Transaction {}: {:?} with overlays, {:?} by copy", hash, overlay, copying));
+        }
+
+        self.compare(&hash)?;
+        Ok(overlay)
+    }
+
+    // Blocks on the tip, on nothing, on garbage or on earlier blocks so side branches and
+    // reorgs come up, interleaved with transactions, until `u` runs out
+    #[cfg(feature = "arbitrary")]
+    pub fn run_arbitrary(&mut self, u: &mut arbitrary::Unstructured) -> Result<(), String> {
+        use arbitrary::Arbitrary;
+
+        while !u.is_empty() {
+            if u.ratio(1, 4).unwrap_or_default() {
+                if let Ok(transaction) = Transaction::arbitrary(u) {
+                    let _ = self.submit_transaction(transaction)?;
+                }
+                continue;
+            }
+
+            let (prev_hash, height) = match u.int_in_range(0..=4).unwrap_or_default() {
+                0 => (None, 0),
+                1 => (Hash::arbitrary(u).ok(), 1),
+                2 if !self.overlay.is_empty() => {
+                    let parent = u.int_in_range(0..=self.overlay.len() as u64 - 1).unwrap_or_default();
+                    (self.overlay.get_block_by_height(parent).map(|block| block.hash()), parent + 1)
+                }
+                _ => (self.overlay.get_last_block_hash(), self.overlay.len() as u64),
+            };
+            let mut block = Block::new(prev_hash);
+            block.set_height(height);
+            for transaction in Vec::<Transaction>::arbitrary(u).unwrap_or_default() {
+                block.add_transaction(transaction);
+            }
+            if let Ok(nonce) = u.arbitrary() {
+                block.set_nonce(nonce);
+            }
+            let _ = self.process_block(block)?;
+        }
+
+        Ok(())
+    }
+
+    fn compare(&self, after: &Hash) -> Result<(), String> {
+        let tip = self.overlay.len().checked_sub(1).map(|tip| tip as u64);
+        let overlay = (
+            self.overlay.state_fingerprint(),
+            self.overlay.pending_transactions().len(),
+            tip.and_then(|tip| self.overlay.accounts_at(tip)),
+        );
+        let copying = (
+            self.copying.state_fingerprint(),
+            self.copying.pending_transactions().len(),
+            tip.and_then(|tip| self.copying.accounts_at(tip)),
+        );
+        if overlay != copying {
+            return Err(format!("States differ after {}", after));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::strategies::{account_id, chain_and_block, TARGET};
+    use crate::testing::GENESIS_TIME;
+    use crate::types::TransactionData;
+    use crate::utils::mine_block;
+    use proptest::prelude::*;
+
+    // The block with a transfer that can't execute added after its own transactions
+    fn with_failing_transfer(block: &Block) -> Block {
+        let mut failing = Transaction::new(
+            TransactionData::Transfer {
+                to: account_id(0),
+                amount: 1,
+            },
+            Some(account_id(1)),
+            0,
+        );
+        failing.set_nonce(u128::MAX);
+
+        let mut broken = Block::new(block.header.prev_hash.clone());
+        broken.set_height(block.height());
+        for transaction in block.transactions() {
+            broken.add_transaction(transaction.clone());
+        }
+        broken.add_transaction(failing);
+        mine_block(&mut broken, TARGET);
+        broken
+    }
+
+    proptest! {
+        #[test]
+        fn test_rollback_paths_agree((chain, block) in chain_and_block(6)) {
+            let mut differential = Differential::new(TARGET, MockClock::new(GENESIS_TIME));
+
+            for block in chain.into_iter().chain(Some(block)) {
+                // Rejected by both, after running part of the way
+                if block.height() > 0 {
+                    let broken = with_failing_transfer(&block);
+                    prop_assert!(differential.process_block(broken).unwrap().is_err());
+                }
+                prop_assert!(differential.process_block(block).unwrap().is_ok());
+            }
+            prop_assert!(differential.chain().validate().is_ok());
+        }
+    }
+
+    // Cheap stand-in for the fuzz target that runs on stable
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary_streams_agree() {
+        use rand::{RngCore, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let mut bytes = vec![0u8; 4096];
+            rng.fill_bytes(&mut bytes);
+
+            let mut differential = Differential::new(u128::MAX, MockClock::new(GENESIS_TIME));
+            differential.run_arbitrary(&mut arbitrary::Unstructured::new(&bytes)).unwrap();
+            assert!(differential.chain().validate().is_ok());
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use crate::testing::MockClock;
+use crate::traits::Clock;
+use crate::types::{AccountId, Timestamp};
+use crate::utils::generate_account_id_with;
+use ed25519_dalek::Keypair;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+pub const DEFAULT_SEED: u64 = 42;
+// 2020-09-13T12:26:40Z
+pub const GENESIS_TIME: Timestamp = 1_600_000_000;
+
+// Keys, account ids and timestamps for tests, all derived from one seed
+pub struct Fixtures {
+    rng: StdRng,
+    clock: MockClock,
+}
+
+impl Fixtures {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            clock: MockClock::new(GENESIS_TIME),
+        }
+    }
+
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    pub fn clock(&self) -> &MockClock {
+        &self.clock
+    }
+
+    pub fn keypair(&mut self) -> Keypair {
+        Keypair::generate(&mut self.rng)
+    }
+
+    pub fn account_id(&mut self) -> AccountId {
+        generate_account_id_with(&mut self.rng)
+    }
+
+    pub fn timestamp(&self) -> Timestamp {
+        self.clock.now()
+    }
+}
+
+impl Default for Fixtures {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_fixtures() {
+        let (mut first, mut second) = (Fixtures::new(7), Fixtures::new(7));
+
+        assert_eq!(first.keypair().to_bytes().to_vec(), second.keypair().to_bytes().to_vec());
+        assert_eq!(first.account_id(), second.account_id());
+        assert_ne!(first.account_id(), Fixtures::new(8).account_id());
+
+        first.clock().advance(600);
+        assert_eq!(first.timestamp(), GENESIS_TIME + 600);
+        assert_eq!(second.timestamp(), GENESIS_TIME);
+    }
+}
@@ -0,0 +1,9 @@
+mod clock;
+mod differential;
+mod fixtures;
+#[cfg(any(test, feature = "proptest"))]
+pub mod strategies;
+
+pub use clock::MockClock;
+pub use differential::Differential;
+pub use fixtures::{Fixtures, DEFAULT_SEED, GENESIS_TIME};
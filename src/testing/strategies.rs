@@ -0,0 +1,249 @@
+use crate::testing::GENESIS_TIME;
+use crate::traits::Hashable;
+use crate::types::{AccountId, Balance, Block, Hash, Timestamp, Transaction, TransactionData};
+use crate::utils::mine_block;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::Index;
+
+// Proptest generators for consensus types. Chains start with a genesis block that creates
+// and funds up to MAX_ACCOUNTS accounts, every later block holds signed transfers between
+// them, so they append cleanly to Blockchain::with_max_target(TARGET) with its clock at
+// GENESIS_TIME.
+
+pub const TARGET: u128 = u128::MAX;
+pub const MAX_ACCOUNTS: usize = 4;
+pub const MAX_SUPPLY: Balance = 1_000_000;
+pub const MAX_BLOCK_TRANSACTIONS: usize = 4;
+
+type TransferPlan = (Index, Index, Balance);
+
+pub fn account_id(index: usize) -> AccountId {
+    format!("account{}", index)
+}
+
+pub fn keypair() -> impl Strategy<Value = Keypair> {
+    any::<[u8; 32]>().prop_map(|bytes| {
+        // Every 32 byte string is a valid ed25519 secret key
+        let secret = SecretKey::from_bytes(&bytes).unwrap();
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    })
+}
+
+pub fn create_account() -> impl Strategy<Value = Transaction> {
+    ("[a-z]{1,12}", keypair(), any::<u64>()).prop_map(|(account_id, keypair, timestamp)| {
+        Transaction::new(
+            TransactionData::CreateAccount(account_id, keypair.public),
+            None,
+            timestamp as Timestamp,
+        )
+    })
+}
+
+// A chain of 1..=max_blocks blocks, genesis first
+pub fn chain(max_blocks: usize) -> impl Strategy<Value = Vec<Block>> {
+    ledger_and_chain(max_blocks).prop_map(|(_, chain)| chain)
+}
+
+// A chain and a valid block on top of its tip
+pub fn chain_and_block(max_blocks: usize) -> impl Strategy<Value = (Vec<Block>, Block)> {
+    (ledger_and_chain(max_blocks), block_plan()).prop_map(|((mut ledger, chain), plan)| {
+        let block = ledger.block(chain.last().map(|block| block.hash()), chain.len() as u64, plan);
+        (chain, block)
+    })
+}
+
+// A chain and a transfer that executes against its final state
+pub fn chain_and_transaction(max_blocks: usize) -> impl Strategy<Value = (Vec<Block>, Transaction)> {
+    (ledger_and_chain(max_blocks), transfer_plan())
+        .prop_map(|((mut ledger, chain), plan)| (chain, ledger.transfer(plan)))
+}
+
+fn transfer_plan() -> impl Strategy<Value = TransferPlan> {
+    (any::<Index>(), any::<Index>(), 0..=MAX_SUPPLY)
+}
+
+fn block_plan() -> impl Strategy<Value = Vec<TransferPlan>> {
+    vec(transfer_plan(), 1..=MAX_BLOCK_TRANSACTIONS)
+}
+
+fn ledger_and_chain(max_blocks: usize) -> impl Strategy<Value = (Ledger, Vec<Block>)> {
+    assert!(max_blocks > 0, "A chain has at least a genesis block");
+
+    (
+        vec((keypair(), 0..=MAX_SUPPLY), 1..=MAX_ACCOUNTS),
+        vec(block_plan(), 0..max_blocks),
+    )
+        .prop_map(|(wallets, plans)| {
+            let (mut ledger, genesis) = Ledger::genesis(wallets);
+            let mut chain = vec![genesis];
+            for plan in plans {
+                let block = ledger.block(chain.last().map(|block| block.hash()), chain.len() as u64, plan);
+                chain.push(block);
+            }
+
+            (ledger, chain)
+        })
+}
+
+// Tracks balances and nonces while a chain is generated so transfers never overdraw
+#[derive(Debug)]
+struct Ledger {
+    accounts: Vec<(Keypair, Balance)>,
+    nonces: Vec<u128>,
+    time: Timestamp,
+}
+
+impl Ledger {
+    fn genesis(accounts: Vec<(Keypair, Balance)>) -> (Self, Block) {
+        let mut genesis = Block::new(None);
+        for (index, (keypair, supply)) in accounts.iter().enumerate() {
+            genesis.add_transaction(Transaction::new(
+                TransactionData::CreateAccount(account_id(index), keypair.public),
+                None,
+                GENESIS_TIME,
+            ));
+            genesis.add_transaction(Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: account_id(index),
+                    amount: *supply,
+                },
+                None,
+                GENESIS_TIME,
+            ));
+        }
+
+        let nonces = vec![0; accounts.len()];
+        (
+            Self {
+                accounts,
+                nonces,
+                time: GENESIS_TIME,
+            },
+            genesis,
+        )
+    }
+
+    fn transfer(&mut self, plan: TransferPlan) -> Transaction {
+        let (transaction, to, amount) = self.debit(plan);
+        self.accounts[to].1 += amount;
+
+        transaction
+    }
+
+    // A transfer taken from the sender's balance only, the recipient is credited by the caller
+    fn debit(&mut self, (from, to, amount): TransferPlan) -> (Transaction, usize, Balance) {
+        let from = from.index(self.accounts.len());
+        let to = to.index(self.accounts.len());
+        let amount = amount.min(self.accounts[from].1);
+        self.accounts[from].1 -= amount;
+        // Distinct timestamps keep otherwise identical transfers apart
+        self.time += 1;
+
+        let mut transaction = Transaction::new(
+            TransactionData::Transfer {
+                to: account_id(to),
+                amount,
+            },
+            Some(account_id(from)),
+            self.time,
+        );
+        self.nonces[from] += 1;
+        transaction.set_nonce(self.nonces[from]);
+        transaction.sign(Some(self.accounts[from].0.sign(&transaction.signing_message())));
+
+        (transaction, to, amount)
+    }
+
+    // Funds received within a block are only spent in later ones, so the transfers execute
+    // in the canonical order, which is by nonce and then hash on a chain without fees
+    fn block(&mut self, prev_hash: Option<Hash>, height: u64, plan: Vec<TransferPlan>) -> Block {
+        let mut transfers: Vec<_> = plan.into_iter().map(|transfer| self.debit(transfer)).collect();
+        for (_, to, amount) in &transfers {
+            self.accounts[*to].1 += amount;
+        }
+        transfers.sort_by_cached_key(|(transaction, _, _)| (transaction.nonce(), transaction.hash()));
+
+        let mut block = Block::new(prev_hash);
+        block.set_height(height);
+        for (transaction, _, _) in transfers {
+            block.add_transaction(transaction);
+        }
+        mine_block(&mut block, TARGET);
+
+        block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockClock;
+    use crate::traits::WorldState;
+    use crate::types::{Blockchain, BlockchainError};
+
+    fn blockchain(chain: Vec<Block>) -> Blockchain {
+        let mut bc = Blockchain::with_max_target(TARGET).with_clock(MockClock::new(GENESIS_TIME));
+        for block in chain {
+            assert!(bc.append_block(block).is_ok());
+        }
+
+        bc
+    }
+
+    fn snapshot(bc: &Blockchain) -> String {
+        let accounts: Vec<_> = (0..MAX_ACCOUNTS)
+            .map(|index| bc.get_account_by_id(account_id(index)).cloned())
+            .collect();
+
+        format!("{:?}", (bc.len(), bc.get_last_block_hash(), bc.target(), accounts))
+    }
+
+    proptest! {
+        #[test]
+        fn test_append_then_validate((chain, block) in chain_and_block(8)) {
+            let mut bc = blockchain(chain);
+
+            prop_assert!(bc.append_block(block).is_ok());
+            prop_assert!(bc.validate().is_ok());
+        }
+
+        #[test]
+        fn test_transaction_is_accepted((chain, transaction) in chain_and_transaction(8)) {
+            let mut bc = blockchain(chain);
+
+            prop_assert_eq!(bc.submit_transaction(transaction.clone()), Ok(transaction.hash()));
+        }
+
+        #[test]
+        fn test_rollback_restores_state((chain, block) in chain_and_block(8)) {
+            let mut bc = blockchain(chain);
+            let before = snapshot(&bc);
+
+            // Whatever valid transfers run before it have to be undone
+            let mut failing = Transaction::new(
+                TransactionData::Transfer { to: account_id(0), amount: 1 },
+                Some("nobody".to_string()),
+                0,
+            );
+            failing.set_nonce(u128::MAX);
+            let mut transactions = block.transactions().to_vec();
+            transactions.push(failing);
+            bc.sort_transactions(&mut transactions);
+            let timestamp = block.timestamp();
+            let height = block.height();
+            let mut block = Block::new(block.header.prev_hash.clone());
+            block.set_height(height);
+            block.set_timestamp(timestamp);
+            for transaction in transactions {
+                block.add_transaction(transaction);
+            }
+            mine_block(&mut block, TARGET);
+
+            prop_assert!(matches!(bc.append_block(block), Err(BlockchainError::TransactionFailed(_))));
+            prop_assert_eq!(snapshot(&bc), before);
+        }
+    }
+}
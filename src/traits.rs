@@ -1,17 +1,234 @@
-use crate::types::{Account, AccountId, AccountType, Error, Hash};
+use crate::types::{
+    Account, AccountId, AccountRole, AccountType, Balance, Block, BlockchainError, Hash, StandingOrder, Timestamp,
+    Transaction, ALIAS_PREFIX,
+};
+use blake2::digest::FixedOutput;
+use blake2::{Blake2s, Digest};
+use std::fmt::Debug;
 use ed25519_dalek::{PublicKey};
 
 pub trait Hashable {
     fn hash(&self) -> Hash;
 }
 
+// The bytes that get hashed. Integers are big endian at their full width, strings and
+// sequences are prefixed with their length as a u32 and options with a 0 or 1 byte, so
+// the encoding is unambiguous and doesn't depend on the compiler like Debug output does.
+pub trait CanonicalEncode {
+    fn encode_to(&self, out: &mut Vec<u8>);
+
+    fn canonical_bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.encode_to(&mut out);
+        out
+    }
+
+    // Hex Blake2s of the canonical bytes
+    fn canonical_hash(&self) -> Hash {
+        let mut hasher = Blake2s::new();
+        hasher.update(self.canonical_bytes());
+        hex::encode(hasher.finalize_fixed())
+    }
+}
+
+macro_rules! encode_integers {
+    ($($int:ty),*) => {
+        $(impl CanonicalEncode for $int {
+            fn encode_to(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_be_bytes());
+            }
+        })*
+    };
+}
+
+encode_integers!(u8, u32, u64, u128);
+
+impl CanonicalEncode for str {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode_to(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl CanonicalEncode for String {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.as_str().encode_to(out);
+    }
+}
+
+impl<T: CanonicalEncode> CanonicalEncode for Option<T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Some(value) => {
+                out.push(1);
+                value.encode_to(out);
+            }
+            None => out.push(0),
+        }
+    }
+}
+
+impl<T: CanonicalEncode> CanonicalEncode for [T] {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode_to(out);
+        for item in self {
+            item.encode_to(out);
+        }
+    }
+}
+
+impl<T: CanonicalEncode> CanonicalEncode for Vec<T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode_to(out);
+    }
+}
+
+impl<T: CanonicalEncode + ?Sized> CanonicalEncode for &T {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (**self).encode_to(out);
+    }
+}
+
+// Always 32 bytes, no prefix needed
+impl CanonicalEncode for PublicKey {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+// Tuples are their fields one after the other
+macro_rules! encode_tuples {
+    ($(($($name:ident),+)),*) => {
+        $(impl<$($name: CanonicalEncode),+> CanonicalEncode for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_to(&self, out: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode_to(out);)+
+            }
+        })*
+    };
+}
+
+encode_tuples!((A, B), (A, B, C), (A, B, C, D), (A, B, C, D, E), (A, B, C, D, E, F));
+
 pub trait WorldState {
     fn create_account(
         &mut self,
         account_id: AccountId,
         account_type: AccountType,
         public_key: PublicKey,
-    ) -> Result<(), Error>;
+    ) -> Result<(), BlockchainError>;
     fn get_account_by_id(&self, account_id: AccountId) -> Option<&Account>;
     fn get_account_by_id_mut(&mut self, account_id: AccountId) -> Option<&mut Account>;
+    fn alias_owner(&self, alias: &str) -> Option<&AccountId>;
+    fn set_alias_owner(&mut self, alias: String, owner: AccountId);
+    // Replaces any order between the same payer and payee
+    fn set_standing_order(&mut self, order: StandingOrder);
+    fn cancel_standing_order(&mut self, payer: &AccountId, payee: &AccountId) -> Option<StandingOrder>;
+    // Height of the block the transactions are executed in
+    fn height(&self) -> u64;
+
+    // Transactions have to name this chain to execute
+    fn chain_id(&self) -> &str {
+        ""
+    }
+
+    // Whether `transaction` carries a valid signature by `public_key`. States that keep a
+    // SignatureCache answer from it.
+    fn is_signed_by(&self, transaction: &Transaction, public_key: &PublicKey) -> bool {
+        transaction.is_signed_by(public_key)
+    }
+
+    // The account an alias reference like "@alice" currently belongs to, other ids as they are
+    fn resolve_account(&self, account_id: &AccountId) -> Result<AccountId, BlockchainError> {
+        match account_id.strip_prefix(ALIAS_PREFIX) {
+            Some(alias) => self
+                .alias_owner(alias)
+                .cloned()
+                .ok_or_else(|| BlockchainError::UnknownAlias(alias.to_string())),
+            None => Ok(account_id.clone()),
+        }
+    }
+
+    // The one place value moves between accounts. Nothing changes unless both sides
+    // succeed, authorization is up to the caller.
+    fn transfer(&mut self, from: AccountId, to: AccountId, amount: Balance) -> Result<(), BlockchainError> {
+        let sender_balance = match self.get_account_by_id(from.clone()) {
+            Some(account) => account.balance,
+            None => return Err(BlockchainError::UnknownAccount(AccountRole::Sender)),
+        };
+        let receiver_balance = match self.get_account_by_id(to.clone()) {
+            Some(account) => account.balance,
+            None => return Err(BlockchainError::UnknownAccount(AccountRole::Receiver)),
+        };
+        let sender_balance = sender_balance
+            .checked_sub(amount)
+            .ok_or(BlockchainError::InsufficientBalance {
+                needed: amount,
+                available: sender_balance,
+            })?;
+        if from == to {
+            return Ok(());
+        }
+        let receiver_balance = receiver_balance
+            .checked_add(amount)
+            .ok_or(BlockchainError::BalanceOverflow)?;
+
+        if let Some(account) = self.get_account_by_id_mut(from) {
+            account.balance = sender_balance;
+        }
+        if let Some(account) = self.get_account_by_id_mut(to) {
+            account.balance = receiver_balance;
+        }
+        Ok(())
+    }
+}
+
+// Where the main chain is kept. Blocks only ever go on or come off the top and heights count
+// from genesis at 0. Chain<Block> keeps them in memory.
+pub trait ChainStore {
+    fn put_block(&mut self, block: Block);
+    // Takes the head off again, for reorgs
+    fn pop_block(&mut self) -> Option<Block>;
+    fn get_block_by_hash(&self, hash: &Hash) -> Option<&Block>;
+    fn get_block_by_height(&self, height: u64) -> Option<&Block>;
+    fn head(&self) -> Option<&Block>;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Newest first. Stores that can walk their blocks faster than one lookup per height
+    // should override this.
+    fn blocks(&self) -> Box<dyn Iterator<Item = &Block> + '_> {
+        Box::new((0..self.len() as u64).rev().filter_map(move |height| self.get_block_by_height(height)))
+    }
+}
+
+// Seconds since the Unix epoch
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Timestamp;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_encoding() {
+        assert_eq!(1u32.canonical_bytes(), vec![0, 0, 0, 1]);
+        assert_eq!(0x0102u128.canonical_bytes(), [vec![0; 14], vec![1, 2]].concat());
+        assert_eq!("ab".canonical_bytes(), vec![0, 0, 0, 2, b'a', b'b']);
+        assert_eq!(None::<u8>.canonical_bytes(), vec![0]);
+        assert_eq!(Some(7u8).canonical_bytes(), vec![1, 7]);
+        assert_eq!(vec![1u8, 2].canonical_bytes(), vec![0, 0, 0, 2, 1, 2]);
+        assert_eq!((1u8, "a").canonical_bytes(), vec![1, 0, 0, 0, 1, b'a']);
+
+        // Length prefixes keep neighbouring fields apart
+        assert_ne!(("ab", "c").canonical_bytes(), ("a", "bc").canonical_bytes());
+        assert_ne!((None::<String>, 0u8).canonical_bytes(), (Some(String::new()), 0u8).canonical_bytes());
+
+        assert_eq!("".canonical_hash(), hex::encode(Blake2s::digest(&[0, 0, 0, 0])));
+    }
 }
@@ -1,17 +1,167 @@
-use crate::types::Balance;
+use crate::traits::{CanonicalEncode, WorldState};
+use crate::types::account_types::{AccountBehavior, Contract, Frozen, Multisig, User, Vesting};
+use crate::types::{AccountId, Balance, BlockchainError, Guardians, Recovery, Transaction};
 use ed25519_dalek::{PublicKey};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+// Each type carries its own state and behavior, see account_types
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AccountType {
     User,
     Contract,
+    Vesting(Vesting),
+    Multisig(Multisig),
+    Frozen,
 }
 
-#[derive(Debug, Clone)]
+impl AccountType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AccountType::User => "User",
+            AccountType::Contract => "Contract",
+            AccountType::Vesting(_) => "Vesting",
+            AccountType::Multisig(_) => "Multisig",
+            AccountType::Frozen => "Frozen",
+        }
+    }
+
+    pub fn behavior(&self) -> &dyn AccountBehavior {
+        match self {
+            AccountType::User => &User,
+            AccountType::Contract => &Contract,
+            AccountType::Vesting(vesting) => vesting,
+            AccountType::Multisig(multisig) => multisig,
+            AccountType::Frozen => &Frozen,
+        }
+    }
+
+    fn behavior_mut(&mut self) -> Option<&mut dyn AccountBehavior> {
+        match self {
+            AccountType::Vesting(vesting) => Some(vesting),
+            AccountType::Multisig(multisig) => Some(multisig),
+            _ => None,
+        }
+    }
+}
+
+// A dead man's switch: `beneficiary` may claim the whole balance once the owner has sent
+// nothing for `inactive_blocks` blocks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Inheritance {
+    pub beneficiary: AccountId,
+    pub inactive_blocks: u64,
+}
+
+// Blocks before a change to an existing spending limit takes effect, time enough for the
+// owner to notice when a stolen key tries to lift it
+pub const SPENDING_LIMIT_DELAY: u64 = 10;
+
+// Caps what an account transfers out to `amount` per epoch of `epoch_blocks` blocks,
+// epochs counted from genesis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpendingLimit {
+    pub amount: Balance,
+    pub epoch_blocks: u64,
+}
+
+impl SpendingLimit {
+    pub fn epoch(&self, height: u64) -> u64 {
+        height / self.epoch_blocks.max(1)
+    }
+}
+
+// A limit, or lifting it, waiting for its delay to pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingLimit {
+    pub limit: Option<SpendingLimit>,
+    pub effective_at: u64,
+}
+
+// The limit of an account and what it has sent so far in `epoch`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpendingPolicy {
+    pub limit: Option<SpendingLimit>,
+    pub pending: Option<PendingLimit>,
+    pub epoch: u64,
+    pub spent: Balance,
+}
+
+impl SpendingPolicy {
+    // The limit in the block at `height`, a pending change counts once it's due
+    pub fn limit_at(&self, height: u64) -> Option<SpendingLimit> {
+        match &self.pending {
+            Some(pending) if height >= pending.effective_at => pending.limit,
+            _ => self.limit,
+        }
+    }
+
+    // What is left to send in the block at `height`, None without a limit
+    pub fn allowance(&self, height: u64) -> Option<Balance> {
+        let limit = self.limit_at(height)?;
+        let spent = if limit.epoch(height) == self.epoch { self.spent } else { 0 };
+        Some(limit.amount.saturating_sub(spent))
+    }
+
+    fn settle(&mut self, height: u64) {
+        if let Some(pending) = self.pending {
+            if height >= pending.effective_at {
+                self.limit = pending.limit;
+                self.pending = None;
+            }
+        }
+        if let Some(limit) = self.limit {
+            if limit.epoch(height) != self.epoch {
+                self.epoch = limit.epoch(height);
+                self.spent = 0;
+            }
+        }
+    }
+
+    // A first limit applies at once, changing or lifting one waits SPENDING_LIMIT_DELAY
+    // blocks and replaces any change still waiting
+    pub(crate) fn change(&mut self, limit: Option<SpendingLimit>, height: u64) {
+        self.settle(height);
+        if self.limit.is_none() {
+            self.limit = limit;
+            self.pending = None;
+            self.settle(height);
+        } else {
+            self.pending = Some(PendingLimit {
+                limit,
+                effective_at: height.saturating_add(SPENDING_LIMIT_DELAY),
+            });
+        }
+    }
+
+    pub(crate) fn record(&mut self, amount: Balance, height: u64) {
+        self.settle(height);
+        if self.limit.is_some() {
+            self.spent = self.spent.saturating_add(amount);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Account {
     account_type: AccountType,
     pub(crate) balance: Balance,
+    #[serde(with = "crate::types::serde_hex::public_key")]
     pub(crate) public_key: PublicKey,
+    // Height of the last block with a transaction sent by this account, or the one that
+    // created it
+    #[serde(default)]
+    pub(crate) last_active: u64,
+    #[serde(default)]
+    pub(crate) inheritance: Option<Inheritance>,
+    #[serde(default)]
+    pub(crate) spending: SpendingPolicy,
+    #[serde(default)]
+    pub(crate) guardians: Option<Guardians>,
+    #[serde(default)]
+    pub(crate) recovery: Option<Recovery>,
+    // Nonce of the last transaction the account signed, the next one has to carry one more
+    #[serde(default)]
+    pub(crate) nonce: u128,
 }
 
 impl Account {
@@ -20,6 +170,120 @@ impl Account {
             account_type,
             balance: 0,
             public_key,
+            last_active: 0,
+            inheritance: None,
+            spending: SpendingPolicy::default(),
+            guardians: None,
+            recovery: None,
+            nonce: 0,
         }
     }
+
+    pub fn nonce(&self) -> u128 {
+        self.nonce
+    }
+
+    pub fn last_active(&self) -> u64 {
+        self.last_active
+    }
+
+    pub fn inheritance(&self) -> Option<&Inheritance> {
+        self.inheritance.as_ref()
+    }
+
+    pub fn spending(&self) -> &SpendingPolicy {
+        &self.spending
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    pub fn guardians(&self) -> Option<&Guardians> {
+        self.guardians.as_ref()
+    }
+
+    pub fn recovery(&self) -> Option<&Recovery> {
+        self.recovery.as_ref()
+    }
+
+    // Whether `beneficiary` may claim the balance in the block at `height`
+    pub fn is_inheritable_by(&self, beneficiary: &AccountId, height: u64) -> bool {
+        match &self.inheritance {
+            Some(inheritance) => {
+                &inheritance.beneficiary == beneficiary
+                    && height.saturating_sub(self.last_active) >= inheritance.inactive_blocks
+            }
+            None => false,
+        }
+    }
+
+    pub fn account_type(&self) -> &AccountType {
+        &self.account_type
+    }
+
+    pub fn authorizes(&self, transaction: &Transaction) -> bool {
+        self.account_type.behavior().authorizes(self, &|key| transaction.is_signed_by(key))
+    }
+
+    // Like authorizes, with signatures checked by `state`, which may remember them
+    pub fn authorizes_in<T: WorldState + ?Sized>(&self, state: &T, transaction: &Transaction) -> bool {
+        self.account_type.behavior().authorizes(self, &|key| state.is_signed_by(transaction, key))
+    }
+
+    pub fn can_send(&self, transaction: &Transaction, amount: Balance) -> Result<(), BlockchainError> {
+        self.account_type.behavior().can_send(self, transaction, amount)
+    }
+
+    pub fn can_receive(&self, transaction: &Transaction, amount: Balance) -> Result<(), BlockchainError> {
+        self.account_type.behavior().can_receive(self, transaction, amount)
+    }
+
+    // Lets the type react to `amount` credited by `transaction`
+    pub(crate) fn on_credit(&mut self, transaction: &Transaction, amount: Balance) {
+        if let Some(behavior) = self.account_type.behavior_mut() {
+            behavior.on_credit(transaction, amount);
+        }
+    }
+}
+
+// A tag byte for the type, then its state
+impl CanonicalEncode for AccountType {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            AccountType::User => 0u8.encode_to(out),
+            AccountType::Contract => 1u8.encode_to(out),
+            AccountType::Vesting(vesting) => (2u8, vesting.locked, vesting.unlock_at).encode_to(out),
+            AccountType::Multisig(multisig) => (3u8, &multisig.keys, multisig.threshold as u64).encode_to(out),
+            AccountType::Frozen => 4u8.encode_to(out),
+        }
+    }
+}
+
+// Every field, for Blockchain::state_fingerprint
+impl CanonicalEncode for Account {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        let limit = |limit: Option<SpendingLimit>| limit.map(|limit| (limit.amount, limit.epoch_blocks));
+        (&self.account_type, self.balance, &self.public_key, self.last_active).encode_to(out);
+        self.inheritance
+            .as_ref()
+            .map(|inheritance| (&inheritance.beneficiary, inheritance.inactive_blocks))
+            .encode_to(out);
+        (
+            limit(self.spending.limit),
+            self.spending.pending.map(|pending| (limit(pending.limit), pending.effective_at)),
+            self.spending.epoch,
+            self.spending.spent,
+        )
+            .encode_to(out);
+        self.guardians
+            .as_ref()
+            .map(|guardians| (&guardians.keys, guardians.threshold as u64))
+            .encode_to(out);
+        self.recovery
+            .as_ref()
+            .map(|recovery| (&recovery.new_key, &recovery.approvals, recovery.started_at))
+            .encode_to(out);
+        self.nonce.encode_to(out);
+    }
 }
@@ -0,0 +1,185 @@
+use crate::types::{Account, Balance, BlockchainError, Timestamp, Transaction};
+use ed25519_dalek::PublicKey;
+use serde::{Deserialize, Serialize};
+
+// What an account of a given type may do. Transfers ask the sender's type whether it
+// authorizes the transaction and can send, and the receiver's type whether it can
+// receive; on_credit runs once the funds have arrived.
+pub trait AccountBehavior {
+    // `is_signed_by` tells whether the transaction carries a valid signature by a key
+    fn authorizes(&self, account: &Account, is_signed_by: &dyn Fn(&PublicKey) -> bool) -> bool {
+        is_signed_by(&account.public_key)
+    }
+
+    fn can_send(&self, _account: &Account, _transaction: &Transaction, _amount: Balance) -> Result<(), BlockchainError> {
+        Ok(())
+    }
+
+    fn can_receive(&self, _account: &Account, _transaction: &Transaction, _amount: Balance) -> Result<(), BlockchainError> {
+        Ok(())
+    }
+
+    fn on_credit(&mut self, _transaction: &Transaction, _amount: Balance) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct User;
+
+impl AccountBehavior for User {}
+
+// Funds of a contract move only through its code, never through signed transfers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contract;
+
+impl AccountBehavior for Contract {
+    fn can_send(&self, _account: &Account, _transaction: &Transaction, _amount: Balance) -> Result<(), BlockchainError> {
+        Err(BlockchainError::ContractCantSend)
+    }
+}
+
+// Keeps `locked` of the balance unspendable until `unlock_at`, including whatever
+// arrives before then. Transaction timestamps stand in for the time, they can't run
+// ahead of the clock by more than MAX_FUTURE_DRIFT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vesting {
+    pub locked: Balance,
+    pub unlock_at: Timestamp,
+}
+
+impl Vesting {
+    pub fn new(locked: Balance, unlock_at: Timestamp) -> Self {
+        Self { locked, unlock_at }
+    }
+
+    fn is_vesting(&self, transaction: &Transaction) -> bool {
+        transaction.timestamp < self.unlock_at
+    }
+}
+
+impl AccountBehavior for Vesting {
+    fn can_send(&self, account: &Account, transaction: &Transaction, amount: Balance) -> Result<(), BlockchainError> {
+        if self.is_vesting(transaction) && account.balance.saturating_sub(amount) < self.locked {
+            return Err(BlockchainError::FundsLocked(self.unlock_at));
+        }
+        Ok(())
+    }
+
+    fn on_credit(&mut self, transaction: &Transaction, amount: Balance) {
+        if self.is_vesting(transaction) {
+            self.locked = self.locked.saturating_add(amount);
+        }
+    }
+}
+
+// Sends only with signatures of `threshold` distinct keys out of `keys`. Transactions
+// carry a single signature for now, so only thresholds of 1 can be met.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Multisig {
+    #[serde(with = "crate::types::serde_hex::public_keys")]
+    pub keys: Vec<PublicKey>,
+    pub threshold: usize,
+}
+
+impl Multisig {
+    pub fn new(keys: Vec<PublicKey>, threshold: usize) -> Self {
+        Self { keys, threshold }
+    }
+}
+
+impl AccountBehavior for Multisig {
+    fn authorizes(&self, _account: &Account, is_signed_by: &dyn Fn(&PublicKey) -> bool) -> bool {
+        let signers = self
+            .keys
+            .iter()
+            .enumerate()
+            .filter(|(i, key)| !self.keys[..*i].contains(key) && is_signed_by(key))
+            .count();
+
+        self.threshold > 0 && signers >= self.threshold
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Frozen;
+
+impl AccountBehavior for Frozen {
+    fn can_send(&self, _account: &Account, _transaction: &Transaction, _amount: Balance) -> Result<(), BlockchainError> {
+        Err(BlockchainError::AccountFrozen)
+    }
+
+    fn can_receive(&self, _account: &Account, _transaction: &Transaction, _amount: Balance) -> Result<(), BlockchainError> {
+        Err(BlockchainError::AccountFrozen)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::traits::WorldState;
+    use crate::types::{AccountType, Blockchain, TransactionData};
+    use ed25519_dalek::{Keypair, Signer};
+    use rand::rngs::OsRng;
+
+    fn transfer(state: &Blockchain, from: &str, to: &str, amount: Balance, timestamp: Timestamp, keypair: &Keypair) -> Transaction {
+        let mut transaction = Transaction::new(
+            TransactionData::Transfer {
+                to: to.to_string(),
+                amount,
+            },
+            Some(from.to_string()),
+            timestamp,
+        );
+        transaction.set_nonce(state.next_nonce(&from.to_string()));
+        transaction.sign(Some(keypair.sign(&transaction.signing_message())));
+        transaction
+    }
+
+    fn fund(state: &mut Blockchain, account_id: &str, amount: Balance) {
+        state.get_account_by_id_mut(account_id.to_string()).unwrap().balance = amount;
+    }
+
+    #[test]
+    fn test_account_behaviors() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let cosigner = Keypair::generate(&mut OsRng {});
+        let mut state = Blockchain::new();
+        let accounts = vec![
+            ("user", AccountType::User),
+            ("contract", AccountType::Contract),
+            ("vesting", AccountType::Vesting(Vesting::new(60, 100))),
+            ("multisig", AccountType::Multisig(Multisig::new(vec![cosigner.public, cosigner.public], 1))),
+            ("multisig_2", AccountType::Multisig(Multisig::new(vec![cosigner.public, keypair.public], 2))),
+            ("frozen", AccountType::Frozen),
+        ];
+        for (account_id, account_type) in accounts {
+            state.create_account(account_id.to_string(), account_type, keypair.public).unwrap();
+            fund(&mut state, account_id, 100);
+        }
+
+        assert!(transfer(&state, "user", "contract", 10, 0, &keypair).execute(&mut state).is_ok());
+        assert_eq!(
+            transfer(&state, "contract", "user", 10, 0, &keypair).execute(&mut state),
+            Err(BlockchainError::ContractCantSend)
+        );
+        assert_eq!(
+            transfer(&state, "user", "frozen", 10, 0, &keypair).execute(&mut state),
+            Err(BlockchainError::AccountFrozen)
+        );
+        assert!(transfer(&state, "frozen", "user", 10, 0, &keypair).execute(&mut state).is_err());
+
+        // Only the unlocked part is spendable until unlock_at, and what arrives meanwhile locks too
+        assert!(transfer(&state, "vesting", "user", 40, 1, &keypair).execute(&mut state).is_ok());
+        assert!(transfer(&state, "vesting", "user", 1, 2, &keypair).execute(&mut state).is_err());
+        assert!(transfer(&state, "user", "vesting", 10, 3, &keypair).execute(&mut state).is_ok());
+        assert!(transfer(&state, "vesting", "user", 1, 4, &keypair).execute(&mut state).is_err());
+        assert!(transfer(&state, "vesting", "user", 70, 100, &keypair).execute(&mut state).is_ok());
+
+        // The account key doesn't count for a multisig, its signers do
+        assert_eq!(
+            transfer(&state, "multisig", "user", 10, 0, &keypair).execute(&mut state),
+            Err(BlockchainError::InvalidSignature)
+        );
+        assert!(transfer(&state, "multisig", "user", 10, 0, &cosigner).execute(&mut state).is_ok());
+        assert!(transfer(&state, "multisig_2", "user", 10, 0, &cosigner).execute(&mut state).is_err());
+    }
+}
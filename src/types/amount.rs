@@ -0,0 +1,149 @@
+use crate::types::{Balance, Error};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+pub const DECIMALS: u32 = 8;
+pub const COIN: Balance = 100_000_000;
+
+// A balance for humans: shown and parsed in coins, stored in base units
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Amount(Balance);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    pub fn from_base_units(base_units: Balance) -> Self {
+        Self(base_units)
+    }
+
+    pub fn from_coins(coins: Balance) -> Option<Self> {
+        coins.checked_mul(COIN).map(Self)
+    }
+
+    pub fn base_units(self) -> Balance {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        Self(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Amount) -> Amount {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    // "1.5" for 150_000_000 base units, without trailing zeros
+    pub fn to_coins_string(self) -> String {
+        let fraction = self.0 % COIN;
+        if fraction == 0 {
+            return (self.0 / COIN).to_string();
+        }
+
+        let fraction = format!("{:0width$}", fraction, width = DECIMALS as usize);
+        format!("{}.{}", self.0 / COIN, fraction.trim_end_matches('0'))
+    }
+
+    // Decimal coins with at most DECIMALS fractional digits, "1.5" is 150_000_000 base units
+    pub fn parse_coins(coins: &str) -> Result<Self, Error> {
+        let invalid = || format!("Invalid amount {}", coins);
+        let (whole, fraction) = match coins.split_once('.') {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (coins, ""),
+        };
+        let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if whole.is_empty() || !is_digits(whole) || !is_digits(fraction) || fraction.len() > DECIMALS as usize {
+            return Err(invalid());
+        }
+
+        let whole = whole.parse::<Balance>().map_err(|_| invalid())?;
+        let fraction = format!("{:0<width$}", fraction, width = DECIMALS as usize)
+            .parse::<Balance>()
+            .map_err(|_| invalid())?;
+        whole
+            .checked_mul(COIN)
+            .and_then(|base_units| base_units.checked_add(fraction))
+            .map(Self)
+            .ok_or_else(invalid)
+    }
+}
+
+impl From<Balance> for Amount {
+    fn from(base_units: Balance) -> Self {
+        Self(base_units)
+    }
+}
+
+impl From<Amount> for Balance {
+    fn from(amount: Amount) -> Self {
+        amount.0
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} COIN", self.to_coins_string())
+    }
+}
+
+// Either coins with the unit, as Display writes them, or plain base units
+impl FromStr for Amount {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().strip_suffix("COIN") {
+            Some(coins) => Self::parse_coins(coins.trim()),
+            None => s
+                .trim()
+                .parse::<Balance>()
+                .map(Self)
+                .map_err(|_| format!("Invalid amount {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_and_parse() {
+        assert_eq!(Amount::from_base_units(150_000_000).to_string(), "1.5 COIN");
+        assert_eq!(Amount::from_base_units(1).to_string(), "0.00000001 COIN");
+        assert_eq!(Amount::from_coins(2).unwrap().to_string(), "2 COIN");
+        assert_eq!(Amount::ZERO.to_coins_string(), "0");
+
+        assert_eq!("1.5 COIN".parse::<Amount>(), Ok(Amount::from_base_units(150_000_000)));
+        assert_eq!("0.00000001COIN".parse::<Amount>(), Ok(Amount::from_base_units(1)));
+        assert_eq!("42".parse::<Amount>(), Ok(Amount::from_base_units(42)));
+        let max = Amount::from_base_units(u128::MAX);
+        assert_eq!(max.to_string().parse::<Amount>(), Ok(max));
+
+        for invalid in &["", "COIN", "1.5", "-1", "0.000000001 COIN", ".5 COIN", "1.5.0 COIN", "1e3 COIN"] {
+            assert!(invalid.parse::<Amount>().is_err(), "{}", invalid);
+        }
+        assert!(Amount::parse_coins(&(u128::MAX / COIN + 1).to_string()).is_err());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let one = Amount::from_coins(1).unwrap();
+        let max = Amount::from_base_units(u128::MAX);
+
+        assert_eq!(one.checked_add(one), Amount::from_coins(2));
+        assert_eq!(max.checked_add(one), None);
+        assert_eq!(Amount::ZERO.checked_sub(one), None);
+        assert_eq!(max.saturating_add(one), max);
+        assert_eq!(Amount::ZERO.saturating_sub(one), Amount::ZERO);
+        assert!(Amount::from_coins(u128::MAX).is_none());
+    }
+}
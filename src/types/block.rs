@@ -1,57 +1,223 @@
-use crate::traits::Hashable;
-use crate::types::{Hash, Transaction};
-use blake2::digest::FixedOutput;
-use blake2::{Blake2s, Digest};
-use ed25519_dalek::{Keypair};
-use std::time::{SystemTime, UNIX_EPOCH};
-
-#[derive(Default, Debug, Clone)]
+use crate::traits::{CanonicalEncode, Hashable};
+use crate::types::{merkle_root, AccountId, BlockHeader, Hash, Timestamp, Transaction};
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+use serde::{Deserialize, Serialize};
+
+// The header is what gets hashed and sealed, it commits to the body through the Merkle
+// roots of the transaction and ommer hashes. Setters keep the roots and the hash up to
+// date.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
-    nonce: u128,
+    pub(crate) header: BlockHeader,
+    pub(crate) body: BlockBody,
     pub(crate) hash: Option<Hash>,
-    pub(crate) prev_hash: Option<Hash>,
-    pub(crate) transactions: Vec<Transaction>,
+    // Producer's signature of the hash, added after sealing
+    #[serde(default, with = "crate::types::serde_hex::option_signature")]
+    signature: Option<Signature>,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct BlockBody {
+    pub transactions: Vec<Transaction>,
+    pub ommers: Vec<Block>,
+}
+
+impl BlockBody {
+    pub fn tx_root(&self) -> Hash {
+        let hashes: Vec<Hash> = self.transactions.iter().map(|tx| tx.hash()).collect();
+        merkle_root(&hashes)
+    }
+
+    pub fn ommers_root(&self) -> Hash {
+        let hashes: Vec<Hash> = self.ommers.iter().map(|ommer| ommer.hash()).collect();
+        merkle_root(&hashes)
+    }
 }
 
 impl Block {
     pub fn new(prev_hash: Option<Hash>) -> Self {
         let mut block = Block {
-            prev_hash,
+            header: BlockHeader {
+                prev_hash,
+                ..Default::default()
+            },
             ..Default::default()
         };
-        block.update_hash();
+        block.update_body();
 
         block
     }
 
+    pub fn header(&self) -> &BlockHeader {
+        &self.header
+    }
+
+    pub fn body(&self) -> &BlockBody {
+        &self.body
+    }
+
+    pub fn prev_hash(&self) -> Option<&Hash> {
+        self.header.prev_hash.as_ref()
+    }
+
+    pub fn height(&self) -> u64 {
+        self.header.height
+    }
+
+    pub fn set_height(&mut self, height: u64) {
+        self.header.height = height;
+        self.update_hash();
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.body.transactions
+    }
+
+    pub fn nonce(&self) -> u128 {
+        self.header.nonce
+    }
+
     pub fn set_nonce(&mut self, nonce: u128) {
-        self.nonce = nonce;
+        self.header.nonce = nonce;
+        self.update_hash();
+    }
+
+    pub fn timestamp(&self) -> Timestamp {
+        self.header.timestamp
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: Timestamp) {
+        self.header.timestamp = timestamp;
+        self.update_hash();
+    }
+
+    // Compact encoding of the target the block claims to be mined at
+    pub fn bits(&self) -> u32 {
+        self.header.bits
+    }
+
+    pub fn set_bits(&mut self, bits: u32) {
+        self.header.bits = bits;
         self.update_hash();
     }
 
     pub fn add_transaction(&mut self, transaction: Transaction) {
-        self.transactions.push(transaction);
+        self.body.transactions.push(transaction);
+        self.update_body();
+    }
+
+    // Puts a transaction in front of all others, like a coinbase
+    pub fn prepend_transaction(&mut self, transaction: Transaction) {
+        self.body.transactions.insert(0, transaction);
+        self.update_body();
+    }
+
+    pub fn beneficiary(&self) -> Option<&AccountId> {
+        self.header.beneficiary.as_ref()
+    }
+
+    pub fn set_beneficiary(&mut self, beneficiary: Option<AccountId>) {
+        self.header.beneficiary = beneficiary;
+        self.update_hash();
+    }
+
+    pub fn ommers(&self) -> &[Block] {
+        &self.body.ommers
+    }
+
+    pub fn add_ommer(&mut self, ommer: Block) {
+        self.body.ommers.push(ommer);
+        self.update_body();
+    }
+
+    pub fn producer(&self) -> Option<&AccountId> {
+        self.header.producer.as_ref()
+    }
+
+    pub fn signature(&self) -> Option<&Signature> {
+        self.signature.as_ref()
+    }
+
+    // Part of the sealed header, so it has to be set before mining
+    pub fn set_producer(&mut self, producer: Option<AccountId>) {
+        self.header.producer = producer;
         self.update_hash();
     }
 
+    // The producer's signature of the sealed hash
+    pub fn sign(&mut self, signature: Option<Signature>) {
+        self.signature = signature;
+    }
+
+    pub fn is_signed_by(&self, public_key: &PublicKey) -> bool {
+        match &self.signature {
+            Some(signature) => public_key.verify(self.hash().as_bytes(), signature).is_ok(),
+            None => false,
+        }
+    }
+
+    // The stored hash is the header's and the header's roots are the body's
     pub fn verify(&self) -> bool {
         matches!(&self.hash, Some(hash) if hash == &self.hash())
+            && self.header.tx_root == self.body.tx_root()
+            && self.header.ommers_root == self.body.ommers_root()
     }
 
+    fn update_body(&mut self) {
+        self.header.tx_root = self.body.tx_root();
+        self.header.ommers_root = self.body.ommers_root();
+        self.update_hash();
+    }
+
+    // Any change to the header voids the producer's signature
     fn update_hash(&mut self) {
         self.hash = Some(self.hash());
+        self.signature = None;
     }
 }
 
-impl Hashable for Block {
-    fn hash(&self) -> Hash {
-        let mut hasher = Blake2s::new();
-        hasher.update(format!("{:?}", (self.prev_hash.clone(), self.nonce)).as_bytes());
-        for tx in self.transactions.iter() {
-            hasher.update(tx.hash())
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Block {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut block = Block::new(u.arbitrary()?);
+        block.header.height = u.arbitrary()?;
+        block.header.nonce = u.arbitrary()?;
+        block.header.timestamp = u.arbitrary()?;
+        block.header.bits = u.arbitrary()?;
+        block.header.beneficiary = u.arbitrary()?;
+        block.header.producer = u.arbitrary()?;
+        block.body.transactions = u.arbitrary()?;
+
+        // Ommers don't get ommers of their own so generation always terminates
+        for _ in 0..u.int_in_range(0..=2)? {
+            let mut ommer = Block::new(u.arbitrary()?);
+            ommer.header.nonce = u.arbitrary()?;
+            ommer.body.transactions = u.arbitrary()?;
+            ommer.update_body();
+            block.body.ommers.push(ommer);
+        }
+
+        // Mostly consistent hashes, sometimes a forged one
+        if u.ratio(1, 8)? {
+            block.hash = u.arbitrary()?;
+        } else {
+            block.update_body();
         }
 
-        hex::encode(hasher.finalize_fixed())
+        Ok(block)
+    }
+}
+
+// The header, which commits to the transactions and ommers by their Merkle roots
+impl CanonicalEncode for Block {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.header.encode_to(out);
+    }
+}
+
+impl Hashable for Block {
+    fn hash(&self) -> Hash {
+        self.canonical_hash()
     }
 }
 
@@ -59,12 +225,14 @@ impl Hashable for Block {
 mod tests {
     use super::*;
     use crate::types::TransactionData;
+    use ed25519_dalek::{Keypair, Signer};
+    use std::time::{SystemTime, UNIX_EPOCH};
 
     #[test]
     fn test_creation() {
         let mut block = Block::new(None);
         let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
-        let tx = Transaction::new(TransactionData::CreateAccount("alice".to_string(), keypair.public), None, SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128);
+        let tx = Transaction::create_account("alice".to_string(), &keypair, SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128);
         block.set_nonce(1);
         block.add_transaction(tx);
 
@@ -75,7 +243,7 @@ mod tests {
     fn test_hash() {
         let mut block = Block::new(None);
         let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
-        let tx = Transaction::new(TransactionData::CreateAccount("alice".to_string(), keypair.public), None, SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128);
+        let tx = Transaction::create_account("alice".to_string(), &keypair, SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128);
         block.set_nonce(1);
 
         let hash1 = block.hash();
@@ -86,4 +254,83 @@ mod tests {
 
         assert_ne!(hash1, hash2);
     }
+
+    #[test]
+    fn test_hash_covers_beneficiary_and_ommers() {
+        let mut block = Block::new(None);
+        let hash1 = block.hash();
+
+        block.set_beneficiary(Some("alice".to_string()));
+        let hash2 = block.hash();
+
+        block.set_timestamp(1);
+        assert_ne!(block.hash(), hash2);
+        let hash_with_timestamp = block.hash();
+        block.set_bits(1);
+        assert_ne!(block.hash(), hash_with_timestamp);
+
+        block.add_ommer(Block::new(None));
+        let hash3 = block.hash();
+
+        assert_ne!(hash1, hash2);
+        assert_ne!(hash2, hash3);
+        assert!(block.verify());
+    }
+
+    #[test]
+    fn test_signature() {
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut block = Block::new(None);
+        block.set_producer(Some("alice".to_string()));
+        block.sign(Some(keypair.sign(block.hash().as_bytes())));
+        assert!(block.is_signed_by(&keypair.public));
+        assert!(!block.is_signed_by(&Keypair::generate(&mut rand::rngs::OsRng {}).public));
+
+        // Sealing again voids it
+        block.set_nonce(1);
+        assert!(block.signature().is_none());
+        assert!(!block.is_signed_by(&keypair.public));
+    }
+
+    // Changing this changes every block hash
+    #[test]
+    fn test_hash_vector() {
+        let transfer = Transaction::new(
+            TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 100,
+            },
+            Some("alice".to_string()),
+            1_600_000_000,
+        );
+        let mut block = Block::new(Some("00ff".to_string()));
+        block.set_nonce(7);
+        block.set_timestamp(1_600_000_000);
+        block.set_bits(0x1d00ffff);
+        block.add_transaction(transfer);
+
+        assert_eq!(block.header().hash(), block.hash());
+        assert_eq!(block.hash(), "7c6a4ee115eb9b1d2987f84cca785b64ed14fdcbff17564d112c4d461c59aeaa");
+    }
+
+    #[test]
+    fn test_header_commits_to_body() {
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut block = Block::new(None);
+        block.add_transaction(Transaction::create_account("alice".to_string(), &keypair, 0));
+        let tx_root = block.header().tx_root.clone();
+        block.add_ommer(Block::new(None));
+        assert_eq!(block.header().tx_root, tx_root);
+        assert_eq!(block.header().ommers_root, block.body().ommers_root());
+        assert!(block.verify());
+
+        // A body swapped under the header doesn't verify, though the header hash still does
+        let mut tampered = block.clone();
+        tampered.body.transactions[0] = Transaction::create_account("bob".to_string(), &keypair, 0);
+        assert_eq!(tampered.hash(), block.hash());
+        assert!(!tampered.verify());
+        let mut tampered = block.clone();
+        tampered.body.ommers.clear();
+        assert!(!tampered.verify());
+    }
 }
@@ -1,32 +1,192 @@
-use crate::traits::{Hashable, WorldState};
-use crate::types::{Account, AccountId, AccountType, Block, Chain, Error, Hash, Transaction};
-use ed25519_dalek::{Keypair, Signature, Signer, PublicKey};
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-
-#[derive(Default, Debug)]
-pub struct Blockchain {
+use crate::consensus::{decode_bits, encode_bits, round_target, target_work, BftVote, Blake2sPow, DifficultyConfig, Pow, VoteKind};
+use crate::storage::{BlockStore, StateChanges, StoredBlocks, StoredChain};
+use crate::traits::{CanonicalEncode, ChainStore, Clock, Hashable, WorldState};
+use crate::types::{
+    Account, AccountId, AccountRole, AccountType, Balance, Block, BlockchainError, Chain, ChainIndex, ChainInfo, ChainStats, DustSweep, Error, FeeConfig, Finality, GenesisConfig, GenesisExecutor, Hash, OrphanPool,
+    SideBranches, SignatureCache, StandardTransition, StandingOrder, StateHistory, StateOverlay, Timestamp, Transaction, TransactionData, TransitionFunction, Vote, ALIAS_PREFIX,
+};
+use crate::utils::SystemClock;
+use blake2::digest::FixedOutput;
+use blake2::{Blake2s, Digest};
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+use serde::{Deserialize, Serialize};
+use std::cell::OnceCell;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+pub const MAX_TARGET: u128 = 0x00000000ffff0000000000000000000000000000;
+pub const BLOCK_REWARD: Balance = 50;
+pub const MAX_OMMERS: usize = 2;
+pub const MAX_OMMER_DEPTH: u64 = 6;
+pub const MAX_FUTURE_DRIFT: Timestamp = 2 * 60 * 60;
+pub const TRANSACTION_EXPIRY: Timestamp = 24 * 60 * 60;
+// Confirmations after which a payment is considered settled
+pub const DEFAULT_SAFE_DEPTH: u64 = 6;
+
+// Ommers are paid less the older they are: 7/8 of the block reward one block back,
+// down to 2/8 at MAX_OMMER_DEPTH
+pub fn ommer_reward(depth: u64) -> Balance {
+    BLOCK_REWARD * (8 - depth.min(8)) as Balance / 8
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum BlockStatus {
+    Appended { attached_orphans: Vec<Hash> },
+    SideBranch { height: u64, attached_orphans: Vec<Hash> },
+    // The block, or orphans it let in, made a side branch heavier than the main chain
+    Reorganized { reorg: Reorg, attached_orphans: Vec<Hash> },
+    Orphan { missing_parent: Hash },
+}
+
+// The main chain blocks above `fork_height` a reorg took out, tip first, and the ones it
+// put in their place, oldest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reorg {
+    pub fork_height: u64,
+    pub reverted: Vec<Block>,
+    pub applied: Vec<Block>,
+}
+
+impl PartialEq for Reorg {
+    fn eq(&self, other: &Self) -> bool {
+        let hashes = |blocks: &[Block]| blocks.iter().map(|block| block.hash()).collect::<Vec<_>>();
+        self.fork_height == other.fork_height
+            && hashes(&self.reverted) == hashes(&other.reverted)
+            && hashes(&self.applied) == hashes(&other.applied)
+    }
+}
+
+// The proof of work, the clock and the transition function are not part of the state, a
+// deserialized chain gets the defaults back and has to be given its own with set_pow,
+// set_clock and set_transition. Main chain
+// blocks are kept in `S`, in memory unless another ChainStore is plugged in.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Blockchain<S = Chain<Block>> {
+    // From the genesis config, empty for chains built without one
+    #[serde(default)]
+    chain_id: String,
     target: u128,
-    blocks: Chain<Block>,
-    accounts: HashMap<AccountId, Account>,
+    max_target: u128,
+    difficulty: DifficultyConfig,
+    #[serde(skip, default = "default_pow")]
+    pow: Arc<dyn Pow>,
+    // Target each main chain block had to meet, by height
+    targets: Vec<u128>,
+    #[serde(skip, default = "default_clock")]
+    clock: Arc<dyn Clock>,
+    #[serde(skip, default = "default_transition")]
+    transition: Arc<dyn TransitionFunction>,
+    blocks: S,
+    // Built from the blocks the first time it's needed, so opening a long chain doesn't
+    // have to hash every transaction in it
+    #[serde(skip)]
+    index: OnceCell<ChainIndex>,
+    // Accounts as of past heights
+    #[serde(default)]
+    history: StateHistory,
+    accounts: StateOverlay<AccountId, Account>,
+    // Owner of every alias
+    #[serde(default)]
+    aliases: StateOverlay<String, AccountId>,
+    // In the order they were created, which is the order they are settled in
+    #[serde(default)]
+    standing_orders: Vec<StandingOrder>,
     transaction_pool: Vec<Transaction>,
+    orphans: OrphanPool,
+    side_branches: SideBranches,
+    finality: Finality,
+    safe_depth: u64,
+    fees: FeeConfig,
+    // What the coinbase of a block mints
+    #[serde(default = "default_block_reward")]
+    block_reward: Balance,
+    // Whether blocks after genesis have to name and be signed by their producer
+    #[serde(default)]
+    require_signed_blocks: bool,
+    // A replica follows the chain it is sent but neither takes transactions nor mines
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default)]
+    dust_sweep: Option<DustSweep>,
+    #[serde(skip)]
+    store: Option<BlockStore>,
+    #[serde(skip)]
+    template: Option<TemplateCache>,
+    // Signatures checked when their transactions entered the pool, so blocks with them
+    // don't check them again
+    #[serde(skip)]
+    signatures: SignatureCache,
+    // How to take back each of the main chain blocks a side branch can still replace, by
+    // height. Blocks from before the chain was loaded can't be taken back.
+    #[serde(skip)]
+    undo: BTreeMap<u64, StateUndo>,
+    #[serde(skip)]
+    rollback_by_copy: bool,
+}
+
+// The last block template with the state its transactions left behind, so transactions
+// joining the pool extend it instead of every pending one running again
+#[derive(Debug)]
+struct TemplateCache {
+    block: Block,
+    state: StateBackup,
+    // Pool transactions it went through, included or not
+    considered: usize,
+    // Ordering key of the last of them, only what is ordered after it can be appended
+    last_key: Option<(u128, Reverse<Balance>, Hash)>,
+}
+
+// Entries of `after` that differ from `before`, as they were in `before`
+fn changed<K, V>(before: &HashMap<K, V>, after: &HashMap<K, V>) -> HashMap<K, Option<V>>
+where
+    K: Clone + Eq + std::hash::Hash,
+    V: Clone + PartialEq,
+{
+    let removed_or_changed = before
+        .iter()
+        .filter(|(key, value)| after.get(*key) != Some(*value))
+        .map(|(key, value)| (key.clone(), Some(value.clone())));
+    let added = after
+        .keys()
+        .filter(|key| !before.contains_key(*key))
+        .map(|key| (key.clone(), None));
+
+    removed_or_changed.chain(added).collect()
+}
+
+fn default_block_reward() -> Balance {
+    BLOCK_REWARD
+}
+
+fn default_pow() -> Arc<dyn Pow> {
+    Arc::new(Blake2sPow)
+}
+
+fn default_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+fn default_transition() -> Arc<dyn TransitionFunction> {
+    Arc::new(StandardTransition)
 }
 
-impl WorldState for Blockchain {
+impl<S: ChainStore> WorldState for Blockchain<S> {
     fn create_account(
         &mut self,
         account_id: AccountId,
         account_type: AccountType,
         public_key: PublicKey,
-    ) -> Result<(), Error> {
-        match self.accounts.entry(account_id.clone()) {
-            Entry::Occupied(_) => Err(format!("AccountId already exist: {}", account_id)),
-            Entry::Vacant(v) => {
-                v.insert(Account::new(account_type, public_key));
-                Ok(())
-            }
+    ) -> Result<(), BlockchainError> {
+        if account_id.starts_with(ALIAS_PREFIX) {
+            return Err(BlockchainError::AliasAsAccountId(account_id));
+        }
+
+        if self.accounts.contains_key(&account_id) {
+            return Err(BlockchainError::AccountExists(account_id));
         }
+        self.accounts.insert(account_id, Account::new(account_type, public_key));
+        Ok(())
     }
 
     fn get_account_by_id(&self, account_id: AccountId) -> Option<&Account> {
@@ -36,514 +196,3897 @@ impl WorldState for Blockchain {
     fn get_account_by_id_mut(&mut self, account_id: AccountId) -> Option<&mut Account> {
         self.accounts.get_mut(&account_id)
     }
+
+    fn alias_owner(&self, alias: &str) -> Option<&AccountId> {
+        self.aliases.get(alias)
+    }
+
+    fn set_alias_owner(&mut self, alias: String, owner: AccountId) {
+        self.aliases.insert(alias, owner);
+    }
+
+    fn set_standing_order(&mut self, order: StandingOrder) {
+        match self
+            .standing_orders
+            .iter_mut()
+            .find(|existing| existing.payer == order.payer && existing.payee == order.payee)
+        {
+            Some(existing) => *existing = order,
+            None => self.standing_orders.push(order),
+        }
+    }
+
+    fn cancel_standing_order(&mut self, payer: &AccountId, payee: &AccountId) -> Option<StandingOrder> {
+        let position = self
+            .standing_orders
+            .iter()
+            .position(|order| &order.payer == payer && &order.payee == payee)?;
+        Some(self.standing_orders.remove(position))
+    }
+
+    // Blocks go on the chain after their transactions ran, as do pool and template dry runs
+    fn height(&self) -> u64 {
+        self.len() as u64
+    }
+
+    fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+
+    fn is_signed_by(&self, transaction: &Transaction, public_key: &PublicKey) -> bool {
+        self.signatures.verify(transaction, public_key)
+    }
+}
+
+// Everything executing transactions can change, for block templates to swap their own
+// state in and out
+#[derive(Debug)]
+struct StateBackup {
+    accounts: StateOverlay<AccountId, Account>,
+    aliases: StateOverlay<String, AccountId>,
+    standing_orders: Vec<StandingOrder>,
+}
+
+// What rolling back to a checkpoint takes besides the overlays, which keep track of the
+// accounts and aliases themselves. Standing orders are few, so they are just copied.
+// Chains rolling back by copy keep a copy of the whole state instead.
+#[derive(Debug)]
+struct Checkpoint {
+    standing_orders: Vec<StandingOrder>,
+    copy: Option<StateBackup>,
+}
+
+// What a block changed, as it was before: the accounts and aliases it wrote to, None for
+// those it created, and the standing orders
+#[derive(Debug)]
+struct StateUndo {
+    accounts: HashMap<AccountId, Option<Account>>,
+    aliases: HashMap<String, Option<AccountId>>,
+    standing_orders: Vec<StandingOrder>,
 }
 
 impl Blockchain {
     pub fn new() -> Self {
-        Default::default()
+        Self::with_max_target(MAX_TARGET)
     }
 
-    pub fn len(&self) -> usize {
-        self.blocks.len()
+    pub fn with_max_target(max_target: u128) -> Self {
+        Self::build(Chain::default(), max_target)
     }
 
-    pub fn append_block(&mut self, block: Block) -> Result<(), Error> {
-        //TODO Task 3: Implement mining
+    // A chain with the genesis block `config` describes, the same one for everyone
+    // building from the same config
+    pub fn from_genesis(config: &GenesisConfig) -> Result<Self, Error> {
+        config.validate()?;
+        let mut blockchain = Self::with_max_target(config.target);
+        blockchain.chain_id = config.chain_id.clone();
+        blockchain.append_block(config.block()).map_err(|error| error.to_string())?;
+        Ok(blockchain)
+    }
+}
 
-        if !block.verify() {
-            return Err("Block has invalid hash".to_string());
+impl Blockchain<StoredBlocks> {
+    // A chain persisted to `store` like with_store does, that opens without reading its
+    // blocks: they are loaded from the store as they are needed, and the world state is
+    // the one stored with the tip. Opening takes as long for a hundred thousand blocks as
+    // for ten.
+    pub fn open(store: BlockStore, max_target: u128) -> Result<Self, Error> {
+        if store.is_empty() {
+            return Self::build(StoredBlocks::default(), max_target).with_store(store);
         }
-        let is_genesis = self.blocks.len() == 0;
 
-        if block.transactions.len() == 0 {
-            return Err("Block has 0 transactions.".to_string());
-        }
+        let (blocks, targets) = store.load_blocks()?;
+        let state = store.load_state()?;
+        let mut blockchain = Self::build(StoredBlocks::default(), max_target);
+        blockchain.restore(blocks, targets, state);
+        blockchain.store = Some(store);
+        Ok(blockchain)
+    }
 
-        let account_backup = self.accounts.clone();
-        for tx in &block.transactions {
-            let res = tx.execute(self, is_genesis);
-            if let Err(error) = res {
-                self.accounts = account_backup;
-                return Err(format!("Error during tx execution: {}", error));
-            }
+    // Like open, for the chain `config` starts. An empty store gets its genesis block, any
+    // other is taken to hold that chain.
+    pub fn open_genesis(store: BlockStore, config: &GenesisConfig) -> Result<Self, Error> {
+        config.validate()?;
+        let mut blockchain = Self::open(store, config.target)?;
+        blockchain.chain_id = config.chain_id.clone();
+        if blockchain.is_empty() {
+            blockchain.append_block(config.block()).map_err(|error| error.to_string())?;
         }
+        Ok(blockchain)
+    }
+}
 
-        // TODO Task 3: Append block only if block.hash < target
-        // Adjust difficulty of target each block generation (epoch)
-        if is_genesis
-        {
-            self.target = 0x00000000ffff0000000000000000000000000000;
-        }
-        else if block.hash().parse::<u128>().unwrap() >= self.target
-        {
-            return Err("The hash of block more than target.".to_string());
+impl<S: ChainStore> Blockchain<S> {
+    // A chain kept in `chain_store`, which has to be empty: the state its blocks produced
+    // is not stored there and can't be recovered
+    pub fn with_chain_store(chain_store: S, max_target: u128) -> Result<Self, Error> {
+        if !chain_store.is_empty() {
+            return Err("Chain store already holds blocks".to_string());
         }
 
-        let first_transaction = block.transactions[0].timestamp;
-        let last_transaction = block.transactions[block.transactions.len() - 1].timestamp;
-        let actual = last_transaction - first_transaction;
-        let expected = block.transactions.len() * 10 * 60;
-        let mut ratio = ((actual as f64)/(expected as f64)) as u128;
-        if ratio > 4 
-        {
-            ratio = 4;
-        }
-        let new_target = self.target*ratio;
-        if new_target > 0x00000000ffff0000000000000000000000000000
-        {
-            self.target = 0x00000000ffff0000000000000000000000000000;
-        }
-        else
-        {
-            self.target = new_target;
+        Ok(Self::build(chain_store, max_target))
+    }
+
+    fn build(blocks: S, max_target: u128) -> Self {
+        Self {
+            chain_id: String::new(),
+            target: round_target(max_target),
+            max_target,
+            difficulty: DifficultyConfig::default(),
+            pow: default_pow(),
+            targets: vec![],
+            clock: default_clock(),
+            transition: default_transition(),
+            blocks,
+            index: OnceCell::new(),
+            history: StateHistory::default(),
+            accounts: StateOverlay::new(),
+            aliases: StateOverlay::new(),
+            standing_orders: vec![],
+            transaction_pool: vec![],
+            orphans: OrphanPool::default(),
+            side_branches: SideBranches::default(),
+            finality: Finality::default(),
+            safe_depth: DEFAULT_SAFE_DEPTH,
+            fees: FeeConfig::default(),
+            block_reward: BLOCK_REWARD,
+            require_signed_blocks: false,
+            read_only: false,
+            dust_sweep: None,
+            store: None,
+            template: None,
+            signatures: SignatureCache::new(),
+            undo: BTreeMap::new(),
+            rollback_by_copy: false,
         }
+    }
 
-        self.blocks.append(block);
-        Ok(())
+    pub fn difficulty(&self) -> &DifficultyConfig {
+        &self.difficulty
     }
 
-    pub fn validate(&self) -> Result<(), Error> {
-        let mut block_num = self.blocks.len();
-        let mut prev_block_hash: Option<Hash> = None;
+    pub fn set_difficulty(&mut self, difficulty: DifficultyConfig) {
+        self.difficulty = difficulty;
+    }
 
-        for block in self.blocks.iter() {
-            let is_genesis = block_num == 1;
+    pub fn pow(&self) -> Arc<dyn Pow> {
+        self.pow.clone()
+    }
 
-            if !block.verify() {
-                return Err(format!("Block {} has invalid hash", block_num));
-            }
+    // Blocks are sealed and verified with this puzzle from the next block on
+    pub fn set_pow(&mut self, pow: Arc<dyn Pow>) {
+        self.pow = pow;
+    }
 
-            if !is_genesis && block.prev_hash.is_none() {
-                return Err(format!("Block {} doesn't have prev_hash", block_num));
-            }
+    pub fn with_pow(mut self, pow: Arc<dyn Pow>) -> Self {
+        self.set_pow(pow);
+        self
+    }
 
-            if is_genesis && block.prev_hash.is_some() {
-                return Err("Genesis block shouldn't have prev_hash".to_string());
-            }
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.set_clock(clock);
+        self
+    }
 
-            if block_num != self.blocks.len() {
-                if let Some(prev_block_hash) = &prev_block_hash {
-                    if prev_block_hash != &block.hash.clone().unwrap() {
-                        return Err(format!(
-                            "Block {} prev_hash doesn't match Block {} hash",
-                            block_num + 1,
-                            block_num
-                        ));
-                    }
-                }
-            }
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
 
-            prev_block_hash = block.prev_hash.clone();
-            block_num -= 1;
-        }
+    pub fn now(&self) -> Timestamp {
+        self.clock.now()
+    }
 
-        Ok(())
+    pub fn transition(&self) -> Arc<dyn TransitionFunction> {
+        self.transition.clone()
     }
 
-    pub fn get_last_block_hash(&self) -> Option<Hash> {
-        self.blocks.head().map(|block| block.hash())
+    // Transactions after genesis execute with this from now on, blocks already on the
+    // chain stay as they were
+    pub fn set_transition(&mut self, transition: Arc<dyn TransitionFunction>) {
+        self.transition = transition;
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::types::TransactionData;
-    use crate::utils::{append_block, append_block_with_tx};
+    pub fn with_transition(mut self, transition: Arc<dyn TransitionFunction>) -> Self {
+        self.set_transition(transition);
+        self
+    }
 
-    #[test]
-    fn test_new() {
-        let bc = Blockchain::new();
-        assert_eq!(bc.get_last_block_hash(), None);
+    pub fn len(&self) -> usize {
+        self.blocks.len()
     }
 
-    #[test]
-    fn test_append() {
-        let bc = &mut Blockchain::new();
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
 
-        append_block(bc, 1);
-        let block = append_block(bc, 2);
+    pub fn target(&self) -> u128 {
+        self.target
+    }
 
-        assert_eq!(bc.get_last_block_hash(), block.hash);
+    // The easiest target blocks may have to meet
+    pub fn max_target(&self) -> u128 {
+        self.max_target
     }
 
-    #[test]
-    fn test_create_genesis_block() {
-        let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let bc = &mut Blockchain::new();
+    pub fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
 
-        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
-        let tx_create_account =
-            Transaction::new(TransactionData::CreateAccount("satoshi".to_string(), keypair.public), None, time);
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_mint_initial_supply = Transaction::new(
-            TransactionData::MintInitialSupply {
-                to: "satoshi".to_string(),
-                amount: 100_000_000,
-            },
-            None,
-            time,
-        );
+    // The current target in compact form, as the next block has to carry it
+    pub fn bits(&self) -> u32 {
+        encode_bits(self.target)
+    }
 
-        let mut block = Block::new(None);
-        block.add_transaction(tx_create_account);
-        block.add_transaction(tx_mint_initial_supply);
+    pub fn orphans(&self) -> &OrphanPool {
+        &self.orphans
+    }
 
-        let mut i = 1;
-        while i < 10000
-        {
-            block.set_nonce(i);
-            if u128::from_str_radix(&block.hash(), 16).ok().unwrap() < bc.target
-            {
-                break;
-            }
-            i += 1;
-        }
-        /*
-        if block.hash() < bc.target
-        {
-            assert!(
-                bc.append_block(block).is_ok()
-            );
+    pub fn side_branches(&self) -> &SideBranches {
+        &self.side_branches
+    }
 
-            let satoshi = bc.get_account_by_id("satoshi".to_string());
+    pub fn set_max_branch_depth(&mut self, max_depth: u64) {
+        self.side_branches.set_max_depth(max_depth);
+        self.side_branches.prune(self.tip_height());
+    }
 
-            assert!(satoshi.is_some());
-            assert_eq!(satoshi.unwrap().balance, 100_000_000);
-        } else
-        {
-            */
-            assert_eq!(
-                bc.append_block(block).err().unwrap(),
-                "Error during tx execution: Invalid account.".to_string()
-            );
-        //}
+    pub fn signatures(&self) -> &SignatureCache {
+        &self.signatures
+    }
 
-        
+    pub fn finality(&self) -> &Finality {
+        &self.finality
     }
 
-    #[test]
-    fn test_create_genesis_block_fails() {
-        let mut bc = Blockchain::new();
+    pub fn set_validators(&mut self, validators: Vec<AccountId>, checkpoint_interval: u64) {
+        self.finality.set_validators(validators);
+        self.finality.set_interval(checkpoint_interval);
+    }
 
-        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
-        let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_create_account =
-            Transaction::new(TransactionData::CreateAccount("satoshi".to_string(), keypair.public), None, time);
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_mint_initial_supply = Transaction::new(
-            TransactionData::MintInitialSupply {
-                to: "satoshi".to_string(),
-                amount: 100_000_000,
-            },
-            None,
-            time,
-        );
-        let mut block = Block::new(None);
-        block.add_transaction(tx_mint_initial_supply);
-        block.add_transaction(tx_create_account);
+    pub fn finalized_height(&self) -> Option<u64> {
+        self.finality.finalized_height()
+    }
 
-        let mut i = 1;
-        while i < 10000
-        {
-            block.set_nonce(i);
-            if block.hash().parse::<u128>().unwrap() < bc.target
-            {
-                break;
-            }
-            i += 1;
-        }
+    pub fn fees(&self) -> &FeeConfig {
+        &self.fees
+    }
 
-        assert_eq!(
-            bc.append_block(block).err().unwrap(),
-            "Error during tx execution: Invalid account.".to_string()
-        );        
+    pub fn set_fees(&mut self, fees: FeeConfig) {
+        self.fees = fees;
+        self.template = None;
     }
 
-    #[test]
-    fn test_state_rollback_works() {
-        let mut bc = Blockchain::new();
+    pub fn block_reward(&self) -> Balance {
+        self.block_reward
+    }
 
-        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
-        let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_create_account =
-            Transaction::new(TransactionData::CreateAccount("satoshi".to_string(), keypair.public), None, time);
-        let tx_mint_initial_supply = Transaction::new(
-            TransactionData::MintInitialSupply {
-                to: "satoshi".to_string(),
-                amount: 100_000_000,
+    pub fn set_block_reward(&mut self, reward: Balance) {
+        self.block_reward = reward;
+    }
+
+    // Puts a coinbase paying the block reward to `miner` in front of the block's
+    // transactions and names the miner as its beneficiary. Done before sealing, both are
+    // part of the hash.
+    pub fn add_coinbase(&self, block: &mut Block, miner: AccountId) {
+        let coinbase = Transaction::new(
+            TransactionData::Coinbase {
+                to: miner.clone(),
+                amount: self.block_reward,
             },
             None,
-            time,
+            block.timestamp(),
         );
-        let mut block = Block::new(None);
-        block.set_nonce(1);
-        block.add_transaction(tx_create_account);
-        block.add_transaction(tx_mint_initial_supply);
+        block.prepend_transaction(coinbase);
+        block.set_beneficiary(Some(miner));
+    }
 
-        assert!(bc.append_block(block).is_ok());
+    pub fn require_signed_blocks(&self) -> bool {
+        self.require_signed_blocks
+    }
 
-        let mut block = Block::new(bc.get_last_block_hash());
-        let keypair_alice = Keypair::generate(&mut rand::rngs::OsRng {});
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_create_alice =
-            Transaction::new(TransactionData::CreateAccount("alice".to_string(), keypair_alice.public), None, time);
-        let keypair_bob = Keypair::generate(&mut rand::rngs::OsRng {});
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_create_bob =
-            Transaction::new(TransactionData::CreateAccount("bob".to_string(), keypair_bob.public), None, time);
-        block.set_nonce(2);
-        block.add_transaction(tx_create_alice);
-        block.add_transaction(tx_create_bob.clone());
-        block.add_transaction(tx_create_bob);
+    pub fn set_require_signed_blocks(&mut self, require: bool) {
+        self.require_signed_blocks = require;
+    }
 
-        assert!(bc.append_block(block).is_err());
+    // Has failed blocks and transactions roll back to a copy of the whole state taken
+    // before they ran, the way it was done before the overlays. Far slower, it is there
+    // for checking the overlays against.
+    pub fn set_rollback_by_copy(&mut self, rollback_by_copy: bool) {
+        self.rollback_by_copy = rollback_by_copy;
+    }
 
-        assert!(bc.get_account_by_id("satoshi".to_string()).is_some());
-        assert!(bc.get_account_by_id("alice".to_string()).is_none());
-        assert!(bc.get_account_by_id("bob".to_string()).is_none());
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
     }
 
-    #[test]
-    fn test_validate() {
-        let bc = &mut Blockchain::new();
+    // Turning a node into a replica drops whatever it had pending
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+        if read_only {
+            self.transaction_pool.clear();
+            self.template = None;
+        }
+    }
 
-        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
-        let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_create_account =
-            Transaction::new(TransactionData::CreateAccount("satoshi".to_string(), keypair.public), None, time);
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_mint_initial_supply = Transaction::new(
-            TransactionData::MintInitialSupply {
-                to: "satoshi".to_string(),
-                amount: 100_000_000,
-            },
-            None,
-            time,
-        );
-        assert!(
-            append_block_with_tx(bc, 1, vec![tx_create_account, tx_mint_initial_supply]).is_ok()
-        );
+    pub fn dust_sweep(&self) -> Option<&DustSweep> {
+        self.dust_sweep.as_ref()
+    }
 
-        append_block(bc, 2);
-        append_block(bc, 3);
+    // Off by default
+    pub fn set_dust_sweep(&mut self, dust_sweep: Option<DustSweep>) {
+        self.dust_sweep = dust_sweep;
+    }
 
-        assert!(bc.validate().is_ok());
+    pub fn safe_depth(&self) -> u64 {
+        self.safe_depth
+    }
 
-        let mut iter = bc.blocks.iter_mut();
-        iter.next();
-        iter.next();
-        let block = iter.next().unwrap();
-        block.transactions[1].data = TransactionData::MintInitialSupply {
-            to: "satoshi".to_string(),
-            amount: 100,
-        };
+    pub fn set_safe_depth(&mut self, safe_depth: u64) {
+        self.safe_depth = safe_depth.max(1);
+    }
 
-        assert!(bc.validate().is_err());
+    // Main chain blocks from the one including the transaction up to the tip, 0 while it
+    // is pending and None if the transaction is unknown
+    pub fn confirmations(&self, transaction_hash: &Hash) -> Option<u64> {
+        match self.index().transaction_height(transaction_hash) {
+            Some(height) => Some(self.tip_height() - height + 1),
+            None if self.transaction_pool.iter().any(|tx| &tx.hash() == transaction_hash) => Some(0),
+            None => None,
+        }
     }
 
-    #[test]
-    fn test_transfer_transaction() {
-        let mut bc = Blockchain::new();
+    // A main chain transaction along with the height of the block including it
+    pub fn get_transaction(&self, transaction_hash: &Hash) -> Option<(&Transaction, u64)> {
+        let height = self.index().transaction_height(transaction_hash)?;
+        self.get_block_by_height(height)?
+            .transactions()
+            .iter()
+            .find(|tx| &tx.hash() == transaction_hash)
+            .map(|tx| (tx, height))
+    }
 
-        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
-        let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_create_account =
-            Transaction::new(TransactionData::CreateAccount("satoshi".to_string(), keypair.public), None, time);
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_mint_initial_supply = Transaction::new(
-            TransactionData::MintInitialSupply {
-                to: "satoshi".to_string(),
-                amount: 100_000_000,
-            },
-            None,
-            time,
+    // Hashes of the main chain transactions sending from or to the account, oldest first
+    pub fn account_history(&self, account_id: &AccountId) -> &[Hash] {
+        self.index().account_history(account_id)
+    }
+
+    // Every account as it was after the main chain block at `height`, for looking back
+    // without replaying the chain. None for heights before the chain was loaded from a
+    // store, or past the tip.
+    pub fn accounts_at(&self, height: u64) -> Option<HashMap<AccountId, Account>> {
+        self.history.accounts_at(height)
+    }
+
+    // Has the lookup indexes rebuilt from the blocks, after they were lost or got out of
+    // sync. The rebuild happens on the next lookup.
+    pub fn reindex(&mut self) {
+        self.index = OnceCell::new();
+    }
+
+    fn index(&self) -> &ChainIndex {
+        self.index.get_or_init(|| {
+            let mut index = ChainIndex::new();
+            let blocks: Vec<&Block> = self.blocks.blocks().collect();
+            for (height, block) in blocks.into_iter().rev().enumerate() {
+                index.insert_block(block, height as u64);
+            }
+            index
+        })
+    }
+
+    // Whether the transaction reached the safe depth or a finalized checkpoint
+    pub fn is_safe(&self, transaction_hash: &Hash) -> bool {
+        match self.confirmations(transaction_hash) {
+            Some(0) | None => false,
+            Some(confirmations) => {
+                let height = self.tip_height() + 1 - confirmations;
+                confirmations >= self.safe_depth
+                    || matches!(self.finalized_height(), Some(finalized) if height <= finalized)
+            }
+        }
+    }
+
+    pub fn submit_vote(&mut self, vote: Vote) -> Result<(), Error> {
+        if !self.finality.is_validator(&vote.validator) {
+            return Err(format!("{} is not a validator", vote.validator));
+        }
+        if !self.finality.is_checkpoint(vote.height) {
+            return Err(format!("Block {} is not a checkpoint", vote.height));
+        }
+        if self.main_chain_hash(vote.height).as_ref() != Some(&vote.block_hash) {
+            return Err("Vote doesn't match the main chain".to_string());
+        }
+
+        self.verify_signature(&vote.validator, &vote.hash(), &vote.signature)?;
+
+        if self.finality.record_vote(&vote)? {
+            self.side_branches.prune_forks_below(vote.height);
+        }
+        Ok(())
+    }
+
+    // Appends a block decided by BFT consensus instead of PoW: `commit` has to hold
+    // precommits for the block from more than 2/3 of the validators, the block is
+    // final right away
+    pub fn append_committed_block(&mut self, block: Block, commit: &[BftVote]) -> Result<(), Error> {
+        let height = self.len() as u64;
+        let hash = block.hash();
+        if block.header.prev_hash != self.get_last_block_hash() {
+            return Err("Block doesn't extend the last block".to_string());
+        }
+
+        let mut signers: Vec<&AccountId> = vec![];
+        for vote in commit {
+            if vote.kind != VoteKind::Precommit || vote.height != height || vote.block_hash.as_ref() != Some(&hash) {
+                return Err("Commit contains a vote for another block".to_string());
+            }
+            if !self.finality.is_validator(&vote.validator) {
+                return Err(format!("{} is not a validator", vote.validator));
+            }
+            if signers.contains(&&vote.validator) {
+                return Err(format!("Validator {} voted twice", vote.validator));
+            }
+            self.verify_signature(&vote.validator, &vote.hash(), &vote.signature)?;
+            signers.push(&vote.validator);
+        }
+        if signers.len() < self.finality.quorum() {
+            return Err("Commit doesn't have a quorum of precommits".to_string());
+        }
+
+        self.apply_block(block, false)?;
+        self.finality.finalize(height, hash);
+        self.side_branches.prune_forks_below(height);
+        Ok(())
+    }
+
+    pub fn append_block(&mut self, block: Block) -> Result<(), BlockchainError> {
+        self.apply_block(block, true)
+    }
+
+    fn apply_block(&mut self, block: Block, check_pow: bool) -> Result<(), BlockchainError> {
+        if !block.verify() {
+            return Err(BlockchainError::InvalidHash);
+        }
+        let is_genesis = self.blocks.is_empty();
+
+        if block.body.transactions.is_empty() {
+            return Err(BlockchainError::EmptyBlock);
+        }
+        // Only genesis comes without a parent, every other block builds on the tip
+        let head = self.get_last_block_hash();
+        if block.header.prev_hash != head {
+            return Err(BlockchainError::InvalidParent {
+                prev_hash: block.header.prev_hash.clone(),
+                expected: head,
+            });
+        }
+        let expected = self.len() as u64;
+        if block.height() != expected {
+            return Err(BlockchainError::InvalidHeight {
+                height: block.height(),
+                expected,
+            });
+        }
+
+        // Genesis is not mined, every other block has to claim the current target in its
+        // bits and meet it
+        if check_pow && !is_genesis {
+            if block.header.bits != self.bits() {
+                return Err(BlockchainError::BitsMismatch {
+                    bits: block.header.bits,
+                    expected: self.bits(),
+                });
+            }
+            if !self.pow.verify(&block) {
+                return Err(BlockchainError::TargetNotMet);
+            }
+        }
+
+        // Genesis is trusted, every other block has to be from the present
+        let now = self.clock.now();
+        if !is_genesis && block.header.timestamp > now + MAX_FUTURE_DRIFT {
+            return Err(BlockchainError::BlockFromFuture);
+        }
+        if !is_genesis && block.body.transactions.iter().any(|tx| tx.timestamp > now + MAX_FUTURE_DRIFT) {
+            return Err(BlockchainError::TransactionFromFuture);
+        }
+        for tx in &block.body.transactions {
+            tx.check_limits().map_err(BlockchainError::InvalidTransaction)?;
+        }
+        // Any block after genesis may lead with a coinbase minting the block reward, which
+        // stays out of the canonical order. A coinbase anywhere else fails to execute.
+        let (coinbase, transactions) = match block.body.transactions.split_first() {
+            Some((first, rest)) if !is_genesis && first.is_coinbase() => (Some(first), rest),
+            _ => (None, &block.body.transactions[..]),
+        };
+        if let Some(TransactionData::Coinbase { amount, .. }) = coinbase.map(|tx| tx.data()) {
+            if *amount != self.block_reward {
+                return Err(BlockchainError::InvalidCoinbase {
+                    amount: *amount,
+                    expected: self.block_reward,
+                });
+            }
+        }
+        // Genesis is trusted to list its transactions in the order they depend on each other
+        if !is_genesis {
+            let keys: Vec<_> = transactions.iter().map(|tx| self.ordering_key(tx)).collect();
+            if let Some(position) = keys.windows(2).position(|pair| pair[0] >= pair[1]) {
+                let offset = block.body.transactions.len() - transactions.len();
+                return Err(BlockchainError::TransactionsOutOfOrder(offset + position + 1));
+            }
+        }
+
+        // Genesis is trusted, the producer of any other block has to be a known account
+        // that signed it
+        if !is_genesis {
+            self.validate_producer(&block)?;
+        }
+
+        let ommer_rewards = self.validate_ommers(&block)?;
+
+        let checkpoint = self.checkpoint();
+        if let Some(coinbase) = coinbase {
+            if let Err(error) = self.pay_coinbase(coinbase) {
+                self.rollback(checkpoint);
+                return Err(BlockchainError::TransactionFailed(Box::new(error)));
+            }
+        }
+        for tx in transactions {
+            let res = self.execute_transaction(tx, is_genesis, block.beneficiary());
+            if let Err(error) = res {
+                self.rollback(checkpoint);
+                return Err(BlockchainError::TransactionFailed(Box::new(error)));
+            }
+        }
+
+        for (account_id, reward) in ommer_rewards {
+            if let Some(account) = self.accounts.get_mut(&account_id) {
+                account.balance = account.balance.saturating_add(reward);
+            }
+        }
+
+        let height = block.height();
+        self.settle_standing_orders(height, block.timestamp());
+        self.sweep_dust(height);
+        if is_genesis
+        {
+            self.target = round_target(self.max_target);
+        }
+        let undo = self.commit(checkpoint);
+        if let Some(store) = &self.store {
+            let changes = self.state_changes(undo.accounts.keys(), undo.aliases.keys(), &undo.standing_orders);
+            if let Err(error) = store.append(height, &block, self.target, &changes) {
+                self.undo(undo);
+                return Err(BlockchainError::Other(error));
+            }
+        }
+        self.history.record(height, &undo.accounts, &self.accounts);
+        self.undo.insert(height, undo);
+        self.undo = self.undo.split_off(&height.saturating_sub(self.side_branches.max_depth()));
+        // Whatever the block confirmed is no longer pending, nor is what can't run anymore
+        // because its nonce got used up
+        let confirmed: Vec<Hash> = block.body.transactions.iter().map(|tx| tx.hash()).collect();
+        let accounts = &self.accounts;
+        let used_up = |pending: &Transaction| match pending.nonce_account().and_then(|id| accounts.get(id)) {
+            Some(account) => pending.nonce() <= account.nonce() && account.authorizes(pending),
+            None => false,
+        };
+        self.transaction_pool
+            .retain(|pending| !confirmed.contains(&pending.hash()) && !used_up(pending));
+        self.template = None;
+
+        self.targets.push(self.target);
+        if let Some(index) = self.index.get_mut() {
+            index.insert_block(&block, height);
+        }
+        self.blocks.put_block(block);
+        self.retarget(height);
+        self.side_branches.prune(self.tip_height());
+        Ok(())
+    }
+
+    // Adjusts the target for the block after `height` with the configured algorithm
+    fn retarget(&mut self, height: u64) {
+        let history_len = self.difficulty.history_len();
+        let mut recent: Vec<(Timestamp, u128)> = self
+            .blocks
+            .blocks()
+            .take(history_len)
+            .map(|block| block.header.timestamp)
+            .zip(self.targets.iter().rev().copied())
+            .collect();
+        recent.reverse();
+        // Targets are kept to what bits can express so every block can state its own
+        self.target = round_target(self.difficulty.next_target(height, &recent, self.max_target));
+    }
+
+    // Persists every block appended from now on to `store`, after loading the chain and
+    // accounts it already holds. An empty store gets the blocks this chain already has.
+    pub fn with_store(mut self, store: BlockStore) -> Result<Self, Error>
+    where
+        S: Default,
+    {
+        if store.is_empty() {
+            // The state is only there as the tip left it, so it goes in with the tip
+            let blocks: Vec<&Block> = self.blocks.blocks().collect();
+            let tip_height = blocks.len().saturating_sub(1);
+            for (height, block) in blocks.into_iter().rev().enumerate() {
+                let changes = if height == tip_height {
+                    StateChanges::everything(&self.accounts, &self.aliases, &self.standing_orders)
+                } else {
+                    StateChanges::default()
+                };
+                store.append(height as u64, block, self.targets[height], &changes)?;
+            }
+        } else {
+            let mut stored = store.load()?;
+            let mut blocks = S::default();
+            let mut targets = vec![];
+            for (block, target) in stored.blocks.drain(..) {
+                blocks.put_block(block);
+                targets.push(target);
+            }
+            self.restore(blocks, targets, stored);
+        }
+
+        self.store = Some(store);
+        Ok(self)
+    }
+
+    // Takes over a chain loaded from a store, `state` being what its tip left behind
+    fn restore(&mut self, blocks: S, targets: Vec<u128>, state: StoredChain) {
+        self.blocks = blocks;
+        self.targets = targets;
+        self.accounts = state.accounts.into();
+        self.aliases = state.aliases.into();
+        self.standing_orders = state.standing_orders;
+        self.transaction_pool.clear();
+        self.template = None;
+        self.reindex();
+        self.history.reset(self.tip_height(), &self.accounts);
+        self.undo.clear();
+        self.retarget(self.tip_height());
+    }
+
+    pub fn store(&self) -> Option<&BlockStore> {
+        self.store.as_ref()
+    }
+
+    pub fn chain_store(&self) -> &S {
+        &self.blocks
+    }
+
+    // Entry point for blocks received from elsewhere: blocks with an unknown parent are
+    // kept as orphans and attached once the parent (reported as `missing_parent`) arrives,
+    // blocks building on anything but the tip are stored as side branches. A side branch
+    // with more work than the main chain above its fork point becomes the main chain.
+    pub fn process_block(&mut self, block: Block) -> Result<BlockStatus, BlockchainError> {
+        if !block.verify() {
+            return Err(BlockchainError::InvalidHash);
+        }
+
+        let hash = block.hash();
+        if self.block_height(&hash).is_some() {
+            return Err(BlockchainError::BlockExists);
+        }
+
+        let side_height = match &block.header.prev_hash {
+            Some(prev_hash) if self.get_last_block_hash().as_ref() != Some(prev_hash) => {
+                match self.block_height(prev_hash) {
+                    Some(parent_height) => Some(parent_height + 1),
+                    None => {
+                        // Orphans can't be checked against their parent yet, but the work
+                        // they claim has to be there, or junk could push real ones out of
+                        // the pool for free
+                        if !self.meets_own_bits(&block) {
+                            return Err(BlockchainError::TargetNotMet);
+                        }
+                        let missing_parent = self.orphans.missing_ancestor(prev_hash);
+                        self.orphans.insert(block);
+                        return Ok(BlockStatus::Orphan { missing_parent });
+                    }
+                }
+            }
+            None if !self.blocks.is_empty() => {
+                return Err(BlockchainError::GenesisExists);
+            }
+            _ => None,
+        };
+
+        match side_height {
+            Some(height) => self.store_side_block(hash.clone(), height, block)?,
+            None => self.append_block(block)?,
+        }
+        let attached_orphans = self.attach_orphans(hash);
+
+        Ok(match (self.reorg_to_heaviest_branch(), side_height) {
+            (Some(reorg), _) => BlockStatus::Reorganized { reorg, attached_orphans },
+            (None, Some(height)) => BlockStatus::SideBranch { height, attached_orphans },
+            (None, None) => BlockStatus::Appended { attached_orphans },
+        })
+    }
+
+    fn attach_orphans(&mut self, parent: Hash) -> Vec<Hash> {
+        let mut attached = vec![];
+        let mut parents = vec![parent];
+
+        while let Some(parent) = parents.pop() {
+            for orphan in self.orphans.take_children(&parent) {
+                let hash = orphan.hash();
+                let res = if self.get_last_block_hash() == orphan.header.prev_hash {
+                    self.append_block(orphan)
+                } else {
+                    match self.block_height(&parent) {
+                        Some(parent_height) => self.store_side_block(hash.clone(), parent_height + 1, orphan),
+                        None => Err(BlockchainError::Other("Unknown parent".to_string())),
+                    }
+                };
+
+                if res.is_ok() {
+                    attached.push(hash.clone());
+                    parents.push(hash);
+                }
+            }
+        }
+
+        attached
+    }
+
+    fn store_side_block(&mut self, hash: Hash, height: u64, block: Block) -> Result<(), BlockchainError> {
+        if block.body.transactions.is_empty() {
+            return Err(BlockchainError::EmptyBlock);
+        }
+        if block.height() != height {
+            return Err(BlockchainError::InvalidHeight {
+                height: block.height(),
+                expected: height,
+            });
+        }
+
+        // The target at the fork point isn't tracked, side blocks have to meet the bits they
+        // state within the pow limit
+        if !self.meets_own_bits(&block) {
+            return Err(BlockchainError::TargetNotMet);
+        }
+
+        if self.side_branches.is_too_deep(height, self.tip_height()) {
+            return Err(BlockchainError::SideBranchTooDeep);
+        }
+
+        // Finalized blocks can never be reorged, so neither can branches forking below them
+        let root_height = match block.header.prev_hash.as_ref().and_then(|p| self.side_branches.root_height(p)) {
+            Some(root_height) => root_height,
+            None => height,
+        };
+        if matches!(self.finalized_height(), Some(finalized) if root_height <= finalized) {
+            return Err(BlockchainError::FinalityConflict);
+        }
+
+        self.side_branches.insert(hash, height, block);
+        Ok(())
+    }
+
+    // Work of the main chain blocks above `height`
+    fn work_above(&self, height: u64) -> u128 {
+        self.targets
+            .iter()
+            .skip(height as usize + 1)
+            .fold(0u128, |work, target| work.saturating_add(target_work(*target)))
+    }
+
+    // Switches to the side branch with the most work if it outweighs the main chain blocks
+    // it would replace. On a tie the main chain stays, being the first seen. Branches that
+    // turn out invalid on the way are dropped and the next heaviest gets its turn.
+    fn reorg_to_heaviest_branch(&mut self) -> Option<Reorg> {
+        loop {
+            let (work, tip) = self
+                .side_branches
+                .tips()
+                .into_iter()
+                .filter_map(|tip| Some((self.side_branches.work(&tip)?, tip)))
+                .max()?;
+            let fork_height = self.side_branches.root_height(&tip)?.checked_sub(1)?;
+            if work <= self.work_above(fork_height) {
+                return None;
+            }
+            if (fork_height + 1..=self.tip_height()).any(|height| !self.undo.contains_key(&height)) {
+                return None;
+            }
+
+            match self.reorg(&tip, fork_height) {
+                Ok(reorg) => return Some(reorg),
+                // Nothing was wrong with the branch, the store failed
+                Err(_) if self.side_branches.contains(&tip) => return None,
+                Err(_) => {}
+            }
+        }
+    }
+
+    // Replaces the main chain above `fork_height` with the side branch ending at `tip`.
+    // If a block of the branch fails to apply, the old main chain is put back and the
+    // block is dropped along with everything building on it.
+    fn reorg(&mut self, tip: &Hash, fork_height: u64) -> Result<Reorg, Error> {
+        let mut branch: Vec<Block> = self.side_branches.branch(tip).into_iter().cloned().collect();
+        branch.reverse();
+        let reverted = self.disconnect_above(fork_height)?;
+
+        for block in &branch {
+            if let Err(error) = self.apply_block(block.clone(), true) {
+                self.side_branches.remove_with_descendants(&block.hash());
+                self.disconnect_above(fork_height)?;
+                for block in reverted.iter().rev() {
+                    self.apply_block(block.clone(), true)?;
+                }
+                return Err(error.to_string());
+            }
+        }
+
+        for block in &branch {
+            self.side_branches.remove(&block.hash());
+        }
+        for block in reverted.iter().rev() {
+            self.side_branches.insert(block.hash(), block.height(), block.clone());
+        }
+        self.side_branches.prune(self.tip_height());
+
+        // Transactions only the old blocks had are pending again, as far as they still run
+        let applied: Vec<Hash> = branch.iter().flat_map(|block| block.transactions()).map(|tx| tx.hash()).collect();
+        for block in reverted.iter().rev() {
+            for tx in block.transactions().iter().filter(|tx| !tx.is_coinbase() && !applied.contains(&tx.hash())) {
+                let _ = self.submit_transaction(tx.clone());
+            }
+        }
+
+        Ok(Reorg {
+            fork_height,
+            reverted,
+            applied: branch,
+        })
+    }
+
+    // Takes every main chain block above `height` off again and undoes what it did to the
+    // state, returning them tip first. Callers make sure their undo records are there.
+    fn disconnect_above(&mut self, height: u64) -> Result<Vec<Block>, Error> {
+        let mut reverted = vec![];
+        let standing_orders = self.standing_orders.clone();
+        let mut touched_accounts = HashSet::new();
+        let mut touched_aliases = HashSet::new();
+        while self.len() as u64 > height + 1 {
+            let block = self.blocks.pop_block().ok_or("Chain store is empty")?;
+            let undo = self
+                .undo
+                .remove(&block.height())
+                .ok_or_else(|| format!("Block {} can't be taken back", block.height()))?;
+            touched_accounts.extend(undo.accounts.keys().cloned());
+            touched_aliases.extend(undo.aliases.keys().cloned());
+            self.undo(undo);
+            self.targets.pop();
+            if let Some(index) = self.index.get_mut() {
+                index.remove_block(&block);
+            }
+            reverted.push(block);
+        }
+
+        self.history.truncate(height);
+        if let Some(store) = &self.store {
+            let changes = self.state_changes(touched_accounts.iter(), touched_aliases.iter(), &standing_orders);
+            store.truncate(height + 1, &changes)?;
+        }
+        self.template = None;
+        self.retarget(height);
+        Ok(reverted)
+    }
+
+    // Side-branch blocks that can still be referenced as ommers by the next block
+    pub fn ommer_candidates(&self) -> Vec<Block> {
+        let next_height = self.tip_height() + 1;
+        let referenced = self.recent_ommers();
+
+        self.side_branches
+            .tips()
+            .iter()
+            .flat_map(|tip| self.side_branches.branch(tip))
+            .filter(|ommer| {
+                let hash = ommer.hash();
+                match ommer.header.prev_hash.as_ref().and_then(|p| self.main_chain_height(p)) {
+                    Some(parent_height) => {
+                        next_height - (parent_height + 1) <= MAX_OMMER_DEPTH
+                            && !referenced.contains(&hash)
+                    }
+                    None => false,
+                }
+            })
+            .take(MAX_OMMERS)
+            .cloned()
+            .collect()
+    }
+
+    // Whether the block meets its own bits, which can't be easier than the pow limit
+    fn meets_own_bits(&self, block: &Block) -> bool {
+        match decode_bits(block.header.bits) {
+            Ok(target) => target <= self.max_target && self.pow.verify(block),
+            Err(_) => false,
+        }
+    }
+
+    // Checks recency and uniqueness of the ommers of a block extending the tip and
+    // returns the rewards owed to their beneficiaries
+    fn validate_ommers(&self, block: &Block) -> Result<Vec<(AccountId, Balance)>, BlockchainError> {
+        if block.body.ommers.len() > MAX_OMMERS {
+            return Err(BlockchainError::TooManyOmmers(MAX_OMMERS));
+        }
+
+        let height = if self.blocks.is_empty() { 0 } else { self.tip_height() + 1 };
+        let referenced = self.recent_ommers();
+        let mut rewards = vec![];
+        let mut seen = vec![];
+
+        for ommer in &block.body.ommers {
+            let hash = ommer.hash();
+            if !ommer.verify() || !self.meets_own_bits(ommer) {
+                return Err(BlockchainError::InvalidOmmer);
+            }
+            if seen.contains(&hash) || referenced.contains(&hash) {
+                return Err(BlockchainError::OmmerAlreadyReferenced);
+            }
+            if self.main_chain_height(&hash).is_some() {
+                return Err(BlockchainError::OmmerInMainChain);
+            }
+
+            let ommer_height = match ommer.header.prev_hash.as_ref().and_then(|p| self.main_chain_height(p)) {
+                Some(parent_height) => parent_height + 1,
+                None => return Err(BlockchainError::UnknownOmmerParent),
+            };
+            if ommer.height() != ommer_height {
+                return Err(BlockchainError::InvalidOmmer);
+            }
+            let depth = height - ommer_height;
+            if depth == 0 || depth > MAX_OMMER_DEPTH {
+                return Err(BlockchainError::OmmerTooOld);
+            }
+
+            if let Some(beneficiary) = &ommer.header.beneficiary {
+                rewards.push((beneficiary.clone(), ommer_reward(depth)));
+            }
+            seen.push(hash);
+        }
+
+        Ok(rewards)
+    }
+
+    fn recent_ommers(&self) -> Vec<Hash> {
+        self.blocks
+            .blocks()
+            .take(MAX_OMMER_DEPTH as usize)
+            .flat_map(|block| block.body.ommers.iter().map(|ommer| ommer.hash()))
+            .collect()
+    }
+
+    // Height of `hash` on the main chain, side branches don't count
+    pub fn main_chain_height(&self, hash: &Hash) -> Option<u64> {
+        self.index().block_height(hash)
+    }
+
+    fn validate_producer(&self, block: &Block) -> Result<(), BlockchainError> {
+        match block.producer() {
+            Some(producer) => match self.accounts.get(producer) {
+                Some(account) if block.is_signed_by(&account.public_key) => Ok(()),
+                Some(_) => Err(BlockchainError::InvalidProducerSignature),
+                None => Err(BlockchainError::UnknownProducer(producer.clone())),
+            },
+            None if block.signature().is_some() => Err(BlockchainError::SignedWithoutProducer),
+            None if self.require_signed_blocks => Err(BlockchainError::UnsignedBlock),
+            None => Ok(()),
+        }
+    }
+
+    fn verify_signature(&self, account_id: &AccountId, hash: &Hash, signature: &Option<Signature>) -> Result<(), Error> {
+        let account = match self.accounts.get(account_id) {
+            Some(account) => account,
+            None => return Err(format!("Invalid account: {}", account_id)),
+        };
+
+        match signature {
+            Some(signature) => {
+                if account.public_key.verify(hash.as_bytes(), signature).is_err() {
+                    return Err("Invalid signature.".to_string());
+                }
+                Ok(())
+            }
+            None => Err("Not sign.".to_string()),
+        }
+    }
+
+    fn main_chain_hash(&self, height: u64) -> Option<Hash> {
+        self.get_block_by_height(height).map(|block| block.hash())
+    }
+
+    fn block_height(&self, hash: &Hash) -> Option<u64> {
+        self.main_chain_height(hash)
+            .or_else(|| self.side_branches.height_of(hash))
+    }
+
+    fn tip_height(&self) -> u64 {
+        (self.blocks.len() as u64).saturating_sub(1)
+    }
+
+    pub fn validate(&self) -> Result<(), BlockchainError> {
+        let mut block_num = self.blocks.len();
+        let mut prev_block_hash: Option<Hash> = None;
+
+        for block in self.blocks.blocks() {
+            let is_genesis = block_num == 1;
+
+            if !block.verify() {
+                return Err(BlockchainError::InvalidBlockHash(block_num));
+            }
+
+            if !is_genesis && block.header.prev_hash.is_none() {
+                return Err(BlockchainError::MissingPrevHash(block_num));
+            }
+
+            if is_genesis && block.header.prev_hash.is_some() {
+                return Err(BlockchainError::GenesisWithPrevHash);
+            }
+
+            if block.height() != block_num as u64 - 1 {
+                return Err(BlockchainError::InvalidBlockHeight {
+                    block: block_num,
+                    height: block.height(),
+                });
+            }
+
+            if block_num != self.blocks.len() {
+                if let Some(prev_block_hash) = &prev_block_hash {
+                    if prev_block_hash != &block.hash.clone().unwrap() {
+                        return Err(BlockchainError::InvalidPrevHash {
+                            block: block_num + 1,
+                            prev: block_num,
+                        });
+                    }
+                }
+            }
+
+            prev_block_hash = block.header.prev_hash.clone();
+            block_num -= 1;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_last_block_hash(&self) -> Option<Hash> {
+        self.blocks.head().map(|block| block.hash())
+    }
+
+    pub fn genesis(&self) -> Option<&Block> {
+        self.blocks.get_block_by_height(0)
+    }
+
+    pub fn genesis_hash(&self) -> Option<Hash> {
+        self.genesis().map(|block| block.hash())
+    }
+
+    pub fn info(&self) -> ChainInfo {
+        ChainInfo {
+            genesis_hash: self.genesis_hash(),
+            chain_id: self.chain_id.clone(),
+            height: (self.len() as u64).saturating_sub(1),
+            tip: self.get_last_block_hash(),
+        }
+    }
+
+    // Hash of the tip, every account in account id order, every alias in alias order and
+    // the standing orders, so two nodes can be compared with a single equality check
+    pub fn state_fingerprint(&self) -> Hash {
+        let mut hasher = Blake2s::new();
+        hasher.update((self.len() as u64, self.get_last_block_hash()).canonical_bytes());
+
+        let mut account_ids: Vec<&AccountId> = self.accounts.keys().collect();
+        account_ids.sort();
+        for account_id in account_ids {
+            hasher.update((account_id, &self.accounts[account_id]).canonical_bytes());
+        }
+        let mut aliases: Vec<(&String, &AccountId)> = self.aliases.iter().collect();
+        aliases.sort();
+        for alias in aliases {
+            hasher.update(alias.canonical_bytes());
+        }
+        hasher.update(self.standing_orders.canonical_bytes());
+
+        hex::encode(hasher.finalize_fixed())
+    }
+
+    pub fn stats(&self) -> ChainStats {
+        let blocks = self.len() as u64;
+        let transactions: u64 = self.blocks.blocks().map(|block| block.body.transactions.len() as u64).sum();
+        let average_block_interval = match (self.blocks.head(), self.genesis()) {
+            (Some(tip), Some(genesis)) if blocks > 1 => {
+                Some(tip.timestamp().saturating_sub(genesis.timestamp()) as f64 / (blocks - 1) as f64)
+            }
+            _ => None,
+        };
+
+        ChainStats {
+            blocks,
+            transactions,
+            average_block_interval,
+            average_transactions_per_block: if blocks == 0 { 0.0 } else { transactions as f64 / blocks as f64 },
+            target: self.target,
+            bits: self.bits(),
+            difficulty: round_target(self.max_target) as f64 / self.target.max(1) as f64,
+            supply: self
+                .accounts
+                .values()
+                .fold(0, |supply: Balance, account| supply.saturating_add(account.balance)),
+        }
+    }
+
+    // An empty block on top of the tip, the one a new block starts out as
+    pub fn next_block(&self) -> Block {
+        let mut block = Block::new(self.get_last_block_hash());
+        block.set_height(self.len() as u64);
+
+        block
+    }
+
+    pub fn get_block_by_height(&self, height: u64) -> Option<&Block> {
+        self.blocks.get_block_by_height(height)
+    }
+
+    pub fn get_block_by_hash(&self, hash: &Hash) -> Option<&Block> {
+        self.blocks.get_block_by_hash(hash)
+    }
+
+    pub fn pending_transactions(&self) -> &[Transaction] {
+        &self.transaction_pool
+    }
+
+    // Nonce for the next transaction `account_id` signs, after whatever it has pending
+    pub fn next_nonce(&self, account_id: &AccountId) -> u128 {
+        let confirmed = self.accounts.get(account_id).map_or(0, |account| account.nonce());
+        let pending = self
+            .transaction_pool
+            .iter()
+            .filter(|pending| pending.nonce_account() == Some(account_id))
+            .map(|pending| pending.nonce())
+            .max()
+            .unwrap_or(0);
+
+        confirmed.max(pending).saturating_add(1)
+    }
+
+    // Queues a transaction that executes against the current state. Transactions older
+    // than TRANSACTION_EXPIRY are refused and dropped from the pool.
+    pub fn submit_transaction(&mut self, transaction: Transaction) -> Result<Hash, Error> {
+        self.admit_transaction(transaction).map_err(String::from)
+    }
+
+    // submit_transaction with the reason for refusing the transaction kept typed, so a
+    // wallet can tell e.g. how much balance is missing
+    pub fn admit_transaction(&mut self, transaction: Transaction) -> Result<Hash, BlockchainError> {
+        if self.read_only {
+            return Err("Node is a read-only replica".to_string().into());
+        }
+        transaction.check_limits().map_err(|error| BlockchainError::Other(error.to_string()))?;
+        let now = self.clock.now();
+        let is_expired = |tx: &Transaction| tx.timestamp.saturating_add(TRANSACTION_EXPIRY) < now;
+        let pending = self.transaction_pool.len();
+        self.transaction_pool.retain(|pending| !is_expired(pending));
+        if self.transaction_pool.len() != pending {
+            self.template = None;
+        }
+        if is_expired(&transaction) {
+            return Err("Transaction has expired".to_string().into());
+        }
+        if transaction.timestamp > now + MAX_FUTURE_DRIFT {
+            return Err("Transaction is from the future".to_string().into());
+        }
+
+        let hash = transaction.hash();
+        if self.transaction_pool.iter().any(|pending| pending.hash() == hash) {
+            return Err("Transaction is already pending".to_string().into());
+        }
+        if self.pending_before(&transaction).iter().any(|pending| pending.nonce() == transaction.nonce()) {
+            return Err("Nonce is already pending".to_string().into());
+        }
+
+        self.simulate_transaction(&transaction)?;
+        self.transaction_pool.push(transaction);
+        Ok(hash)
+    }
+
+    // Runs `transaction` against the current state after whatever the same account has
+    // pending, then rolls everything back. This is the check transactions pass to get
+    // into the pool, wallets can make it before submitting.
+    pub fn simulate_transaction(&mut self, transaction: &Transaction) -> Result<(), BlockchainError> {
+        let earlier = self.pending_before(transaction);
+
+        let checkpoint = self.checkpoint();
+        for pending in &earlier {
+            let _ = self.execute_transaction(pending, false, None);
+        }
+        let result = self.execute_transaction(transaction, false, None);
+        self.rollback(checkpoint);
+        result
+    }
+
+    // Whatever the same account queued up to the nonce of `transaction` runs first, so it
+    // can have several transactions in flight. In nonce order.
+    fn pending_before(&self, transaction: &Transaction) -> Vec<Transaction> {
+        let mut earlier: Vec<Transaction> = match transaction.nonce_account() {
+            Some(account_id) => self
+                .transaction_pool
+                .iter()
+                .filter(|pending| pending.nonce_account() == Some(account_id) && pending.nonce() <= transaction.nonce())
+                .cloned()
+                .collect(),
+            None => vec![],
+        };
+        earlier.sort_by_key(|pending| pending.nonce());
+        earlier
+    }
+
+    // Pays every standing order due at `height` as a transfer from the payer at `timestamp`,
+    // so account types get their say. A payment that can't be made is skipped until the
+    // next interval, orders that have paid out their cap are dropped.
+    fn settle_standing_orders(&mut self, height: u64, timestamp: Timestamp) {
+        let mut orders = std::mem::take(&mut self.standing_orders);
+        for order in orders.iter_mut().filter(|order| order.next_due <= height) {
+            let amount = order.next_payment();
+            if self.settle_standing_order(order, amount, timestamp).is_ok() {
+                order.paid = order.paid.saturating_add(amount);
+            }
+            order.next_due = height.saturating_add(order.interval);
+        }
+        orders.retain(|order| !order.is_exhausted());
+        self.standing_orders = orders;
+    }
+
+    // Moves dust balances to the treasury when `height` starts an epoch. Nothing is swept
+    // without a treasury account to sweep to.
+    fn sweep_dust(&mut self, height: u64) {
+        let dust_sweep = match &self.dust_sweep {
+            Some(dust_sweep) if dust_sweep.is_epoch_boundary(height) => dust_sweep,
+            _ => return,
+        };
+        if !self.accounts.contains_key(&dust_sweep.treasury) {
+            return;
+        }
+
+        let mut swept: Vec<AccountId> = self
+            .accounts
+            .iter()
+            .filter(|(account_id, account)| **account_id != dust_sweep.treasury && dust_sweep.is_sweepable(account, height))
+            .map(|(account_id, _)| account_id.clone())
+            .collect();
+        swept.sort();
+        let treasury = dust_sweep.treasury.clone();
+        for account_id in swept {
+            let amount = self.accounts[&account_id].balance;
+            let _ = self.transfer(account_id, treasury.clone(), amount);
+        }
+    }
+
+    fn settle_standing_order(
+        &mut self,
+        order: &StandingOrder,
+        amount: Balance,
+        timestamp: Timestamp,
+    ) -> Result<(), BlockchainError> {
+        let settlement = Transaction::new(
+            TransactionData::Transfer {
+                to: order.payee.clone(),
+                amount,
+            },
+            Some(order.payer.clone()),
+            timestamp,
+        );
+        let payer = self
+            .accounts
+            .get(&order.payer)
+            .ok_or(BlockchainError::UnknownAccount(AccountRole::Sender))?;
+        let payee = self
+            .accounts
+            .get(&order.payee)
+            .ok_or(BlockchainError::UnknownAccount(AccountRole::Receiver))?;
+        payer.can_send(&settlement, amount)?;
+        payee.can_receive(&settlement, amount)?;
+        self.transfer(order.payer.clone(), order.payee.clone(), amount)?;
+
+        if let Some(account) = self.accounts.get_mut(&order.payee) {
+            account.on_credit(&settlement, amount);
+        }
+        Ok(())
+    }
+
+    pub fn standing_orders(&self) -> &[StandingOrder] {
+        &self.standing_orders
+    }
+
+    fn backup_state(&self) -> StateBackup {
+        StateBackup {
+            accounts: self.accounts.clone(),
+            aliases: self.aliases.clone(),
+            standing_orders: self.standing_orders.clone(),
+        }
+    }
+
+    // Like backup_state, but leaves the state empty rather than copying it
+    fn take_state(&mut self) -> StateBackup {
+        StateBackup {
+            accounts: std::mem::take(&mut self.accounts),
+            aliases: std::mem::take(&mut self.aliases),
+            standing_orders: std::mem::take(&mut self.standing_orders),
+        }
+    }
+
+    fn restore_state(&mut self, backup: StateBackup) {
+        self.accounts = backup.accounts;
+        self.aliases = backup.aliases;
+        self.standing_orders = backup.standing_orders;
+    }
+
+    // Rolling back to it costs what changed since rather than the whole state
+    fn checkpoint(&mut self) -> Checkpoint {
+        if self.rollback_by_copy {
+            return Checkpoint {
+                standing_orders: self.standing_orders.clone(),
+                copy: Some(self.backup_state()),
+            };
+        }
+
+        self.accounts.checkpoint();
+        self.aliases.checkpoint();
+        Checkpoint {
+            standing_orders: self.standing_orders.clone(),
+            copy: None,
+        }
+    }
+
+    fn rollback(&mut self, checkpoint: Checkpoint) {
+        match checkpoint.copy {
+            Some(copy) => self.restore_state(copy),
+            None => {
+                self.accounts.rollback();
+                self.aliases.rollback();
+                self.standing_orders = checkpoint.standing_orders;
+            }
+        }
+    }
+
+    // Keeps the changes since the checkpoint, returns what they touched as it was before
+    fn commit(&mut self, checkpoint: Checkpoint) -> StateUndo {
+        match checkpoint.copy {
+            Some(copy) => StateUndo {
+                accounts: changed(&copy.accounts, &self.accounts),
+                aliases: changed(&copy.aliases, &self.aliases),
+                standing_orders: checkpoint.standing_orders,
+            },
+            None => StateUndo {
+                accounts: self.accounts.commit(),
+                aliases: self.aliases.commit(),
+                standing_orders: checkpoint.standing_orders,
+            },
+        }
+    }
+
+    // The store entries for the given accounts and aliases as they are now, along with
+    // the standing orders that differ from `standing_orders`
+    fn state_changes<'a>(
+        &self,
+        accounts: impl Iterator<Item = &'a AccountId>,
+        aliases: impl Iterator<Item = &'a String>,
+        standing_orders: &[StandingOrder],
+    ) -> StateChanges {
+        let mut changes = StateChanges {
+            accounts: accounts.map(|id| (id.clone(), self.accounts.get(id).cloned())).collect(),
+            aliases: aliases.map(|alias| (alias.clone(), self.aliases.get(alias).cloned())).collect(),
+            ..StateChanges::default()
+        };
+        changes.set_standing_orders(standing_orders, &self.standing_orders);
+        changes
+    }
+
+    fn undo(&mut self, undo: StateUndo) {
+        for (account_id, account) in undo.accounts {
+            match account {
+                Some(account) => self.accounts.insert(account_id, account),
+                None => self.accounts.remove(&account_id),
+            };
+        }
+        for (alias, owner) in undo.aliases {
+            match owner {
+                Some(owner) => self.aliases.insert(alias, owner),
+                None => self.aliases.remove(&alias),
+            };
+        }
+        self.standing_orders = undo.standing_orders;
+    }
+
+    // Genesis follows its own rules and pays no fees. Callers restore the state if this
+    // fails.
+    fn execute_transaction(
+        &mut self,
+        transaction: &Transaction,
+        is_genesis: bool,
+        producer: Option<&AccountId>,
+    ) -> Result<(), BlockchainError> {
+        if is_genesis {
+            return GenesisExecutor::execute(self, transaction);
+        }
+
+        let fees = self.fees;
+        fees.charge(self, transaction, producer)?;
+        let transition = self.transition.clone();
+        transition.execute(transaction, self)
+    }
+
+    // Mints the block reward to the account the coinbase names, which has to exist
+    fn pay_coinbase(&mut self, coinbase: &Transaction) -> Result<(), BlockchainError> {
+        let (to, amount) = match coinbase.data() {
+            TransactionData::Coinbase { to, amount } => (to, *amount),
+            _ => return Err(BlockchainError::MisplacedCoinbase),
+        };
+        let account = self
+            .accounts
+            .get_mut(to)
+            .ok_or(BlockchainError::UnknownAccount(AccountRole::Receiver))?;
+        account.balance = account
+            .balance
+            .checked_add(amount)
+            .ok_or(BlockchainError::BalanceOverflow)?;
+        Ok(())
+    }
+
+    // Where a transaction goes within a block: by sender nonce, then the highest fee first,
+    // then by hash. Every block after genesis lists its transactions in this order, so
+    // nodes build the same block from the same transactions.
+    pub fn ordering_key(&self, transaction: &Transaction) -> (u128, Reverse<Balance>, Hash) {
+        (transaction.nonce(), Reverse(self.fees.fee(transaction)), transaction.hash())
+    }
+
+    pub fn sort_transactions(&self, transactions: &mut [Transaction]) {
+        transactions.sort_by_cached_key(|transaction| self.ordering_key(transaction));
+    }
+
+    // An unsealed block on top of the tip with every pending transaction that still
+    // executes, in canonical order. Empty when there is nothing to mine, as on a replica.
+    // The template is kept until the tip or the pool changes other than by transactions
+    // joining it. Those ordered after everything it went through run on top of it, others
+    // make it start over, so it always comes out as if built from scratch.
+    pub fn block_template(&mut self) -> Block {
+        let tip = self.get_last_block_hash();
+        let cache = match self.template.take() {
+            Some(cache)
+                if cache.block.header.prev_hash == tip
+                    && cache.block.bits() == self.bits()
+                    && cache.considered <= self.transaction_pool.len() =>
+            {
+                let mut arrived = self.transaction_pool[cache.considered..].to_vec();
+                self.sort_transactions(&mut arrived);
+                let appendable = match (&cache.last_key, arrived.first()) {
+                    (Some(last_key), Some(first)) => &self.ordering_key(first) > last_key,
+                    _ => true,
+                };
+                if appendable {
+                    self.extend_template(cache, arrived)
+                } else {
+                    self.rebuild_template()
+                }
+            }
+            _ => self.rebuild_template(),
+        };
+
+        let mut block = cache.block.clone();
+        block.set_timestamp(self.clock.now());
+        self.template = Some(cache);
+
+        block
+    }
+
+    fn rebuild_template(&mut self) -> TemplateCache {
+        let mut block = self.next_block();
+        block.set_bits(self.bits());
+        let cache = TemplateCache {
+            block,
+            state: self.backup_state(),
+            considered: 0,
+            last_key: None,
+        };
+
+        let mut pending = self.transaction_pool.clone();
+        self.sort_transactions(&mut pending);
+        self.extend_template(cache, pending)
+    }
+
+    // Runs `arrived`, in canonical order, on the state the template left behind. Whatever
+    // depends on a transaction ordered after it waits for the next block.
+    fn extend_template(&mut self, mut cache: TemplateCache, arrived: Vec<Transaction>) -> TemplateCache {
+        let live = self.take_state();
+        self.restore_state(cache.state);
+        for transaction in arrived {
+            cache.last_key = Some(self.ordering_key(&transaction));
+            // Fees are charged before a transaction runs, one that fails keeps nothing
+            let checkpoint = self.checkpoint();
+            match self.execute_transaction(&transaction, false, None) {
+                Ok(()) => {
+                    self.commit(checkpoint);
+                    cache.block.add_transaction(transaction);
+                }
+                Err(_) => self.rollback(checkpoint),
+            }
+        }
+        cache.state = self.take_state();
+        self.restore_state(live);
+        cache.considered = self.transaction_pool.len();
+
+        cache
+    }
+
+    // The template for a producer sealing blocks itself, with what it includes taken out of
+    // the pool. Whatever no longer executes stays pending. Transactions of a block that
+    // doesn't make it onto the chain have to be submitted again.
+    pub fn build_block_from_pool(&mut self) -> Block {
+        let block = self.block_template();
+        let included: Vec<Hash> = block.body.transactions.iter().map(|tx| tx.hash()).collect();
+        self.transaction_pool.retain(|pending| !included.contains(&pending.hash()));
+        self.template = None;
+
+        block
+    }
+}
+
+impl Default for Blockchain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::{meets_target, PowAlgorithm, Sha256dPow, BLOCK_TIME};
+    use crate::testing::{Fixtures, MockClock, GENESIS_TIME};
+    use crate::types::{Amount, DustSweep, FeeDestination, LimitError, Multisig, SpendingLimit, TransactionData, MAX_ACCOUNT_ID_LENGTH, MAX_ORPHANS, RECOVERY_CHALLENGE_PERIOD, SPENDING_LIMIT_DELAY};
+    use crate::utils::{append_block, append_block_with_tx, generate_account_id, mine_block};
+    use ed25519_dalek::{Keypair, Signer};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const TEST_TARGET: u128 = u128::MAX >> 8;
+    // Powers of two survive the trip through bits unrounded
+    const EXACT_TARGET: u128 = 1 << 120;
+
+    #[test]
+    fn test_new() {
+        let bc = Blockchain::new();
+        assert_eq!(bc.get_last_block_hash(), None);
+    }
+
+    #[test]
+    fn test_append() {
+        let bc = &mut Blockchain::with_max_target(TEST_TARGET);
+        let fixtures = &mut Fixtures::default();
+
+        append_block(bc, fixtures, 1);
+        let block = append_block(bc, fixtures, 2);
+
+        assert_eq!(bc.get_last_block_hash(), block.hash);
+    }
+
+    #[test]
+    fn test_create_genesis_block() {
+        let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let bc = &mut Blockchain::with_max_target(TEST_TARGET);
+
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let tx_create_account =
+            Transaction::create_account("satoshi".to_string(), &keypair, time);
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_mint_initial_supply = Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: "satoshi".to_string(),
+                amount: 100_000_000,
+            },
+            None,
+            time,
+        );
+
+        let mut block = Block::new(None);
+        block.add_transaction(tx_create_account);
+        block.add_transaction(tx_mint_initial_supply);
+
+        mine_block(&mut block, bc.target());
+
+        assert!(bc.append_block(block).is_ok());
+
+        let satoshi = bc.get_account_by_id("satoshi".to_string());
+
+        assert!(satoshi.is_some());
+        assert_eq!(satoshi.unwrap().balance, 100_000_000);
+    }
+
+    #[test]
+    fn test_create_genesis_block_fails() {
+        let mut bc = Blockchain::with_max_target(TEST_TARGET);
+
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_create_account =
+            Transaction::create_account("satoshi".to_string(), &keypair, time);
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_mint_initial_supply = Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: "satoshi".to_string(),
+                amount: 100_000_000,
+            },
+            None,
+            time,
+        );
+        let mut block = Block::new(None);
+        block.add_transaction(tx_mint_initial_supply);
+        block.add_transaction(tx_create_account);
+
+        mine_block(&mut block, bc.target());
+
+        assert_eq!(
+            bc.append_block(block),
+            Err(BlockchainError::TransactionFailed(Box::new(BlockchainError::UnknownAccount(AccountRole::Receiver))))
+        );
+    }
+
+    #[test]
+    fn test_state_rollback_works() {
+        let mut bc = Blockchain::new();
+
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_create_account =
+            Transaction::create_account("satoshi".to_string(), &keypair, time);
+        let tx_mint_initial_supply = Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: "satoshi".to_string(),
+                amount: 100_000_000,
+            },
+            None,
+            time,
+        );
+        let mut block = Block::new(None);
+        block.set_nonce(1);
+        block.add_transaction(tx_create_account);
+        block.add_transaction(tx_mint_initial_supply);
+
+        assert!(bc.append_block(block).is_ok());
+
+        let mut block = bc.next_block();
+        let keypair_alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_create_alice =
+            Transaction::create_account("alice".to_string(), &keypair_alice, time);
+        let keypair_bob = Keypair::generate(&mut rand::rngs::OsRng {});
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_create_bob =
+            Transaction::create_account("bob".to_string(), &keypair_bob, time);
+        block.set_nonce(2);
+        block.add_transaction(tx_create_alice);
+        block.add_transaction(tx_create_bob.clone());
+        block.add_transaction(tx_create_bob);
+
+        assert!(bc.append_block(block).is_err());
+
+        assert!(bc.get_account_by_id("satoshi".to_string()).is_some());
+        assert!(bc.get_account_by_id("alice".to_string()).is_none());
+        assert!(bc.get_account_by_id("bob".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_validate() {
+        let bc = &mut Blockchain::with_max_target(TEST_TARGET);
+        let fixtures = &mut Fixtures::default();
+
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_create_account =
+            Transaction::create_account("satoshi".to_string(), &keypair, time);
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_mint_initial_supply = Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: "satoshi".to_string(),
+                amount: 100_000_000,
+            },
+            None,
+            time,
+        );
+        assert!(
+            append_block_with_tx(bc, 1, vec![tx_create_account, tx_mint_initial_supply]).is_ok()
+        );
+
+        append_block(bc, fixtures, 2);
+        append_block(bc, fixtures, 3);
+
+        assert!(bc.validate().is_ok());
+
+        let mut iter = bc.blocks.iter_mut();
+        iter.next();
+        iter.next();
+        let block = iter.next().unwrap();
+        block.body.transactions[1].data = TransactionData::MintInitialSupply {
+            to: "satoshi".to_string(),
+            amount: 100,
+        };
+
+        assert!(bc.validate().is_err());
+    }
+
+    #[test]
+    fn test_transfer_transaction() {
+        let mut bc = Blockchain::with_max_target(TEST_TARGET);
+
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_create_account =
+            Transaction::create_account("satoshi".to_string(), &keypair, time);
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_mint_initial_supply = Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: "satoshi".to_string(),
+                amount: 100_000_000,
+            },
+            None,
+            time,
+        );
+
+        let keypair_alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_create_alice =
+            Transaction::create_account("alice".to_string(), &keypair_alice, time);
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_mint_initial_supply_alice = Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: "alice".to_string(),
+                    amount: 100_000,
+                },
+                None,
+                time,
+        );
+
+        let mut block = Block::new(None);
+        block.set_nonce(1);
+        block.add_transaction(tx_create_account);
+        block.add_transaction(tx_mint_initial_supply);
+        block.add_transaction(tx_create_alice);
+        block.add_transaction(tx_mint_initial_supply_alice);
+
+        assert!(bc.append_block(block).is_ok());
+
+        let mut block = bc.next_block();
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let mut tx_transfer_satoshi_to_alice = Transaction::new(
+                TransactionData::Transfer{
+                    to: "alice".to_string(),
+                    amount: 1000,
+                },
+                Some("satoshi".to_string()),
+                time,
+        );
+        tx_transfer_satoshi_to_alice.set_nonce(1);
+        tx_transfer_satoshi_to_alice.sign(Some(keypair.sign(&tx_transfer_satoshi_to_alice.signing_message())));
+
+        block.set_nonce(2);
+        block.add_transaction(tx_transfer_satoshi_to_alice);
+        mine_block(&mut block, bc.target());
+
+        assert!(bc.append_block(block).is_ok());
+
+        let satoshi = bc.get_account_by_id("satoshi".to_string());
+
+        assert!(satoshi.is_some());
+        assert_eq!(satoshi.unwrap().balance, 99_999_000);
+
+        let alice = bc.get_account_by_id("alice".to_string());
+
+        assert!(alice.is_some());
+        assert_eq!(alice.unwrap().balance, 101_000);
+    }
+
+    #[test]
+    fn test_transfer_transaction_fails() {
+        let mut bc = Blockchain::new();
+
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_create_account =
+            Transaction::create_account("satoshi".to_string(), &keypair, time);
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_mint_initial_supply = Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: "satoshi".to_string(),
+                amount: 100_000_000,
+            },
+            None,
+            time,
+        );
+
+        let keypair_alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_create_alice =
+            Transaction::create_account("alice".to_string(), &keypair_alice, time);
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_mint_initial_supply_alice = Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: "alice".to_string(),
+                    amount: 100_000,
+                },
+                None,
+                time,
+        );
+
+        let mut block = Block::new(None);
+        block.set_nonce(1);
+        block.add_transaction(tx_create_account);
+        block.add_transaction(tx_mint_initial_supply);
+        block.add_transaction(tx_create_alice);
+        block.add_transaction(tx_mint_initial_supply_alice);
+
+        assert!(bc.append_block(block).is_ok());
+
+        let mut block = bc.next_block();
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let mut tx_transfer_satoshi_to_alice = Transaction::new(
+                TransactionData::Transfer{
+                    to: "satoshi".to_string(),
+                    amount: 102_000,
+                },
+                Some("alice".to_string()),
+                time,
+        );
+        tx_transfer_satoshi_to_alice.set_nonce(1);
+        tx_transfer_satoshi_to_alice.sign(Some(keypair_alice.sign(&tx_transfer_satoshi_to_alice.signing_message())));
+        block.set_nonce(2);
+        block.add_transaction(tx_transfer_satoshi_to_alice);
+
+        assert!(bc.append_block(block).is_err());
+
+        let satoshi = bc.get_account_by_id("satoshi".to_string());
+
+        assert!(satoshi.is_some());
+        assert_eq!(satoshi.unwrap().balance, 100_000_000);
+
+        let alice = bc.get_account_by_id("alice".to_string());
+
+        assert!(alice.is_some());
+        assert_eq!(alice.unwrap().balance, 100_000);
+    }
+
+    #[test]
+    fn test_transfer_transaction_sign_fails() {
+        let mut bc = Blockchain::new();
+
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_create_account =
+            Transaction::create_account("satoshi".to_string(), &keypair, time);
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_mint_initial_supply = Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: "satoshi".to_string(),
+                amount: 100_000_000,
+            },
+            None,
+            time,
+        );
+
+        let keypair_alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_create_alice =
+            Transaction::create_account("alice".to_string(), &keypair_alice, time);
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_mint_initial_supply_alice = Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: "alice".to_string(),
+                    amount: 100_000,
+                },
+                None,
+                time,
+        );
+
+        let mut block = Block::new(None);
+        block.set_nonce(1);
+        block.add_transaction(tx_create_account);
+        block.add_transaction(tx_mint_initial_supply);
+        block.add_transaction(tx_create_alice);
+        block.add_transaction(tx_mint_initial_supply_alice);
+
+        assert!(bc.append_block(block).is_ok());
+
+        let mut block = bc.next_block();
+        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let mut tx_transfer_satoshi_to_alice = Transaction::new(
+                TransactionData::Transfer{
+                    to: "satoshi".to_string(),
+                    amount: 2_000,
+                },
+                Some("alice".to_string()),
+                time,
+        );
+        tx_transfer_satoshi_to_alice.set_nonce(1);
+        tx_transfer_satoshi_to_alice.sign(Some(keypair.sign(&tx_transfer_satoshi_to_alice.signing_message())));
+        block.set_nonce(2);
+        block.add_transaction(tx_transfer_satoshi_to_alice);
+
+        assert!(bc.append_block(block).is_err());
+
+        let satoshi = bc.get_account_by_id("satoshi".to_string());
+
+        assert!(satoshi.is_some());
+        assert_eq!(satoshi.unwrap().balance, 100_000_000);
+
+        let alice = bc.get_account_by_id("alice".to_string());
+
+        assert!(alice.is_some());
+        assert_eq!(alice.unwrap().balance, 100_000);
+    }
+
+    #[test]
+    fn test_world_state_transfer() {
+        let mut bc = Blockchain::new();
+        let public_key = Keypair::generate(&mut rand::rngs::OsRng {}).public;
+        for account_id in &["alice", "bob"] {
+            bc.create_account(account_id.to_string(), AccountType::User, public_key).unwrap();
+        }
+        bc.get_account_by_id_mut("alice".to_string()).unwrap().balance = 10;
+        bc.get_account_by_id_mut("bob".to_string()).unwrap().balance = u128::MAX - 5;
+        let balances = |bc: &Blockchain| {
+            let balance = |account_id: &str| bc.get_account_by_id(account_id.to_string()).unwrap().balance;
+            (balance("alice"), balance("bob"))
+        };
+
+        assert_eq!(bc.transfer("alice".to_string(), "bob".to_string(), 11), Err(BlockchainError::InsufficientBalance { needed: 11, available: 10 }));
+        assert_eq!(bc.transfer("alice".to_string(), "bob".to_string(), 6), Err(BlockchainError::BalanceOverflow));
+        assert!(bc.transfer("alice".to_string(), "carol".to_string(), 1).is_err());
+        assert_eq!(balances(&bc), (10, u128::MAX - 5));
+
+        assert!(bc.transfer("alice".to_string(), "alice".to_string(), 10).is_ok());
+        assert!(bc.transfer("alice".to_string(), "bob".to_string(), 5).is_ok());
+        assert_eq!(balances(&bc), (5, u128::MAX));
+    }
+
+    #[test]
+    fn test_state_fingerprint() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let mut other = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        assert_eq!(bc.state_fingerprint(), other.state_fingerprint());
+
+        for nonce in 0..3 {
+            let block = append_block(&mut bc, fixtures, nonce);
+            assert_ne!(bc.state_fingerprint(), other.state_fingerprint());
+            assert!(other.append_block(block).is_ok());
+            assert_eq!(bc.state_fingerprint(), other.state_fingerprint());
+        }
+
+        let account_id = bc.accounts.keys().next().unwrap().clone();
+        other.get_account_by_id_mut(account_id.clone()).unwrap().balance += 1;
+        assert_ne!(bc.state_fingerprint(), other.state_fingerprint());
+        other.get_account_by_id_mut(account_id.clone()).unwrap().balance -= 1;
+        assert_eq!(bc.state_fingerprint(), other.state_fingerprint());
+        // Nested state counts as well
+        other.get_account_by_id_mut(account_id).unwrap().spending.spent = 1;
+        assert_ne!(bc.state_fingerprint(), other.state_fingerprint());
+    }
+
+    #[test]
+    fn test_stats() {
+        let clock = MockClock::new(1);
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(clock.clone());
+        let stats = bc.stats();
+        assert_eq!(stats.blocks, 0);
+        assert_eq!(stats.average_block_interval, None);
+        assert_eq!(stats.average_transactions_per_block, 0.0);
+        assert_eq!(stats.difficulty, 1.0);
+
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &keypair, 1),
+            Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: "alice".to_string(),
+                    amount: 100,
+                },
+                None,
+                1,
+            ),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        clock.advance(60);
+        let bob = Transaction::create_account("bob".to_string(), &keypair, 61);
+        assert!(append_block_with_tx(&mut bc, 2, vec![bob]).is_ok());
+
+        let stats = bc.stats();
+        assert_eq!(stats.blocks, 2);
+        assert_eq!(stats.transactions, 3);
+        assert_eq!(stats.average_block_interval, Some(60.0));
+        assert_eq!(stats.average_transactions_per_block, 1.5);
+        assert_eq!(stats.bits, bc.bits());
+        assert_eq!(stats.supply, 100);
+    }
+
+    #[test]
+    fn test_reindex() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        for nonce in 0..3 {
+            append_block(&mut bc, fixtures, nonce);
+        }
+        let hashes: Vec<Hash> = (0..3)
+            .map(|height| bc.get_block_by_height(height).unwrap().body.transactions[0].hash())
+            .collect();
+        let account_id = bc.accounts.keys().next().unwrap().clone();
+        let history = bc.account_history(&account_id).to_vec();
+        assert!(!history.is_empty());
+        assert_eq!(bc.confirmations(&hashes[0]), Some(3));
+
+        bc.index.get_mut().unwrap().clear();
+        assert_eq!(bc.confirmations(&hashes[0]), None);
+        assert!(bc.account_history(&account_id).is_empty());
+
+        bc.reindex();
+        for (height, hash) in hashes.iter().enumerate() {
+            assert_eq!(bc.confirmations(hash), Some(3 - height as u64));
+        }
+        assert_eq!(bc.account_history(&account_id), history.as_slice());
+    }
+
+    // Only what a backend has to provide
+    #[derive(Debug, Default)]
+    struct VecStore(Vec<Block>);
+
+    impl ChainStore for VecStore {
+        fn put_block(&mut self, block: Block) {
+            self.0.push(block);
+        }
+
+        fn pop_block(&mut self) -> Option<Block> {
+            self.0.pop()
+        }
+
+        fn get_block_by_hash(&self, hash: &Hash) -> Option<&Block> {
+            self.0.iter().find(|block| &block.hash() == hash)
+        }
+
+        fn get_block_by_height(&self, height: u64) -> Option<&Block> {
+            self.0.get(height as usize)
+        }
+
+        fn head(&self) -> Option<&Block> {
+            self.0.last()
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    #[test]
+    fn test_chain_store() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_chain_store(VecStore::default(), TEST_TARGET).unwrap();
+        for nonce in 0..3 {
+            let mut block = bc.next_block();
+            block.set_nonce(nonce);
+            block.add_transaction(Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), fixtures.timestamp()));
+            if !bc.is_empty() {
+                mine_block(&mut block, bc.target());
+            }
+            assert!(bc.append_block(block).is_ok());
+        }
+
+        assert_eq!(bc.chain_store().len(), 3);
+        assert!(bc.validate().is_ok());
+        let genesis = bc.chain_store().get_block_by_height(0).unwrap();
+        assert_eq!(bc.genesis_hash(), Some(genesis.hash()));
+        assert_eq!(bc.chain_store().get_block_by_hash(&genesis.hash()).unwrap().hash(), genesis.hash());
+        let transaction = bc.get_block_by_height(1).unwrap().body.transactions[0].hash();
+        assert_eq!(bc.confirmations(&transaction), Some(2));
+        assert_eq!(bc.stats().transactions, 3);
+
+        let err = Blockchain::with_chain_store(bc.blocks, TEST_TARGET).unwrap_err();
+        assert_eq!(err, "Chain store already holds blocks");
+    }
+
+    #[test]
+    fn test_serde_roundtrip() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        for nonce in 0..3 {
+            append_block(&mut bc, fixtures, nonce);
+        }
+        let keys = vec![fixtures.keypair().public, fixtures.keypair().public];
+        let multisig = Account::new(AccountType::Multisig(Multisig::new(keys.clone(), 2)), keys[0]);
+        bc.accounts.insert("multisig".to_string(), multisig);
+
+        let json: Blockchain = serde_json::from_str(&serde_json::to_string(&bc).unwrap()).unwrap();
+        let binary: Blockchain = bincode::deserialize(&bincode::serialize(&bc).unwrap()).unwrap();
+        let fingerprint = bc.state_fingerprint();
+        let next = append_block(&mut bc, fixtures, 3);
+        for copy in [json, binary] {
+            assert_eq!(copy.state_fingerprint(), fingerprint);
+            assert_eq!(copy.len(), 3);
+            assert!(copy.validate().is_ok());
+            let transaction = &copy.get_block_by_height(0).unwrap().body.transactions[0];
+            assert_eq!(copy.confirmations(&transaction.hash()), Some(3));
+            match copy.accounts["multisig"].account_type() {
+                AccountType::Multisig(multisig) => assert_eq!(multisig.keys, keys),
+                account_type => panic!("Unexpected account type {:?}", account_type),
+            }
+
+            // Keeps going where the original left off once it has a clock again
+            let mut copy = copy.with_clock(fixtures.clock().clone());
+            assert!(copy.append_block(next.clone()).is_ok());
+            assert_eq!(copy.state_fingerprint(), bc.state_fingerprint());
+        }
+    }
+
+    #[test]
+    fn test_block_store() {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let path = std::env::temp_dir().join(format!("block-store-{}-{}", std::process::id(), nanos));
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        append_block(&mut bc, fixtures, 0);
+
+        // Blocks from before the store was attached are written too
+        let mut bc = bc.with_store(BlockStore::open(&path).unwrap()).unwrap();
+        for nonce in 1..3 {
+            append_block(&mut bc, fixtures, nonce);
+        }
+        let fingerprint = bc.state_fingerprint();
+        let target = bc.target;
+        drop(bc);
+
+        let mut reopened = Blockchain::with_max_target(TEST_TARGET)
+            .with_clock(fixtures.clock().clone())
+            .with_store(BlockStore::open(&path).unwrap())
+            .unwrap();
+        assert_eq!(reopened.len(), 3);
+        assert_eq!(reopened.state_fingerprint(), fingerprint);
+        assert_eq!(reopened.target, target);
+        assert!(reopened.validate().is_ok());
+        let transaction = reopened.get_block_by_height(0).unwrap().body.transactions[0].hash();
+        assert_eq!(reopened.confirmations(&transaction), Some(3));
+        // History starts over at the loaded tip
+        assert!(reopened.accounts_at(1).is_none());
+        assert_eq!(reopened.accounts_at(2).map(|accounts| accounts.len()), Some(3));
+
+        append_block(&mut reopened, fixtures, 3);
+        assert_eq!(reopened.store().unwrap().load().unwrap().blocks.len(), 4);
+        drop(reopened);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_without_reading_blocks() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET)
+            .with_clock(fixtures.clock().clone())
+            .with_store(BlockStore::temporary().unwrap())
+            .unwrap();
+        let blocks: Vec<Block> = (0..15).map(|nonce| append_block(&mut bc, fixtures, nonce)).collect();
+        let store = bc.store().unwrap().clone();
+
+        let mut opened = Blockchain::open(store, TEST_TARGET).unwrap().with_clock(fixtures.clock().clone());
+        assert_eq!(opened.len(), 15);
+        assert_eq!(opened.get_last_block_hash(), bc.get_last_block_hash());
+        assert_eq!(opened.state_fingerprint(), bc.state_fingerprint());
+        assert_eq!(opened.target, bc.target);
+        // Retargeting only read the blocks it looks at
+        let loaded = opened.chain_store().loaded();
+        assert_eq!(loaded, opened.difficulty().history_len());
+
+        assert_eq!(opened.get_block_by_hash(&blocks[1].hash()).unwrap().hash(), blocks[1].hash());
+        assert_eq!(opened.chain_store().loaded(), loaded + 1);
+        assert_eq!(opened.confirmations(&blocks[0].transactions()[0].hash()), Some(15));
+        assert!(opened.validate().is_ok());
+
+        append_block(&mut opened, fixtures, 15);
+        assert_eq!(opened.store().unwrap().load().unwrap().blocks.len(), 16);
+    }
+
+    #[test]
+    fn test_open_genesis() {
+        let mut config = GenesisConfig::new("class");
+        config.add_account("alice".to_string(), Keypair::generate(&mut rand::rngs::OsRng {}).public, Amount::from_base_units(100));
+        let store = BlockStore::temporary().unwrap();
+
+        let started = Blockchain::open_genesis(store.clone(), &config).unwrap();
+        assert_eq!(started.info(), Blockchain::from_genesis(&config).unwrap().info());
+        assert_eq!(store.load().unwrap().blocks.len(), 1);
+        drop(started);
+
+        let opened = Blockchain::open_genesis(store, &config).unwrap();
+        assert_eq!(opened.chain_id(), "class");
+        assert_eq!(opened.len(), 1);
+        assert_eq!(opened.get_account_by_id("alice".to_string()).unwrap().balance, 100);
+    }
+
+    #[test]
+    fn test_submit_transaction() {
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(MockClock::new(1));
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let txs = vec![
+            Transaction::create_account("alice".to_string(), &keypair, 0),
+            Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: "alice".to_string(),
+                    amount: 100,
+                },
+                None,
+                0,
+            ),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, txs).is_ok());
+
+        let mut transfer = Transaction::new(
+            TransactionData::Transfer {
+                to: "alice".to_string(),
+                amount: 10,
+            },
+            Some("alice".to_string()),
+            1,
+        );
+        assert!(bc.submit_transaction(transfer.clone()).is_err());
+
+        transfer.set_nonce(1);
+        transfer.sign(Some(keypair.sign(&transfer.signing_message())));
+        assert_eq!(bc.submit_transaction(transfer.clone()), Ok(transfer.hash()));
+        assert!(bc.submit_transaction(transfer).is_err());
+        assert_eq!(bc.pending_transactions().len(), 1);
+        assert_eq!(bc.get_account_by_id("alice".to_string()).unwrap().balance, 100);
+
+        assert_eq!(bc.get_block_by_height(0).map(|block| block.hash()), bc.get_last_block_hash());
+        assert!(bc.get_block_by_height(1).is_none());
+    }
+
+    #[test]
+    fn test_read_only_replica() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let genesis = append_block(&mut bc, fixtures, 1);
+        let mut replica = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        replica.set_read_only(true);
+        assert!(replica.process_block(genesis).is_ok());
+
+        // Follows what it is sent
+        let transaction = Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), fixtures.timestamp());
+        assert!(bc.submit_transaction(transaction.clone()).is_ok());
+        let mut block = bc.build_block_from_pool();
+        mine_block(&mut block, bc.target());
+        assert!(bc.append_block(block.clone()).is_ok());
+        assert!(matches!(replica.process_block(block), Ok(BlockStatus::Appended { .. })));
+        assert_eq!(replica.get_last_block_hash(), bc.get_last_block_hash());
+
+        // But takes nothing in, so there is never anything to mine
+        let transaction = Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), fixtures.timestamp());
+        assert_eq!(replica.submit_transaction(transaction), Err("Node is a read-only replica".to_string()));
+        assert!(replica.block_template().transactions().is_empty());
+    }
+
+    #[test]
+    fn test_nonces_prevent_replay() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let alice = fixtures.keypair();
+        let time = fixtures.timestamp();
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &alice, time),
+            Transaction::create_account("bob".to_string(), &fixtures.keypair(), time),
+            Transaction::new(TransactionData::MintInitialSupply { to: "alice".to_string(), amount: 100 }, None, time),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        let pay_bob = |amount, nonce| {
+            let mut transaction = Transaction::new(
+                TransactionData::Transfer { to: "bob".to_string(), amount },
+                Some("alice".to_string()),
+                time,
+            );
+            transaction.set_nonce(nonce);
+            transaction.sign(Some(alice.sign(&transaction.signing_message())));
+            transaction
+        };
+
+        // Queued ones count, gaps don't go through
+        assert_eq!(bc.submit_transaction(pay_bob(10, 0)), Err("Invalid nonce 0, expected 1.".to_string()));
+        assert!(bc.submit_transaction(pay_bob(10, 1)).is_ok());
+        assert_eq!(bc.submit_transaction(pay_bob(10, 3)), Err("Invalid nonce 3, expected 2.".to_string()));
+        assert!(bc.submit_transaction(pay_bob(20, 2)).is_ok());
+        assert_eq!(bc.submit_transaction(pay_bob(30, 1)), Err("Nonce is already pending".to_string()));
+        assert_eq!(bc.next_nonce(&"alice".to_string()), 3);
+
+        let mut block = bc.build_block_from_pool();
+        assert_eq!(block.body.transactions.len(), 2);
+        mine_block(&mut block, bc.target());
+        assert!(bc.append_block(block).is_ok());
+        assert_eq!(bc.accounts["alice"].nonce(), 2);
+        assert_eq!(bc.accounts["bob"].balance, 30);
+
+        // The same signed transfer doesn't pay twice
+        assert_eq!(bc.submit_transaction(pay_bob(10, 1)), Err("Invalid nonce 1, expected 3.".to_string()));
+        assert_eq!(
+            append_block_with_tx(&mut bc, 3, vec![pay_bob(10, 1)]),
+            Err(BlockchainError::TransactionFailed(Box::new(BlockchainError::InvalidNonce { expected: 3, got: 1 })))
+        );
+
+        // A pending transaction whose nonce a block used up is dropped
+        assert!(bc.submit_transaction(pay_bob(5, 3)).is_ok());
+        assert!(append_block_with_tx(&mut bc, 4, vec![pay_bob(6, 3)]).is_ok());
+        assert!(bc.pending_transactions().is_empty());
+        assert_eq!(bc.accounts["bob"].balance, 36);
+    }
+
+    #[test]
+    fn test_pool_signatures_are_not_checked_again() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let alice = fixtures.keypair();
+        let time = fixtures.timestamp();
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &alice, time),
+            Transaction::create_account("bob".to_string(), &fixtures.keypair(), time),
+            Transaction::new(TransactionData::MintInitialSupply { to: "alice".to_string(), amount: 100 }, None, time),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        let mut transfer = Transaction::new(
+            TransactionData::Transfer { to: "bob".to_string(), amount: 10 },
+            Some("alice".to_string()),
+            time,
+        );
+        transfer.set_nonce(1);
+        transfer.sign(Some(alice.sign(&transfer.signing_message())));
+
+        assert!(bc.submit_transaction(transfer.clone()).is_ok());
+        assert!(bc.signatures().contains(&transfer, &alice.public));
+        let cached = bc.signatures().len();
+
+        let mut block = bc.build_block_from_pool();
+        mine_block(&mut block, bc.target());
+        assert!(bc.append_block(block).is_ok());
+        assert_eq!(bc.signatures().len(), cached);
+        assert_eq!(bc.accounts["bob"].balance, 10);
+
+        // A signature by someone else is still turned down
+        let mut forged = transfer.clone();
+        forged.set_nonce(2);
+        forged.sign(Some(fixtures.keypair().sign(&forged.signing_message())));
+        assert_eq!(bc.submit_transaction(forged), Err("Invalid signature.".to_string()));
+    }
+
+    #[test]
+    fn test_coinbase() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let time = fixtures.timestamp();
+        let create_alice = Transaction::create_account("alice".to_string(), &fixtures.keypair(), time);
+        assert!(append_block_with_tx(&mut bc, 1, vec![create_alice]).is_ok());
+        let coinbase = |to: &str, amount| {
+            Transaction::new(TransactionData::Coinbase { to: to.to_string(), amount }, None, time)
+        };
+        let mined = |bc: &Blockchain, transactions: Vec<Transaction>| {
+            let mut block = bc.next_block();
+            block.set_timestamp(time);
+            for transaction in transactions {
+                block.add_transaction(transaction);
+            }
+            mine_block(&mut block, bc.target());
+            block
+        };
+
+        // Only ever mined, never pending
+        assert_eq!(
+            bc.submit_transaction(coinbase("alice", BLOCK_REWARD)),
+            Err("Coinbase can only be the first transaction of a block.".to_string())
+        );
+
+        // Behind another transaction, where the canonical order puts it
+        let create_bob = Transaction::create_account("bob".to_string(), &fixtures.keypair(), time);
+        let mut misplaced = coinbase("alice", BLOCK_REWARD);
+        misplaced.set_nonce(1);
+        let block = mined(&bc, vec![create_bob.clone(), misplaced]);
+        assert_eq!(
+            bc.append_block(block),
+            Err(BlockchainError::TransactionFailed(Box::new(BlockchainError::MisplacedCoinbase)))
+        );
+        let block = mined(&bc, vec![coinbase("alice", BLOCK_REWARD + 1), create_bob.clone()]);
+        assert_eq!(
+            bc.append_block(block),
+            Err(BlockchainError::InvalidCoinbase {
+                amount: BLOCK_REWARD + 1,
+                expected: BLOCK_REWARD
+            })
+        );
+        let block = mined(&bc, vec![coinbase("mallory", BLOCK_REWARD), create_bob.clone()]);
+        assert_eq!(
+            bc.append_block(block),
+            Err(BlockchainError::TransactionFailed(Box::new(BlockchainError::UnknownAccount(
+                AccountRole::Receiver
+            ))))
+        );
+        assert_eq!(bc.get_account_by_id("alice".to_string()).unwrap().balance, 0);
+
+        let mut block = bc.next_block();
+        block.set_timestamp(time);
+        block.add_transaction(create_bob);
+        bc.add_coinbase(&mut block, "alice".to_string());
+        assert_eq!(block.beneficiary(), Some(&"alice".to_string()));
+        mine_block(&mut block, bc.target());
+        assert!(bc.append_block(block).is_ok());
+        assert_eq!(bc.get_account_by_id("alice".to_string()).unwrap().balance, BLOCK_REWARD);
+
+        // The reward is whatever the chain is configured with
+        bc.set_block_reward(10);
+        let create_carol = Transaction::create_account("carol".to_string(), &fixtures.keypair(), time);
+        assert!(bc.append_block(mined(&bc, vec![coinbase("bob", 10), create_carol])).is_ok());
+        assert_eq!(bc.get_account_by_id("bob".to_string()).unwrap().balance, 10);
+    }
+
+    #[test]
+    fn test_accounts_at() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let alice = fixtures.keypair();
+        let time = fixtures.timestamp();
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &alice, time),
+            Transaction::new(TransactionData::MintInitialSupply { to: "alice".to_string(), amount: 100 }, None, time),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        let create_bob = Transaction::create_account("bob".to_string(), &fixtures.keypair(), time);
+        assert!(append_block_with_tx(&mut bc, 2, vec![create_bob]).is_ok());
+        for nonce in 1..=3 {
+            let mut pay_bob = Transaction::new(
+                TransactionData::Transfer { to: "bob".to_string(), amount: 10 },
+                Some("alice".to_string()),
+                time,
+            );
+            pay_bob.set_nonce(nonce);
+            pay_bob.sign(Some(alice.sign(&pay_bob.signing_message())));
+            assert!(append_block_with_tx(&mut bc, 2 + nonce, vec![pay_bob]).is_ok());
+        }
+
+        let genesis = bc.accounts_at(0).unwrap();
+        assert_eq!(genesis.len(), 1);
+        assert_eq!(genesis["alice"].balance, 100);
+        for height in 1..=4 {
+            let accounts = bc.accounts_at(height).unwrap();
+            let paid = 10 * (height as Balance - 1);
+            assert_eq!(accounts["alice"].balance, 100 - paid);
+            assert_eq!(accounts["bob"].balance, paid);
+        }
+        assert_eq!(bc.accounts_at(4).unwrap()["alice"], bc.accounts["alice"]);
+        assert!(bc.accounts_at(5).is_none());
+    }
+
+    #[test]
+    fn test_block_template_is_kept_up_to_date() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        append_block(&mut bc, fixtures, 1);
+        let fresh = |bc: &mut Blockchain| {
+            let cache = bc.template.take();
+            let block = bc.block_template();
+            bc.template = cache;
+            block.body.transactions.iter().map(|tx| tx.hash()).collect::<Vec<_>>()
+        };
+
+        assert!(bc.block_template().body.transactions.is_empty());
+        for i in 1..=8 {
+            let transaction = Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), fixtures.timestamp());
+            assert!(bc.submit_transaction(transaction).is_ok());
+            let template = bc.block_template();
+            assert_eq!(template.body.transactions.len(), i);
+            let hashes: Vec<Hash> = template.body.transactions.iter().map(|tx| tx.hash()).collect();
+            assert_eq!(hashes, fresh(&mut bc));
+            assert_eq!(bc.template.as_ref().unwrap().considered, i);
+        }
+
+        // The same transactions taken out of the pool don't come back
+        let mut block = bc.build_block_from_pool();
+        assert!(bc.template.is_none());
+        assert!(bc.block_template().body.transactions.is_empty());
+        mine_block(&mut block, bc.target());
+        assert!(bc.append_block(block).is_ok());
+        assert!(bc.template.is_none());
+        let template = bc.block_template();
+        assert_eq!(template.header.prev_hash, bc.get_last_block_hash());
+        assert!(template.body.transactions.is_empty());
+    }
+
+    #[test]
+    fn test_failed_template_transaction_keeps_no_fee() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let (alice, carol) = (fixtures.keypair(), fixtures.keypair());
+        let time = fixtures.timestamp();
+        let mint = |to: &str| Transaction::new(TransactionData::MintInitialSupply { to: to.to_string(), amount: 100 }, None, time);
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &alice, time),
+            Transaction::create_account("carol".to_string(), &carol, time),
+            mint("alice"),
+            mint("carol"),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        bc.set_fees(FeeConfig {
+            account_creation_fee: 10,
+            ..Default::default()
+        });
+
+        // Both pay for creating bob, only one of them gets to
+        let bob = fixtures.keypair().public;
+        for (payer, keypair) in [("alice", &alice), ("carol", &carol)] {
+            let mut create_bob = Transaction::new(TransactionData::CreateAccount("bob".to_string(), bob), Some(payer.to_string()), time);
+            create_bob.set_nonce(1);
+            create_bob.sign(Some(keypair.sign(&create_bob.signing_message())));
+            assert!(bc.submit_transaction(create_bob).is_ok());
+        }
+        let template = bc.block_template();
+        assert_eq!(template.body.transactions.len(), 1);
+
+        let payer = template.body.transactions[0].from().unwrap().clone();
+        let state = &bc.template.as_ref().unwrap().state;
+        for account_id in ["alice", "carol"] {
+            let expected = if account_id == payer { 90 } else { 100 };
+            assert_eq!(state.accounts[account_id].balance, expected);
+        }
+    }
+
+    #[test]
+    fn test_canonical_transaction_order() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let alice = fixtures.keypair();
+        let time = fixtures.timestamp();
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &alice, time),
+            Transaction::new(TransactionData::MintInitialSupply { to: "alice".to_string(), amount: 100 }, None, time),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        let block_of = |bc: &Blockchain, transactions: &[Transaction]| {
+            let mut block = bc.next_block();
+            block.set_timestamp(bc.now());
+            for transaction in transactions {
+                block.add_transaction(transaction.clone());
+            }
+            mine_block(&mut block, bc.target());
+            block
+        };
+
+        let mut creates: Vec<Transaction> = (0..3)
+            .map(|_| Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), time))
+            .collect();
+        bc.sort_transactions(&mut creates);
+        let reversed: Vec<Transaction> = creates.iter().rev().cloned().collect();
+        assert_eq!(
+            bc.append_block(block_of(&bc, &reversed)),
+            Err(BlockchainError::TransactionsOutOfOrder(1))
+        );
+        let repeated = [creates[0].clone(), creates[0].clone()];
+        assert_eq!(
+            bc.append_block(block_of(&bc, &repeated)),
+            Err(BlockchainError::TransactionsOutOfOrder(1))
+        );
+        assert!(bc.append_block(block_of(&bc, &creates)).is_ok());
+
+        // Fees rank before hashes
+        bc.set_fees(FeeConfig {
+            account_creation_fee: 10,
+            ..FeeConfig::default()
+        });
+        let mut paid = Transaction::new(
+            TransactionData::CreateAccount(fixtures.account_id(), fixtures.keypair().public),
+            Some("alice".to_string()),
+            time,
+        );
+        paid.sign(Some(alice.sign(&paid.signing_message())));
+        let mut free = Transaction::new(TransactionData::Transfer { to: "alice".to_string(), amount: 0 }, None, time);
+        free.sign(Some(alice.sign(&free.signing_message())));
+        let mut transactions = vec![free.clone(), paid.clone()];
+        bc.sort_transactions(&mut transactions);
+        assert_eq!(transactions[0].hash(), paid.hash());
+    }
+
+    #[test]
+    fn test_build_block_from_pool() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let (alice, bob) = (fixtures.keypair(), fixtures.keypair());
+        let time = fixtures.timestamp();
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &alice, time),
+            Transaction::create_account("bob".to_string(), &bob, time),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        let claim = |owner: &str, keypair: &Keypair| {
+            let mut transaction = Transaction::new(
+                TransactionData::RegisterAlias { alias: "ali".to_string(), owner: owner.to_string() },
+                None,
+                time,
+            );
+            transaction.set_nonce(1);
+            transaction.sign(Some(keypair.sign(&transaction.signing_message())));
+            transaction
+        };
+
+        // Each goes through on its own, not both. The one first in canonical order makes it in.
+        let (first, second) = (claim("alice", &alice), claim("bob", &bob));
+        assert!(bc.submit_transaction(first.clone()).is_ok());
+        assert!(bc.submit_transaction(second.clone()).is_ok());
+        let (included, left, owner) = if bc.ordering_key(&first) < bc.ordering_key(&second) {
+            (first, second, "alice")
+        } else {
+            (second, first, "bob")
+        };
+        let mut block = bc.build_block_from_pool();
+        assert_eq!(block.body.transactions.len(), 1);
+        assert_eq!(block.body.transactions[0].hash(), included.hash());
+        assert_eq!(bc.pending_transactions().len(), 1);
+        assert_eq!(bc.pending_transactions()[0].hash(), left.hash());
+
+        mine_block(&mut block, TEST_TARGET);
+        assert!(bc.append_block(block).is_ok());
+        assert_eq!(bc.alias_owner("ali"), Some(&owner.to_string()));
+        assert!(bc.build_block_from_pool().body.transactions.is_empty());
+        assert_eq!(bc.pending_transactions().len(), 1);
+    }
+
+    #[test]
+    fn test_transaction_expiry() {
+        let clock = MockClock::new(GENESIS_TIME);
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(clock.clone());
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let create_alice = |time| {
+            Transaction::create_account("alice".to_string(), &keypair, time)
+        };
+
+        assert!(bc.submit_transaction(create_alice(GENESIS_TIME + MAX_FUTURE_DRIFT + 1)).is_err());
+        assert!(bc.submit_transaction(create_alice(GENESIS_TIME)).is_ok());
+
+        clock.advance(TRANSACTION_EXPIRY + 1);
+        assert_eq!(bc.submit_transaction(create_alice(GENESIS_TIME)), Err("Transaction has expired".to_string()));
+        assert!(bc.submit_transaction(create_alice(clock.now())).is_ok());
+        assert_eq!(bc.pending_transactions().len(), 1);
+    }
+
+    #[test]
+    fn test_transaction_limits() {
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(MockClock::new(1));
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let long_id = "a".repeat(MAX_ACCOUNT_ID_LENGTH + 1);
+        let oversized = Transaction::create_account(long_id, &keypair, 1);
+
+        assert_eq!(
+            bc.submit_transaction(oversized.clone()),
+            Err(oversized.check_limits().unwrap_err().to_string())
+        );
+        assert!(bc.pending_transactions().is_empty());
+
+        // Not even genesis can carry one
+        let result = append_block_with_tx(&mut bc, 1, vec![oversized]);
+        assert!(matches!(result, Err(BlockchainError::InvalidTransaction(LimitError::AccountIdTooLong { .. }))));
+        assert!(bc.is_empty());
+    }
+
+    #[test]
+    fn test_confirmations() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        bc.set_safe_depth(3);
+        let genesis = append_block(&mut bc, fixtures, 1);
+        let confirmed = genesis.body.transactions[0].hash();
+
+        let keypair = fixtures.keypair();
+        let pending = Transaction::create_account("alice".to_string(), &keypair, fixtures.timestamp());
+        bc.submit_transaction(pending.clone()).unwrap();
+        assert_eq!(bc.confirmations(&confirmed), Some(1));
+        assert_eq!(bc.confirmations(&pending.hash()), Some(0));
+        assert_eq!(bc.confirmations(&"unknown".to_string()), None);
+        assert!(!bc.is_safe(&pending.hash()));
+
+        append_block(&mut bc, fixtures, 2);
+        assert!(!bc.is_safe(&confirmed));
+        append_block(&mut bc, fixtures, 3);
+        assert_eq!(bc.confirmations(&confirmed), Some(3));
+        assert!(bc.is_safe(&confirmed));
+    }
+
+    #[test]
+    fn test_aliases() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let (alice, bob) = (fixtures.keypair(), fixtures.keypair());
+        let time = fixtures.timestamp();
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &alice, time),
+            Transaction::create_account("bob".to_string(), &bob, time),
+            Transaction::new(TransactionData::MintInitialSupply { to: "alice".to_string(), amount: 100 }, None, time),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        let signed = |data: TransactionData, from: &str, keypair: &Keypair, nonce: u128| {
+            let mut transaction = Transaction::new(data, Some(from.to_string()), time);
+            transaction.set_nonce(nonce);
+            transaction.sign(Some(keypair.sign(&transaction.signing_message())));
+            transaction
+        };
+        let register = |alias: &str, owner: &str| TransactionData::RegisterAlias {
+            alias: alias.to_string(),
+            owner: owner.to_string(),
+        };
+        let pay = |to: &str, amount| TransactionData::Transfer { to: to.to_string(), amount };
+
+        assert_eq!(
+            bc.submit_transaction(signed(register("bobby", "bob"), "bob", &alice, 1)),
+            Err("Invalid signature.".to_string())
+        );
+        assert_eq!(
+            bc.submit_transaction(signed(pay("@bobby", 10), "alice", &alice, 1)),
+            Err("Unknown alias: bobby".to_string())
+        );
+        assert!(append_block_with_tx(&mut bc, 2, vec![signed(register("bobby", "bob"), "bob", &bob, 1)]).is_ok());
+        assert_eq!(bc.resolve_account(&"@bobby".to_string()), Ok("bob".to_string()));
+        assert_eq!(
+            bc.submit_transaction(signed(register("bobby", "alice"), "alice", &alice, 1)),
+            Err("Alias already taken: bobby".to_string())
+        );
+
+        // Paid to whoever holds the alias when the transfer executes
+        assert!(append_block_with_tx(&mut bc, 3, vec![signed(pay("@bobby", 10), "alice", &alice, 1)]).is_ok());
+        assert_eq!(bc.accounts["bob"].balance, 10);
+        let handover = TransactionData::TransferAlias {
+            alias: "bobby".to_string(),
+            to: "alice".to_string(),
+        };
+        assert_eq!(
+            bc.submit_transaction(signed(handover.clone(), "alice", &alice, 2)),
+            Err("Only the owner can transfer an alias.".to_string())
+        );
+        assert!(append_block_with_tx(&mut bc, 4, vec![signed(handover, "bob", &bob, 2)]).is_ok());
+        assert!(append_block_with_tx(&mut bc, 5, vec![signed(pay("@bobby", 10), "alice", &alice, 2)]).is_ok());
+        assert_eq!(bc.accounts["alice"].balance, 90);
+        assert_eq!(bc.accounts["bob"].balance, 10);
+
+        // A failing block takes its alias changes back with it
+        let fingerprint = bc.state_fingerprint();
+        let failing = vec![signed(register("ali", "alice"), "alice", &alice, 3), signed(pay("bob", 1000), "alice", &alice, 4)];
+
+        assert!(append_block_with_tx(&mut bc, 6, failing).is_err());
+        assert_eq!(bc.alias_owner("ali"), None);
+        assert_eq!(bc.state_fingerprint(), fingerprint);
+
+        let create = Transaction::create_account("@carol".to_string(), &fixtures.keypair(), time);
+        assert_eq!(bc.submit_transaction(create), Err("AccountId can't start with @: @carol".to_string()));
+    }
+
+    #[test]
+    fn test_inheritance() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let (alice, bob, carol) = (fixtures.keypair(), fixtures.keypair(), fixtures.keypair());
+        let time = fixtures.timestamp();
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &alice, time),
+            Transaction::create_account("bob".to_string(), &bob, time),
+            Transaction::new(TransactionData::MintInitialSupply { to: "alice".to_string(), amount: 100 }, None, time),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        let signed = |data: TransactionData, from: &str, keypair: &Keypair, nonce: u128| {
+            let mut transaction = Transaction::new(data, Some(from.to_string()), time);
+            transaction.set_nonce(nonce);
+            transaction.sign(Some(keypair.sign(&transaction.signing_message())));
+            transaction
+        };
+        let switch = |beneficiary: &str, inactive_blocks| TransactionData::SetInheritance {
+            beneficiary: beneficiary.to_string(),
+            inactive_blocks,
+        };
+        let claim = TransactionData::ClaimInheritance { owner: "alice".to_string() };
+
+        assert_eq!(
+            bc.submit_transaction(signed(switch("alice", 3), "alice", &alice, 1)),
+            Err("Can't inherit from yourself.".to_string())
+        );
+        assert_eq!(
+            bc.submit_transaction(signed(switch("bob", 0), "alice", &alice, 1)),
+            Err("Inactivity period must be at least one block.".to_string())
+        );
+        assert_eq!(
+            bc.submit_transaction(signed(claim.clone(), "bob", &bob, 1)),
+            Err("Not a beneficiary of alice.".to_string())
+        );
+        assert!(append_block_with_tx(&mut bc, 2, vec![signed(switch("bob", 3), "alice", &alice, 1)]).is_ok());
+        assert_eq!(bc.accounts["alice"].last_active(), 1);
+        assert_eq!(
+            bc.submit_transaction(signed(claim.clone(), "bob", &bob, 1)),
+            Err("alice is still active.".to_string())
+        );
+
+        // Sending resets the switch
+        let create_carol = Transaction::create_account("carol".to_string(), &carol, time);
+        assert!(append_block_with_tx(&mut bc, 3, vec![create_carol]).is_ok());
+        let pay_carol = TransactionData::Transfer { to: "carol".to_string(), amount: 10 };
+        assert!(append_block_with_tx(&mut bc, 4, vec![signed(pay_carol, "alice", &alice, 2)]).is_ok());
+        assert_eq!(bc.accounts["alice"].last_active(), 3);
+
+        // Naming alice as the sender of something she didn't sign doesn't count
+        let register = TransactionData::RegisterAlias {
+            alias: "carrie".to_string(),
+            owner: "carol".to_string(),
+        };
+        assert!(append_block_with_tx(&mut bc, 5, vec![signed(register, "alice", &carol, 1)]).is_ok());
+        assert_eq!(bc.accounts["alice"].last_active(), 3);
+        assert_eq!(
+            bc.submit_transaction(signed(claim.clone(), "bob", &bob, 1)),
+            Err("alice is still active.".to_string())
+        );
+        assert_eq!(
+            bc.submit_transaction(signed(claim.clone(), "carol", &carol, 2)),
+            Err("Not a beneficiary of alice.".to_string())
+        );
+        assert_eq!(
+            bc.submit_transaction(signed(claim.clone(), "bob", &alice, 1)),
+            Err("Invalid signature.".to_string())
+        );
+
+        let create_dave = Transaction::create_account("dave".to_string(), &fixtures.keypair(), time);
+        assert!(append_block_with_tx(&mut bc, 6, vec![create_dave]).is_ok());
+        assert!(append_block_with_tx(&mut bc, 7, vec![signed(claim, "bob", &bob, 1)]
+).is_ok());
+        assert_eq!(bc.accounts["alice"].balance, 0);
+        assert_eq!(bc.accounts["bob"].balance, 90);
+        assert_eq!(bc.accounts["bob"].last_active(), 6);
+    }
+
+    #[test]
+    fn test_standing_orders() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let (alice, bob, carol) = (fixtures.keypair(), fixtures.keypair(), fixtures.keypair());
+        let time = fixtures.timestamp();
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &alice, time),
+            Transaction::create_account("bob".to_string(), &bob, time),
+            Transaction::create_account("carol".to_string(), &carol, time),
+            Transaction::new(TransactionData::MintInitialSupply { to: "alice".to_string(), amount: 100 }, None, time),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        let signed = |data: TransactionData, from: &str, keypair: &Keypair, nonce: u128| {
+            let mut transaction = Transaction::new(data, Some(from.to_string()), time);
+            transaction.set_nonce(nonce);
+            transaction.sign(Some(keypair.sign(&transaction.signing_message())));
+            transaction
+        };
+        let order = |payee: &str, amount, interval, cap| TransactionData::CreateStandingOrder {
+            payee: payee.to_string(),
+            amount,
+            interval,
+            cap,
+        };
+        let cancel = |payee: &str| TransactionData::CancelStandingOrder { payee: payee.to_string() };
+        let mut next_nonce = 2;
+        let mut append = |bc: &mut Blockchain, fixtures: &mut Fixtures, transactions: Vec<Transaction>| {
+            let mut transactions = transactions;
+            if transactions.is_empty() {
+                transactions.push(Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), time));
+            }
+            next_nonce += 1;
+            assert!(append_block_with_tx(bc, next_nonce, transactions).is_ok());
+        };
+
+        assert_eq!(
+            bc.submit_transaction(signed(order("bob", 30, 0, 70), "alice", &alice, 1)),
+            Err("Standing order interval must be at least one block.".to_string())
+        );
+        assert_eq!(
+            bc.submit_transaction(signed(order("alice", 30, 2, 70), "alice", &alice, 1)),
+            Err("Can't pay yourself.".to_string())
+        );
+        assert_eq!(
+            bc.submit_transaction(signed(cancel("bob"), "alice", &alice, 1)),
+            Err("No standing order to bob.".to_string())
+        );
+
+        // 30 every 2 blocks from height 3 on, 70 in total. Carol has nothing to pay with.
+        let orders = vec![signed(order("bob", 30, 2, 70), "alice", &alice, 1), signed(order("bob", 5, 1, 5), "carol", &carol, 1)];
+        append(&mut bc, fixtures, orders);
+        append(&mut bc, fixtures, vec![]);
+        assert_eq!(bc.accounts["bob"].balance, 0);
+        append(&mut bc, fixtures, vec![]);
+        assert_eq!(bc.accounts["bob"].balance, 30);
+        assert_eq!(bc.standing_orders().len(), 2);
+        append(&mut bc, fixtures, vec![]);
+        append(&mut bc, fixtures, vec![]);
+        assert_eq!(bc.accounts["bob"].balance, 60);
+        append(&mut bc, fixtures, vec![]);
+        append(&mut bc, fixtures, vec![]);
+        assert_eq!(bc.accounts["bob"].balance, 70);
+        assert_eq!(bc.accounts["alice"].balance, 30);
+        assert_eq!(bc.standing_orders().len(), 1);
+        assert_eq!(bc.standing_orders()[0].payer, "carol");
+        assert_eq!(bc.standing_orders()[0].paid, 0);
+
+        // Cancelled before it was ever due
+        append(&mut bc, fixtures, vec![signed(order("carol", 10, 1, 10), "alice", &alice, 2)]);
+        assert_eq!(
+            bc.submit_transaction(signed(cancel("carol"), "alice", &bob, 3)),
+            Err("Invalid signature.".to_string())
+        );
+        append(&mut bc, fixtures, vec![signed(cancel("carol"), "alice", &alice, 3)]
+);
+        assert_eq!(bc.accounts["carol"].balance, 0);
+        assert_eq!(bc.standing_orders().len(), 1);
+    }
+
+    #[test]
+    fn test_spending_limits() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let (alice, bob) = (fixtures.keypair(), fixtures.keypair());
+        let time = fixtures.timestamp();
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &alice, time),
+            Transaction::create_account("bob".to_string(), &bob, time),
+            Transaction::new(TransactionData::MintInitialSupply { to: "alice".to_string(), amount: 100 }, None, time),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        let mut next_nonce = 1;
+        let signed = |data: TransactionData, keypair: &Keypair, nonce: u128, next_nonce: &mut u128| {
+            // Distinct timestamps keep repeated transfers apart
+            *next_nonce += 1;
+            let mut transaction = Transaction::new(data, Some("alice".to_string()), time + *next_nonce as Timestamp);
+            transaction.set_nonce(nonce);
+            transaction.sign(Some(keypair.sign(&transaction.signing_message())));
+            transaction
+        };
+        let limit = |amount, epoch_blocks| TransactionData::SetSpendingLimit {
+            account: "alice".to_string(),
+            limit: Some(SpendingLimit { amount, epoch_blocks }),
+        };
+        let pay_bob = |amount| TransactionData::Transfer { to: "bob".to_string(), amount };
+        let append = |bc: &mut Blockchain, fixtures: &mut Fixtures, transactions: Vec<Transaction>, nonce: u128| {
+            let mut transactions = transactions;
+            if transactions.is_empty() {
+                transactions.push(Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), time));
+            }
+            append_block_with_tx(bc, nonce, transactions)
+        };
+
+        assert_eq!(
+            bc.submit_transaction(signed(limit(20, 0), &alice, 1, &mut next_nonce)),
+            Err("Spending limit epoch must be at least one block.".to_string())
+        );
+        assert_eq!(
+            bc.submit_transaction(signed(limit(20, 5), &bob, 1, &mut next_nonce)),
+            Err("Invalid signature.".to_string())
+        );
+
+        // The first limit applies at once, epochs are heights 0-4, 5-9 and so on
+        let set = signed(limit(20, 5), &alice, 1, &mut next_nonce);
+        assert!(append(&mut bc, fixtures, vec![set], next_nonce).is_ok());
+        let over = signed(pay_bob(30), &alice, 2, &mut next_nonce);
+        assert_eq!(bc.submit_transaction(over), Err("Spending limit exceeded, 20 left this epoch.".to_string()));
+        let transfers = vec![signed(pay_bob(15), &alice, 2, &mut next_nonce), signed(pay_bob(10), &alice, 3, &mut next_nonce)];
+        assert_eq!(
+            append(&mut bc, fixtures, transfers, next_nonce),
+            Err(BlockchainError::TransactionFailed(Box::new(BlockchainError::SpendingLimitExceeded(5))))
+        );
+        let transfer = signed(pay_bob(15), &alice, 2, &mut next_nonce);
+        assert!(append(&mut bc, fixtures, vec![transfer], next_nonce).is_ok());
+        assert_eq!(bc.accounts["alice"].spending().allowance(bc.len() as u64), Some(5));
+
+        // Lifting the limit waits out the delay, the allowance still renews every epoch
+        let lift = TransactionData::SetSpendingLimit {
+            account: "alice".to_string(),
+            limit: None,
+        };
+        let lift = signed(lift, &alice, 3, &mut next_nonce);
+        assert!(append(&mut bc, fixtures, vec![lift], next_nonce).is_ok());
+        let effective_at = bc.accounts["alice"].spending().pending.unwrap().effective_at;
+        assert_eq!(effective_at, 3 + SPENDING_LIMIT_DELAY);
+        while bc.len() < 5 {
+            next_nonce += 1;
+            assert!(append(&mut bc, fixtures, vec![], next_nonce).is_ok());
+        }
+        assert_eq!(bc.accounts["alice"].spending().allowance(bc.len() as u64), Some(20));
+        let transfer = signed(pay_bob(20), &alice, 4, &mut next_nonce);
+        assert!(append(&mut bc, fixtures, vec![transfer], next_nonce).is_ok());
+        assert_eq!(bc.accounts["bob"].balance, 35);
+
+        while (bc.len() as u64) < effective_at {
+            next_nonce += 1;
+            assert!(append(&mut bc, fixtures, vec![], next_nonce).is_ok());
+        }
+        assert_eq!(bc.accounts["alice"].spending().allowance(bc.len() as u64), None);
+        let transfer = signed(pay_bob(65), &alice, 5, &mut next_nonce);
+
+        assert!(append(&mut bc, fixtures, vec![transfer], next_nonce).is_ok());
+        assert_eq!(bc.accounts["alice"].balance, 0);
+    }
+
+    #[test]
+    fn test_social_recovery() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let (alice, new_key) = (fixtures.keypair(), fixtures.keypair());
+        let guardians = [fixtures.keypair(), fixtures.keypair(), fixtures.keypair()];
+        let time = fixtures.timestamp();
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &alice, time),
+            Transaction::create_account("bob".to_string(), &fixtures.keypair(), time),
+            Transaction::new(TransactionData::MintInitialSupply { to: "alice".to_string(), amount: 100 }, None, time),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        // Distinct timestamps keep repeated approvals apart
+        let mut sequence = 0;
+        let mut signed = |data: TransactionData, from: Option<&str>, keypair: &Keypair, nonce: u128| {
+            sequence += 1;
+            let mut transaction = Transaction::new(data, from.map(str::to_string), time + sequence);
+            transaction.set_nonce(nonce);
+            transaction.sign(Some(keypair.sign(&transaction.signing_message())));
+            transaction
+        };
+        let set_guardians = |threshold| TransactionData::SetGuardians {
+            account: "alice".to_string(),
+            guardians: guardians.iter().map(|guardian| guardian.public).collect(),
+            threshold,
+        };
+        let approve = |new_key: &Keypair| TransactionData::ApproveRecovery {
+            account: "alice".to_string(),
+            new_key: new_key.public,
+        };
+        let cancel = TransactionData::CancelRecovery { account: "alice".to_string() };
+        let complete = TransactionData::CompleteRecovery { account: "alice".to_string() };
+        let mut next_nonce = 1;
+        let mut append = |bc: &mut Blockchain, fixtures: &mut Fixtures, transactions: Vec<Transaction>| {
+            let mut transactions = transactions;
+            if transactions.is_empty() {
+                transactions.push(Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), time));
+            }
+            next_nonce += 1;
+            assert!(append_block_with_tx(bc, next_nonce, transactions).is_ok());
+        };
+
+        assert_eq!(
+            bc.submit_transaction(signed(set_guardians(4), Some("alice"), &alice, 1)),
+            Err("Guardians need a threshold between 1 and the number of distinct keys.".to_string())
+        );
+        assert_eq!(
+            bc.submit_transaction(signed(approve(&new_key), None, &guardians[0], 0)),
+            Err("alice has no guardians.".to_string())
+        );
+        append(&mut bc, fixtures, vec![signed(set_guardians(2), Some("alice"), &alice, 1)]);
+        assert_eq!(
+            bc.submit_transaction(signed(approve(&new_key), None, &new_key, 0)),
+            Err("Invalid signature.".to_string())
+        );
+
+        // Two of three guardians start the challenge period, the owner can call it off
+        append(&mut bc, fixtures, vec![signed(approve(&new_key), None, &guardians[0], 0)]);
+        assert_eq!(
+            bc.submit_transaction(signed(complete.clone(), None, &new_key, 0)),
+            Err("Recovery of alice lacks guardian approvals.".to_string())
+        );
+        append(&mut bc, fixtures, vec![signed(approve(&new_key), None, &guardians[1], 0)]);
+        assert_eq!(bc.accounts["alice"].recovery().unwrap().ready_at(), Some(3 + RECOVERY_CHALLENGE_PERIOD));
+        assert_eq!(
+            bc.submit_transaction(signed(complete.clone(), None, &new_key, 0)),
+            Err(format!("Recovery can complete from block {} on.", 3 + RECOVERY_CHALLENGE_PERIOD))
         );
+        append(&mut bc, fixtures, vec![signed(cancel.clone(), Some("alice"), &alice, 2)]);
+        assert!(bc.accounts["alice"].recovery().is_none());
+        assert_eq!(
+            bc.submit_transaction(signed(cancel, Some("alice"), &alice, 3)),
+            Err("No recovery of alice is under way.".to_string())
+        );
+
+        let approvals = vec![
+            signed(approve(&new_key), None, &guardians[1], 0),
+            signed(approve(&new_key), None, &guardians[2], 0),
+        ];
+        append(&mut bc, fixtures, approvals);
+        let other_key = fixtures.keypair();
+        assert_eq!(
+            bc.submit_transaction(signed(approve(&other_key), None, &guardians[0], 0)),
+            Err("A recovery of alice to another key is under way.".to_string())
+        );
+        while bc.len() < 5 + RECOVERY_CHALLENGE_PERIOD as usize {
+            append(&mut bc, fixtures, vec![]);
+        }
+        assert_eq!(
+            bc.submit_transaction(signed(complete.clone(), None, &guardians[0], 0)),
+            Err("Invalid signature.".to_string())
+        );
+        append(&mut bc, fixtures, vec![signed(complete, None, &new_key, 0)]);
+        assert_eq!(bc.accounts["alice"].public_key(), &new_key.public);
+        assert!(bc.accounts["alice"].recovery().is_none());
+
+        let pay_bob = TransactionData::Transfer { to: "bob".to_string(), amount: 10 };
+        assert_eq!(
+            bc.submit_transaction(signed(pay_bob.clone(), Some("alice"), &alice, 3)),
+            Err("Invalid signature.".to_string())
+        );
+        append(&mut bc, fixtures, vec![signed(pay_bob, Some("alice"), &new_key, 3)
+]);
+        assert_eq!(bc.accounts["bob"].balance, 10);
+    }
+
+    #[test]
+    fn test_dust_sweep() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let carol = fixtures.keypair();
+        let time = fixtures.timestamp();
+        let mint = |to: &str, amount| Transaction::new(TransactionData::MintInitialSupply { to: to.to_string(), amount }, None, time);
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &fixtures.keypair(), time),
+            Transaction::create_account("bob".to_string(), &fixtures.keypair(), time),
+            Transaction::create_account("carol".to_string(), &carol, time),
+            Transaction::create_account("treasury".to_string(), &fixtures.keypair(), time),
+            mint("alice", 100),
+            mint("bob", 5),
+            mint("carol", 5),
+            mint("treasury", 1),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        bc.set_dust_sweep(Some(DustSweep {
+            threshold: 10,
+            epoch_blocks: 4,
+            inactive_epochs: 1,
+            treasury: "treasury".to_string(),
+        }));
+        let mut next_nonce = 1;
+        let mut append = |bc: &mut Blockchain, fixtures: &mut Fixtures, transactions: Vec<Transaction>| {
+            let mut transactions = transactions;
+            if transactions.is_empty() {
+                transactions.push(Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), time));
+            }
+            next_nonce += 1;
+            assert!(append_block_with_tx(bc, next_nonce, transactions).is_ok());
+        };
+
+        // Carol sending at height 1 keeps her balance through the first sweep
+        let mut pay_alice = Transaction::new(
+            TransactionData::Transfer { to: "alice".to_string(), amount: 1 },
+            Some("carol".to_string()),
+            time,
+        );
+        pay_alice.set_nonce(1);
+        pay_alice.sign(
+Some(carol.sign(&pay_alice.signing_message())));
+        append(&mut bc, fixtures, vec![pay_alice]);
+        while bc.len() < 4 {
+            append(&mut bc, fixtures, vec![]);
+        }
+        assert_eq!(bc.accounts["bob"].balance, 5);
+        let fingerprint = bc.state_fingerprint();
+
+        append(&mut bc, fixtures, vec![]);
+        assert_eq!(bc.accounts["bob"].balance, 0);
+        assert_eq!(bc.accounts["carol"].balance, 4);
+        assert_eq!(bc.accounts["alice"].balance, 101);
+        // The treasury keeps its own dust
+        assert_eq!(bc.accounts["treasury"].balance, 6);
+        assert_ne!(bc.state_fingerprint(), fingerprint);
+
+        while bc.len() < 9 {
+            append(&mut bc, fixtures, vec![]);
+        }
+        assert_eq!(bc.accounts["carol"].balance, 0);
+        assert_eq!(bc.accounts["treasury"].balance, 10);
+    }
+
+    #[test]
+    fn test_account_creation_fee_and_dust() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let alice = fixtures.keypair();
+        let time = fixtures.timestamp();
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &alice, time),
+            Transaction::create_account("miner".to_string(), &fixtures.keypair(), time),
+            Transaction::new(TransactionData::MintInitialSupply { to: "alice".to_string(), amount: 100 }, None, time),
+        ];
+        assert!(append_block_with_tx(&mut bc, 1, genesis).is_ok());
+        bc.set_fees(FeeConfig {
+            account_creation_fee: 10,
+            destination: FeeDestination::Producer,
+            min_transfer: 5,
+        });
+        let signed = |data: TransactionData, from: Option<&str>, nonce: u128| {
+            let mut transaction = Transaction::new(data, from.map(str::to_string), time);
+            transaction.set_nonce(nonce);
+            transaction.sign(Some(alice.sign(&transaction.signing_message())));
+            transaction
+        };
+        let bob = fixtures.keypair().public;
+        let create_bob = |from, nonce| signed(TransactionData::CreateAccount("bob".to_string(), bob), from, nonce);
+
+        assert_eq!(
+            bc.submit_transaction(create_bob(None, 0)),
+            Err("Account creation needs a payer for the fee".to_string())
+        );
+        assert_eq!(bc.submit_transaction(create_bob(Some("miner"), 1)), Err("Invalid signature.".to_string()));
+        let dust = signed(TransactionData::Transfer { to: "miner".to_string(), amount: 4 }, Some("alice"), 1);
+        assert_eq!(bc.submit_transaction(dust), Err("Transfer below the minimum of 5".to_string()));
+
+        let mut block = bc.next_block();
+        block.set_timestamp(bc.now());
+        block.set_beneficiary(Some("miner".to_string()));
+        block.add_transaction(create_bob(Some("alice"), 1));
+        block.add_transaction(signed(TransactionData::Transfer { to: "miner".to_string(), amount: 5 }, Some("alice"), 2));
+        mine_block(&mut block, bc.target());
+        assert!(bc.append_block(block).is_ok());
+
+        let balance = |bc: &Blockchain, account_id: &str| bc.get_account_by_id(account_id.to_string()).unwrap().balance;
+        assert_eq!(balance(&bc, "alice"), 85);
+        assert_eq!(balance(&bc, "miner"), 15);
+        assert_eq!(balance(&bc, "bob"), 0);
+
+        // Burned fees leave the supply
+        bc.set_fees(FeeConfig { destination: FeeDestination::Burn, ..*bc.fees() });
+        let create_carol = signed(TransactionData::CreateAccount("carol".to_string(), fixtures.keypair().public), Some("alice"), 3);
+
+        assert!(append_block_with_tx(&mut bc, 3, vec![create_carol]).is_ok());
+        assert_eq!(balance(&bc, "alice"), 75);
+        assert_eq!(balance(&bc, "miner"), 15);
+    }
 
-        let keypair_alice = Keypair::generate(&mut rand::rngs::OsRng {});
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+    #[test]
+    fn test_block_from_the_future_fails() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        append_block(&mut bc, fixtures, 1);
+
+        let keypair = fixtures.keypair();
+        let time = fixtures.timestamp() + MAX_FUTURE_DRIFT + 1;
         let tx_create_alice =
-            Transaction::new(TransactionData::CreateAccount("alice".to_string(), keypair_alice.public), None, time);
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_mint_initial_supply_alice = Transaction::new(
-                TransactionData::MintInitialSupply {
-                    to: "alice".to_string(),
-                    amount: 100_000,
-                },
-                None,
-                time,
+            Transaction::create_account("alice".to_string(), &keypair, time);
+        assert_eq!(
+            append_block_with_tx(&mut bc, 2, vec![tx_create_alice.clone()]),
+            Err(BlockchainError::TransactionFromFuture)
         );
 
-        let mut block = Block::new(None);
-        block.set_nonce(1);
-        block.add_transaction(tx_create_account);
-        block.add_transaction(tx_mint_initial_supply);
-        block.add_transaction(tx_create_alice);
-        block.add_transaction(tx_mint_initial_supply_alice);
+        let mut block = bc.next_block();
+        let tx_create_bob =
+            Transaction::create_account("bob".to_string(), &keypair, fixtures.timestamp());
+        block.add_transaction(tx_create_bob);
+        block.set_timestamp(time);
+        mine_block(&mut block, bc.target());
+        assert_eq!(bc.append_block(block.clone()), Err(BlockchainError::BlockFromFuture));
 
+        fixtures.clock().advance(MAX_FUTURE_DRIFT + 1);
         assert!(bc.append_block(block).is_ok());
+        assert!(append_block_with_tx(&mut bc, 2, vec![tx_create_alice]).is_ok());
+    }
 
-        let mut block = Block::new(bc.get_last_block_hash());
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let mut tx_transfer_satoshi_to_alice = Transaction::new(
-                TransactionData::Transfer{
-                    to: "alice".to_string(),
-                    amount: 1000,
-                },
-                Some("satoshi".to_string()),
-                time,
+    #[test]
+    fn test_block_height() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET);
+        append_block(&mut bc, fixtures, 1);
+        let block = append_block(&mut bc, fixtures, 2);
+        assert_eq!(block.height(), 1);
+        assert_eq!(bc.get_block_by_height(1).map(|block| block.hash()), block.hash);
+        assert_eq!(bc.get_block_by_hash(&block.hash()).map(Block::height), Some(1));
+
+        // Anything but one above the tip is rejected, even on the right parent
+        let mut block = bc.next_block();
+        block.add_transaction(Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), fixtures.timestamp()));
+        block.set_height(3);
+        mine_block(&mut block, bc.target());
+        assert_eq!(
+            bc.append_block(block.clone()),
+            Err(BlockchainError::InvalidHeight { height: 3, expected: 2 })
         );
-        tx_transfer_satoshi_to_alice.sign(Some(keypair.sign(tx_transfer_satoshi_to_alice.hash().as_bytes())));
 
-        block.set_nonce(2);
-        block.add_transaction(tx_transfer_satoshi_to_alice);
+        block.set_height(2);
+        mine_block(&mut block, bc.target());
+        assert!(bc.append_block(block).is_ok());
+        assert!(bc.validate().is_ok());
+    }
+
+    #[test]
+    fn test_block_must_build_on_the_tip() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET);
+        let genesis = append_block(&mut bc, fixtures, 1);
+        let tip = append_block(&mut bc, fixtures, 2);
+
+        let unlinked = |prev_hash: Option<Hash>, fixtures: &mut Fixtures| {
+            let mut block = Block::new(prev_hash);
+            block.set_height(2);
+            block.add_transaction(Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), fixtures.timestamp()));
+            mine_block(&mut block, TEST_TARGET);
+            block
+        };
+        for prev_hash in [None, genesis.hash.clone(), Some("nope".to_string())] {
+            assert_eq!(
+                bc.append_block(unlinked(prev_hash.clone(), fixtures)),
+                Err(BlockchainError::InvalidParent {
+                    prev_hash,
+                    expected: tip.hash.clone()
+                })
+            );
+        }
+        assert_eq!(bc.len(), 2);
 
+        // Genesis is the one block without a parent
+        let mut bc = Blockchain::with_max_target(TEST_TARGET);
+        let mut block = unlinked(tip.hash.clone(), fixtures);
+        block.set_height(0);
+        assert!(matches!(bc.append_block(block), Err(BlockchainError::InvalidParent { expected: None, .. })));
+    }
+
+    #[test]
+    fn test_signed_blocks() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET);
+        let keypair = fixtures.keypair();
+        let tx_create_alice = Transaction::create_account("alice".to_string(), &keypair, fixtures.timestamp());
+        assert!(append_block_with_tx(&mut bc, 1, vec![tx_create_alice]).is_ok());
+        bc.set_require_signed_blocks(true);
+
+        let mut block = mined_block(&bc, bc.get_last_block_hash());
+        assert_eq!(bc.append_block(block.clone()), Err(BlockchainError::UnsignedBlock));
+
+        block.set_producer(Some("alice".to_string()));
+        mine_block(&mut block, bc.target());
+        let signature = keypair.sign(block.hash().as_bytes());
+        block.sign(Some(fixtures.keypair().sign(block.hash().as_bytes())));
+        assert_eq!(bc.append_block(block.clone()), Err(BlockchainError::InvalidProducerSignature));
+
+        let mut unknown = block.clone();
+        unknown.set_producer(Some("bob".to_string()));
+        mine_block(&mut unknown, bc.target());
+        unknown.sign(Some(keypair.sign(unknown.hash().as_bytes())));
+        assert_eq!(bc.append_block(unknown), Err(BlockchainError::UnknownProducer("bob".to_string())));
+
+        block.sign(Some(signature));
         assert!(bc.append_block(block).is_ok());
+        assert_eq!(bc.get_block_by_height(1).unwrap().producer(), Some(&"alice".to_string()));
+    }
 
-        let satoshi = bc.get_account_by_id("satoshi".to_string());
+    #[test]
+    fn test_block_bits_must_match_target() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET);
+        append_block(&mut bc, fixtures, 1);
+        assert_eq!(bc.target(), round_target(TEST_TARGET));
+
+        // Claiming an easier target than the chain's
+        let mut block = mined_block(&bc, bc.get_last_block_hash());
+        block.set_bits(encode_bits(u128::MAX));
+        assert!(matches!(bc.append_block(block), Err(BlockchainError::BitsMismatch { .. })));
+
+        let block = mined_block(&bc, bc.get_last_block_hash());
+        assert_eq!(block.bits(), bc.bits());
+        assert!(bc.append_block(block).is_ok());
+    }
 
-        assert!(satoshi.is_some());
-        assert_eq!(satoshi.unwrap().balance, 99_999_000);
+    #[test]
+    fn test_pow_is_swappable() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_pow(PowAlgorithm::Sha256d.pow().unwrap());
+        append_block(&mut bc, fixtures, 1);
+
+        let mut block = bc.next_block();
+        block.add_transaction(Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), fixtures.timestamp()));
+        // Blake2s work doesn't count on a SHA-256d chain
+        mine_block(&mut block, bc.target());
+        while Sha256dPow.verify(&block) {
+            block.set_nonce(block.nonce() + 1);
+            mine_block(&mut block, bc.target());
+        }
+        assert!(bc.append_block(block.clone()).is_err());
 
-        let alice = bc.get_account_by_id("alice".to_string());
+        bc.pow().seal(&mut block, bc.target());
+        assert!(bc.append_block(block).is_ok());
+    }
 
-        assert!(alice.is_some());
-        assert_eq!(alice.unwrap().balance, 101_000);
+    #[test]
+    fn test_epoch_retarget() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(u128::MAX).with_clock(fixtures.clock().clone());
+        bc.set_difficulty(DifficultyConfig::new(2, BLOCK_TIME));
+        append_block(&mut bc, fixtures, 1);
+        bc.target = EXACT_TARGET;
+
+        // Twice as fast as planned: the target halves at the end of the epoch
+        fixtures.clock().advance(BLOCK_TIME / 2);
+        append_block(&mut bc, fixtures, 2);
+        assert_eq!(bc.target(), EXACT_TARGET);
+        fixtures.clock().advance(BLOCK_TIME / 2);
+        append_block(&mut bc, fixtures, 3);
+        assert_eq!(bc.target(), EXACT_TARGET >> 1);
+
+        // Far too slow: at most 4x easier
+        fixtures.clock().advance(BLOCK_TIME * 100);
+        append_block(&mut bc, fixtures, 4);
+        append_block(&mut bc, fixtures, 5);
+        assert_eq!(bc.target(), EXACT_TARGET * 2);
+
+        // Blocks stamped with the same time: at most 4x harder
+        append_block(&mut bc, fixtures, 6);
+        append_block(&mut bc, fixtures, 7);
+        assert_eq!(bc.target(), EXACT_TARGET >> 1);
     }
 
     #[test]
-    fn test_transfer_transaction_fails() {
-        let mut bc = Blockchain::new();
+    fn test_lwma_retarget() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(u128::MAX).with_clock(fixtures.clock().clone());
+        bc.set_difficulty(DifficultyConfig::lwma(2, BLOCK_TIME));
+        append_block(&mut bc, fixtures, 1);
+        bc.target = EXACT_TARGET;
+        bc.targets[0] = bc.target;
+
+        // Every block adjusts, no waiting for the end of an epoch
+        fixtures.clock().advance(BLOCK_TIME / 2);
+        append_block(&mut bc, fixtures, 2);
+        assert_eq!(bc.target(), EXACT_TARGET >> 1);
+
+        fixtures.clock().advance(BLOCK_TIME * 2);
+        append_block(&mut bc, fixtures, 3);
+        assert!(bc.target() > EXACT_TARGET);
+    }
+
+    // Cheap stand-in for the fuzz targets that runs on stable
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary_blocks_dont_panic() {
+        use arbitrary::{Arbitrary, Unstructured};
+        use rand::{RngCore, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut appended = 0;
+        for _ in 0..100 {
+            let mut bytes = vec![0u8; 4096];
+            rng.fill_bytes(&mut bytes);
+            let u = &mut Unstructured::new(&bytes);
+
+            let mut bc = Blockchain::with_max_target(u128::MAX);
+            while !u.is_empty() {
+                let prev_hash = match u.int_in_range(0..=3).unwrap() {
+                    0 => None,
+                    1 => Some(Hash::arbitrary(u).unwrap()),
+                    _ => bc.get_last_block_hash(),
+                };
+                let mut block = Block::new(prev_hash);
+                block.set_height(u.int_in_range(0..=bc.len() as u64).unwrap());
+                for transaction in Vec::<Transaction>::arbitrary(u).unwrap() {
+                    block.add_transaction(transaction);
+                }
+
+                if let Ok(BlockStatus::Appended { .. }) = bc.process_block(block) {
+                    appended += 1;
+                }
+            }
+            assert!(bc.validate().is_ok());
+        }
+        assert!(appended > 0);
+    }
+
+    fn mined_block(bc: &Blockchain, prev_hash: Option<Hash>) -> Block {
+        mined_block_with_ommers(bc, prev_hash, None, vec![])
+    }
+
+    // On top of a parent the chain doesn't know yet
+    fn mined_child(bc: &Blockchain, parent: &Block) -> Block {
+        let mut block = mined_block(bc, Some(parent.hash()));
+        block.set_height(parent.height() + 1);
+        mine_block(&mut block, bc.target());
 
+        block
+    }
+
+    fn mined_block_with_ommers(
+        bc: &Blockchain,
+        prev_hash: Option<Hash>,
+        beneficiary: Option<AccountId>,
+        ommers: Vec<Block>,
+    ) -> Block {
         let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
-        let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_create_account =
-            Transaction::new(TransactionData::CreateAccount("satoshi".to_string(), keypair.public), None, time);
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_mint_initial_supply = Transaction::new(
-            TransactionData::MintInitialSupply {
-                to: "satoshi".to_string(),
-                amount: 100_000_000,
-            },
-            None,
-            time,
-        );
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let height = prev_hash.as_ref().and_then(|p| bc.block_height(p)).map_or(0, |parent| parent + 1);
+        let mut block = Block::new(prev_hash);
+        block.set_height(height);
+        block.set_beneficiary(beneficiary);
+        block.add_transaction(Transaction::create_account(generate_account_id(), &keypair, time));
+        for ommer in ommers {
+            block.add_ommer(ommer);
+        }
+        mine_block(&mut block, bc.target());
 
-        let keypair_alice = Keypair::generate(&mut rand::rngs::OsRng {});
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        block
+    }
+
+    fn chain_with_ommer(fixtures: &mut Fixtures) -> (Blockchain, Block) {
+        let mut bc = Blockchain::with_max_target(TEST_TARGET);
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
         let tx_create_alice =
-            Transaction::new(TransactionData::CreateAccount("alice".to_string(), keypair_alice.public), None, time);
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_mint_initial_supply_alice = Transaction::new(
-                TransactionData::MintInitialSupply {
-                    to: "alice".to_string(),
-                    amount: 100_000,
-                },
-                None,
-                time,
-        );
+            Transaction::create_account("alice".to_string(), &keypair, time);
+        assert!(append_block_with_tx(&mut bc, 1, vec![tx_create_alice]).is_ok());
+        let genesis_hash = bc.get_last_block_hash();
+        append_block(&mut bc, fixtures, 2);
 
-        let mut block = Block::new(None);
-        block.set_nonce(1);
-        block.add_transaction(tx_create_account);
-        block.add_transaction(tx_mint_initial_supply);
-        block.add_transaction(tx_create_alice);
-        block.add_transaction(tx_mint_initial_supply_alice);
+        let ommer = mined_block_with_ommers(&bc, genesis_hash, Some("alice".to_string()), vec![]);
+        assert!(bc.process_block(ommer.clone()).is_ok());
+
+        (bc, ommer)
+    }
 
+    #[test]
+    fn test_ommer_reward() {
+        let (mut bc, ommer) = chain_with_ommer(&mut Fixtures::default());
+        assert_eq!(bc.ommer_candidates().iter().map(|b| b.hash()).collect::<Vec<_>>(), vec![ommer.hash()]);
+
+        let block = mined_block_with_ommers(&bc, bc.get_last_block_hash(), None, vec![ommer]);
         assert!(bc.append_block(block).is_ok());
 
-        let mut block = Block::new(bc.get_last_block_hash());
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let mut tx_transfer_satoshi_to_alice = Transaction::new(
-                TransactionData::Transfer{
-                    to: "satoshi".to_string(),
-                    amount: 102_000,
-                },
-                Some("alice".to_string()),
-                time,
-        );
-        tx_transfer_satoshi_to_alice.sign(Some(keypair_alice.sign(tx_transfer_satoshi_to_alice.hash().as_bytes())));
-        block.set_nonce(2);
-        block.add_transaction(tx_transfer_satoshi_to_alice);
+        assert_eq!(bc.get_account_by_id("alice".to_string()).unwrap().balance, ommer_reward(1));
+        assert!(bc.ommer_candidates().is_empty());
+    }
+
+    #[test]
+    fn test_ommer_referenced_twice_fails() {
+        let (mut bc, ommer) = chain_with_ommer(&mut Fixtures::default());
 
+        let block = mined_block_with_ommers(&bc, bc.get_last_block_hash(), None, vec![ommer.clone(), ommer.clone()]);
         assert!(bc.append_block(block).is_err());
 
-        let satoshi = bc.get_account_by_id("satoshi".to_string());
+        let block = mined_block_with_ommers(&bc, bc.get_last_block_hash(), None, vec![ommer.clone()]);
+        assert!(bc.append_block(block).is_ok());
 
-        assert!(satoshi.is_some());
-        assert_eq!(satoshi.unwrap().balance, 100_000_000);
+        let block = mined_block_with_ommers(&bc, bc.get_last_block_hash(), None, vec![ommer]);
+        assert!(bc.append_block(block).is_err());
+        assert_eq!(bc.get_account_by_id("alice".to_string()).unwrap().balance, ommer_reward(1));
+    }
 
-        let alice = bc.get_account_by_id("alice".to_string());
+    #[test]
+    fn test_ommer_must_be_recent_sibling() {
+        let mut fixtures = Fixtures::default();
+        let (mut bc, ommer) = chain_with_ommer(&mut fixtures);
 
-        assert!(alice.is_some());
-        assert_eq!(alice.unwrap().balance, 100_000);
+        let main_chain_block = mined_block_with_ommers(&bc, bc.get_last_block_hash(), None, vec![]);
+        let block = mined_block_with_ommers(&bc, bc.get_last_block_hash(), None, vec![main_chain_block.clone()]);
+        assert!(bc.append_block(main_chain_block).is_ok());
+        assert!(bc.append_block(block).is_err());
+
+        for nonce in 0..MAX_OMMER_DEPTH as u128 {
+            append_block(&mut bc, &mut fixtures, nonce);
+        }
+        let block = mined_block_with_ommers(&bc, bc.get_last_block_hash(), None, vec![ommer]);
+        assert!(bc.append_block(block).is_err());
     }
 
     #[test]
-    fn test_transfer_transaction_sign_fails() {
-        let mut bc = Blockchain::new();
+    fn test_orphan_attached_when_parent_arrives() {
+        let bc = &mut Blockchain::with_max_target(TEST_TARGET);
+        let fixtures = &mut Fixtures::default();
+        append_block(bc, fixtures, 1);
 
-        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
-        let mut time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_create_account =
-            Transaction::new(TransactionData::CreateAccount("satoshi".to_string(), keypair.public), None, time);
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_mint_initial_supply = Transaction::new(
-            TransactionData::MintInitialSupply {
-                to: "satoshi".to_string(),
-                amount: 100_000_000,
-            },
-            None,
-            time,
+        let parent = mined_block(bc, bc.get_last_block_hash());
+        let child = mined_child(bc, &parent);
+        let grandchild = mined_child(bc, &child);
+
+        assert_eq!(
+            bc.process_block(grandchild.clone()),
+            Ok(BlockStatus::Orphan { missing_parent: child.hash() })
+        );
+        assert_eq!(
+            bc.process_block(child.clone()),
+            Ok(BlockStatus::Orphan { missing_parent: parent.hash() })
         );
+        assert_eq!(bc.orphans().len(), 2);
 
-        let keypair_alice = Keypair::generate(&mut rand::rngs::OsRng {});
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_create_alice =
-            Transaction::new(TransactionData::CreateAccount("alice".to_string(), keypair_alice.public), None, time);
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let tx_mint_initial_supply_alice = Transaction::new(
-                TransactionData::MintInitialSupply {
-                    to: "alice".to_string(),
-                    amount: 100_000,
-                },
-                None,
-                time,
+        assert_eq!(
+            bc.process_block(parent),
+            Ok(BlockStatus::Appended {
+                attached_orphans: vec![child.hash(), grandchild.hash()]
+            })
         );
+        assert_eq!(bc.len(), 4);
+        assert_eq!(bc.get_last_block_hash(), Some(grandchild.hash()));
+        assert!(bc.orphans().is_empty());
+        assert!(bc.validate().is_ok());
+    }
 
-        let mut block = Block::new(None);
-        block.set_nonce(1);
-        block.add_transaction(tx_create_account);
-        block.add_transaction(tx_mint_initial_supply);
-        block.add_transaction(tx_create_alice);
-        block.add_transaction(tx_mint_initial_supply_alice);
+    #[test]
+    fn test_orphans_need_proof_of_work() {
+        let bc = &mut Blockchain::with_max_target(TEST_TARGET);
+        let fixtures = &mut Fixtures::default();
+        append_block(bc, fixtures, 1);
+
+        let parent = mined_block(bc, bc.get_last_block_hash());
+        let orphan = mined_child(bc, &parent);
+        assert!(matches!(bc.process_block(orphan.clone()), Ok(BlockStatus::Orphan { .. })));
+
+        // Enough junk to fill the pool, none of it with the work its bits claim
+        for _ in 0..MAX_ORPHANS {
+            let mut junk = mined_child(bc, &parent);
+            while meets_target(&junk.hash(), TEST_TARGET) {
+                junk.set_nonce(junk.nonce() + 1);
+            }
+            assert_eq!(bc.process_block(junk), Err(BlockchainError::TargetNotMet));
+        }
 
-        assert!(bc.append_block(block).is_ok());
+        // Nor are bits easier than the pow limit taken, however good the hash
+        let mut easy = mined_child(bc, &parent);
+        easy.set_bits(encode_bits(u128::MAX));
+        while !meets_target(&easy.hash(), TEST_TARGET) {
+            easy.set_nonce(easy.nonce() + 1);
+        }
+        assert_eq!(bc.process_block(easy), Err(BlockchainError::TargetNotMet));
 
-        let mut block = Block::new(bc.get_last_block_hash());
-        time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
-        let mut tx_transfer_satoshi_to_alice = Transaction::new(
-                TransactionData::Transfer{
-                    to: "satoshi".to_string(),
-                    amount: 2_000,
-                },
-                Some("alice".to_string()),
-                time,
+        assert_eq!(bc.orphans().len(), 1);
+        assert!(bc.orphans().contains(&orphan.hash()));
+    }
+
+    #[test]
+    fn test_process_block_stores_side_branch() {
+        let bc = &mut Blockchain::with_max_target(TEST_TARGET);
+        let fixtures = &mut Fixtures::default();
+        let genesis = append_block(bc, fixtures, 1);
+        append_block(bc, fixtures, 2);
+        let tip = append_block(bc, fixtures, 3);
+
+        let fork = mined_block(bc, genesis.hash.clone());
+        let fork_child = mined_child(bc, &fork);
+
+        assert_eq!(
+            bc.process_block(fork_child.clone()),
+            Ok(BlockStatus::Orphan { missing_parent: fork.hash() })
         );
-        tx_transfer_satoshi_to_alice.sign(Some(keypair.sign(tx_transfer_satoshi_to_alice.hash().as_bytes())));
-        block.set_nonce(2);
-        block.add_transaction(tx_transfer_satoshi_to_alice);
+        assert_eq!(
+            bc.process_block(fork.clone()),
+            Ok(BlockStatus::SideBranch {
+                height: 1,
+                attached_orphans: vec![fork_child.hash()]
+            })
+        );
+        assert_eq!(bc.get_last_block_hash(), tip.hash);
+        assert_eq!(bc.side_branches().len(), 2);
+        assert_eq!(bc.side_branches().tips(), vec![fork_child.hash()]);
+        assert!(bc.process_block(fork).is_err());
+
+        // Bits easier than the pow limit don't count, however good the hash is
+        let mut easy = mined_block(bc, genesis.hash.clone());
+        easy.set_bits(encode_bits(u128::MAX));
+        while !meets_target(&easy.hash(), TEST_TARGET) {
+            easy.set_nonce(easy.nonce() + 1);
+        }
+        assert_eq!(bc.process_block(easy), Err(BlockchainError::TargetNotMet));
+    }
 
-        assert!(bc.append_block(block).is_err());
+    #[test]
+    fn test_reorg_to_heavier_branch() {
+        let fixtures = &mut Fixtures::default();
+        let mut bc = Blockchain::with_max_target(TEST_TARGET)
+            .with_store(BlockStore::temporary().unwrap())
+            .unwrap();
+        let genesis = append_block(&mut bc, fixtures, 1);
+        let main_first = mined_block(&bc, genesis.hash.clone());
+        assert!(bc.append_block(main_first.clone()).is_ok());
+        let main_second = mined_child(&bc, &main_first);
+        assert!(bc.append_block(main_second.clone()).is_ok());
+        let before: Vec<AccountId> = main_second.transactions().iter().map(|tx| tx.target().clone()).collect();
+
+        // As much work as the main chain is not enough
+        let first = mined_block(&bc, genesis.hash.clone());
+        let second = mined_child(&bc, &first);
+        let third = mined_child(&bc, &second);
+        assert!(bc.process_block(first.clone()).is_ok());
+        assert!(matches!(bc.process_block(second.clone()), Ok(BlockStatus::SideBranch { height: 2, .. })));
+        assert_eq!(bc.get_last_block_hash(), main_second.hash);
+
+        let reorg = Reorg {
+            fork_height: 0,
+            reverted: vec![main_second.clone(), main_first.clone()],
+            applied: vec![first.clone(), second, third.clone()],
+        };
+        assert_eq!(
+            bc.process_block(third.clone()),
+            Ok(BlockStatus::Reorganized {
+                reorg,
+                attached_orphans: vec![]
+            })
+        );
+        assert_eq!(bc.get_last_block_hash(), third.hash);
+        assert_eq!(bc.len(), 4);
+        assert!(bc.validate().is_ok());
+        assert!(bc.get_account_by_id(before[0].clone()).is_none());
+        assert!(bc.get_account_by_id(third.transactions()[0].target().clone()).is_some());
+        assert_eq!(bc.main_chain_height(&first.hash()), Some(1));
+        assert_eq!(bc.main_chain_height(&main_second.hash()), None);
+        assert_eq!(bc.accounts_at(3).unwrap().len(), bc.accounts.len());
+        assert!(bc.accounts_at(4).is_none());
+        // The old blocks can win again, their transactions are pending in the meantime
+        assert!(bc.side_branches().contains(&main_first.hash()));
+        assert!(bc.side_branches().contains(&main_second.hash()));
+        assert!(!bc.side_branches().contains(&first.hash()));
+        assert_eq!(bc.pending_transactions().len(), 2);
+        let stored = bc.store().unwrap().load().unwrap();
+        assert_eq!(stored.blocks.last().unwrap().0.hash(), third.hash());
+        // Only the entries the blocks touched were rewritten, yet they add up to the state
+        assert_eq!(stored.accounts, *bc.accounts);
+        assert_eq!(stored.aliases, *bc.aliases);
+        assert_eq!(stored.standing_orders, bc.standing_orders);
+
+        // A branch that turns out invalid halfway leaves the main chain as it was
+        let valid = mined_block(&bc, genesis.hash.clone());
+        let mut invalid = mined_child(&bc, &valid);
+        invalid.add_transaction(invalid.transactions()[0].clone());
+        mine_block(&mut invalid, bc.target());
+        let after_invalid = mined_child(&bc, &invalid);
+        let last = mined_child(&bc, &after_invalid);
+        for block in [last.clone(), after_invalid.clone(), invalid.clone()] {
+            assert!(matches!(bc.process_block(block), Ok(BlockStatus::Orphan { .. })));
+        }
+        assert!(matches!(bc.process_block(valid.clone()), Ok(BlockStatus::SideBranch { height: 1, .. })));
+        assert_eq!(bc.get_last_block_hash(), third.hash);
+        assert!(bc.validate().is_ok());
+        assert!(bc.side_branches().contains(&valid.hash()));
+        assert!(!bc.side_branches().contains(&invalid.hash()));
+        assert!(!bc.side_branches().contains(&last.hash()));
+        assert_eq!(bc.store().unwrap().load().unwrap().blocks.len(), 4);
+    }
 
-        let satoshi = bc.get_account_by_id("satoshi".to_string());
+    #[test]
+    fn test_side_branches_are_pruned() {
+        let bc = &mut Blockchain::with_max_target(TEST_TARGET);
+        let fixtures = &mut Fixtures::default();
+        bc.set_max_branch_depth(1);
+        let genesis = append_block(bc, fixtures, 1);
+        append_block(bc, fixtures, 2);
 
-        assert!(satoshi.is_some());
-        assert_eq!(satoshi.unwrap().balance, 100_000_000);
+        let fork = mined_block(bc, genesis.hash.clone());
+        assert!(bc.process_block(fork).is_ok());
+        assert_eq!(bc.side_branches().len(), 1);
 
-        let alice = bc.get_account_by_id("alice".to_string());
+        append_block(bc, fixtures, 3);
+        assert_eq!(bc.side_branches().len(), 1);
 
-        assert!(alice.is_some());
-        assert_eq!(alice.unwrap().balance, 100_000);
+        append_block(bc, fixtures, 4);
+        assert!(bc.side_branches().is_empty());
+
+        let too_deep = mined_block(bc, genesis.hash);
+        assert!(bc.process_block(too_deep).is_err());
+    }
+
+    #[test]
+    fn test_process_block_rejects_second_genesis() {
+        let bc = &mut Blockchain::with_max_target(TEST_TARGET);
+        let fixtures = &mut Fixtures::default();
+        append_block(bc, fixtures, 1);
+
+        assert!(bc.process_block(mined_block(bc, None)).is_err());
+    }
+
+    fn chain_with_validators() -> (Blockchain, Keypair, Keypair) {
+        let mut bc = Blockchain::with_max_target(TEST_TARGET);
+        let keypair_alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        let keypair_bob = Keypair::generate(&mut rand::rngs::OsRng {});
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+        let tx_create_alice =
+            Transaction::create_account("alice".to_string(), &keypair_alice, time);
+        let tx_create_bob =
+            Transaction::create_account("bob".to_string(), &keypair_bob, time);
+        assert!(append_block_with_tx(&mut bc, 1, vec![tx_create_alice, tx_create_bob]).is_ok());
+        bc.set_validators(vec!["alice".to_string(), "bob".to_string()], 2);
+
+        (bc, keypair_alice, keypair_bob)
+    }
+
+    fn signed_vote(bc: &Blockchain, height: u64, validator: &str, keypair: &Keypair) -> Vote {
+        let mut vote = Vote::new(height, bc.main_chain_hash(height).unwrap(), validator.to_string());
+        vote.sign(Some(keypair.sign(vote.hash().as_bytes())));
+        vote
+    }
+
+    #[test]
+    fn test_finalize_checkpoint() {
+        let (mut bc, keypair_alice, keypair_bob) = chain_with_validators();
+        let genesis_hash = bc.get_last_block_hash();
+        let mut fixtures = Fixtures::default();
+        append_block(&mut bc, &mut fixtures, 2);
+        append_block(&mut bc, &mut fixtures, 3);
+
+        assert!(bc.submit_vote(signed_vote(&bc, 2, "alice", &keypair_alice)).is_ok());
+        assert_eq!(bc.finalized_height(), None);
+
+        let fork = mined_block(&bc, genesis_hash.clone());
+        assert!(bc.process_block(fork.clone()).is_ok());
+
+        assert!(bc.submit_vote(signed_vote(&bc, 2, "bob", &keypair_bob)).is_ok());
+        assert_eq!(bc.finalized_height(), Some(2));
+        assert!(!bc.side_branches().contains(&fork.hash()));
+
+        assert!(bc.process_block(mined_block(&bc, genesis_hash)).is_err());
+    }
+
+    #[test]
+    fn test_invalid_votes() {
+        let (mut bc, keypair_alice, keypair_bob) = chain_with_validators();
+        let mut fixtures = Fixtures::default();
+        append_block(&mut bc, &mut fixtures, 2);
+        append_block(&mut bc, &mut fixtures, 3);
+
+        assert!(bc.submit_vote(signed_vote(&bc, 1, "alice", &keypair_alice)).is_err());
+        assert!(bc.submit_vote(signed_vote(&bc, 2, "alice", &keypair_bob)).is_err());
+        assert!(bc.submit_vote(signed_vote(&bc, 2, "carol", &keypair_alice)).is_err());
+        assert!(bc.submit_vote(Vote::new(2, bc.main_chain_hash(2).unwrap(), "bob".to_string())).is_err());
+
+        let mut vote = Vote::new(2, bc.main_chain_hash(1).unwrap(), "alice".to_string());
+        vote.sign(Some(keypair_alice.sign(vote.hash().as_bytes())));
+        assert!(bc.submit_vote(vote).is_err());
+
+        assert_eq!(bc.finalized_height(), None);
+    }
+
+    #[test]
+    fn test_transactions_bound_to_chain() {
+        let alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        let chain = |chain_id: &str| {
+            let mut config = GenesisConfig::new(chain_id);
+            config.add_account("alice".to_string(), alice.public, Amount::from_base_units(100));
+            config.add_account("bob".to_string(), Keypair::generate(&mut rand::rngs::OsRng {}).public, Amount::ZERO);
+            Blockchain::from_genesis(&config).unwrap()
+        };
+        let (mut test, mut class) = (chain("test"), chain("class"));
+
+        let mut transfer = Transaction::new(
+            TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 10,
+            },
+            Some("alice".to_string()),
+            test.now(),
+        );
+        transfer.set_nonce(1);
+        transfer.set_chain_id("test");
+        transfer.sign(Some(alice.sign(&transfer.signing_message())));
+        assert!(append_block_with_tx(&mut test, 1, vec![transfer.clone()]).is_ok());
+
+        // Replayed on another chain
+        assert_eq!(
+            append_block_with_tx(&mut class, 1, vec![transfer.clone()]),
+            Err(BlockchainError::TransactionFailed(Box::new(BlockchainError::WrongChain {
+                expected: "class".to_string(),
+                got: "test".to_string(),
+            })))
+        );
+        // Relabeled, without a signature for the new chain
+        transfer.set_chain_id("class");
+        assert_eq!(
+            append_block_with_tx(&mut class, 1, vec![transfer]),
+            Err(BlockchainError::TransactionFailed(Box::new(BlockchainError::InvalidSignature)))
+        );
+        assert_eq!(class.get_account_by_id("alice".to_string()).unwrap().balance, 100);
     }
 }
@@ -1,3 +1,7 @@
+use crate::traits::{ChainStore, Hashable};
+use crate::types::{Block, Hash};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
 #[derive(Default, Debug)]
 pub struct Node<T> {
     data: T,
@@ -55,10 +59,22 @@ impl<T: Default> Chain<T> {
         self.len += 1;
     }
 
+    pub fn pop(&mut self) -> Option<T> {
+        self.head.take().map(|node| {
+            self.head = node.prev;
+            self.len -= 1;
+            node.data
+        })
+    }
+
     pub fn len(&self) -> usize {
         self.len
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     pub fn head(&self) -> Option<&T> {
         match &self.head {
             None => None,
@@ -66,19 +82,69 @@ impl<T: Default> Chain<T> {
         }
     }
 
-    pub fn iter(&self) -> ChainIter<T> {
+    pub fn iter(&self) -> ChainIter<'_, T> {
         ChainIter {
             next: self.head.as_deref(),
         }
     }
 
-    pub fn iter_mut(&mut self) -> ChainIterMut<T> {
+    pub fn iter_mut(&mut self) -> ChainIterMut<'_, T> {
         ChainIterMut {
             next: self.head.as_deref_mut(),
         }
     }
 }
 
+impl ChainStore for Chain<Block> {
+    fn put_block(&mut self, block: Block) {
+        self.append(block);
+    }
+
+    fn pop_block(&mut self) -> Option<Block> {
+        self.pop()
+    }
+
+    fn get_block_by_hash(&self, hash: &Hash) -> Option<&Block> {
+        self.iter().find(|block| &block.hash() == hash)
+    }
+
+    fn get_block_by_height(&self, height: u64) -> Option<&Block> {
+        let depth = (self.len as u64).checked_sub(height + 1)?;
+        self.iter().nth(depth as usize)
+    }
+
+    fn head(&self) -> Option<&Block> {
+        Chain::head(self)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn blocks(&self) -> Box<dyn Iterator<Item = &Block> + '_> {
+        Box::new(self.iter())
+    }
+}
+
+// Oldest first, as a flat sequence so long chains don't nest
+impl<T: Serialize + Default> Serialize for Chain<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut items: Vec<&T> = self.iter().collect();
+        items.reverse();
+        serializer.collect_seq(items)
+    }
+}
+
+impl<'de, T: Deserialize<'de> + Default> Deserialize<'de> for Chain<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut chain = Chain::new();
+        for item in Vec::<T>::deserialize(deserializer)? {
+            chain.append(item);
+        }
+        Ok(chain)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -90,6 +156,9 @@ mod tests {
         chain.append(1);
 
         assert_eq!(chain.head(), Some(&1));
+        assert_eq!(chain.pop(), Some(1));
+        assert_eq!(chain.head(), Some(&3));
+        assert_eq!(chain.len(), 1);
     }
 
     #[test]
@@ -0,0 +1,173 @@
+use crate::traits::{ChainStore, Hashable};
+use crate::types::{short_hash, Block, Blockchain, Hash};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    Main,
+    Side,
+    Orphan,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub hash: Hash,
+    pub height: u64,
+    pub kind: BlockKind,
+    pub transactions: usize,
+}
+
+// From parent to child
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphEdge {
+    pub from: Hash,
+    pub to: Hash,
+}
+
+// Every block a chain knows of, main chain, side branches and orphans, with the links to
+// their parents, for drawing forks and reorgs. Serializes to JSON as is; `to_dot` gives
+// Graphviz input:
+//   dot -Tsvg chain.dot > chain.svg
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChainGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl ChainGraph {
+    pub fn from_blockchain<S: ChainStore>(bc: &Blockchain<S>) -> Self {
+        let main = (0..bc.len() as u64).filter_map(|height| bc.get_block_by_height(height).map(|block| (height, block)));
+        let side = bc.side_branches().blocks();
+        let orphans = bc.orphans().blocks().map(|block| (block.height(), block));
+
+        let mut graph = Self::default();
+        let blocks = main
+            .map(|(height, block)| (BlockKind::Main, height, block))
+            .chain(side.map(|(height, block)| (BlockKind::Side, height, block)))
+            .chain(orphans.map(|(height, block)| (BlockKind::Orphan, height, block)));
+        for (kind, height, block) in blocks {
+            graph.add(kind, height, block);
+        }
+
+        graph.nodes.sort_by(|a, b| (a.height, a.kind, &a.hash).cmp(&(b.height, b.kind, &b.hash)));
+        graph.edges.sort_by(|a, b| (&a.from, &a.to).cmp(&(&b.from, &b.to)));
+        graph
+    }
+
+    fn add(&mut self, kind: BlockKind, height: u64, block: &Block) {
+        let hash = block.hash();
+        if let Some(parent) = &block.header.prev_hash {
+            self.edges.push(GraphEdge {
+                from: parent.clone(),
+                to: hash.clone(),
+            });
+        }
+        self.nodes.push(GraphNode {
+            hash,
+            height,
+            kind,
+            transactions: block.transactions().len(),
+        });
+    }
+
+    // Blocks are laid out left to right by height. Parents nobody has, the ones orphans
+    // wait for, show up as dotted boxes.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph chain {\n    rankdir=LR;\n    node [shape=box, style=filled];\n");
+
+        let known: HashSet<&Hash> = self.nodes.iter().map(|node| &node.hash).collect();
+        for node in &self.nodes {
+            let color = match node.kind {
+                BlockKind::Main => "lightblue",
+                BlockKind::Side => "gold",
+                BlockKind::Orphan => "lightgrey",
+            };
+            let _ = writeln!(
+                dot,
+                "    \"{}\" [label=\"#{}\\n{}\\n{} txs\", fillcolor={}];",
+                node.hash,
+                node.height,
+                short_hash(&node.hash),
+                node.transactions,
+                color
+            );
+        }
+
+        let mut missing: Vec<&Hash> = self.edges.iter().map(|edge| &edge.from).filter(|from| !known.contains(from)).collect();
+        missing.sort();
+        missing.dedup();
+        for hash in missing {
+            let _ = writeln!(dot, "    \"{}\" [label=\"{}\\nmissing\", style=dotted];", hash, short_hash(hash));
+        }
+
+        for edge in &self.edges {
+            let _ = writeln!(dot, "    \"{}\" -> \"{}\";", edge.from, edge.to);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Fixtures;
+    use crate::types::{BlockStatus, Transaction};
+    use crate::utils::{append_block, mine_block};
+
+    const TEST_TARGET: u128 = u128::MAX >> 8;
+
+    fn block_on(bc: &Blockchain, fixtures: &mut Fixtures, prev_hash: Hash, height: u64) -> Block {
+        let mut block = Block::new(Some(prev_hash));
+        block.set_height(height);
+        block.set_timestamp(bc.now());
+        block.add_transaction(Transaction::create_account(fixtures.account_id(), &fixtures.keypair(), fixtures.timestamp()));
+        mine_block(&mut block, TEST_TARGET);
+        block
+    }
+
+    #[test]
+    fn test_graph_of_forks_and_orphans() {
+        let mut fixtures = Fixtures::new(1);
+        let mut bc = Blockchain::with_max_target(TEST_TARGET).with_clock(fixtures.clock().clone());
+        let genesis = append_block(&mut bc, &mut fixtures, 0);
+        append_block(&mut bc, &mut fixtures, 1);
+        append_block(&mut bc, &mut fixtures, 2);
+
+        let side = block_on(&bc, &mut fixtures, genesis.hash(), 1);
+        assert!(matches!(bc.process_block(side.clone()), Ok(BlockStatus::SideBranch { .. })));
+        let orphan = block_on(&bc, &mut fixtures, "unknown".to_string(), 7);
+        assert!(matches!(bc.process_block(orphan.clone()), Ok(BlockStatus::Orphan { .. })));
+
+        let graph = ChainGraph::from_blockchain(&bc);
+        let kinds: Vec<(u64, BlockKind)> = graph.nodes.iter().map(|node| (node.height, node.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (0, BlockKind::Main),
+                (1, BlockKind::Main),
+                (1, BlockKind::Side),
+                (2, BlockKind::Main),
+                (7, BlockKind::Orphan)
+            ]
+        );
+        assert_eq!(graph.edges.len(), 4);
+        assert!(graph.edges.contains(&GraphEdge {
+            from: genesis.hash(),
+            to: side.hash()
+        }));
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph chain {"));
+        assert!(dot.contains(&format!("\"{}\" -> \"{}\";", genesis.hash(), side.hash())));
+        assert!(dot.contains(&format!("\"{}\" [label=\"#1\\n{}\\n1 txs\", fillcolor=gold];", side.hash(), &side.hash()[..8])));
+        assert!(dot.contains("\"unknown\" [label=\"unknown\\nmissing\", style=dotted];"));
+
+        let json = serde_json::to_string(&graph).unwrap();
+        assert!(json.contains("\"kind\":\"orphan\""));
+        assert_eq!(serde_json::from_str::<ChainGraph>(&json).unwrap(), graph);
+    }
+}
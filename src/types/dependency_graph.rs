@@ -0,0 +1,230 @@
+use crate::types::{AccountId, Block, Transaction, TransactionData, ALIAS_PREFIX};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+// A piece of state a transaction reads or writes
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", content = "key", rename_all = "snake_case")]
+pub enum StateKey {
+    Account(AccountId),
+    Alias(String),
+    // By payer and payee
+    StandingOrder(AccountId, AccountId),
+    // Some account only known once an alias is resolved, which conflicts with all of them
+    AnyAccount,
+}
+
+impl StateKey {
+    fn overlaps(&self, other: &StateKey) -> bool {
+        match (self, other) {
+            (StateKey::AnyAccount, StateKey::Account(_) | StateKey::AnyAccount)
+            | (StateKey::Account(_), StateKey::AnyAccount) => true,
+            _ => self == other,
+        }
+    }
+}
+
+// What a transaction touches, worked out from the transaction alone. Fees credited to the
+// block producer are left out, credits commute.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Access {
+    pub reads: BTreeSet<StateKey>,
+    pub writes: BTreeSet<StateKey>,
+}
+
+impl Access {
+    pub fn of(transaction: &Transaction) -> Self {
+        let mut access = Self::default();
+        let account = |id: &AccountId| StateKey::Account(id.clone());
+
+        // The signer's nonce, and the sender's activity for the dead man's switch
+        for signer in transaction.nonce_account().into_iter().chain(transaction.from()) {
+            access.writes.insert(account(signer));
+        }
+
+        match transaction.data() {
+            TransactionData::CreateAccount(id, _) => {
+                access.writes.insert(account(id));
+            }
+            TransactionData::MintInitialSupply { to, .. } | TransactionData::Coinbase { to, .. } => {
+                access.writes.insert(account(to));
+            }
+            TransactionData::Transfer { to, .. } => match to.strip_prefix(ALIAS_PREFIX) {
+                Some(alias) => {
+                    access.reads.insert(StateKey::Alias(alias.to_string()));
+                    access.writes.insert(StateKey::AnyAccount);
+                }
+                None => {
+                    access.writes.insert(account(to));
+                }
+            },
+            TransactionData::RegisterAlias { alias, .. } => {
+                access.writes.insert(StateKey::Alias(alias.clone()));
+            }
+            TransactionData::TransferAlias { alias, to } => {
+                access.writes.insert(StateKey::Alias(alias.clone()));
+                access.reads.insert(account(to));
+            }
+            TransactionData::SetInheritance { beneficiary, .. } => {
+                access.reads.insert(account(beneficiary));
+            }
+            TransactionData::ClaimInheritance { owner } => {
+                access.writes.insert(account(owner));
+            }
+            TransactionData::CreateStandingOrder { payee, .. } => {
+                access.reads.insert(account(payee));
+                if let Some(payer) = transaction.from() {
+                    access.writes.insert(StateKey::StandingOrder(payer.clone(), payee.clone()));
+                }
+            }
+            TransactionData::CancelStandingOrder { payee } => {
+                if let Some(payer) = transaction.from() {
+                    access.writes.insert(StateKey::StandingOrder(payer.clone(), payee.clone()));
+                }
+            }
+            TransactionData::SetSpendingLimit { account: id, .. }
+            | TransactionData::SetGuardians { account: id, .. }
+            | TransactionData::ApproveRecovery { account: id, .. }
+            | TransactionData::CancelRecovery { account: id }
+            | TransactionData::CompleteRecovery { account: id } => {
+                access.writes.insert(account(id));
+            }
+        }
+
+        let writes = access.writes.clone();
+        access.reads.retain(|key| !writes.contains(key));
+        access
+    }
+
+    // The keys where running `self` before `later` or after it could make a difference
+    fn conflicts(&self, later: &Access) -> Vec<StateKey> {
+        let mut keys = BTreeSet::new();
+        for write in &self.writes {
+            for key in later.reads.iter().chain(&later.writes) {
+                if write.overlaps(key) {
+                    keys.insert(write.clone());
+                }
+            }
+        }
+        for read in &self.reads {
+            for write in &later.writes {
+                if read.overlaps(write) {
+                    keys.insert(read.clone());
+                }
+            }
+        }
+        keys.into_iter().collect()
+    }
+}
+
+// `to` has to run after `from`, both indexes into the block's transactions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Dependency {
+    pub from: usize,
+    pub to: usize,
+    pub keys: Vec<StateKey>,
+}
+
+// Read/write conflicts between the transactions of a block. Transactions without a path
+// between them touch disjoint state and can run in either order, or at the same time.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DependencyGraph {
+    pub accesses: Vec<Access>,
+    pub dependencies: Vec<Dependency>,
+}
+
+impl DependencyGraph {
+    pub fn new(transactions: &[Transaction]) -> Self {
+        let accesses: Vec<Access> = transactions.iter().map(Access::of).collect();
+        let mut dependencies = vec![];
+        for (to, later) in accesses.iter().enumerate() {
+            for (from, earlier) in accesses[..to].iter().enumerate() {
+                let keys = earlier.conflicts(later);
+                if !keys.is_empty() {
+                    dependencies.push(Dependency { from, to, keys });
+                }
+            }
+        }
+
+        Self { accesses, dependencies }
+    }
+
+    // Transactions `index` has to wait for
+    pub fn dependencies_of(&self, index: usize) -> Vec<usize> {
+        self.dependencies
+            .iter()
+            .filter(|dependency| dependency.to == index)
+            .map(|dependency| dependency.from)
+            .collect()
+    }
+
+    // Transactions in waves that only depend on earlier waves, each of which can run in
+    // parallel
+    pub fn levels(&self) -> Vec<Vec<usize>> {
+        let mut level = vec![0; self.accesses.len()];
+        // Dependencies are ordered by `to`, and `from` is always lower
+        for dependency in &self.dependencies {
+            level[dependency.to] = level[dependency.to].max(level[dependency.from] + 1);
+        }
+
+        let mut levels: Vec<Vec<usize>> = vec![];
+        for (index, level) in level.into_iter().enumerate() {
+            if levels.len() <= level {
+                levels.resize(level + 1, vec![]);
+            }
+            levels[level].push(index);
+        }
+        levels
+    }
+}
+
+impl Block {
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        DependencyGraph::new(self.transactions())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(from: &str, to: &str) -> Transaction {
+        Transaction::new(
+            TransactionData::Transfer {
+                to: to.to_string(),
+                amount: 1,
+            },
+            Some(from.to_string()),
+            0,
+        )
+    }
+
+    #[test]
+    fn test_dependency_graph() {
+        let mut block = Block::new(None);
+        block.add_transaction(transfer("alice", "bob"));
+        block.add_transaction(transfer("carol", "dave"));
+        block.add_transaction(transfer("bob", "erin"));
+        block.add_transaction(Transaction::new(
+            TransactionData::RegisterAlias {
+                alias: "shop".to_string(),
+                owner: "frank".to_string(),
+            },
+            Some("frank".to_string()),
+            0,
+        ));
+        block.add_transaction(transfer("gina", "@shop"));
+
+        let graph = block.dependency_graph();
+        assert_eq!(graph.dependencies_of(1), Vec::<usize>::new());
+        assert_eq!(graph.dependencies_of(2), vec![0]);
+        assert_eq!(graph.dependencies[0].keys, vec![StateKey::Account("bob".to_string())]);
+        // Paying an alias may credit anyone
+        assert_eq!(graph.dependencies_of(4), vec![0, 1, 2, 3]);
+        assert_eq!(graph.levels(), vec![vec![0, 1, 3], vec![2], vec![4]]);
+
+        let json = serde_json::to_value(&graph).unwrap();
+        assert_eq!(json["dependencies"][0]["keys"][0]["type"], "account");
+        assert!(Block::new(None).dependency_graph().levels().is_empty());
+    }
+}
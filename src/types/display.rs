@@ -0,0 +1,213 @@
+use crate::traits::Hashable;
+use crate::types::{Account, Amount, Balance, Block, Transaction, TransactionData};
+use ed25519_dalek::PublicKey;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+// Hex digits kept of hashes and keys shown to people
+const SHORT_LENGTH: usize = 8;
+
+pub fn short_hash(hash: &str) -> &str {
+    hash.get(..SHORT_LENGTH).unwrap_or(hash)
+}
+
+pub fn short_key(key: &PublicKey) -> String {
+    format!("{}…", short_hash(&hex::encode(key.as_bytes())))
+}
+
+fn coins(balance: Balance) -> Amount {
+    Amount::from_base_units(balance)
+}
+
+impl fmt::Display for TransactionData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionData::CreateAccount(id, key) => write!(f, "create account {} with key {}", id, short_key(key)),
+            TransactionData::MintInitialSupply { to, amount } => write!(f, "mint {} to {}", coins(*amount), to),
+            TransactionData::Transfer { to, amount } => write!(f, "transfer {} to {}", coins(*amount), to),
+            TransactionData::RegisterAlias { alias, owner } => write!(f, "register alias @{} for {}", alias, owner),
+            TransactionData::TransferAlias { alias, to } => write!(f, "transfer alias @{} to {}", alias, to),
+            TransactionData::SetInheritance {
+                beneficiary,
+                inactive_blocks,
+            } => write!(f, "leave everything to {} after {} inactive blocks", beneficiary, inactive_blocks),
+            TransactionData::ClaimInheritance { owner } => write!(f, "claim the inheritance of {}", owner),
+            TransactionData::CreateStandingOrder {
+                payee,
+                amount,
+                interval,
+                cap,
+            } => write!(
+                f,
+                "pay {} {} every {} blocks, up to {}",
+                payee,
+                coins(*amount),
+                interval,
+                coins(*cap)
+            ),
+            TransactionData::CancelStandingOrder { payee } => write!(f, "cancel the standing order to {}", payee),
+            TransactionData::SetSpendingLimit { account, limit: Some(limit) } => write!(
+                f,
+                "limit {} to {} per {} blocks",
+                account,
+                coins(limit.amount),
+                limit.epoch_blocks
+            ),
+            TransactionData::SetSpendingLimit { account, limit: None } => {
+                write!(f, "lift the spending limit of {}", account)
+            }
+            TransactionData::SetGuardians {
+                account,
+                guardians,
+                threshold,
+            } => write!(f, "set {} of {} guardians for {}", threshold, guardians.len(), account),
+            TransactionData::ApproveRecovery { account, new_key } => {
+                write!(f, "approve handing {} over to key {}", account, short_key(new_key))
+            }
+            TransactionData::CancelRecovery { account } => write!(f, "cancel the recovery of {}", account),
+            TransactionData::CompleteRecovery { account } => write!(f, "complete the recovery of {}", account),
+            TransactionData::Coinbase { to, amount } => write!(f, "coinbase {} to {}", coins(*amount), to),
+        }
+    }
+}
+
+// "1a2b3c4d by alice, nonce 1: transfer 1.5 COIN to bob"
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", short_hash(&self.hash()))?;
+        if let Some(from) = self.from() {
+            write!(f, " by {}, nonce {}", from, self.nonce())?;
+        }
+        if self.signature().is_none() {
+            write!(f, " (unsigned)")?;
+        }
+        write!(f, ": {}", self.data())
+    }
+}
+
+// "#12 1a2b3c4d on 9f8e7d6c, 3 transactions". The alternate form, `{:#}`, lists the
+// transactions below, one per line.
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {}", self.height(), short_hash(&self.hash()))?;
+        match self.prev_hash() {
+            Some(parent) => write!(f, " on {}", short_hash(parent))?,
+            None => write!(f, " (genesis)")?,
+        }
+        write!(f, ", {} transactions", self.transactions().len())?;
+        if !self.ommers().is_empty() {
+            write!(f, ", {} ommers", self.ommers().len())?;
+        }
+        if let Some(producer) = self.producer() {
+            write!(f, ", produced by {}", producer)?;
+        }
+
+        if f.alternate() {
+            for transaction in self.transactions() {
+                write!(f, "\n  {}", transaction)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// "User, 1.5 COIN, key 1a2b3c4d…, nonce 3"
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, {}, key {}, nonce {}",
+            self.account_type().name(),
+            coins(self.balance),
+            short_key(self.public_key()),
+            self.nonce()
+        )
+    }
+}
+
+// Debug output safe for logs: the value as JSON with every key, signature and hash cut
+// short, at any depth. Hashes can't be told from keys by their looks, so they go too.
+//   println!("{:?}", Redacted(&block));
+// `{:#?}` pretty prints.
+pub struct Redacted<'a, T: ?Sized>(pub &'a T);
+
+impl<T: Serialize + ?Sized> fmt::Debug for Redacted<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut value = serde_json::to_value(self.0).map_err(|_| fmt::Error)?;
+        redact(&mut value);
+        let json = if f.alternate() {
+            serde_json::to_string_pretty(&value)
+        } else {
+            serde_json::to_string(&value)
+        };
+        f.write_str(&json.map_err(|_| fmt::Error)?)
+    }
+}
+
+// Keys and hashes are 64 hex digits, signatures 128
+fn redact(value: &mut Value) {
+    match value {
+        Value::String(string) if string.len() >= 64 && string.chars().all(|c| c.is_ascii_hexdigit()) => {
+            *string = format!("{}…", short_hash(string));
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        Value::Object(fields) => fields.values_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AccountType;
+    use ed25519_dalek::{Keypair, Signer};
+
+    #[test]
+    fn test_display_and_redaction() {
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let key = hex::encode(keypair.public.as_bytes());
+        let mut transfer = Transaction::new(
+            TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 150_000_000,
+            },
+            Some("alice".to_string()),
+            0,
+        );
+        transfer.set_nonce(1);
+        let hash = transfer.hash();
+        assert_eq!(
+            transfer.to_string(),
+            format!("{} by alice, nonce 1 (unsigned): transfer 1.5 COIN to bob", &hash[..8])
+        );
+        let signature = keypair.sign(&transfer.signing_message());
+        transfer.sign(Some(signature));
+        assert_eq!(
+            transfer.to_string(),
+            format!("{} by alice, nonce 1: transfer 1.5 COIN to bob", &hash[..8])
+        );
+
+        let create = Transaction::create_account("carol".to_string(), &keypair, 0);
+        assert!(create.to_string().ends_with(&format!("create account carol with key {}…", &key[..8])));
+
+        let mut block = Block::new(None);
+        block.add_transaction(transfer.clone());
+        let summary = format!("#0 {} (genesis), 1 transactions", short_hash(&block.hash()));
+        assert_eq!(block.to_string(), summary);
+        assert_eq!(format!("{:#}", block), format!("{}\n  {}", summary, transfer));
+
+        let mut account = Account::new(AccountType::User, keypair.public);
+        account.balance = 100_000_000;
+        assert_eq!(account.to_string(), format!("User, 1 COIN, key {}…, nonce 0", &key[..8]));
+
+        // Neither the key nor the signature make it out whole
+        let signature = hex::encode(signature.to_bytes());
+        for debug in [format!("{:?}", Redacted(&block)), format!("{:#?}", Redacted(&create))].iter() {
+            assert!(!debug.contains(&signature) && !debug.contains(&key), "{}", debug);
+        }
+        let debug = format!("{:?}", Redacted(&block));
+        assert!(debug.contains(&format!("\"{}…\"", &signature[..8])));
+        assert!(debug.contains("\"to\":\"bob\""));
+    }
+}
@@ -0,0 +1,27 @@
+use crate::types::{Account, AccountId, Balance};
+use serde::{Deserialize, Serialize};
+
+// Escheatment: balances below `threshold` of accounts that sent nothing for
+// `inactive_epochs` epochs of `epoch_blocks` blocks go to `treasury`. Sweeps run at the end
+// of the first block of every epoch, in account id order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DustSweep {
+    pub threshold: Balance,
+    pub epoch_blocks: u64,
+    pub inactive_epochs: u64,
+    pub treasury: AccountId,
+}
+
+impl DustSweep {
+    pub fn is_epoch_boundary(&self, height: u64) -> bool {
+        self.epoch_blocks > 0 && height > 0 && height.is_multiple_of(self.epoch_blocks)
+    }
+
+    // Whether the account is swept in a sweep at `height`
+    pub fn is_sweepable(&self, account: &Account, height: u64) -> bool {
+        let inactive_blocks = self.inactive_epochs.saturating_mul(self.epoch_blocks);
+        account.balance > 0
+            && account.balance < self.threshold
+            && height.saturating_sub(account.last_active()) >= inactive_blocks
+    }
+}
@@ -0,0 +1,204 @@
+use crate::types::{AccountId, Balance, Hash, LimitError, Timestamp};
+use std::fmt;
+use thiserror::Error;
+
+// The part an account plays in a transaction, to tell which one is missing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountRole {
+    Sender,
+    Receiver,
+    Payer,
+    Owner,
+    Beneficiary,
+}
+
+impl fmt::Display for AccountRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let role = match self {
+            AccountRole::Sender => "sender",
+            AccountRole::Receiver => "receiver",
+            AccountRole::Payer => "payer",
+            AccountRole::Owner => "owner",
+            AccountRole::Beneficiary => "beneficiary",
+        };
+        write!(f, "{}", role)
+    }
+}
+
+// Why a transaction didn't execute or a block didn't go on the chain. The messages are the
+// ones these errors had as plain strings, so whatever matched on those still does.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BlockchainError {
+    // Transactions
+    #[error("Invalid signature.")]
+    InvalidSignature,
+    #[error("Not sign.")]
+    MissingSignature,
+    #[error("Invalid sender ID.")]
+    MissingSender,
+    #[error("Invalid {0} account.")]
+    UnknownAccount(AccountRole),
+    #[error("AccountId already exist: {0}")]
+    AccountExists(AccountId),
+    #[error("AccountId can't start with @: {0}")]
+    AliasAsAccountId(AccountId),
+    #[error("Insufficient balance: {needed} needed, {available} available")]
+    InsufficientBalance { needed: Balance, available: Balance },
+    #[error("Type overflow")]
+    BalanceOverflow,
+    #[error("Contract accounts can't send transfers")]
+    ContractCantSend,
+    #[error("Account is frozen")]
+    AccountFrozen,
+    #[error("Funds are locked until {0}")]
+    FundsLocked(Timestamp),
+    #[error("Initial supply can be minted only in genesis block.")]
+    MintAfterGenesis,
+    #[error("Unknown alias: {0}")]
+    UnknownAlias(String),
+    #[error("Alias already taken: {0}")]
+    AliasTaken(String),
+    #[error("Only the owner can transfer an alias.")]
+    NotAliasOwner,
+    #[error("Inactivity period must be at least one block.")]
+    ZeroInactivityPeriod,
+    #[error("Can't inherit from yourself.")]
+    InheritanceToSelf,
+    #[error("Not a beneficiary of {0}.")]
+    NotBeneficiary(AccountId),
+    #[error("{0} is still active.")]
+    OwnerStillActive(AccountId),
+    #[error("Standing order interval must be at least one block.")]
+    ZeroInterval,
+    #[error("Standing order has to pay something.")]
+    EmptyStandingOrder,
+    #[error("Can't pay yourself.")]
+    PaymentToSelf,
+    #[error("No standing order to {0}.")]
+    NoStandingOrder(AccountId),
+    #[error("Spending limit epoch must be at least one block.")]
+    ZeroEpoch,
+    #[error("Spending limit exceeded, {0} left this epoch.")]
+    SpendingLimitExceeded(Balance),
+    #[error("Guardians need a threshold between 1 and the number of distinct keys.")]
+    InvalidGuardians,
+    #[error("{0} has no guardians.")]
+    NoGuardians(AccountId),
+    #[error("A recovery of {0} to another key is under way.")]
+    RecoveryInProgress(AccountId),
+    #[error("No recovery of {0} is under way.")]
+    NoRecovery(AccountId),
+    #[error("Recovery of {0} lacks guardian approvals.")]
+    RecoveryNotApproved(AccountId),
+    #[error("Recovery can complete from block {0} on.")]
+    RecoveryNotReady(u64),
+    #[error("Invalid nonce {got}, expected {expected}.")]
+    InvalidNonce { expected: u128, got: u128 },
+    #[error("Transaction is for chain \"{got}\", not \"{expected}\".")]
+    WrongChain { expected: String, got: String },
+    #[error("Coinbase can only be the first transaction of a block.")]
+    MisplacedCoinbase,
+
+    // Blocks
+    #[error("Block has invalid hash")]
+    InvalidHash,
+    #[error("Block already exists")]
+    BlockExists,
+    #[error("Genesis block already exists")]
+    GenesisExists,
+    #[error("Side branch is too far behind the best tip")]
+    SideBranchTooDeep,
+    #[error("Block conflicts with a finalized checkpoint")]
+    FinalityConflict,
+    #[error("Block has 0 transactions.")]
+    EmptyBlock,
+    #[error("Block builds on {prev_hash:?}, expected {expected:?}.")]
+    InvalidParent { prev_hash: Option<Hash>, expected: Option<Hash> },
+    #[error("Block height {height} doesn't follow its parent, expected {expected}.")]
+    InvalidHeight { height: u64, expected: u64 },
+    #[error("Block bits {bits:08x} don't match the target {expected:08x}.")]
+    BitsMismatch { bits: u32, expected: u32 },
+    #[error("The hash of block more than target.")]
+    TargetNotMet,
+    #[error("Block is from the future.")]
+    BlockFromFuture,
+    #[error("Block has a transaction from the future.")]
+    TransactionFromFuture,
+    #[error("Coinbase mints {amount}, the block reward is {expected}.")]
+    InvalidCoinbase { amount: Balance, expected: Balance },
+    #[error("Block transaction {0} is out of canonical order.")]
+    TransactionsOutOfOrder(usize),
+    #[error("Block has an invalid transaction: {0}")]
+    InvalidTransaction(LimitError),
+    #[error("Block has an invalid producer signature.")]
+    InvalidProducerSignature,
+    #[error("Block producer {0} doesn't exist.")]
+    UnknownProducer(AccountId),
+    #[error("Block is signed without a producer.")]
+    SignedWithoutProducer,
+    #[error("Block is not signed by its producer.")]
+    UnsignedBlock,
+    #[error("Block can't reference more than {0} ommers")]
+    TooManyOmmers(usize),
+    #[error("Ommer has invalid hash")]
+    InvalidOmmer,
+    #[error("Ommer is already referenced")]
+    OmmerAlreadyReferenced,
+    #[error("Ommer is part of the main chain")]
+    OmmerInMainChain,
+    #[error("Ommer parent isn't part of the main chain")]
+    UnknownOmmerParent,
+    #[error("Ommer is too old")]
+    OmmerTooOld,
+    #[error("Error during tx execution: {0}")]
+    TransactionFailed(Box<BlockchainError>),
+
+    // Chain validation, blocks are counted from 1 at genesis
+    #[error("Block {0} has invalid hash")]
+    InvalidBlockHash(usize),
+    #[error("Block {0} doesn't have prev_hash")]
+    MissingPrevHash(usize),
+    #[error("Block {block} has height {height}")]
+    InvalidBlockHeight { block: usize, height: u64 },
+    #[error("Genesis block shouldn't have prev_hash")]
+    GenesisWithPrevHash,
+    #[error("Block {block} prev_hash doesn't match Block {prev} hash")]
+    InvalidPrevHash { block: usize, prev: usize },
+
+    // Errors of the parts still reporting plain strings, like fees and storage
+    #[error("{0}")]
+    Other(String),
+}
+
+impl BlockchainError {
+    // Whether the block is broken whichever node checks it: its hash or proof of work is
+    // off, or it's malformed. Anything else can come down to the checking node's clock,
+    // state or view of the chain, so a peer relaying such a block isn't to blame.
+    pub fn is_invalid_block(&self) -> bool {
+        matches!(
+            self,
+            BlockchainError::InvalidHash
+                | BlockchainError::TargetNotMet
+                | BlockchainError::BitsMismatch { .. }
+                | BlockchainError::EmptyBlock
+                | BlockchainError::InvalidHeight { .. }
+                | BlockchainError::MisplacedCoinbase
+                | BlockchainError::TransactionsOutOfOrder(_)
+                | BlockchainError::InvalidTransaction(_)
+                | BlockchainError::TooManyOmmers(_)
+                | BlockchainError::InvalidOmmer
+        )
+    }
+}
+
+impl From<String> for BlockchainError {
+    fn from(error: String) -> Self {
+        BlockchainError::Other(error)
+    }
+}
+
+impl From<BlockchainError> for String {
+    fn from(error: BlockchainError) -> Self {
+        error.to_string()
+    }
+}
@@ -0,0 +1,76 @@
+use crate::traits::WorldState;
+use crate::types::{AccountId, Balance, Error, Transaction, TransactionData};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeDestination {
+    #[default]
+    Burn,
+    // The beneficiary of the block, burned when it has none
+    Producer,
+}
+
+// Anti-spam rules for everything after genesis. Creating an account costs
+// `account_creation_fee`, paid by the signed `from` of the CreateAccount, and transfers
+// move at least `min_transfer`. Both are off by default.
+// Fees are always paid in the chain's own coin. Paying them in other assets at the rate
+// of an on-chain feed needs accounts to hold other assets first, which they can't yet.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeConfig {
+    pub account_creation_fee: Balance,
+    pub destination: FeeDestination,
+    pub min_transfer: Balance,
+}
+
+impl FeeConfig {
+    // What the transaction pays on top of what it moves, which ranks it within a block
+    pub fn fee(&self, transaction: &Transaction) -> Balance {
+        match transaction.data() {
+            TransactionData::CreateAccount(..) if transaction.from().is_some() => self.account_creation_fee,
+            _ => 0,
+        }
+    }
+
+    // Checks the transaction against the rules and charges its fee, before it executes
+    pub fn charge<T: WorldState>(
+        &self,
+        state: &mut T,
+        transaction: &Transaction,
+        producer: Option<&AccountId>,
+    ) -> Result<(), Error> {
+        match transaction.data() {
+            TransactionData::Transfer { amount, .. } if *amount < self.min_transfer => {
+                Err(format!("Transfer below the minimum of {}", self.min_transfer))
+            }
+            TransactionData::CreateAccount(..) if self.account_creation_fee > 0 => {
+                let payer = transaction
+                    .from()
+                    .ok_or_else(|| "Account creation needs a payer for the fee".to_string())?;
+                let account = state
+                    .get_account_by_id(payer.clone())
+                    .ok_or_else(|| "Invalid payer account.".to_string())?;
+                if !account.authorizes_in(state, transaction) {
+                    return Err("Invalid signature.".to_string());
+                }
+                account.can_send(transaction, self.account_creation_fee)?;
+                if account.balance < self.account_creation_fee {
+                    return Err("Insufficient balance for the account creation fee".to_string());
+                }
+
+                match (self.destination, producer) {
+                    (FeeDestination::Producer, Some(producer)) if state.get_account_by_id(producer.clone()).is_some() => {
+                        state.transfer(payer.clone(), producer.clone(), self.account_creation_fee)?
+                    }
+                    _ => {
+                        if let Some(account) = state.get_account_by_id_mut(payer.clone()) {
+                            account.balance -= self.account_creation_fee;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
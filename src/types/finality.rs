@@ -0,0 +1,180 @@
+use crate::traits::{CanonicalEncode, Hashable};
+use crate::types::{AccountId, Error, Hash};
+use ed25519_dalek::Signature;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+
+pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Vote {
+    pub(crate) height: u64,
+    pub(crate) block_hash: Hash,
+    pub(crate) validator: AccountId,
+    #[serde(with = "crate::types::serde_hex::option_signature")]
+    pub(crate) signature: Option<Signature>,
+}
+
+impl Vote {
+    pub fn new(height: u64, block_hash: Hash, validator: AccountId) -> Self {
+        Self {
+            height,
+            block_hash,
+            validator,
+            signature: None,
+        }
+    }
+
+    pub fn sign(&mut self, signature: Option<Signature>) {
+        self.signature = signature;
+    }
+}
+
+impl Hashable for Vote {
+    fn hash(&self) -> Hash {
+        (self.height, &self.block_hash, &self.validator).canonical_hash()
+    }
+}
+
+// Validators vote on every `interval`-th block, a checkpoint voted by more than 2/3 of
+// them becomes final
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Finality {
+    interval: u64,
+    validators: Vec<AccountId>,
+    #[serde(serialize_with = "serialize_votes", deserialize_with = "deserialize_votes")]
+    votes: HashMap<(u64, Hash), Vec<AccountId>>,
+    finalized: Option<(u64, Hash)>,
+}
+
+type Votes = HashMap<(u64, Hash), Vec<AccountId>>;
+
+// As a list, JSON maps can't have tuple keys
+fn serialize_votes<S: Serializer>(votes: &Votes, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(votes.iter())
+}
+
+fn deserialize_votes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Votes, D::Error> {
+    Ok(Vec::<((u64, Hash), Vec<AccountId>)>::deserialize(deserializer)?
+        .into_iter()
+        .collect())
+}
+
+impl Default for Finality {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_CHECKPOINT_INTERVAL,
+            validators: vec![],
+            votes: HashMap::new(),
+            finalized: None,
+        }
+    }
+}
+
+impl Finality {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    pub fn set_interval(&mut self, interval: u64) {
+        self.interval = interval.max(1);
+    }
+
+    pub fn validators(&self) -> &[AccountId] {
+        &self.validators
+    }
+
+    pub fn set_validators(&mut self, validators: Vec<AccountId>) {
+        self.validators = validators;
+        self.votes.clear();
+    }
+
+    pub fn is_validator(&self, account_id: &AccountId) -> bool {
+        self.validators.contains(account_id)
+    }
+
+    pub fn is_checkpoint(&self, height: u64) -> bool {
+        height.is_multiple_of(self.interval)
+    }
+
+    pub fn quorum(&self) -> usize {
+        self.validators.len() * 2 / 3 + 1
+    }
+
+    pub fn finalized(&self) -> Option<&(u64, Hash)> {
+        self.finalized.as_ref()
+    }
+
+    pub fn finalized_height(&self) -> Option<u64> {
+        self.finalized.as_ref().map(|(height, _)| *height)
+    }
+
+    // Marks a checkpoint final without votes, e.g. a block committed by BFT consensus
+    pub fn finalize(&mut self, height: u64, block_hash: Hash) {
+        if matches!(self.finalized_height(), Some(finalized) if height <= finalized) {
+            return;
+        }
+
+        self.finalized = Some((height, block_hash));
+        self.votes.retain(|(vote_height, _), _| *vote_height > height);
+    }
+
+    // Records an already verified vote, returns true if it finalized its checkpoint
+    pub fn record_vote(&mut self, vote: &Vote) -> Result<bool, Error> {
+        if matches!(self.finalized_height(), Some(finalized) if vote.height <= finalized) {
+            return Err("Checkpoint is already final".to_string());
+        }
+
+        let voters = self.votes.entry((vote.height, vote.block_hash.clone())).or_default();
+        if voters.contains(&vote.validator) {
+            return Err(format!("Validator {} already voted", vote.validator));
+        }
+        voters.push(vote.validator.clone());
+
+        if voters.len() < self.quorum() {
+            return Ok(false);
+        }
+
+        self.finalize(vote.height, vote.block_hash.clone());
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quorum() {
+        let mut finality = Finality::new();
+        finality.set_validators(vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+        assert_eq!(finality.quorum(), 3);
+
+        finality.set_validators((0..4).map(|i| i.to_string()).collect());
+        assert_eq!(finality.quorum(), 3);
+    }
+
+    #[test]
+    fn test_record_vote() {
+        let mut finality = Finality::new();
+        finality.set_validators(vec!["a".to_string(), "b".to_string()]);
+
+        let vote_a = Vote::new(10, "hash".to_string(), "a".to_string());
+        let vote_b = Vote::new(10, "hash".to_string(), "b".to_string());
+        let next_vote = Vote::new(20, "next".to_string(), "a".to_string());
+
+        assert_eq!(finality.record_vote(&next_vote), Ok(false));
+        assert_eq!(finality.record_vote(&vote_a), Ok(false));
+        assert!(finality.record_vote(&vote_a).is_err());
+        assert_eq!(finality.record_vote(&vote_b), Ok(true));
+        assert_eq!(finality.finalized_height(), Some(10));
+        assert!(finality.record_vote(&Vote::new(0, "old".to_string(), "a".to_string())).is_err());
+
+        assert_eq!(finality.record_vote(&Vote::new(20, "next".to_string(), "b".to_string())), Ok(true));
+        assert_eq!(finality.finalized_height(), Some(20));
+    }
+}
@@ -0,0 +1,53 @@
+use crate::traits::WorldState;
+use crate::types::{AccountRole, AccountType, BlockchainError, Transaction, TransactionData};
+
+// Rules that only hold in the genesis block: the initial supply is minted there and
+// accounts are created without signatures. Everything else executes as usual.
+pub struct GenesisExecutor;
+
+impl GenesisExecutor {
+    pub fn execute<T: WorldState>(state: &mut T, transaction: &Transaction) -> Result<(), BlockchainError> {
+        match transaction.data() {
+            TransactionData::CreateAccount(account_id, public_key) => {
+                state.create_account(account_id.clone(), AccountType::User, *public_key)
+            }
+            TransactionData::MintInitialSupply { to, amount } => match state.get_account_by_id_mut(to.clone()) {
+                Some(account) => match account.balance.checked_add(*amount) {
+                    Some(balance) => {
+                        account.balance = balance;
+                        Ok(())
+                    }
+                    None => Err(BlockchainError::BalanceOverflow),
+                },
+                None => Err(BlockchainError::UnknownAccount(AccountRole::Receiver)),
+            },
+            _ => transaction.execute(state),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::types::Blockchain;
+    use ed25519_dalek::Keypair;
+
+    #[test]
+    fn test_genesis_only_rules() {
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let create_alice = Transaction::new(TransactionData::CreateAccount("alice".to_string(), keypair.public), None, 0);
+        let mint = |amount| Transaction::new(TransactionData::MintInitialSupply { to: "alice".to_string(), amount }, None, 0);
+
+        // Outside genesis accounts need a signature and nothing can be minted
+        let mut state = Blockchain::new();
+        assert_eq!(create_alice.execute(&mut state), Err(BlockchainError::InvalidSignature));
+        assert!(Transaction::create_account("alice".to_string(), &keypair, 0).execute(&mut state).is_ok());
+        assert_eq!(mint(1).execute(&mut state), Err(BlockchainError::MintAfterGenesis));
+
+        let mut state = Blockchain::new();
+        assert!(GenesisExecutor::execute(&mut state, &create_alice).is_ok());
+        assert!(GenesisExecutor::execute(&mut state, &mint(u128::MAX)).is_ok());
+        assert_eq!(GenesisExecutor::execute(&mut state, &mint(1)), Err(BlockchainError::BalanceOverflow));
+        assert!(GenesisExecutor::execute(&mut state, &mint(0)).is_ok());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::types::{check_account_id, AccountId, Balance, Error, Timestamp, Transaction, TransactionData};
+use ed25519_dalek::PublicKey;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+// Initial balances in the `alloc` format of Ethereum-style genesis files, for bringing
+// exercise data over from other tools:
+//   {"alloc": {"0x7e57...": {"balance": "0xde0b6b3a7640000"}, ...}, ...}
+// Balances are decimal or 0x-prefixed hex, as strings or plain numbers, and an entry may
+// also be just the balance. Everything else in the file (code, storage, chain config) has
+// no counterpart here and is ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenesisAlloc {
+    // By account id, the address in lower case
+    balances: BTreeMap<AccountId, Balance>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AllocEntry {
+    Account { balance: Value },
+    Balance(Value),
+}
+
+fn parse_balance(address: &str, balance: &Value) -> Result<Balance, Error> {
+    let invalid = || format!("Invalid balance for {}: {}", address, balance);
+    match balance {
+        Value::String(balance) => match balance.strip_prefix("0x").or_else(|| balance.strip_prefix("0X")) {
+            Some(hex) => Balance::from_str_radix(hex, 16).map_err(|_| invalid()),
+            None => balance.parse().map_err(|_| invalid()),
+        },
+        Value::Number(balance) => balance.as_u64().map(Balance::from).ok_or_else(invalid),
+        _ => Err(invalid()),
+    }
+}
+
+impl GenesisAlloc {
+    pub fn balances(&self) -> &BTreeMap<AccountId, Balance> {
+        &self.balances
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let value: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let alloc = value.get("alloc").ok_or("Genesis file has no alloc")?;
+        let entries: BTreeMap<String, AllocEntry> = serde_json::from_value(alloc.clone()).map_err(|e| e.to_string())?;
+
+        let mut balances = BTreeMap::new();
+        for (address, entry) in entries {
+            let balance = match &entry {
+                AllocEntry::Account { balance } | AllocEntry::Balance(balance) => parse_balance(&address, balance)?,
+            };
+            let account_id = address.to_lowercase();
+            check_account_id(&account_id).map_err(|e| e.to_string())?;
+            if balances.insert(account_id, balance).is_some() {
+                return Err(format!("Address {} is allocated twice", address));
+            }
+        }
+
+        Ok(Self { balances })
+    }
+
+    // Genesis transactions creating every account and minting its balance, in address
+    // order. Addresses carry no ed25519 key, so `public_key` has to give one per account.
+    pub fn to_transactions<F>(&self, timestamp: Timestamp, public_key: F) -> Result<Vec<Transaction>, Error>
+    where
+        F: Fn(&AccountId) -> Result<PublicKey, Error>,
+    {
+        let mut create = vec![];
+        let mut mint = vec![];
+        for (account_id, balance) in &self.balances {
+            let data = TransactionData::CreateAccount(account_id.clone(), public_key(account_id)?);
+            create.push(Transaction::new(data, None, timestamp));
+            if *balance > 0 {
+                let data = TransactionData::MintInitialSupply {
+                    to: account_id.clone(),
+                    amount: *balance,
+                };
+                mint.push(Transaction::new(data, None, timestamp));
+            }
+        }
+
+        create.extend(mint);
+        Ok(create)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::traits::WorldState;
+    use crate::types::{Block, Blockchain};
+    use ed25519_dalek::Keypair;
+
+    #[test]
+    fn test_import_alloc() {
+        let json = r#"{
+            "config": { "chainId": 1337 },
+            "difficulty": "0x1",
+            "alloc": {
+                "0xAB5801a7D398351b8bE11C439e05C5B3259aeC9B": { "balance": "0xde0b6b3a7640000", "nonce": "0x0" },
+                "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf": { "balance": "250", "code": "0x6080" },
+                "0x2b5ad5c4795c026514f8317c7a215e218dccd6cf": 0
+            }
+        }"#;
+        let alloc = GenesisAlloc::from_json(json).unwrap();
+        assert_eq!(alloc.balances().len(), 3);
+        assert_eq!(alloc.balances()["0xab5801a7d398351b8be11c439e05c5b3259aec9b"], 1_000_000_000_000_000_000);
+
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let transactions = alloc.to_transactions(0, |_| Ok(keypair.public)).unwrap();
+        // Nothing to mint for the empty account
+        assert_eq!(transactions.len(), 5);
+
+        let mut bc = Blockchain::new();
+        let mut genesis = Block::new(None);
+        for transaction in transactions {
+            genesis.add_transaction(transaction);
+        }
+        assert!(bc.append_block(genesis).is_ok());
+        let account = bc.get_account_by_id("0x7e5f4552091a69125d5dfcb7b8c2659029395bdf".to_string()).unwrap();
+        assert_eq!(account.balance, 250);
+        assert_eq!(bc.get_account_by_id("0x2b5ad5c4795c026514f8317c7a215e218dccd6cf".to_string()).unwrap().balance, 0);
+    }
+
+    #[test]
+    fn test_invalid_alloc() {
+        let err = GenesisAlloc::from_json(r#"{"alloc": {"0xab": {"balance": "lots"}}}"#).unwrap_err();
+        assert_eq!(err, "Invalid balance for 0xab: \"lots\"");
+        let err = GenesisAlloc::from_json(r#"{"alloc": {"0xAB": "1", "0xab": "2"}}"#).unwrap_err();
+        assert_eq!(err, "Address 0xab is allocated twice");
+        assert!(GenesisAlloc::from_json(r#"{"alloc": {"0xab": "0x1000000000000000000000000000000000"}}"#).is_err());
+        assert!(GenesisAlloc::from_json(r#"{"config": {}}"#).is_err());
+
+        let alloc = GenesisAlloc::from_json(r#"{"alloc": {"0xab": "1"}}"#).unwrap();
+        let err = alloc.to_transactions(0, |account_id| Err(format!("No key for {}", account_id))).unwrap_err();
+        assert_eq!(err, "No key for 0xab");
+    }
+}
@@ -0,0 +1,277 @@
+use crate::types::{
+    check_account_id, AccountId, Amount, Block, Error, Timestamp, Transaction, TransactionData, MAX_TARGET,
+};
+use ed25519_dalek::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+// Everything a chain starts from, in JSON or TOML:
+//
+//   chain_id = "workshop-2026"
+//   target = "0x00ffffffffffffffffffffffffffffff"
+//   timestamp = 1792144815
+//
+//   [[accounts]]
+//   id = "alice"
+//   public_key = "c0cbd7f3..."
+//   balance = "1000 COIN"
+//
+// Everyone building a chain from the same config gets the same genesis block, so the
+// timestamp is part of the config rather than taken from the clock. The target is the
+// easiest one the chain allows, as hex or decimal, and balances are amounts like "1.5 COIN"
+// or base units. Both are strings since TOML integers stop at 2^63.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisConfig {
+    pub chain_id: String,
+    #[serde(default = "default_target", with = "target")]
+    pub target: u128,
+    #[serde(default)]
+    pub timestamp: u64,
+    #[serde(default)]
+    pub accounts: Vec<GenesisAccount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisAccount {
+    pub id: AccountId,
+    #[serde(with = "crate::types::serde_hex::public_key")]
+    pub public_key: PublicKey,
+    #[serde(default, with = "amount")]
+    pub balance: Amount,
+}
+
+fn default_target() -> u128 {
+    MAX_TARGET
+}
+
+// Written as 0x-prefixed hex, read as that, decimal or a plain number
+mod target {
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Target {
+        Number(u64),
+        Text(String),
+    }
+
+    pub fn serialize<S: Serializer>(target: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:#034x}", target))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        match Target::deserialize(deserializer)? {
+            Target::Number(target) => Ok(target.into()),
+            Target::Text(target) => match target.strip_prefix("0x") {
+                Some(hex) => u128::from_str_radix(hex, 16),
+                None => target.parse(),
+            }
+            .map_err(|_| D::Error::custom(format!("invalid target {}", target))),
+        }
+    }
+}
+
+// Written as Amount displays, read as anything Amount parses or a plain number
+mod amount {
+    use crate::types::Amount;
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawAmount {
+        Number(u64),
+        Text(String),
+    }
+
+    pub fn serialize<S: Serializer>(amount: &Amount, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(amount)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Amount, D::Error> {
+        match RawAmount::deserialize(deserializer)? {
+            RawAmount::Number(amount) => Ok(Amount::from_base_units(amount.into())),
+            RawAmount::Text(amount) => amount.parse().map_err(D::Error::custom),
+        }
+    }
+}
+
+impl GenesisConfig {
+    pub fn new(chain_id: &str) -> Self {
+        Self {
+            chain_id: chain_id.to_string(),
+            target: MAX_TARGET,
+            timestamp: 0,
+            accounts: vec![],
+        }
+    }
+
+    // TOML if the file ends in .toml, JSON otherwise
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|error| format!("Can't read {}: {}", path.display(), error))?;
+        let config = match path.extension() {
+            Some(extension) if extension == "toml" => Self::from_toml(&text),
+            _ => Self::from_json(&text),
+        };
+        config.map_err(|error| format!("Invalid genesis config {}: {}", path.display(), error))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let config: Self = serde_json::from_str(json).map_err(|error| error.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, Error> {
+        let config: Self = toml::from_str(toml).map_err(|error| error.message().to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string(self).map_err(|error| error.to_string())
+    }
+
+    pub fn add_account(&mut self, id: AccountId, public_key: PublicKey, balance: Amount) {
+        self.accounts.push(GenesisAccount { id, public_key, balance });
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.chain_id.trim().is_empty() {
+            return Err("Genesis config has no chain id".to_string());
+        }
+        if self.target == 0 {
+            return Err("No block meets a target of 0".to_string());
+        }
+        let mut ids = BTreeSet::new();
+        for account in &self.accounts {
+            check_account_id(&account.id).map_err(|error| error.to_string())?;
+            if !ids.insert(&account.id) {
+                return Err(format!("Account {} is in the genesis config twice", account.id));
+            }
+        }
+        Ok(())
+    }
+
+    // Creates every account, then mints the balances, both in the order of the config and
+    // for the config's chain
+    pub fn transactions(&self) -> Vec<Transaction> {
+        let timestamp = self.timestamp as Timestamp;
+        let create = self.accounts.iter().map(|account| {
+            let mut transaction = Transaction::new(
+                TransactionData::CreateAccount(account.id.clone(), account.public_key),
+                None,
+                timestamp,
+            );
+            transaction.set_chain_id(&self.chain_id);
+            transaction
+        });
+        let mint = self.accounts.iter().filter(|account| account.balance > Amount::ZERO).map(|account| {
+            let mut transaction = Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: account.id.clone(),
+                    amount: account.balance.base_units(),
+                },
+                None,
+                timestamp,
+            );
+            transaction.set_chain_id(&self.chain_id);
+            transaction
+        });
+        create.chain(mint).collect()
+    }
+
+    pub fn block(&self) -> Block {
+        let mut block = Block::new(None);
+        block.set_timestamp(self.timestamp as Timestamp);
+        for transaction in self.transactions() {
+            block.add_transaction(transaction);
+        }
+        block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::{Hashable, WorldState};
+    use crate::types::{Blockchain, ChainInfo, COIN};
+    use ed25519_dalek::Keypair;
+
+    #[test]
+    fn test_from_genesis() {
+        let (alice, bob) = (
+            Keypair::generate(&mut rand::rngs::OsRng {}),
+            Keypair::generate(&mut rand::rngs::OsRng {}),
+        );
+        let toml = format!(
+            r#"
+            chain_id = "workshop"
+            target = "0x00ffffffffffffffffffffffffffffff"
+            timestamp = 1792144815
+
+            [[accounts]]
+            id = "alice"
+            public_key = "{}"
+            balance = "1.5 COIN"
+
+            [[accounts]]
+            id = "bob"
+            public_key = "{}"
+            "#,
+            hex::encode(alice.public.as_bytes()),
+            hex::encode(bob.public.as_bytes())
+        );
+        let config = GenesisConfig::from_toml(&toml).unwrap();
+        assert_eq!(config.target, u128::MAX >> 8);
+        assert_eq!(config.accounts[0].balance, Amount::from_base_units(150_000_000));
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(GenesisConfig::from_json(&json).unwrap(), config);
+        assert_eq!(GenesisConfig::from_toml(&config.to_toml().unwrap()).unwrap(), config);
+
+        let bc = Blockchain::from_genesis(&config).unwrap();
+        assert_eq!(bc.chain_id(), "workshop");
+        assert_eq!(bc.genesis().unwrap().hash(), config.block().hash());
+        assert_eq!(
+            bc.info(),
+            ChainInfo {
+                genesis_hash: Some(config.block().hash()),
+                chain_id: "workshop".to_string(),
+                height: 0,
+                tip: Some(config.block().hash()),
+            }
+        );
+        assert_eq!(bc.len(), 1);
+        assert_eq!(bc.get_account_by_id("alice".to_string()).unwrap().balance, 3 * COIN / 2);
+        assert_eq!(bc.get_account_by_id("bob".to_string()).unwrap().public_key, bob.public);
+        // Every node building from the config ends up on the same chain
+        let other = Blockchain::from_genesis(&GenesisConfig::from_json(&json).unwrap()).unwrap();
+        assert_eq!(other.get_last_block_hash(), Some(config.block().hash()));
+        assert_eq!(other.state_fingerprint(), bc.state_fingerprint());
+    }
+
+    #[test]
+    fn test_invalid_genesis_config() {
+        let key = hex::encode(Keypair::generate(&mut rand::rngs::OsRng {}).public.as_bytes());
+        let account = |id: &str| format!(r#"{{"id": "{}", "public_key": "{}", "balance": 1}}"#, id, key);
+
+        assert!(GenesisConfig::from_json(r#"{"chain_id": " "}"#).is_err());
+        assert!(GenesisConfig::from_json(r#"{"chain_id": "a", "target": "0"}"#).is_err());
+        assert!(GenesisConfig::from_json(r#"{"chain_id": "a", "target": "lots"}"#).is_err());
+        assert!(GenesisConfig::from_json(r#"{"chain_id": "a", "chian": "b"}"#).is_err());
+        let twice = format!(r#"{{"chain_id": "a", "accounts": [{}, {}]}}"#, account("alice"), account("alice"));
+        assert_eq!(
+            GenesisConfig::from_json(&twice).unwrap_err(),
+            "Account alice is in the genesis config twice"
+        );
+        let config = format!(r#"{{"chain_id": "a", "accounts": [{}]}}"#, account("alice"));
+        assert_eq!(GenesisConfig::from_json(&config).unwrap().target, MAX_TARGET);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::traits::{CanonicalEncode, Hashable};
+use crate::types::{AccountId, Hash, Timestamp};
+use serde::{Deserialize, Serialize};
+
+// Everything the block hash commits to, with the transactions and ommers down to the
+// Merkle roots of their hashes. Enough to check linkage and proof of work without the
+// block bodies.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub prev_hash: Option<Hash>,
+    // Genesis is at 0, every other block one above its parent
+    pub height: u64,
+    pub nonce: u128,
+    pub timestamp: Timestamp,
+    pub bits: u32,
+    pub beneficiary: Option<AccountId>,
+    pub producer: Option<AccountId>,
+    pub tx_root: Hash,
+    pub ommers_root: Hash,
+}
+
+// Pairs of hashes are hashed together level by level, an odd one out moves up as it is.
+// No hashes have the hash of the empty list as their root.
+pub fn merkle_root(hashes: &[Hash]) -> Hash {
+    if hashes.is_empty() {
+        return hashes.canonical_hash();
+    }
+
+    let mut level = hashes.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => (left, right).canonical_hash(),
+                [single] => single.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level.remove(0)
+}
+
+impl CanonicalEncode for BlockHeader {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (
+            (&self.prev_hash, self.height),
+            self.nonce,
+            self.timestamp,
+            self.bits,
+            (&self.beneficiary, &self.producer),
+            (&self.tx_root, &self.ommers_root),
+        )
+            .encode_to(out);
+    }
+}
+
+impl Hashable for BlockHeader {
+    fn hash(&self) -> Hash {
+        self.canonical_hash()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merkle_root() {
+        let hashes: Vec<Hash> = ["a", "b", "c"].iter().map(|leaf| leaf.to_string()).collect();
+        let ab = (&hashes[0], &hashes[1]).canonical_hash();
+
+        assert_eq!(merkle_root(&hashes[..1]), hashes[0]);
+        assert_eq!(merkle_root(&hashes[..2]), ab);
+        assert_eq!(merkle_root(&hashes), (&ab, &hashes[2]).canonical_hash());
+        assert_ne!(merkle_root(&[]), merkle_root(&hashes[..1]));
+
+        // Order matters
+        let swapped = vec![hashes[1].clone(), hashes[0].clone()];
+        assert_ne!(merkle_root(&swapped), ab);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::traits::Hashable;
+use crate::types::{AccountId, Block, Hash};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Lookups over main chain blocks that would otherwise scan the whole chain. Everything in
+// here can be rebuilt from the blocks.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct ChainIndex {
+    // Height of each block
+    blocks: HashMap<Hash, u64>,
+    // Height of the block including each transaction
+    transactions: HashMap<Hash, u64>,
+    // Transactions sending from or to each account, oldest first
+    accounts: HashMap<AccountId, Vec<Hash>>,
+}
+
+impl ChainIndex {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.transactions.clear();
+        self.accounts.clear();
+    }
+
+    pub fn insert_block(&mut self, block: &Block, height: u64) {
+        self.blocks.insert(block.hash(), height);
+        for transaction in &block.body.transactions {
+            let hash = transaction.hash();
+            let to = transaction.target();
+
+            self.accounts.entry(to.clone()).or_default().push(hash.clone());
+            if let Some(from) = transaction.from().filter(|from| *from != to) {
+                self.accounts.entry(from.clone()).or_default().push(hash.clone());
+            }
+            self.transactions.insert(hash, height);
+        }
+    }
+
+    // Takes the tip block back out, undoing `insert_block`
+    pub fn remove_block(&mut self, block: &Block) {
+        self.blocks.remove(&block.hash());
+        for transaction in block.body.transactions.iter().rev() {
+            let hash = transaction.hash();
+            let to = transaction.target();
+
+            let mut accounts = vec![to.clone()];
+            accounts.extend(transaction.from().filter(|from| *from != to).cloned());
+            for account_id in accounts {
+                if let Some(history) = self.accounts.get_mut(&account_id) {
+                    history.retain(|other| *other != hash);
+                    if history.is_empty() {
+                        self.accounts.remove(&account_id);
+                    }
+                }
+            }
+            self.transactions.remove(&hash);
+        }
+    }
+
+    pub fn block_height(&self, hash: &Hash) -> Option<u64> {
+        self.blocks.get(hash).copied()
+    }
+
+    pub fn transaction_height(&self, hash: &Hash) -> Option<u64> {
+        self.transactions.get(hash).copied()
+    }
+
+    pub fn account_history(&self, account_id: &AccountId) -> &[Hash] {
+        self.accounts.get(account_id).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Transaction, TransactionData};
+    use ed25519_dalek::Keypair;
+
+    #[test]
+    fn test_insert_block() {
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let create = Transaction::create_account("alice".to_string(), &keypair, 0);
+        let transfer = Transaction::new(
+            TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 1,
+            },
+            Some("alice".to_string()),
+            0,
+        );
+        let mut block = Block::new(None);
+        block.add_transaction(create.clone());
+        block.add_transaction(transfer.clone());
+
+        let mut index = ChainIndex::new();
+        index.insert_block(&block, 3);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.block_height(&block.hash()), Some(3));
+        assert_eq!(index.transaction_height(&transfer.hash()), Some(3));
+        assert_eq!(index.account_history(&"alice".to_string()), &[create.hash(), transfer.hash()]);
+        assert_eq!(index.account_history(&"bob".to_string()), &[transfer.hash()]);
+        assert!(index.account_history(&"carol".to_string()).is_empty());
+
+        index.remove_block(&block);
+        assert!(index.is_empty());
+        assert_eq!(index.block_height(&block.hash()), None);
+        assert!(index.account_history(&"alice".to_string()).is_empty());
+
+        index.insert_block(&block, 3);
+        index.clear();
+        assert!(index.is_empty());
+        assert_eq!(index.transaction_height(&transfer.hash()), None);
+        assert_eq!(index.block_height(&block.hash()), None);
+    }
+}
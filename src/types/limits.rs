@@ -0,0 +1,62 @@
+use std::fmt;
+
+pub const MAX_ACCOUNT_ID_LENGTH: usize = 64;
+pub const MAX_ALIAS_LENGTH: usize = 32;
+// Encoded transaction plus signature. Far above what the current fields can reach, the
+// bound is there for whatever gets added next.
+pub const MAX_TRANSACTION_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LimitError {
+    EmptyAccountId,
+    AccountIdTooLong { length: usize, max: usize },
+    TransactionTooLarge { size: usize, max: usize },
+    AliasTooLong { length: usize, max: usize },
+    // Empty, or with something other than lowercase letters, digits, '-' and '_'
+    InvalidAlias(String),
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::EmptyAccountId => write!(f, "Account id is empty"),
+            LimitError::AccountIdTooLong { length, max } => {
+                write!(f, "Account id of {} bytes is longer than {}", length, max)
+            }
+            LimitError::TransactionTooLarge { size, max } => {
+                write!(f, "Transaction of {} bytes is larger than {}", size, max)
+            }
+            LimitError::AliasTooLong { length, max } => {
+                write!(f, "Alias of {} bytes is longer than {}", length, max)
+            }
+            LimitError::InvalidAlias(alias) => write!(f, "Invalid alias: {:?}", alias),
+        }
+    }
+}
+
+pub fn check_account_id(account_id: &str) -> Result<(), LimitError> {
+    if account_id.is_empty() {
+        return Err(LimitError::EmptyAccountId);
+    }
+    if account_id.len() > MAX_ACCOUNT_ID_LENGTH {
+        return Err(LimitError::AccountIdTooLong {
+            length: account_id.len(),
+            max: MAX_ACCOUNT_ID_LENGTH,
+        });
+    }
+    Ok(())
+}
+
+pub fn check_alias(alias: &str) -> Result<(), LimitError> {
+    if alias.len() > MAX_ALIAS_LENGTH {
+        return Err(LimitError::AliasTooLong {
+            length: alias.len(),
+            max: MAX_ALIAS_LENGTH,
+        });
+    }
+    let is_valid = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_';
+    if alias.is_empty() || !alias.chars().all(is_valid) {
+        return Err(LimitError::InvalidAlias(alias.to_string()));
+    }
+    Ok(())
+}
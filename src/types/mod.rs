@@ -1,14 +1,72 @@
 mod account;
+mod account_types;
+mod amount;
 mod block;
+#[cfg(feature = "std")]
 mod blockchain;
 mod chain;
+#[cfg(feature = "std")]
+mod chain_graph;
+mod dependency_graph;
+mod display;
+mod dust;
+mod error;
+mod fees;
+mod finality;
+mod genesis;
+mod genesis_alloc;
+#[cfg(feature = "std")]
+mod genesis_config;
+mod header;
+mod index;
+mod limits;
+mod orphan_pool;
+mod recovery;
+mod serde_hex;
+mod side_branches;
+mod signature_cache;
+mod standing_order;
+mod state_history;
+mod state_overlay;
+mod stats;
 mod transaction;
+mod transition;
 
-pub use account::{Account, AccountType};
-pub use block::Block;
-pub use blockchain::Blockchain;
+pub use account::{Account, AccountType, Inheritance, PendingLimit, SpendingLimit, SpendingPolicy, SPENDING_LIMIT_DELAY};
+pub use account_types::{AccountBehavior, Contract, Frozen, Multisig, User, Vesting};
+pub use amount::{Amount, COIN, DECIMALS};
+pub use block::{Block, BlockBody};
+#[cfg(feature = "std")]
+pub use blockchain::{
+    ommer_reward, BlockStatus, Blockchain, Reorg, BLOCK_REWARD, DEFAULT_SAFE_DEPTH, MAX_FUTURE_DRIFT, MAX_OMMERS,
+    MAX_OMMER_DEPTH, MAX_TARGET, TRANSACTION_EXPIRY,
+};
 pub use chain::Chain;
-pub use transaction::{Transaction, TransactionData};
+#[cfg(feature = "std")]
+pub use chain_graph::{BlockKind, ChainGraph, GraphEdge, GraphNode};
+pub use dependency_graph::{Access, Dependency, DependencyGraph, StateKey};
+pub use display::{short_hash, short_key, Redacted};
+pub use dust::DustSweep;
+pub use error::{AccountRole, BlockchainError};
+pub use fees::{FeeConfig, FeeDestination};
+pub use finality::{Finality, Vote, DEFAULT_CHECKPOINT_INTERVAL};
+pub use genesis::GenesisExecutor;
+pub use genesis_alloc::GenesisAlloc;
+#[cfg(feature = "std")]
+pub use genesis_config::{GenesisAccount, GenesisConfig};
+pub use header::{merkle_root, BlockHeader};
+pub use index::ChainIndex;
+pub use limits::{check_account_id, check_alias, LimitError, MAX_ACCOUNT_ID_LENGTH, MAX_ALIAS_LENGTH, MAX_TRANSACTION_SIZE};
+pub use orphan_pool::{OrphanPool, MAX_ORPHANS};
+pub use side_branches::{SideBranches, DEFAULT_MAX_BRANCH_DEPTH};
+pub use signature_cache::{SignatureCache, MAX_CACHED_SIGNATURES};
+pub use recovery::{Guardians, Recovery, RECOVERY_CHALLENGE_PERIOD};
+pub use standing_order::StandingOrder;
+pub use state_history::{StateHistory, SNAPSHOT_INTERVAL};
+pub use state_overlay::StateOverlay;
+pub use stats::{ChainInfo, ChainStats};
+pub use transaction::{Transaction, TransactionData, ALIAS_PREFIX};
+pub use transition::{StandardTransition, TransitionFunction};
 
 pub type Hash = String;
 pub type Timestamp = u128;
@@ -0,0 +1,144 @@
+use crate::traits::Hashable;
+use crate::types::{Block, Hash};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+pub const MAX_ORPHANS: usize = 100;
+
+// Blocks whose parent is not known yet, indexed by their parent hash so they can be
+// attached as soon as the parent arrives.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct OrphanPool {
+    blocks: HashMap<Hash, Block>,
+    by_parent: HashMap<Hash, Vec<Hash>>,
+    order: VecDeque<Hash>,
+}
+
+impl OrphanPool {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.blocks.contains_key(hash)
+    }
+
+    // In no particular order
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    pub fn insert(&mut self, block: Block) {
+        let hash = block.hash();
+        let parent = match &block.header.prev_hash {
+            Some(prev_hash) => prev_hash.clone(),
+            None => return,
+        };
+
+        if self.blocks.contains_key(&hash) {
+            return;
+        }
+        if self.blocks.len() >= MAX_ORPHANS {
+            self.evict_oldest();
+        }
+
+        self.by_parent.entry(parent).or_default().push(hash.clone());
+        self.order.push_back(hash.clone());
+        self.blocks.insert(hash, block);
+    }
+
+    pub fn take_children(&mut self, parent: &Hash) -> Vec<Block> {
+        let hashes = self.by_parent.remove(parent).unwrap_or_default();
+        self.order.retain(|hash| !hashes.contains(hash));
+
+        hashes
+            .iter()
+            .filter_map(|hash| self.blocks.remove(hash))
+            .collect()
+    }
+
+    // Follows `parent` through the pool and returns the first ancestor we don't have,
+    // which is the block that has to be requested from peers.
+    pub fn missing_ancestor(&self, parent: &Hash) -> Hash {
+        let mut missing = parent.clone();
+        while let Some(Some(prev_hash)) = self.blocks.get(&missing).map(|b| b.header.prev_hash.clone()) {
+            missing = prev_hash;
+        }
+
+        missing
+    }
+
+    fn evict_oldest(&mut self) {
+        let hash = match self.order.pop_front() {
+            Some(hash) => hash,
+            None => return,
+        };
+
+        if let Some(Some(parent)) = self.blocks.remove(&hash).map(|b| b.header.prev_hash) {
+            if let Some(children) = self.by_parent.get_mut(&parent) {
+                children.retain(|child| child != &hash);
+                if children.is_empty() {
+                    self.by_parent.remove(&parent);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn orphan(prev_hash: &str, nonce: u128) -> Block {
+        let mut block = Block::new(Some(prev_hash.to_string()));
+        block.set_nonce(nonce);
+        block
+    }
+
+    #[test]
+    fn test_take_children() {
+        let mut pool = OrphanPool::new();
+        pool.insert(orphan("a", 1));
+        pool.insert(orphan("a", 2));
+        pool.insert(orphan("b", 3));
+
+        assert_eq!(pool.take_children(&"a".to_string()).len(), 2);
+        assert_eq!(pool.len(), 1);
+        assert!(pool.take_children(&"a".to_string()).is_empty());
+    }
+
+    #[test]
+    fn test_missing_ancestor() {
+        let mut pool = OrphanPool::new();
+        let first = orphan("root", 1);
+        let second = orphan(&first.hash(), 2);
+        let second_hash = second.hash();
+        pool.insert(first);
+        pool.insert(second);
+
+        assert_eq!(pool.missing_ancestor(&second_hash), "root".to_string());
+        assert_eq!(pool.missing_ancestor(&"other".to_string()), "other".to_string());
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut pool = OrphanPool::new();
+        let oldest = orphan("a", 0);
+        let oldest_hash = oldest.hash();
+        pool.insert(oldest);
+        for nonce in 1..=MAX_ORPHANS as u128 {
+            pool.insert(orphan("a", nonce));
+        }
+
+        assert_eq!(pool.len(), MAX_ORPHANS);
+        assert!(!pool.contains(&oldest_hash));
+    }
+}
@@ -0,0 +1,54 @@
+use ed25519_dalek::PublicKey;
+use serde::{Deserialize, Serialize};
+
+// Blocks the owner has to cancel a recovery the guardians agreed on before the new key
+// can take over
+pub const RECOVERY_CHALLENGE_PERIOD: u64 = 10;
+
+// Keys that together may hand an account over to a new key, `threshold` of them have to
+// agree. Guardians don't need accounts of their own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Guardians {
+    #[serde(with = "crate::types::serde_hex::public_keys")]
+    pub keys: Vec<PublicKey>,
+    pub threshold: usize,
+}
+
+impl Guardians {
+    // Whether `threshold` distinct keys can ever agree
+    pub fn is_valid(&self) -> bool {
+        let mut distinct = self.keys.clone();
+        distinct.sort_by_key(|key| *key.as_bytes());
+        distinct.dedup();
+
+        self.threshold > 0 && distinct.len() >= self.threshold && distinct.len() == self.keys.len()
+    }
+}
+
+// A rotation to `new_key` collecting guardian approvals. Once enough are in it starts,
+// and the new key may complete it RECOVERY_CHALLENGE_PERIOD blocks later.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recovery {
+    #[serde(with = "crate::types::serde_hex::public_key")]
+    pub new_key: PublicKey,
+    #[serde(with = "crate::types::serde_hex::public_keys")]
+    pub approvals: Vec<PublicKey>,
+    // Height of the block the last approval it needed came in
+    pub started_at: Option<u64>,
+}
+
+impl Recovery {
+    pub fn new(new_key: PublicKey) -> Self {
+        Self {
+            new_key,
+            approvals: Vec::new(),
+            started_at: None,
+        }
+    }
+
+    // Height of the first block the new key can complete it in
+    pub fn ready_at(&self) -> Option<u64> {
+        self.started_at
+            .map(|started_at| started_at.saturating_add(RECOVERY_CHALLENGE_PERIOD))
+    }
+}
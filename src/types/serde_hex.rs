@@ -0,0 +1,66 @@
+// serde helpers for key material, used with #[serde(with = "...")]. Keys and signatures
+// go out as hex strings, as everywhere else on the wire.
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+fn decode<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    hex::decode(&encoded).map_err(D::Error::custom)
+}
+
+pub(crate) mod public_key {
+    use super::*;
+    use ed25519_dalek::PublicKey;
+
+    pub fn serialize<S: Serializer>(key: &PublicKey, serializer: S) -> Result<S::Ok, S::Error> {
+        hex::encode(key.as_bytes()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PublicKey, D::Error> {
+        PublicKey::from_bytes(&decode(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+pub(crate) mod public_keys {
+    use super::*;
+    use ed25519_dalek::PublicKey;
+
+    pub fn serialize<S: Serializer>(keys: &[PublicKey], serializer: S) -> Result<S::Ok, S::Error> {
+        keys.iter()
+            .map(|key| hex::encode(key.as_bytes()))
+            .collect::<Vec<String>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<PublicKey>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|key| {
+                let bytes = hex::decode(key).map_err(D::Error::custom)?;
+                PublicKey::from_bytes(&bytes).map_err(D::Error::custom)
+            })
+            .collect()
+    }
+}
+
+pub(crate) mod option_signature {
+    use super::*;
+    use ed25519_dalek::Signature;
+    use std::convert::TryFrom;
+
+    pub fn serialize<S: Serializer>(signature: &Option<Signature>, serializer: S) -> Result<S::Ok, S::Error> {
+        signature
+            .map(|signature| hex::encode(signature.to_bytes()))
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Signature>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(encoded) => {
+                let bytes = hex::decode(&encoded).map_err(D::Error::custom)?;
+                Signature::try_from(&bytes[..]).map(Some).map_err(D::Error::custom)
+            }
+            None => Ok(None),
+        }
+    }
+}
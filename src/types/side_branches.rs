@@ -0,0 +1,245 @@
+use crate::consensus::{decode_bits, target_work};
+use crate::traits::Hashable;
+use crate::types::{Block, Hash};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const DEFAULT_MAX_BRANCH_DEPTH: u64 = 10;
+
+// Blocks of competing branches that don't belong to the main chain, kept for fork-choice
+// until they fall more than `max_depth` blocks behind the best tip. Every block is kept
+// with the work of its branch from the fork point up to and including it, so the main
+// chain blocks above the fork point are all it has to be weighed against.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SideBranches {
+    max_depth: u64,
+    blocks: HashMap<Hash, (u64, u128, Block)>,
+}
+
+impl Default for SideBranches {
+    fn default() -> Self {
+        Self::with_max_depth(DEFAULT_MAX_BRANCH_DEPTH)
+    }
+}
+
+impl SideBranches {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_max_depth(max_depth: u64) -> Self {
+        Self {
+            max_depth,
+            blocks: HashMap::new(),
+        }
+    }
+
+    pub fn max_depth(&self) -> u64 {
+        self.max_depth
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: u64) {
+        self.max_depth = max_depth;
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.blocks.contains_key(hash)
+    }
+
+    pub fn get(&self, hash: &Hash) -> Option<&Block> {
+        self.blocks.get(hash).map(|(_, _, block)| block)
+    }
+
+    // Every stored block with its height, in no particular order
+    pub fn blocks(&self) -> impl Iterator<Item = (u64, &Block)> {
+        self.blocks.values().map(|(height, _, block)| (*height, block))
+    }
+
+    pub fn height_of(&self, hash: &Hash) -> Option<u64> {
+        self.blocks.get(hash).map(|(height, _, _)| *height)
+    }
+
+    // Work of the branch from the fork point up to `hash`
+    pub fn work(&self, hash: &Hash) -> Option<u128> {
+        self.blocks.get(hash).map(|(_, work, _)| *work)
+    }
+
+    pub fn is_too_deep(&self, height: u64, best_height: u64) -> bool {
+        height + self.max_depth < best_height
+    }
+
+    // Parents go in before their children for the work to add up. Bits that don't decode
+    // count for nothing.
+    pub fn insert(&mut self, hash: Hash, height: u64, block: Block) {
+        let parent_work = block.header.prev_hash.as_ref().and_then(|p| self.work(p)).unwrap_or(0);
+        let work = decode_bits(block.header.bits).map(target_work).unwrap_or(0);
+        self.blocks.insert(hash, (height, parent_work.saturating_add(work), block));
+    }
+
+    pub fn remove(&mut self, hash: &Hash) -> Option<Block> {
+        self.blocks.remove(hash).map(|(_, _, block)| block)
+    }
+
+    // Drops `hash` along with every stored block building on it
+    pub fn remove_with_descendants(&mut self, hash: &Hash) {
+        let mut removed = vec![hash.clone()];
+        while let Some(hash) = removed.pop() {
+            if self.blocks.remove(&hash).is_some() {
+                removed.extend(
+                    self.blocks
+                        .iter()
+                        .filter(|(_, (_, _, block))| block.header.prev_hash.as_ref() == Some(&hash))
+                        .map(|(child, _)| child.clone()),
+                );
+            }
+        }
+    }
+
+    // Branch tips are stored blocks that no other stored block builds on
+    pub fn tips(&self) -> Vec<Hash> {
+        let parents: Vec<&Hash> = self
+            .blocks
+            .values()
+            .filter_map(|(_, _, block)| block.header.prev_hash.as_ref())
+            .collect();
+
+        self.blocks
+            .keys()
+            .filter(|hash| !parents.contains(hash))
+            .cloned()
+            .collect()
+    }
+
+    // Blocks from `tip` back to the fork point with the main chain, tip first
+    pub fn branch(&self, tip: &Hash) -> Vec<&Block> {
+        let mut branch = vec![];
+        let mut next = Some(tip.clone());
+
+        while let Some((_, _, block)) = next.and_then(|hash| self.blocks.get(&hash)) {
+            branch.push(block);
+            next = block.header.prev_hash.clone();
+        }
+
+        branch
+    }
+
+    // Height of the first block of the branch `hash` belongs to, i.e. right after the fork point
+    pub fn root_height(&self, hash: &Hash) -> Option<u64> {
+        self.branch(hash)
+            .last()
+            .and_then(|root| self.height_of(&root.hash()))
+    }
+
+    // Drops every branch forking off the main chain at or below `height`
+    pub fn prune_forks_below(&mut self, height: u64) {
+        let conflicting: Vec<Hash> = self
+            .blocks
+            .keys()
+            .filter(|hash| matches!(self.root_height(hash), Some(root) if root <= height))
+            .cloned()
+            .collect();
+
+        for hash in conflicting {
+            self.blocks.remove(&hash);
+        }
+    }
+
+    pub fn prune(&mut self, best_height: u64) {
+        let max_depth = self.max_depth;
+        self.blocks
+            .retain(|_, (height, _, _)| *height + max_depth >= best_height);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::encode_bits;
+
+    fn side_block(prev_hash: &str, nonce: u128) -> Block {
+        let mut block = Block::new(Some(prev_hash.to_string()));
+        block.set_nonce(nonce);
+        block
+    }
+
+    #[test]
+    fn test_branch_and_tips() {
+        let mut branches = SideBranches::new();
+        let first = side_block("fork", 1);
+        let second = side_block(&first.hash(), 2);
+        let (first_hash, second_hash) = (first.hash(), second.hash());
+        branches.insert(first_hash.clone(), 3, first);
+        branches.insert(second_hash.clone(), 4, second);
+
+        assert_eq!(branches.tips(), vec![second_hash.clone()]);
+        let branch: Vec<Hash> = branches.branch(&second_hash).iter().map(|b| b.hash()).collect();
+        assert_eq!(branch, vec![second_hash, first_hash]);
+    }
+
+    #[test]
+    fn test_work_and_remove() {
+        let mut branches = SideBranches::new();
+        let mut first = side_block("fork", 1);
+        first.set_bits(encode_bits(u128::MAX >> 8));
+        let mut second = side_block(&first.hash(), 2);
+        second.set_bits(encode_bits(u128::MAX >> 16));
+        let mut third = side_block(&second.hash(), 3);
+        third.set_bits(0x0180_0001);
+        let (first_hash, second_hash, third_hash) = (first.hash(), second.hash(), third.hash());
+        branches.insert(first_hash.clone(), 3, first);
+        branches.insert(second_hash.clone(), 4, second);
+        // Bits that don't decode add nothing
+        branches.insert(third_hash.clone(), 5, third);
+
+        let work = |shift: u32| target_work(decode_bits(encode_bits(u128::MAX >> shift)).unwrap());
+        assert_eq!(branches.work(&first_hash), Some(work(8)));
+        assert_eq!(branches.work(&third_hash), Some(work(8) + work(16)));
+
+        branches.remove_with_descendants(&second_hash);
+        assert_eq!(branches.len(), 1);
+        assert!(branches.contains(&first_hash));
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut branches = SideBranches::with_max_depth(2);
+        let old = side_block("a", 1);
+        let recent = side_block("b", 2);
+        let (old_hash, recent_hash) = (old.hash(), recent.hash());
+        branches.insert(old_hash.clone(), 1, old);
+        branches.insert(recent_hash.clone(), 3, recent);
+
+        branches.prune(4);
+
+        assert!(!branches.contains(&old_hash));
+        assert!(branches.contains(&recent_hash));
+        assert!(branches.is_too_deep(1, 4));
+        assert!(!branches.is_too_deep(2, 4));
+    }
+
+    #[test]
+    fn test_prune_forks_below() {
+        let mut branches = SideBranches::new();
+        let old_fork = side_block("a", 1);
+        let old_fork_child = side_block(&old_fork.hash(), 2);
+        let new_fork = side_block("b", 3);
+        let (old_child_hash, new_hash) = (old_fork_child.hash(), new_fork.hash());
+        branches.insert(old_fork.hash(), 2, old_fork);
+        branches.insert(old_child_hash.clone(), 3, old_fork_child);
+        branches.insert(new_hash.clone(), 4, new_fork);
+
+        assert_eq!(branches.root_height(&old_child_hash), Some(2));
+        branches.prune_forks_below(3);
+
+        assert_eq!(branches.len(), 1);
+        assert!(branches.contains(&new_hash));
+    }
+}
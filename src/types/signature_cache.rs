@@ -0,0 +1,124 @@
+use crate::types::Transaction;
+use ed25519_dalek::{PublicKey, Verifier};
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+
+// Most signatures remembered, the oldest are forgotten first
+pub const MAX_CACHED_SIGNATURES: usize = 10_000;
+
+// Signed message, key and signature
+type Entry = (Vec<u8>, [u8; 32], [u8; 64]);
+
+// Transaction signatures already found valid, so one checked when its transaction entered
+// the pool isn't checked again when the block with it arrives. Only valid signatures are
+// kept, anything else is checked every time.
+#[derive(Debug, Default)]
+pub struct SignatureCache {
+    entries: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    verified: HashSet<Entry>,
+    order: VecDeque<Entry>,
+}
+
+impl SignatureCache {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().verified.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, transaction: &Transaction, public_key: &PublicKey) -> bool {
+        match entry(transaction, public_key) {
+            Some(entry) => self.entries.lock().unwrap().verified.contains(&entry),
+            None => false,
+        }
+    }
+
+    // Same answer as Transaction::is_signed_by, remembered when it is yes
+    pub fn verify(&self, transaction: &Transaction, public_key: &PublicKey) -> bool {
+        let entry = match entry(transaction, public_key) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if self.entries.lock().unwrap().verified.contains(&entry) {
+            return true;
+        }
+
+        let signature = match transaction.signature() {
+            Some(signature) => signature,
+            None => return false,
+        };
+        if public_key.verify(&entry.0, signature).is_err() {
+            return false;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.verified.insert(entry.clone()) {
+            entries.order.push_back(entry);
+            while entries.order.len() > MAX_CACHED_SIGNATURES {
+                if let Some(oldest) = entries.order.pop_front() {
+                    entries.verified.remove(&oldest);
+                }
+            }
+        }
+        true
+    }
+}
+
+fn entry(transaction: &Transaction, public_key: &PublicKey) -> Option<Entry> {
+    let signature = transaction.signature()?;
+    Some((transaction.signing_message(), public_key.to_bytes(), signature.to_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TransactionData;
+    use ed25519_dalek::{Keypair, Signer};
+
+    #[test]
+    fn test_signature_cache() {
+        let mut rng = rand::rngs::OsRng {};
+        let (alice, mallory) = (Keypair::generate(&mut rng), Keypair::generate(&mut rng));
+        let transfer = |timestamp| {
+            let data = TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 1,
+            };
+            let mut transaction = Transaction::new(data, Some("alice".to_string()), timestamp);
+            transaction.sign(Some(alice.sign(&transaction.signing_message())));
+            transaction
+        };
+        let cache = SignatureCache::new();
+
+        let transaction = transfer(0);
+        assert!(!cache.verify(&transaction, &mallory.public));
+        assert!(cache.is_empty());
+        assert!(cache.verify(&transaction, &alice.public));
+        assert!(cache.contains(&transaction, &alice.public));
+        assert!(cache.verify(&transaction, &alice.public));
+        assert_eq!(cache.len(), 1);
+
+        // The signature is part of the entry, not only the message
+        let mut forged = transaction.clone();
+        forged.sign(Some(mallory.sign(&transaction.signing_message())));
+        assert!(!cache.verify(&forged, &alice.public));
+        forged.sign(None);
+        assert!(!cache.verify(&forged, &alice.public));
+
+        for timestamp in 1..=MAX_CACHED_SIGNATURES as u128 {
+            assert!(cache.verify(&transfer(timestamp), &alice.public));
+        }
+        assert_eq!(cache.len(), MAX_CACHED_SIGNATURES);
+        assert!(!cache.contains(&transaction, &alice.public));
+    }
+}
@@ -0,0 +1,36 @@
+use crate::traits::CanonicalEncode;
+use crate::types::{AccountId, Balance};
+use serde::{Deserialize, Serialize};
+
+// Pays `amount` from `payer` to `payee` every `interval` blocks until `cap` has been paid
+// out. Settled by the chain itself at the end of every block it is due in, there is at
+// most one order per payer and payee.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StandingOrder {
+    pub payer: AccountId,
+    pub payee: AccountId,
+    pub amount: Balance,
+    pub interval: u64,
+    pub cap: Balance,
+    pub paid: Balance,
+    // Height of the next block to settle the order in
+    pub next_due: u64,
+}
+
+impl StandingOrder {
+    // The last payment only makes up the rest of the cap
+    pub fn next_payment(&self) -> Balance {
+        self.amount.min(self.cap.saturating_sub(self.paid))
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.next_payment() == 0
+    }
+}
+
+impl CanonicalEncode for StandingOrder {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (&self.payer, &self.payee, self.amount, self.interval).encode_to(out);
+        (self.cap, self.paid, self.next_due).encode_to(out);
+    }
+}
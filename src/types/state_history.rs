@@ -0,0 +1,128 @@
+use crate::types::{Account, AccountId};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+// Heights the history keeps every account at, in between it only keeps what changed
+pub const SNAPSHOT_INTERVAL: u64 = 64;
+
+// Accounts as of past main chain heights, to look back without replaying the chain. Full
+// snapshots every SNAPSHOT_INTERVAL blocks, and the accounts each block changed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StateHistory {
+    snapshots: BTreeMap<u64, HashMap<AccountId, Account>>,
+    // By height, None for accounts the block removed
+    diffs: BTreeMap<u64, HashMap<AccountId, Option<Account>>>,
+}
+
+impl StateHistory {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.diffs.clear();
+    }
+
+    // Starts over from the accounts at `height`, for chains not built block by block
+    pub fn reset(&mut self, height: u64, accounts: &HashMap<AccountId, Account>) {
+        self.clear();
+        self.snapshots.insert(height, accounts.clone());
+    }
+
+    // Heights the history can tell the accounts at
+    pub fn first_height(&self) -> Option<u64> {
+        self.snapshots.keys().next().copied()
+    }
+
+    pub fn last_height(&self) -> Option<u64> {
+        let snapshot = self.snapshots.keys().next_back();
+        let diff = self.diffs.keys().next_back();
+        snapshot.max(diff).copied()
+    }
+
+    // The block at `height` left the accounts at `after`. `touched` has every account it
+    // wrote to as it was before, None for those it created.
+    pub fn record(&mut self, height: u64, touched: &HashMap<AccountId, Option<Account>>, after: &HashMap<AccountId, Account>) {
+        if self.snapshots.is_empty() || height.is_multiple_of(SNAPSHOT_INTERVAL) {
+            self.snapshots.insert(height, after.clone());
+            return;
+        }
+
+        let diff = touched
+            .iter()
+            .filter(|(account_id, before)| after.get(*account_id) != before.as_ref())
+            .map(|(account_id, _)| (account_id.clone(), after.get(account_id).cloned()))
+            .collect();
+        self.diffs.insert(height, diff);
+    }
+
+    // Forgets everything after `height`, for blocks a reorg took off the main chain
+    pub fn truncate(&mut self, height: u64) {
+        self.snapshots.split_off(&(height + 1));
+        self.diffs.split_off(&(height + 1));
+    }
+
+    // Every account as it was after the block at `height`, None outside the history
+    pub fn accounts_at(&self, height: u64) -> Option<HashMap<AccountId, Account>> {
+        if height > self.last_height()? {
+            return None;
+        }
+        let (snapshot_height, snapshot) = self.snapshots.range(..=height).next_back()?;
+
+        let mut accounts = snapshot.clone();
+        let diffs = self.diffs.range(snapshot_height + 1..).take_while(|(diff_height, _)| **diff_height <= height);
+        for (_, diff) in diffs {
+            for (account_id, account) in diff {
+                match account {
+                    Some(account) => accounts.insert(account_id.clone(), account.clone()),
+                    None => accounts.remove(account_id),
+                };
+            }
+        }
+        Some(accounts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AccountType;
+    use ed25519_dalek::Keypair;
+
+    #[test]
+    fn test_accounts_at() {
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let mut history = StateHistory::new();
+        assert!(history.accounts_at(0).is_none());
+
+        // Alice gets one more coin every block, bob is there for the first ten
+        let mut accounts = HashMap::new();
+        accounts.insert("alice".to_string(), Account::new(AccountType::User, keypair.public));
+        accounts.insert("bob".to_string(), Account::new(AccountType::User, keypair.public));
+        history.record(0, &HashMap::new(), &accounts);
+        for height in 1..=SNAPSHOT_INTERVAL + 5 {
+            // Bob is touched every block without changing until he is removed
+            let touched: HashMap<_, _> = accounts.iter().map(|(id, account)| (id.clone(), Some(account.clone()))).collect();
+            accounts.get_mut("alice").unwrap().balance = height as u128;
+            if height == 10 {
+                accounts.remove("bob");
+            }
+            history.record(height, &touched, &accounts);
+        }
+        assert_eq!(history.first_height(), Some(0));
+        assert_eq!(history.last_height(), Some(SNAPSHOT_INTERVAL + 5));
+
+        for height in [0, 1, 9, 10, SNAPSHOT_INTERVAL - 1, SNAPSHOT_INTERVAL, SNAPSHOT_INTERVAL + 5] {
+            let past = history.accounts_at(height).unwrap();
+            assert_eq!(past["alice"].balance, height as u128);
+            assert_eq!(past.contains_key("bob"), height < 10);
+        }
+        assert!(history.accounts_at(SNAPSHOT_INTERVAL + 6).is_none());
+
+        // Picked up at a later height only knows from there on
+        history.reset(100, &accounts);
+        assert!(history.accounts_at(99).is_none());
+        assert_eq!(history.accounts_at(100), Some(accounts));
+    }
+}
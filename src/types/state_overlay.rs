@@ -0,0 +1,160 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Deref;
+
+// A map that can go back to how it was at a checkpoint. While one is open, the first write
+// to a key notes the value it had, so rolling back or committing costs what was touched
+// since rather than a copy of the whole map. Reads go straight to the map through Deref.
+#[derive(Debug, Clone)]
+pub struct StateOverlay<K, V> {
+    map: HashMap<K, V>,
+    // Values before the open checkpoint of the keys written since, None for new keys
+    journal: Option<HashMap<K, Option<V>>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> StateOverlay<K, V> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn is_checkpointed(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    // Starts over from the current state, anything since an earlier checkpoint stays
+    pub fn checkpoint(&mut self) {
+        self.journal = Some(HashMap::new());
+    }
+
+    // Puts back every key written since the checkpoint and closes it
+    pub fn rollback(&mut self) {
+        for (key, value) in self.journal.take().unwrap_or_default() {
+            match value {
+                Some(value) => self.map.insert(key, value),
+                None => self.map.remove(&key),
+            };
+        }
+    }
+
+    // Keeps the writes since the checkpoint and closes it, returning what the keys they
+    // touched held before
+    pub fn commit(&mut self) -> HashMap<K, Option<V>> {
+        self.journal.take().unwrap_or_default()
+    }
+
+    fn touch(&mut self, key: &K) {
+        if let Some(journal) = &mut self.journal {
+            if !journal.contains_key(key) {
+                journal.insert(key.clone(), self.map.get(key).cloned());
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if self.map.contains_key(key) {
+            self.touch(key);
+        }
+        self.map.get_mut(key)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.touch(&key);
+        self.map.insert(key, value)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        if self.map.contains_key(key) {
+            self.touch(key);
+        }
+        self.map.remove(key)
+    }
+}
+
+impl<K, V> Default for StateOverlay<K, V> {
+    fn default() -> Self {
+        Self {
+            map: HashMap::new(),
+            journal: None,
+        }
+    }
+}
+
+impl<K, V> Deref for StateOverlay<K, V> {
+    type Target = HashMap<K, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.map
+    }
+}
+
+impl<K, V> From<HashMap<K, V>> for StateOverlay<K, V> {
+    fn from(map: HashMap<K, V>) -> Self {
+        Self { map, journal: None }
+    }
+}
+
+// Just the map, an open checkpoint isn't part of the state
+impl<K: Serialize + Eq + Hash, V: Serialize> Serialize for StateOverlay<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.map.serialize(serializer)
+    }
+}
+
+impl<'de, K: Deserialize<'de> + Eq + Hash, V: Deserialize<'de>> Deserialize<'de> for StateOverlay<K, V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::deserialize(deserializer).map(StateOverlay::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback_and_commit() {
+        let mut overlay: StateOverlay<&str, u32> = StateOverlay::new();
+        overlay.insert("alice", 1);
+        overlay.insert("bob", 2);
+        let before = overlay.clone();
+
+        overlay.checkpoint();
+        *overlay.get_mut(&"alice").unwrap() += 10;
+        *overlay.get_mut(&"alice").unwrap() += 10;
+        overlay.remove(&"bob");
+        overlay.insert("carol", 3);
+        assert!(overlay.get_mut(&"dave").is_none());
+        assert_eq!(overlay.get("alice"), Some(&21));
+        overlay.rollback();
+        assert_eq!(*overlay, *before);
+        assert!(!overlay.is_checkpointed());
+
+        // What the touched keys held before, the first write counts
+        overlay.checkpoint();
+        *overlay.get_mut(&"alice").unwrap() += 10;
+        *overlay.get_mut(&"alice").unwrap() += 10;
+        overlay.insert("carol", 3);
+        let touched = overlay.commit();
+        assert_eq!(touched, HashMap::from([("alice", Some(1)), ("carol", None)]));
+        assert_eq!(overlay.get("alice"), Some(&21));
+
+        // Without a checkpoint nothing is kept
+        overlay.insert("dave", 4);
+        overlay.rollback();
+        assert_eq!(overlay.get("dave"), Some(&4));
+    }
+
+    #[test]
+    fn test_serializes_as_the_map() {
+        let mut overlay = StateOverlay::new();
+        overlay.insert("alice".to_string(), 1u32);
+        overlay.checkpoint();
+        overlay.insert("bob".to_string(), 2);
+
+        let json = serde_json::to_string(&overlay).unwrap();
+        let map: HashMap<String, u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(map, *overlay);
+        let back: StateOverlay<String, u32> = serde_json::from_str(&json).unwrap();
+        assert!(!back.is_checkpointed());
+    }
+}
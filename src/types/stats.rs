@@ -0,0 +1,27 @@
+use crate::types::{Balance, Hash};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainStats {
+    pub blocks: u64,
+    pub transactions: u64,
+    // Seconds between genesis and the tip per block, None until there are two blocks
+    pub average_block_interval: Option<f64>,
+    pub average_transactions_per_block: f64,
+    pub target: u128,
+    pub bits: u32,
+    // How many times harder than the easiest target bits can encode
+    pub difficulty: f64,
+    // Sum of every account balance
+    pub supply: Balance,
+}
+
+// Which chain this is and how far it got, for handshakes, light clients and the CLI. The
+// hashes are None and the height 0 until there is a genesis block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainInfo {
+    pub genesis_hash: Option<Hash>,
+    pub chain_id: String,
+    pub height: u64,
+    pub tip: Option<Hash>,
+}
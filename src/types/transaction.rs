@@ -1,23 +1,72 @@
-use crate::traits::{Hashable, WorldState};
-use crate::types::{AccountId, AccountType, Balance, Error, Hash, Timestamp};
-use ed25519_dalek::{Verifier, PublicKey, Signature};
-use blake2::digest::FixedOutput;
-use blake2::{Blake2s, Digest};
+use crate::traits::{CanonicalEncode, Hashable, WorldState};
+use crate::types::{
+    check_account_id, check_alias, Account, AccountId, AccountRole, AccountType, Balance, BlockchainError, Hash,
+    Guardians, Inheritance, LimitError, Recovery, SpendingLimit, StandingOrder, Timestamp, MAX_TRANSACTION_SIZE,
+};
+use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+// Marks a transfer recipient as an alias, resolved to its owner when the transfer executes
+pub const ALIAS_PREFIX: char = '@';
+
+// Prefix of what transaction signatures sign, so they can't pass for signatures of
+// anything else
+pub const SIGNING_DOMAIN: &str = "tx-v1";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transaction {
     nonce: u128,
     pub(crate) timestamp: Timestamp,
     from: Option<AccountId>,
     pub(crate) data: TransactionData,
+    // The chain the transaction is meant for, part of the hash so the same transfer on
+    // two chains is two transactions
+    #[serde(default)]
+    chain_id: String,
+    #[serde(with = "crate::types::serde_hex::option_signature")]
     signature: Option<Signature>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TransactionData {
-    CreateAccount(AccountId, PublicKey),
+    CreateAccount(AccountId, #[serde(with = "crate::types::serde_hex::public_key")] PublicKey),
     MintInitialSupply { to: AccountId, amount: Balance },
     Transfer { to: AccountId, amount: Balance },
+    // Claims an unused alias for `owner`, signed by the owner
+    RegisterAlias { alias: String, owner: AccountId },
+    // Hands an alias over to another account, sent by its current owner
+    TransferAlias { alias: String, to: AccountId },
+    // Arms the sender's dead man's switch, replacing any earlier one
+    SetInheritance { beneficiary: AccountId, inactive_blocks: u64 },
+    // Moves the whole balance of `owner` to the sender once the switch has tripped
+    ClaimInheritance { owner: AccountId },
+    // Pays `payee` `amount` every `interval` blocks until `cap` is paid out, starting
+    // `interval` blocks from now
+    CreateStandingOrder { payee: AccountId, amount: Balance, interval: u64, cap: Balance },
+    CancelStandingOrder { payee: AccountId },
+    // Caps what `account` may transfer out per epoch, or lifts the cap, signed by the account
+    SetSpendingLimit { account: AccountId, limit: Option<SpendingLimit> },
+    // Names the keys that may recover `account`, signed by the account. No keys drop the
+    // guardians, either way a recovery under way is called off.
+    SetGuardians {
+        account: AccountId,
+        #[serde(with = "crate::types::serde_hex::public_keys")]
+        guardians: Vec<PublicKey>,
+        threshold: usize,
+    },
+    // One guardian's approval to hand `account` over to `new_key`, signed by the guardian
+    ApproveRecovery {
+        account: AccountId,
+        #[serde(with = "crate::types::serde_hex::public_key")]
+        new_key: PublicKey,
+    },
+    // Calls off a recovery of `account`, signed by the account
+    CancelRecovery { account: AccountId },
+    // Hands `account` over to the new key once the challenge period is over, signed by
+    // the new key
+    CompleteRecovery { account: AccountId },
+    // Mints the block reward to `to`, only ever as the first transaction of a block
+    Coinbase { to: AccountId, amount: Balance },
 }
 
 impl Transaction {
@@ -27,6 +76,7 @@ impl Transaction {
             timestamp,
             from,
             data,
+            chain_id: String::new(),
             signature: None,
         }
     }
@@ -36,112 +86,768 @@ impl Transaction {
         self.signature = signature;
     }
 
-    pub fn execute<T: WorldState>(&self, state: &mut T, is_genesis: bool) -> Result<(), Error> {
-        //TODO Task 2: Implement signature
+    // Has to be set before signing, the nonce is part of the hash
+    pub fn set_nonce(&mut self, nonce: u128) {
+        self.nonce = nonce;
+    }
+
+    pub fn nonce(&self) -> u128 {
+        self.nonce
+    }
+
+    // Has to be set before signing too, see signing_message
+    pub fn set_chain_id(&mut self, chain_id: &str) {
+        self.chain_id = chain_id.to_string();
+    }
+
+    pub fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+
+    // What gets signed: the domain and the hash. The hash covers the chain id, so a
+    // transaction signed for one chain doesn't verify on another.
+    pub fn signing_message(&self) -> Vec<u8> {
+        (SIGNING_DOMAIN, self.hash()).canonical_bytes()
+    }
+
+    // The account signing the transaction, which has to use up the next of its nonces.
+    // Guardians and recovery keys sign for accounts that aren't theirs and go without, as
+    // do new keys creating their own account.
+    pub fn nonce_account(&self) -> Option<&AccountId> {
         match &self.data {
-            TransactionData::CreateAccount(account_id, public_key) => {
-                state.create_account(account_id.clone(), AccountType::User, public_key.clone())
+            TransactionData::RegisterAlias { owner, .. } => Some(owner),
+            TransactionData::SetSpendingLimit { account, .. }
+            | TransactionData::SetGuardians { account, .. }
+            | TransactionData::CancelRecovery { account } => Some(account),
+            TransactionData::MintInitialSupply { .. }
+            | TransactionData::Coinbase { .. }
+            | TransactionData::ApproveRecovery { .. }
+            | TransactionData::CompleteRecovery { .. } => None,
+            _ => self.from.as_ref(),
+        }
+    }
+
+    pub fn from(&self) -> Option<&AccountId> {
+        self.from.as_ref()
+    }
+
+    pub fn signature(&self) -> Option<&Signature> {
+        self.signature.as_ref()
+    }
+
+    pub fn data(&self) -> &TransactionData {
+        &self.data
+    }
+
+    // Encoded size with the chain id and signature
+    pub fn size(&self) -> usize {
+        self.canonical_bytes().len()
+            + self.chain_id.len()
+            + self.signature.map_or(0, |signature| signature.to_bytes().len())
+    }
+
+    // Bounds every transaction has to stay within, checked when one is decoded, enters
+    // the pool or arrives in a block
+    pub fn check_limits(&self) -> Result<(), LimitError> {
+        check_account_id(self.target())?;
+        if let Some(from) = &self.from {
+            check_account_id(from)?;
+        }
+
+        match &self.data {
+            TransactionData::RegisterAlias { alias, .. } | TransactionData::TransferAlias { alias, .. } => check_alias(alias)?,
+            TransactionData::Transfer { to, .. } => {
+                if let Some(alias) = to.strip_prefix(ALIAS_PREFIX) {
+                    check_alias(alias)?;
+                }
             }
-            TransactionData::MintInitialSupply { to, amount } => {
-                if !is_genesis {
-                    return Err("Initial supply can be minted only in genesis block.".to_string());
+            _ => {}
+        }
+
+        let size = self.size();
+        if size > MAX_TRANSACTION_SIZE {
+            return Err(LimitError::TransactionTooLarge {
+                size,
+                max: MAX_TRANSACTION_SIZE,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn is_signed_by(&self, public_key: &PublicKey) -> bool {
+        match &self.signature {
+            Some(signature) => public_key.verify(&self.signing_message(), signature).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        matches!(self.data, TransactionData::Coinbase { .. })
+    }
+
+    // The account created, minted to or paid, getting an alias, named as beneficiary,
+    // inherited from, paid by a standing order, getting a spending limit or recovered.
+    // Payments to an alias name the alias.
+    pub fn target(&self) -> &AccountId {
+        match &self.data {
+            TransactionData::CreateAccount(account_id, _) => account_id,
+            TransactionData::MintInitialSupply { to, .. } => to,
+            TransactionData::Transfer { to, .. } => to,
+            TransactionData::RegisterAlias { owner, .. } => owner,
+            TransactionData::TransferAlias { to, .. } => to,
+            TransactionData::SetInheritance { beneficiary, .. } => beneficiary,
+            TransactionData::ClaimInheritance { owner } => owner,
+            TransactionData::CreateStandingOrder { payee, .. } => payee,
+            TransactionData::CancelStandingOrder { payee } => payee,
+            TransactionData::SetSpendingLimit { account, .. } => account,
+            TransactionData::SetGuardians { account, .. } => account,
+            TransactionData::ApproveRecovery { account, .. } => account,
+            TransactionData::CancelRecovery { account } => account,
+            TransactionData::CompleteRecovery { account } => account,
+            TransactionData::Coinbase { to, .. } => to,
+        }
+    }
+
+    pub fn involves(&self, account_id: &AccountId) -> bool {
+        self.target() == account_id || self.from.as_ref() == Some(account_id)
+    }
+
+    // A CreateAccount signed by the key it registers, as accounts are created after genesis.
+    // For chains with an id, see create_account_on.
+    pub fn create_account(account_id: AccountId, keypair: &Keypair, timestamp: Timestamp) -> Self {
+        Self::create_account_on("", account_id, keypair, timestamp)
+    }
+
+    pub fn create_account_on(chain_id: &str, account_id: AccountId, keypair: &Keypair, timestamp: Timestamp) -> Self {
+        let mut transaction = Self::new(TransactionData::CreateAccount(account_id, keypair.public), None, timestamp);
+        transaction.set_chain_id(chain_id);
+        transaction.sign(Some(keypair.sign(&transaction.signing_message())));
+
+        transaction
+    }
+
+    // Regular execution, genesis has its own rules in GenesisExecutor
+    pub fn execute<T: WorldState + ?Sized>(&self, state: &mut T) -> Result<(), BlockchainError> {
+        if self.chain_id != state.chain_id() {
+            return Err(BlockchainError::WrongChain {
+                expected: state.chain_id().to_string(),
+                got: self.chain_id.clone(),
+            });
+        }
+
+        // Only what the account signed is held to its nonce, anything else fails on the
+        // signature
+        let nonce = match self.nonce_account().and_then(|id| Some((id, state.get_account_by_id(id.clone())?))) {
+            Some((account_id, account)) if account.authorizes_in(state, self) => {
+                let expected = account.nonce.saturating_add(1);
+                if self.nonce != expected {
+                    return Err(BlockchainError::InvalidNonce {
+                        expected,
+                        got: self.nonce,
+                    });
                 }
-                match state.get_account_by_id_mut(to.clone()) {
-                    Some(account) => {
-                        account.balance += amount;
-                        Ok(())
-                    }
-                    None => Err("Invalid account.".to_string()),
+                Some(account_id.clone())
+            }
+            _ => None,
+        };
+        self.execute_data(state)?;
+
+        if let Some(account_id) = nonce {
+            if let Some(account) = state.get_account_by_id_mut(account_id) {
+                account.nonce = self.nonce;
+            }
+        }
+
+        // Anything the sender signed shows they are around, which resets their dead man's
+        // switch. Only their own signature counts, `from` alone proves nothing.
+        if let Some(from) = &self.from {
+            let height = state.height();
+            let active = match state.get_account_by_id(from.clone()) {
+                Some(account) => account.authorizes_in(state, self),
+                None => false,
+            };
+            if let Some(account) = state.get_account_by_id_mut(from.clone()).filter(|_| active) {
+                account.last_active = height;
+            }
+        }
+        Ok(())
+    }
+
+    // Signed by the sender, who has to exist
+    fn authorized_sender<'a, T: WorldState + ?Sized>(&self, state: &'a T) -> Result<(AccountId, &'a Account), BlockchainError> {
+        let sender_id = match &self.from {
+            Some(account_id) => account_id.clone(),
+            None => return Err(BlockchainError::MissingSender),
+        };
+        match state.get_account_by_id(sender_id.clone()) {
+            Some(account) if account.authorizes_in(state, self) => Ok((sender_id, account)),
+            Some(_) => Err(BlockchainError::InvalidSignature),
+            None => Err(BlockchainError::UnknownAccount(AccountRole::Sender)),
+        }
+    }
+
+    // Signed by `account` itself, for settings of an account named in the transaction
+    fn authorized_owner<'a, T: WorldState + ?Sized>(&self, state: &'a T, account: &AccountId) -> Result<&'a Account, BlockchainError> {
+        match state.get_account_by_id(account.clone()) {
+            Some(owner) if owner.authorizes_in(state, self) => Ok(owner),
+            Some(_) => Err(BlockchainError::InvalidSignature),
+            None => Err(BlockchainError::UnknownAccount(AccountRole::Owner)),
+        }
+    }
+
+    fn execute_data<T: WorldState + ?Sized>(&self, state: &mut T) -> Result<(), BlockchainError> {
+        match &self.data {
+            TransactionData::CreateAccount(account_id, public_key) => {
+                // Authorized by the payer if there is one, otherwise by the owner of the new key
+                let authorized = match &self.from {
+                    Some(payer) => match state.get_account_by_id(payer.clone()) {
+                        Some(account) => account.authorizes_in(state, self),
+                        None => return Err(BlockchainError::UnknownAccount(AccountRole::Payer)),
+                    },
+                    None => state.is_signed_by(self, public_key),
+                };
+                if !authorized {
+                    return Err(BlockchainError::InvalidSignature);
                 }
+
+                let height = state.height();
+                state.create_account(account_id.clone(), AccountType::User, *public_key)?;
+                if let Some(account) = state.get_account_by_id_mut(account_id.clone()) {
+                    account.last_active = height;
+                }
+                Ok(())
+            }
+            TransactionData::MintInitialSupply { .. } => {
+                Err(BlockchainError::MintAfterGenesis)
             }
+            // The chain pays the coinbase leading a block itself, see Blockchain::pay_coinbase
+            TransactionData::Coinbase { .. } => Err(BlockchainError::MisplacedCoinbase),
             // TODO Task 1: Implement transfer transition function
             // 1. Check that receiver and sender accounts exist
             // 2. Check sender balance
             // 3. Change sender/receiver balances and save to state
             // 4. Test
             TransactionData::Transfer { to, amount } => {
-                let sender;
-                let senderId;
-                let receiver;
-                
-                match &self.from {
-                    Some(account_id) => {
-                        senderId = account_id.clone();
+                let to = &state.resolve_account(to)?;
+                let sender_id = match &self.from {
+                    Some(account_id) => account_id.clone(),
+                    None => return Err(BlockchainError::MissingSender),
+                };
+
+                let sender = match state.get_account_by_id(sender_id.clone()) {
+                    Some(account) => account.clone(),
+                    None => return Err(BlockchainError::UnknownAccount(AccountRole::Sender)),
+                };
+                let receiver = match state.get_account_by_id(to.clone()) {
+                    Some(account) => account.clone(),
+                    None => return Err(BlockchainError::UnknownAccount(AccountRole::Receiver)),
+                };
+
+                if self.signature.is_none() {
+                    return Err(BlockchainError::MissingSignature);
+                }
+                if !sender.authorizes_in(&*state, self) {
+                    return Err(BlockchainError::InvalidSignature);
+                }
+                sender.can_send(self, *amount)?;
+                receiver.can_receive(self, *amount)?;
+                let height = state.height();
+                if let Some(allowance) = sender.spending().allowance(height) {
+                    if *amount > allowance {
+                        return Err(BlockchainError::SpendingLimitExceeded(allowance));
                     }
-                    None => return Err("Invalid sender ID.".to_string()),
                 }
-                
-                if matches!(state.get_account_by_id_mut(senderId.clone()), Some(account))
-                {
-                    sender = state.get_account_by_id_mut(senderId.clone()).unwrap().clone();
+                state.transfer(sender_id.clone(), to.clone(), *amount)?;
+
+                if let Some(account) = state.get_account_by_id_mut(sender_id) {
+                    account.spending.record(*amount, height);
                 }
-                else
-                {
-                    return Err("Invalid sender account.".to_string());
+
+                if let Some(account) = state.get_account_by_id_mut(to.clone()) {
+                    account.on_credit(self, *amount);
                 }
-                if matches!(state.get_account_by_id_mut(to.clone()), Some(account))
-                {
-                    receiver = state.get_account_by_id_mut(to.clone()).unwrap().clone();
+
+                Ok(())
+            },
+            TransactionData::RegisterAlias { alias, owner } => {
+                match state.get_account_by_id(owner.clone()) {
+                    Some(account) if account.authorizes_in(state, self) => {}
+                    Some(_) => return Err(BlockchainError::InvalidSignature),
+                    None => return Err(BlockchainError::UnknownAccount(AccountRole::Owner)),
                 }
-                else
-                {
-                    return Err("Invalid receiver account.".to_string());
+                if state.alias_owner(alias).is_some() {
+                    return Err(BlockchainError::AliasTaken(alias.clone()));
                 }
 
-                match &self.signature
-                {
-                    Some(signature) => {
-                        if !sender.public_key.verify(self.hash().as_bytes(), &Signature::from(signature.to_bytes())).is_ok()
-                        {
-                            return Err("Invalid signature.".to_string());
-                        }
-                    }
-                    None => return Err("Not sign.".to_string()),
+                state.set_alias_owner(alias.clone(), owner.clone());
+                Ok(())
+            }
+            TransactionData::TransferAlias { alias, to } => {
+                let owner = match state.alias_owner(alias) {
+                    Some(owner) => owner.clone(),
+                    None => return Err(BlockchainError::UnknownAlias(alias.clone())),
+                };
+                if self.from.as_ref() != Some(&owner) {
+                    return Err(BlockchainError::NotAliasOwner);
+                }
+                match state.get_account_by_id(owner) {
+                    Some(account) if account.authorizes_in(state, self) => {}
+                    _ => return Err(BlockchainError::InvalidSignature),
+                }
+                if state.get_account_by_id(to.clone()).is_none() {
+                    return Err(BlockchainError::UnknownAccount(AccountRole::Receiver));
+                }
+
+                state.set_alias_owner(alias.clone(), to.clone());
+                Ok(())
+            }
+            TransactionData::SetInheritance {
+                beneficiary,
+                inactive_blocks,
+            } => {
+                let (owner, _) = self.authorized_sender(state)?;
+                if *inactive_blocks == 0 {
+                    return Err(BlockchainError::ZeroInactivityPeriod);
+                }
+                if &owner == beneficiary {
+                    return Err(BlockchainError::InheritanceToSelf);
+                }
+                if state.get_account_by_id(beneficiary.clone()).is_none() {
+                    return Err(BlockchainError::UnknownAccount(AccountRole::Beneficiary));
                 }
 
-                if sender.balance < *amount
-                {
-                    return Err("Insufficient balance".to_string());
+                if let Some(account) = state.get_account_by_id_mut(owner) {
+                    account.inheritance = Some(Inheritance {
+                        beneficiary: beneficiary.clone(),
+                        inactive_blocks: *inactive_blocks,
+                    });
+                }
+                Ok(())
+            }
+            TransactionData::ClaimInheritance { owner } => {
+                let (beneficiary, receiver) = self.authorized_sender(state)?;
+                let receiver = receiver.clone();
+                let account = match state.get_account_by_id(owner.clone()) {
+                    Some(account) => account.clone(),
+                    None => return Err(BlockchainError::UnknownAccount(AccountRole::Owner)),
+                };
+                match account.inheritance() {
+                    Some(inheritance) if inheritance.beneficiary == beneficiary => {}
+                    _ => return Err(BlockchainError::NotBeneficiary(owner.clone())),
                 }
-                if u128::MAX - *amount < receiver.balance
-                {
-                    return Err("Type overflow".to_string());
+                if !account.is_inheritable_by(&beneficiary, state.height()) {
+                    return Err(BlockchainError::OwnerStillActive(owner.clone()));
                 }
 
-                match state.get_account_by_id_mut(senderId.clone()) {
-                    Some(account) => {
-                        account.balance -= amount;
-                    }
-                    None => return Err("Invalid sender account.".to_string()),
+                let amount = account.balance;
+                account.can_send(self, amount)?;
+                receiver.can_receive(self, amount)?;
+                state.transfer(owner.clone(), beneficiary.clone(), amount)?;
+
+                if let Some(account) = state.get_account_by_id_mut(beneficiary) {
+                    account.on_credit(self, amount);
+                }
+                Ok(())
+            }
+            TransactionData::CreateStandingOrder {
+                payee,
+                amount,
+                interval,
+                cap,
+            } => {
+                let (payer, _) = self.authorized_sender(state)?;
+                if *interval == 0 {
+                    return Err(BlockchainError::ZeroInterval);
+                }
+                if *amount == 0 || *cap == 0 {
+                    return Err(BlockchainError::EmptyStandingOrder);
+                }
+                if &payer == payee {
+                    return Err(BlockchainError::PaymentToSelf);
+                }
+                if state.get_account_by_id(payee.clone()).is_none() {
+                    return Err(BlockchainError::UnknownAccount(AccountRole::Receiver));
+                }
+
+                let next_due = state.height().saturating_add(*interval);
+                state.set_standing_order(StandingOrder {
+                    payer,
+                    payee: payee.clone(),
+                    amount: *amount,
+                    interval: *interval,
+                    cap: *cap,
+                    paid: 0,
+                    next_due,
+                });
+                Ok(())
+            }
+            TransactionData::CancelStandingOrder { payee } => {
+                let (payer, _) = self.authorized_sender(state)?;
+                match state.cancel_standing_order(&payer, payee) {
+                    Some(_) => Ok(()),
+                    None => Err(BlockchainError::NoStandingOrder(payee.clone())),
+                }
+            }
+            TransactionData::SetSpendingLimit { account, limit } => {
+                self.authorized_owner(state, account)?;
+                if matches!(limit, Some(limit) if limit.epoch_blocks == 0) {
+                    return Err(BlockchainError::ZeroEpoch);
+                }
+
+                let height = state.height();
+                if let Some(owner) = state.get_account_by_id_mut(account.clone()) {
+                    owner.spending.change(*limit, height);
+                }
+                Ok(())
+            }
+            TransactionData::SetGuardians {
+                account,
+                guardians,
+                threshold,
+            } => {
+                self.authorized_owner(state, account)?;
+                let guardians = Guardians {
+                    keys: guardians.clone(),
+                    threshold: *threshold,
+                };
+                if !guardians.keys.is_empty() && !guardians.is_valid() {
+                    return Err(BlockchainError::InvalidGuardians);
                 }
 
-                match state.get_account_by_id_mut(to.clone()) {
-                    Some(account) => {
-                        account.balance += amount;
+                if let Some(owner) = state.get_account_by_id_mut(account.clone()) {
+                    owner.guardians = if guardians.keys.is_empty() { None } else { Some(guardians) };
+                    owner.recovery = None;
+                }
+                Ok(())
+            }
+            TransactionData::ApproveRecovery { account, new_key } => {
+                let owner = match state.get_account_by_id(account.clone()) {
+                    Some(owner) => owner,
+                    None => return Err(BlockchainError::UnknownAccount(AccountRole::Owner)),
+                };
+                let guardians = match owner.guardians() {
+                    Some(guardians) => guardians,
+                    None => return Err(BlockchainError::NoGuardians(account.clone())),
+                };
+                let guardian = match guardians.keys.iter().find(|key| state.is_signed_by(self, key)) {
+                    Some(guardian) => *guardian,
+                    None => return Err(BlockchainError::InvalidSignature),
+                };
+                // Approvals for another key start over, unless that recovery already started
+                let mut recovery = match owner.recovery() {
+                    Some(recovery) if &recovery.new_key == new_key => recovery.clone(),
+                    Some(recovery) if recovery.started_at.is_some() => {
+                        return Err(BlockchainError::RecoveryInProgress(account.clone()))
                     }
-                    None => return Err("Invalid receiver account.".to_string()),
+                    _ => Recovery::new(*new_key),
+                };
+
+                if !recovery.approvals.contains(&guardian) {
+                    recovery.approvals.push(guardian);
+                }
+                if recovery.started_at.is_none() && recovery.approvals.len() >= guardians.threshold {
+                    recovery.started_at = Some(state.height());
+                }
+                if let Some(owner) = state.get_account_by_id_mut(account.clone()) {
+                    owner.recovery = Some(recovery);
+                }
+                Ok(())
+            }
+            TransactionData::CancelRecovery { account } => {
+                let owner = self.authorized_owner(state, account)?;
+                if owner.recovery().is_none() {
+                    return Err(BlockchainError::NoRecovery(account.clone()));
                 }
 
-                return Ok(());
+                if let Some(owner) = state.get_account_by_id_mut(account.clone()) {
+                    owner.recovery = None;
+                }
+                Ok(())
+            }
+            TransactionData::CompleteRecovery { account } => {
+                let recovery = match state.get_account_by_id(account.clone()) {
+                    Some(owner) => owner.recovery().cloned(),
+                    None => return Err(BlockchainError::UnknownAccount(AccountRole::Owner)),
+                };
+                let recovery = match recovery {
+                    Some(recovery) => recovery,
+                    None => return Err(BlockchainError::NoRecovery(account.clone())),
+                };
+                if !state.is_signed_by(self, &recovery.new_key) {
+                    return Err(BlockchainError::InvalidSignature);
+                }
+                match recovery.ready_at() {
+                    Some(ready_at) if state.height() >= ready_at => {}
+                    Some(ready_at) => return Err(BlockchainError::RecoveryNotReady(ready_at)),
+                    None => return Err(BlockchainError::RecoveryNotApproved(account.clone())),
+                }
+
+                if let Some(owner) = state.get_account_by_id_mut(account.clone()) {
+                    owner.public_key = recovery.new_key;
+                    owner.recovery = None;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Account ids come from a small set so generated transfers hit existing accounts
+#[cfg(feature = "arbitrary")]
+fn arbitrary_account_id(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<AccountId> {
+    Ok(u.choose(&["alice", "bob", "carol"])?.to_string())
+}
+
+#[cfg(feature = "arbitrary")]
+fn arbitrary_alias(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<String> {
+    Ok(u.choose(&["ali", "bobby"])?.to_string())
+}
+
+#[cfg(feature = "arbitrary")]
+fn arbitrary_public_key(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<PublicKey> {
+    let secret = ed25519_dalek::SecretKey::from_bytes(&u.arbitrary::<[u8; 32]>()?)
+        .map_err(|_| arbitrary::Error::IncorrectFormat)?;
+    Ok(PublicKey::from(&secret))
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for TransactionData {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(match u.int_in_range(0..=14)? {
+            0 => TransactionData::CreateAccount(arbitrary_account_id(u)?, arbitrary_public_key(u)?),
+            1 => TransactionData::MintInitialSupply {
+                to: arbitrary_account_id(u)?,
+                amount: u.arbitrary()?,
+            },
+            2 => TransactionData::Transfer {
+                to: arbitrary_account_id(u)?,
+                amount: u.arbitrary()?,
+            },
+            3 => TransactionData::RegisterAlias {
+                alias: arbitrary_alias(u)?,
+                owner: arbitrary_account_id(u)?,
+            },
+            4 => TransactionData::TransferAlias {
+                alias: arbitrary_alias(u)?,
+                to: arbitrary_account_id(u)?,
+            },
+            5 => TransactionData::SetInheritance {
+                beneficiary: arbitrary_account_id(u)?,
+                inactive_blocks: u.int_in_range(0..=3)?,
+            },
+            6 => TransactionData::ClaimInheritance {
+                owner: arbitrary_account_id(u)?,
+            },
+            7 => TransactionData::CreateStandingOrder {
+                payee: arbitrary_account_id(u)?,
+                amount: u.arbitrary()?,
+                interval: u.int_in_range(0..=3)?,
+                cap: u.arbitrary()?,
+            },
+            8 => TransactionData::CancelStandingOrder {
+                payee: arbitrary_account_id(u)?,
+            },
+            9 => TransactionData::SetSpendingLimit {
+                account: arbitrary_account_id(u)?,
+                limit: if u.arbitrary()? {
+                    Some(SpendingLimit {
+                        amount: u.arbitrary()?,
+                        epoch_blocks: u.int_in_range(0..=3)?,
+                    })
+                } else {
+                    None
+                },
             },
+            10 => TransactionData::SetGuardians {
+                account: arbitrary_account_id(u)?,
+                guardians: (0..u.int_in_range(0..=3)?)
+                    .map(|_| arbitrary_public_key(u))
+                    .collect::<arbitrary::Result<_>>()?,
+                threshold: u.int_in_range(0..=3)?,
+            },
+            11 => TransactionData::ApproveRecovery {
+                account: arbitrary_account_id(u)?,
+                new_key: arbitrary_public_key(u)?,
+            },
+            12 => TransactionData::CancelRecovery {
+                account: arbitrary_account_id(u)?,
+            },
+            13 => TransactionData::CompleteRecovery {
+                account: arbitrary_account_id(u)?,
+            },
+            _ => TransactionData::Coinbase {
+                to: arbitrary_account_id(u)?,
+                amount: u.arbitrary()?,
+            },
+        })
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Transaction {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        use std::convert::TryFrom;
+
+        let from = if u.arbitrary()? { Some(arbitrary_account_id(u)?) } else { None };
+        let signature = if u.arbitrary()? {
+            Signature::try_from(&u.arbitrary::<[u8; 64]>()?[..]).ok()
+        } else {
+            None
+        };
+
+        Ok(Self {
+            nonce: u.arbitrary()?,
+            timestamp: u.arbitrary()?,
+            from,
+            data: u.arbitrary()?,
+            chain_id: u.arbitrary()?,
+            signature,
+        })
+    }
+}
+
+// A tag byte for the variant, then its fields
+impl CanonicalEncode for TransactionData {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            TransactionData::CreateAccount(account_id, public_key) => (0u8, account_id, public_key).encode_to(out),
+            TransactionData::MintInitialSupply { to, amount } => (1u8, to, amount).encode_to(out),
+            TransactionData::Transfer { to, amount } => (2u8, to, amount).encode_to(out),
+            TransactionData::RegisterAlias { alias, owner } => (3u8, alias, owner).encode_to(out),
+            TransactionData::TransferAlias { alias, to } => (4u8, alias, to).encode_to(out),
+            TransactionData::SetInheritance {
+                beneficiary,
+                inactive_blocks,
+            } => (5u8, beneficiary, inactive_blocks).encode_to(out),
+            TransactionData::ClaimInheritance { owner } => (6u8, owner).encode_to(out),
+            TransactionData::CreateStandingOrder {
+                payee,
+                amount,
+                interval,
+                cap,
+            } => (7u8, payee, amount, interval, cap).encode_to(out),
+            TransactionData::CancelStandingOrder { payee } => (8u8, payee).encode_to(out),
+            TransactionData::SetSpendingLimit { account, limit } => {
+                (9u8, account, limit.map(|limit| (limit.amount, limit.epoch_blocks))).encode_to(out)
+            }
+            TransactionData::SetGuardians {
+                account,
+                guardians,
+                threshold,
+            } => (10u8, account, guardians, *threshold as u64).encode_to(out),
+            TransactionData::ApproveRecovery { account, new_key } => (11u8, account, new_key).encode_to(out),
+            TransactionData::CancelRecovery { account } => (12u8, account).encode_to(out),
+            TransactionData::CompleteRecovery { account } => (13u8, account).encode_to(out),
+            TransactionData::Coinbase { to, amount } => (14u8, to, amount).encode_to(out),
         }
     }
 }
 
+// Everything but the signature, see signing_message
+impl CanonicalEncode for Transaction {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (self.nonce, self.timestamp, &self.from, &self.data, &self.chain_id).encode_to(out);
+    }
+}
+
 impl Hashable for Transaction {
     fn hash(&self) -> Hash {
-        let mut hasher = Blake2s::new();
-
-        hasher.update(format!(
-            "{:?}",
-            (
-                self.nonce,
-                self.timestamp,
-                self.from.clone(),
-                self.data.clone()
+        self.canonical_hash()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::MAX_ACCOUNT_ID_LENGTH;
+    use ed25519_dalek::SecretKey;
+
+    // Changing any of these changes every hash on the chain
+    #[test]
+    fn test_hash_vectors() {
+        let public_key = PublicKey::from(&SecretKey::from_bytes(&[1; 32]).unwrap());
+        let create = Transaction::new(
+            TransactionData::CreateAccount("alice".to_string(), public_key),
+            None,
+            1_600_000_000,
+        );
+        let transfer = Transaction::new(
+            TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 100,
+            },
+            Some("alice".to_string()),
+            1_600_000_000,
+        );
+
+        assert_eq!(
+            hex::encode(transfer.canonical_bytes()),
+            concat!(
+                "00000000000000000000000000000000",
+                "0000000000000000000000005f5e1000",
+                "0100000005616c696365",
+                "020000000362",
+                "6f6200000000000000000000000000000064",
+                "00000000",
+            )
+        );
+        assert_eq!(transfer.hash(), "d7c6f89f430fd951c3516b7300c51889837b609de470b7a267a89c4a00ba84ef");
+        assert_eq!(create.hash(), "880411d7d20c92fadff88c9630f2108a31e29d74f3f23e415808a2f1308bd25d");
+
+        // However the key was constructed
+        let decoded = PublicKey::from_bytes(public_key.as_bytes()).unwrap();
+        let create_decoded = Transaction::new(
+            TransactionData::CreateAccount("alice".to_string(), decoded),
+            None,
+            1_600_000_000,
+        );
+        assert_eq!(create_decoded.hash(), create.hash());
+    }
+
+    #[test]
+    fn test_chain_id_changes_hash() {
+        let transfer = |chain_id: &str| {
+            let mut transaction = Transaction::new(
+                TransactionData::Transfer {
+                    to: "bob".to_string(),
+                    amount: 100,
+                },
+                Some("alice".to_string()),
+                1_600_000_000,
+            );
+            transaction.set_chain_id(chain_id);
+            transaction
+        };
+
+        assert_ne!(transfer("class").hash(), transfer("test").hash());
+        assert_ne!(transfer("class").hash(), transfer("").hash());
+        assert_eq!(transfer("class").hash(), transfer("class").hash());
+    }
+
+    #[test]
+    fn test_limits() {
+        let transfer = |from: &str, to: &str| {
+            Transaction::new(
+                TransactionData::Transfer {
+                    to: to.to_string(),
+                    amount: 1,
+                },
+                Some(from.to_string()),
+                0,
             )
-        ));
+        };
+        let longest = "a".repeat(MAX_ACCOUNT_ID_LENGTH);
+        let transaction = transfer(&longest, &longest);
+        assert_eq!(transaction.check_limits(), Ok(()));
+        assert!(transaction.size() < MAX_TRANSACTION_SIZE);
 
-        hex::encode(hasher.finalize_fixed())
+        assert_eq!(transfer("alice", "").check_limits(), Err(LimitError::EmptyAccountId));
+        assert_eq!(
+            transfer(&format!("{}a", longest), "bob").check_limits(),
+            Err(LimitError::AccountIdTooLong {
+                length: MAX_ACCOUNT_ID_LENGTH + 1,
+                max: MAX_ACCOUNT_ID_LENGTH
+            })
+        );
     }
 }
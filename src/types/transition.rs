@@ -0,0 +1,94 @@
+use crate::traits::WorldState;
+use crate::types::{BlockchainError, Transaction};
+use std::fmt::Debug;
+
+// How a transaction changes the world state. A chain executes every transaction after
+// genesis through its transition function, so an exercise can plug in one with a broken
+// rule and leave the chains that execute as usual to notice they no longer agree.
+// Implementations usually handle the transactions they change and hand the rest to
+// StandardTransition.
+pub trait TransitionFunction: Debug + Send + Sync {
+    fn execute(&self, transaction: &Transaction, state: &mut dyn WorldState) -> Result<(), BlockchainError>;
+}
+
+// The rules in Transaction::execute, which every chain uses unless given others
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StandardTransition;
+
+impl TransitionFunction for StandardTransition {
+    fn execute(&self, transaction: &Transaction, state: &mut dyn WorldState) -> Result<(), BlockchainError> {
+        transaction.execute(state)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::types::{AccountRole, Blockchain, TransactionData};
+    use crate::utils::append_block_with_tx;
+    use ed25519_dalek::{Keypair, Signer};
+    use std::sync::Arc;
+
+    // The kind of bug an exercise plants: transfers credit the receiver without
+    // checking or debiting the sender
+    #[derive(Debug)]
+    struct FreeMoney;
+
+    impl TransitionFunction for FreeMoney {
+        fn execute(&self, transaction: &Transaction, state: &mut dyn WorldState) -> Result<(), BlockchainError> {
+            match transaction.data() {
+                TransactionData::Transfer { to, amount } => {
+                    let receiver = state
+                        .get_account_by_id_mut(to.clone())
+                        .ok_or(BlockchainError::UnknownAccount(AccountRole::Receiver))?;
+                    receiver.balance += amount;
+                    Ok(())
+                }
+                _ => StandardTransition.execute(transaction, state),
+            }
+        }
+    }
+
+    #[test]
+    fn test_validators_catch_a_broken_transition() {
+        let mut instructor = Blockchain::with_max_target(u128::MAX >> 8).with_transition(Arc::new(FreeMoney));
+        let mut student = Blockchain::with_max_target(u128::MAX >> 8);
+        let alice = Keypair::generate(&mut rand::rngs::OsRng {});
+        let genesis = vec![
+            Transaction::create_account("alice".to_string(), &alice, instructor.now()),
+            Transaction::create_account("bob".to_string(), &Keypair::generate(&mut rand::rngs::OsRng {}), instructor.now()),
+            Transaction::new(
+                TransactionData::MintInitialSupply {
+                    to: "alice".to_string(),
+                    amount: 10,
+                },
+                None,
+                instructor.now(),
+            ),
+        ];
+        append_block_with_tx(&mut instructor, 0, genesis).unwrap();
+        student.append_block(instructor.get_block_by_height(0).unwrap().clone()).unwrap();
+
+        let mut overspend = Transaction::new(
+            TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 100,
+            },
+            Some("alice".to_string()),
+            instructor.now(),
+        );
+        overspend.set_nonce(1);
+        overspend.sign(Some(alice.sign(&overspend.signing_message())));
+        append_block_with_tx(&mut instructor, 1, vec![overspend]).unwrap();
+        assert_eq!(instructor.get_account_by_id("bob".to_string()).unwrap().balance, 100);
+
+        assert_eq!(
+            student.append_block(instructor.get_block_by_height(1).unwrap().clone()),
+            Err(BlockchainError::TransactionFailed(Box::new(BlockchainError::InsufficientBalance {
+                needed: 100,
+                available: 10,
+            })))
+        );
+        assert_eq!(student.len(), 1);
+    }
+}
@@ -1,24 +1,45 @@
-use crate::types::{AccountId, Block, Blockchain, Error, Transaction, TransactionData};
+use crate::mining::Miner;
+use crate::testing::Fixtures;
+use crate::traits::{ChainStore, Clock};
+use crate::types::{AccountId, Block, Blockchain, BlockchainError, Timestamp, Transaction};
 use blake2::{Blake2s, Digest};
-use ed25519_dalek::{Keypair};
 use std::time::{SystemTime, UNIX_EPOCH};
 use rand::Rng;
 
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as Timestamp
+    }
+}
+
 pub fn generate_account_id() -> AccountId {
-    let mut rng = rand::thread_rng();
+    generate_account_id_with(&mut rand::thread_rng())
+}
+
+pub fn generate_account_id_with<R: Rng>(rng: &mut R) -> AccountId {
     let seed: u128 = rng.gen();
 
     hex::encode(Blake2s::digest(&seed.to_be_bytes()))
 }
 
-pub fn append_block(bc: &mut Blockchain, nonce: u128) -> Block {
-    let mut block = Block::new(bc.get_last_block_hash());
-    let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
-    let time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as u128;
+pub fn mine_block(block: &mut Block, target: u128) {
+    Miner::mine(block, target).expect("Target is reachable");
+}
+
+pub fn append_block<S: ChainStore>(bc: &mut Blockchain<S>, fixtures: &mut Fixtures, nonce: u128) -> Block {
+    let mut block = bc.next_block();
+    let keypair = fixtures.keypair();
     let tx_create_account =
-        Transaction::new(TransactionData::CreateAccount(generate_account_id(), keypair.public), None, time);
+        Transaction::create_account(fixtures.account_id(), &keypair, fixtures.timestamp());
     block.set_nonce(nonce);
+    block.set_timestamp(bc.now());
     block.add_transaction(tx_create_account);
+    if !bc.is_empty() {
+        mine_block(&mut block, bc.target());
+    }
     let block_clone = block.clone();
 
     assert!(bc.append_block(block).is_ok());
@@ -26,17 +47,26 @@ pub fn append_block(bc: &mut Blockchain, nonce: u128) -> Block {
     block_clone
 }
 
-pub fn append_block_with_tx(
-    bc: &mut Blockchain,
+pub fn append_block_with_tx<S: ChainStore>(
+    bc: &mut Blockchain<S>,
     nonce: u128,
     transactions: Vec<Transaction>,
-) -> Result<(), Error> {
-    let mut block = Block::new(bc.get_last_block_hash());
+) -> Result<(), BlockchainError> {
+    let mut block = bc.next_block();
     block.set_nonce(nonce);
+    block.set_timestamp(bc.now());
 
+    // Genesis goes in the order given, everything after in canonical order
+    let mut transactions = transactions;
+    if !bc.is_empty() {
+        bc.sort_transactions(&mut transactions);
+    }
     for tx in transactions {
         block.add_transaction(tx);
     }
+    if !bc.is_empty() {
+        mine_block(&mut block, bc.target());
+    }
 
     bc.append_block(block)
 }
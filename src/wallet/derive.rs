@@ -0,0 +1,66 @@
+use crate::traits::CanonicalEncode;
+use crate::types::AccountId;
+use blake2::{Blake2s, Digest};
+use ed25519_dalek::{Keypair, SecretKey};
+
+// Prefix of what workshop keys are hashed from, so a seed used for anything else doesn't
+// give away these keys
+pub const DERIVATION_DOMAIN: &str = "workshop-key-v1";
+
+// One attendee's key, the same for everyone deriving it from the same seed and index
+#[derive(Debug)]
+pub struct WorkshopKey {
+    pub index: u32,
+    pub account_id: AccountId,
+    pub keypair: Keypair,
+}
+
+// The account of the attendee with `index`, which doesn't depend on the seed so the
+// instructor can tell who is who
+pub fn workshop_account_id(index: u32) -> AccountId {
+    format!("attendee-{}", index)
+}
+
+// The secret key is the Blake2s hash of the domain, the seed and the index. Anyone who
+// knows the seed can derive every attendee's key, which is fine for a classroom chain and
+// nowhere else.
+pub fn derive_workshop_key(seed: &str, index: u32) -> WorkshopKey {
+    let digest = Blake2s::digest(&(DERIVATION_DOMAIN, seed, index).canonical_bytes());
+    let secret = SecretKey::from_bytes(&digest).expect("Blake2s digests are 32 bytes");
+    let public = (&secret).into();
+    WorkshopKey {
+        index,
+        account_id: workshop_account_id(index),
+        keypair: Keypair { secret, public },
+    }
+}
+
+// Keys 0 to n - 1, for pre-funding every attendee in genesis
+pub fn derive_workshop_keys(seed: &str, n: u32) -> Vec<WorkshopKey> {
+    (0..n).map(|index| derive_workshop_key(seed, index)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_derive_workshop_keys() {
+        let keys = derive_workshop_keys("oct-16", 20);
+        assert_eq!(keys.len(), 20);
+        assert_eq!(keys[7].account_id, "attendee-7");
+        let public_keys: HashSet<_> = keys.iter().map(|key| key.keypair.public.to_bytes()).collect();
+        assert_eq!(public_keys.len(), 20);
+
+        // An attendee re-deriving their own key gets the one the instructor funded
+        let mine = derive_workshop_key("oct-16", 7);
+        assert_eq!(mine.keypair.to_bytes(), keys[7].keypair.to_bytes());
+        assert_ne!(derive_workshop_key("oct-17", 7).keypair.public, mine.keypair.public);
+        // Changing this changes every attendee's key, and the ones already handed out
+        assert_eq!(
+            hex::encode(keys[0].keypair.public.as_bytes()),
+            "2bfe3a6be81d48b98579808867791ae938c4d99ddddcc1bf0e5f2f4f03cf8eb1"
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use crate::types::{AccountId, Error};
+use crate::wallet::Labels;
+use ed25519_dalek::{Keypair, SecretKey};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// Name of the file in the chain's keystore directory
+pub const KEYSTORE_FILE: &str = "keystore.json";
+
+// The wallet's file: the secret keys of its accounts, hex encoded and unencrypted, this
+// being a workshop chain, plus the labels and notes the owner keeps on transactions and
+// addresses. Nothing in here goes on chain.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Keystore {
+    #[serde(default)]
+    keys: BTreeMap<AccountId, String>,
+    #[serde(default)]
+    labels: Labels,
+}
+
+impl Keystore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // An empty keystore if the file doesn't exist yet
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+        let json = fs::read_to_string(path).map_err(|error| format!("Can't read {}: {}", path.display(), error))?;
+        serde_json::from_str(&json).map_err(|error| format!("Invalid keystore {}: {}", path.display(), error))
+    }
+
+    // Written next to the file first and moved over it, so a crash leaves the old one
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self).map_err(|error| error.to_string())?;
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, json)
+            .and_then(|_| fs::rename(&temporary, path))
+            .map_err(|error| format!("Can't write {}: {}", path.display(), error))
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = &AccountId> {
+        self.keys.keys()
+    }
+
+    pub fn add_keypair(&mut self, account_id: AccountId, keypair: &Keypair) {
+        self.keys.insert(account_id, hex::encode(keypair.secret.as_bytes()));
+    }
+
+    pub fn keypair(&self, account_id: &AccountId) -> Result<Option<Keypair>, Error> {
+        let secret = match self.keys.get(account_id) {
+            Some(secret) => secret,
+            None => return Ok(None),
+        };
+        let invalid = |error: &dyn std::fmt::Display| format!("Invalid key for {}: {}", account_id, error);
+        let bytes = hex::decode(secret).map_err(|error| invalid(&error))?;
+        let secret = SecretKey::from_bytes(&bytes).map_err(|error| invalid(&error))?;
+        let public = (&secret).into();
+        Ok(Some(Keypair { secret, public }))
+    }
+
+    pub fn labels(&self) -> &Labels {
+        &self.labels
+    }
+
+    pub fn labels_mut(&mut self) -> &mut Labels {
+        &mut self.labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::ChainEvent;
+    use crate::traits::Hashable;
+    use crate::types::{Hash, Transaction, TransactionData};
+    use crate::wallet::WalletTracker;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn transfer(to: &str, amount: u128) -> Transaction {
+        Transaction::new(
+            TransactionData::Transfer {
+                to: to.to_string(),
+                amount,
+            },
+            Some("alice".to_string()),
+            amount,
+        )
+    }
+
+    #[test]
+    fn test_labels_in_keystore() {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let path = std::env::temp_dir().join(format!("keystore-{}-{}.json", std::process::id(), nanos));
+        assert_eq!(Keystore::load(&path), Ok(Keystore::new()));
+
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng {});
+        let (january, february, groceries) = (transfer("landlord", 500), transfer("landlord", 501), transfer("shop", 20));
+        let mut keystore = Keystore::new();
+        keystore.add_keypair("alice".to_string(), &keypair);
+        let labels = keystore.labels_mut();
+        labels.label_address("landlord".to_string(), "Rent");
+        labels.set_address_note("landlord".to_string(), Some("Flat on Main St".to_string()));
+        labels.label_transaction(groceries.hash(), "food");
+        labels.set_transaction_note(groceries.hash(), Some("Birthday party".to_string()));
+        keystore.save(&path).unwrap();
+
+        let keystore = Keystore::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(keystore.accounts().collect::<Vec<_>>(), vec!["alice"]);
+        assert_eq!(keystore.keypair(&"alice".to_string()).unwrap().unwrap().public, keypair.public);
+        assert!(keystore.keypair(&"bob".to_string()).unwrap().is_none());
+        let note = keystore.labels().transaction(&groceries.hash()).unwrap();
+        assert_eq!(note.text.as_deref(), Some("Birthday party"));
+
+        let mut wallet = WalletTracker::new();
+        wallet.track("alice".to_string());
+        for transaction in [&january, &groceries, &february].iter() {
+            wallet.apply(&ChainEvent::PendingTransaction((*transaction).clone()));
+        }
+        let rent: Vec<Hash> = wallet
+            .history_with_label(keystore.labels(), "rent")
+            .iter()
+            .map(|(transaction, _)| transaction.hash())
+            .collect();
+        assert_eq!(rent, vec![january.hash(), february.hash()]);
+        assert_eq!(wallet.history_with_label(keystore.labels(), "FOOD").len(), 1);
+        assert!(wallet.history_with_label(keystore.labels(), "travel").is_empty());
+
+        let mut labels = keystore.labels().clone();
+        assert!(labels.unlabel_transaction(&groceries.hash(), "food"));
+        labels.set_transaction_note(groceries.hash(), None);
+        assert!(labels.transaction(&groceries.hash()).is_none());
+        assert!(!labels.unlabel_address(&"landlord".to_string(), "food"));
+    }
+}
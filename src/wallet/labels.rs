@@ -0,0 +1,105 @@
+use crate::types::{AccountId, Hash, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+// What the owner wrote down about a transaction or an address
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Note {
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub labels: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+impl Note {
+    fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.text.is_none()
+    }
+}
+
+// Local bookkeeping of a wallet, kept in the keystore file and never sent anywhere.
+// Labels are matched case insensitively, so "Rent" and "rent" are the same label.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Labels {
+    #[serde(default)]
+    transactions: BTreeMap<Hash, Note>,
+    #[serde(default)]
+    addresses: BTreeMap<AccountId, Note>,
+}
+
+impl Labels {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn transaction(&self, hash: &Hash) -> Option<&Note> {
+        self.transactions.get(hash)
+    }
+
+    pub fn address(&self, account_id: &AccountId) -> Option<&Note> {
+        self.addresses.get(account_id)
+    }
+
+    pub fn label_transaction(&mut self, hash: Hash, label: &str) {
+        self.transactions.entry(hash).or_default().labels.insert(normalize(label));
+    }
+
+    pub fn label_address(&mut self, account_id: AccountId, label: &str) {
+        self.addresses.entry(account_id).or_default().labels.insert(normalize(label));
+    }
+
+    // Whether the label was there
+    pub fn unlabel_transaction(&mut self, hash: &Hash, label: &str) -> bool {
+        remove_label(&mut self.transactions, hash, label)
+    }
+
+    pub fn unlabel_address(&mut self, account_id: &AccountId, label: &str) -> bool {
+        remove_label(&mut self.addresses, account_id, label)
+    }
+
+    // None clears the note
+    pub fn set_transaction_note(&mut self, hash: Hash, text: Option<String>) {
+        set_text(&mut self.transactions, hash, text);
+    }
+
+    pub fn set_address_note(&mut self, account_id: AccountId, text: Option<String>) {
+        set_text(&mut self.addresses, account_id, text);
+    }
+
+    // A transaction matches when it carries the label itself or touches an address that
+    // does, so labeling the landlord's account finds every rent payment
+    pub fn matches(&self, transaction: &Transaction, hash: &Hash, label: &str) -> bool {
+        let label = normalize(label);
+        let has_label = |note: Option<&Note>| matches!(note, Some(note) if note.labels.contains(&label));
+
+        has_label(self.transactions.get(hash))
+            || self
+                .addresses
+                .iter()
+                .any(|(account_id, note)| note.labels.contains(&label) && transaction.involves(account_id))
+    }
+}
+
+fn normalize(label: &str) -> String {
+    label.trim().to_lowercase()
+}
+
+fn remove_label(notes: &mut BTreeMap<String, Note>, key: &str, label: &str) -> bool {
+    let note = match notes.get_mut(key) {
+        Some(note) => note,
+        None => return false,
+    };
+    let removed = note.labels.remove(&normalize(label));
+    if note.is_empty() {
+        notes.remove(key);
+    }
+    removed
+}
+
+fn set_text(notes: &mut BTreeMap<String, Note>, key: String, text: Option<String>) {
+    let note = notes.entry(key.clone()).or_default();
+    note.text = text;
+    if note.is_empty() {
+        notes.remove(&key);
+    }
+}
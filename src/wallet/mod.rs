@@ -0,0 +1,9 @@
+mod derive;
+mod keystore;
+mod labels;
+mod tracker;
+
+pub use derive::{derive_workshop_key, derive_workshop_keys, workshop_account_id, WorkshopKey, DERIVATION_DOMAIN};
+pub use keystore::{Keystore, KEYSTORE_FILE};
+pub use labels::{Labels, Note};
+pub use tracker::{ConfirmationStatus, WalletBalance, WalletTracker};
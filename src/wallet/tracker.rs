@@ -0,0 +1,313 @@
+use crate::rpc::ChainEvent;
+use crate::traits::Hashable;
+use crate::types::{AccountId, Amount, Hash, Transaction, TransactionData, ALIAS_PREFIX, DEFAULT_SAFE_DEPTH};
+use crate::wallet::Labels;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfirmationStatus {
+    Pending,
+    // Included at `height`, `confirmations` is 1 while that block is the tip
+    Confirmed { height: u64, confirmations: u64 },
+    // Included at or below the finalized checkpoint, no reorg can take it out anymore
+    Finalized { height: u64 },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WalletBalance {
+    pub finalized: Amount,
+    // Including finalized transactions
+    pub confirmed: Amount,
+    // Including confirmed and pending transactions
+    pub pending: Amount,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    transaction: Transaction,
+    block: Option<(u64, Hash)>,
+}
+
+// History of the transactions touching a set of accounts, kept up to date from chain
+// events. Transactions in a block that a reorg reverts go back to pending.
+#[derive(Debug)]
+pub struct WalletTracker {
+    accounts: HashSet<AccountId>,
+    // Aliases of tracked accounts, payments to "@alias" count for the account
+    aliases: HashMap<String, AccountId>,
+    entries: HashMap<Hash, Entry>,
+    // Transaction hashes in the order they were first seen
+    order: Vec<Hash>,
+    tip_height: Option<u64>,
+    finalized_height: Option<u64>,
+    safe_depth: u64,
+}
+
+impl Default for WalletTracker {
+    fn default() -> Self {
+        Self {
+            accounts: HashSet::new(),
+            aliases: HashMap::new(),
+            entries: HashMap::new(),
+            order: vec![],
+            tip_height: None,
+            finalized_height: None,
+            safe_depth: DEFAULT_SAFE_DEPTH,
+        }
+    }
+}
+
+impl WalletTracker {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn track(&mut self, account_id: AccountId) {
+        self.accounts.insert(account_id);
+    }
+
+    pub fn is_tracked(&self, account_id: &AccountId) -> bool {
+        self.accounts.contains(account_id)
+    }
+
+    // Tracks `account_id` along with an alias it holds
+    pub fn track_alias(&mut self, alias: String, account_id: AccountId) {
+        self.track(account_id.clone());
+        self.aliases.insert(alias, account_id);
+    }
+
+    // The tracked account an alias reference belongs to, other ids as they are
+    pub fn resolve<'a>(&'a self, account_id: &'a AccountId) -> &'a AccountId {
+        account_id
+            .strip_prefix(ALIAS_PREFIX)
+            .and_then(|alias| self.aliases.get(alias))
+            .unwrap_or(account_id)
+    }
+
+    pub fn set_finalized_height(&mut self, finalized_height: Option<u64>) {
+        self.finalized_height = finalized_height;
+    }
+
+    pub fn set_safe_depth(&mut self, safe_depth: u64) {
+        self.safe_depth = safe_depth.max(1);
+    }
+
+    pub fn apply(&mut self, event: &ChainEvent) {
+        match event {
+            ChainEvent::PendingTransaction(transaction) => {
+                if self.is_relevant(transaction) {
+                    self.entry(transaction);
+                }
+            }
+            ChainEvent::NewBlock { height, block } => {
+                self.tip_height = Some(*height);
+                let block_hash = block.hash();
+                for transaction in &block.body.transactions {
+                    if self.is_relevant(transaction) {
+                        self.entry(transaction).block = Some((*height, block_hash.clone()));
+                    }
+                }
+            }
+            ChainEvent::BlockReverted { height, block } => {
+                self.tip_height = height.checked_sub(1);
+                let block_hash = block.hash();
+                for entry in self.entries.values_mut() {
+                    if matches!(&entry.block, Some((_, hash)) if hash == &block_hash) {
+                        entry.block = None;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn status(&self, transaction_hash: &Hash) -> Option<ConfirmationStatus> {
+        self.entries.get(transaction_hash).map(|entry| self.entry_status(entry))
+    }
+
+    // Whether a payment can be treated as settled: finalized, or confirmed at least
+    // safe depth times
+    pub fn is_safe(&self, transaction_hash: &Hash) -> bool {
+        match self.status(transaction_hash) {
+            Some(ConfirmationStatus::Finalized { .. }) => true,
+            Some(ConfirmationStatus::Confirmed { confirmations, .. }) => confirmations >= self.safe_depth,
+            _ => false,
+        }
+    }
+
+    // Oldest first
+    pub fn history(&self) -> Vec<(&Transaction, ConfirmationStatus)> {
+        self.order
+            .iter()
+            .filter_map(|hash| self.entries.get(hash))
+            .map(|entry| (&entry.transaction, self.entry_status(entry)))
+            .collect()
+    }
+
+    // The history filtered down to what matches `label`, as `wallet history --label rent`
+    // shows it
+    pub fn history_with_label(&self, labels: &Labels, label: &str) -> Vec<(&Transaction, ConfirmationStatus)> {
+        self.order
+            .iter()
+            .filter_map(|hash| self.entries.get(hash).map(|entry| (hash, entry)))
+            .filter(|(hash, entry)| labels.matches(&entry.transaction, hash, label))
+            .map(|(_, entry)| (&entry.transaction, self.entry_status(entry)))
+            .collect()
+    }
+
+    // Balance of a tracked account from its history, by how settled the transactions are
+    pub fn balance(&self, account_id: &AccountId) -> WalletBalance {
+        let mut balance = WalletBalance::default();
+
+        for (transaction, status) in self.history() {
+            let (credit, debit) = match transaction.data() {
+                TransactionData::MintInitialSupply { to, amount } | TransactionData::Coinbase { to, amount } if to == account_id => {
+                    (Amount::from(*amount), Amount::ZERO)
+                }
+                TransactionData::Transfer { to, amount } => {
+                    let credit = if self.resolve(to) == account_id { Amount::from(*amount) } else { Amount::ZERO };
+                    let debit = if transaction.from() == Some(account_id) { Amount::from(*amount) } else { Amount::ZERO };
+                    (credit, debit)
+                }
+                _ => continue,
+            };
+            let apply = |balance: &mut Amount| *balance = balance.saturating_add(credit).saturating_sub(debit);
+
+            apply(&mut balance.pending);
+            if status != ConfirmationStatus::Pending {
+                apply(&mut balance.confirmed);
+            }
+            if matches!(status, ConfirmationStatus::Finalized { .. }) {
+                apply(&mut balance.finalized);
+            }
+        }
+
+        balance
+    }
+
+    fn is_relevant(&self, transaction: &Transaction) -> bool {
+        self.accounts.iter().any(|account_id| transaction.involves(account_id))
+            || self.accounts.contains(self.resolve(transaction.target()))
+    }
+
+    fn entry(&mut self, transaction: &Transaction) -> &mut Entry {
+        let hash = transaction.hash();
+        if !self.entries.contains_key(&hash) {
+            self.order.push(hash.clone());
+        }
+
+        self.entries.entry(hash).or_insert_with(|| Entry {
+            transaction: transaction.clone(),
+            block: None,
+        })
+    }
+
+    fn entry_status(&self, entry: &Entry) -> ConfirmationStatus {
+        match (&entry.block, self.tip_height) {
+            (Some((height, _)), _) if matches!(self.finalized_height, Some(finalized) if *height <= finalized) => {
+                ConfirmationStatus::Finalized { height: *height }
+            }
+            (Some((height, _)), Some(tip_height)) => ConfirmationStatus::Confirmed {
+                height: *height,
+                confirmations: tip_height.saturating_sub(*height) + 1,
+            },
+            _ => ConfirmationStatus::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Balance, Block};
+
+    fn transfer(from: &str, to: &str, amount: Balance) -> Transaction {
+        Transaction::new(
+            TransactionData::Transfer {
+                to: to.to_string(),
+                amount,
+            },
+            Some(from.to_string()),
+            amount,
+        )
+    }
+
+    fn block(prev_hash: Option<Hash>, transactions: Vec<Transaction>) -> Block {
+        let mut block = Block::new(prev_hash);
+        for transaction in transactions {
+            block.add_transaction(transaction);
+        }
+        block
+    }
+
+    #[test]
+    fn test_confirmations_and_reorg() {
+        let mut wallet = WalletTracker::new();
+        wallet.track("alice".to_string());
+        wallet.set_safe_depth(2);
+
+        let mint = Transaction::new(
+            TransactionData::MintInitialSupply {
+                to: "alice".to_string(),
+                amount: 100,
+            },
+            None,
+            0,
+        );
+        let genesis = block(None, vec![mint.clone()]);
+        let payment = transfer("alice", "bob", 30);
+        let unrelated = transfer("bob", "carol", 5);
+        wallet.apply(&ChainEvent::NewBlock { height: 0, block: genesis.clone() });
+        wallet.apply(&ChainEvent::PendingTransaction(payment.clone()));
+        wallet.apply(&ChainEvent::PendingTransaction(unrelated.clone()));
+
+        assert_eq!(wallet.status(&payment.hash()), Some(ConfirmationStatus::Pending));
+        assert_eq!(wallet.status(&unrelated.hash()), None);
+        assert_eq!(
+            wallet.balance(&"alice".to_string()),
+            WalletBalance {
+                finalized: Amount::ZERO,
+                confirmed: Amount::from(100),
+                pending: Amount::from(70)
+            }
+        );
+
+        let confirming = block(Some(genesis.hash()), vec![payment.clone()]);
+        wallet.apply(&ChainEvent::NewBlock { height: 1, block: confirming.clone() });
+        wallet.apply(&ChainEvent::NewBlock { height: 2, block: block(Some(confirming.hash()), vec![unrelated]) });
+        assert_eq!(
+            wallet.status(&payment.hash()),
+            Some(ConfirmationStatus::Confirmed { height: 1, confirmations: 2 })
+        );
+        assert!(wallet.is_safe(&payment.hash()));
+        assert_eq!(wallet.balance(&"alice".to_string()).confirmed, Amount::from(70));
+
+        // A reorg takes the payment's block out again
+        wallet.apply(&ChainEvent::BlockReverted { height: 2, block: Block::new(None) });
+        wallet.apply(&ChainEvent::BlockReverted { height: 1, block: confirming });
+        assert_eq!(wallet.status(&payment.hash()), Some(ConfirmationStatus::Pending));
+        assert!(!wallet.is_safe(&payment.hash()));
+        assert_eq!(wallet.balance(&"alice".to_string()).confirmed, Amount::from(100));
+        assert_eq!(wallet.history().len(), 2);
+
+        wallet.set_finalized_height(Some(0));
+        assert_eq!(wallet.status(&mint.hash()), Some(ConfirmationStatus::Finalized { height: 0 }));
+        assert!(wallet.is_safe(&mint.hash()));
+        assert_eq!(wallet.balance(&"alice".to_string()).finalized, Amount::from(100));
+    }
+
+    #[test]
+    fn test_aliases() {
+        let mut wallet = WalletTracker::new();
+        wallet.track_alias("bobby".to_string(), "bob".to_string());
+        assert!(wallet.is_tracked(&"bob".to_string()));
+        assert_eq!(wallet.resolve(&"@bobby".to_string()), "bob");
+        assert_eq!(wallet.resolve(&"@ali".to_string()), "@ali");
+
+        let payment = transfer("alice", "@bobby", 30);
+        wallet.apply(&ChainEvent::PendingTransaction(payment.clone()));
+        wallet.apply(&ChainEvent::PendingTransaction(transfer("alice", "@ali", 5)));
+        assert_eq!(wallet.status(&payment.hash()), Some(ConfirmationStatus::Pending));
+        assert_eq!(wallet.history().len(), 1);
+        assert_eq!(wallet.balance(&"bob".to_string()).pending, Amount::from(30));
+    }
+}